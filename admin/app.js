@@ -0,0 +1,129 @@
+"use strict";
+
+const LOGIN_PAGE = "/admin/ui/login.html";
+
+async function api(path, options = {}) {
+    const response = await fetch("/api/v1" + path, {
+        headers: {"Content-Type": "application/json"},
+        ...options,
+    });
+    if (response.status === 401) {
+        // the session expired
+        location.href = LOGIN_PAGE;
+    }
+    if (!response.ok) {
+        const body = await response.json().catch(() => ({}));
+        throw new Error(body.detail || body.title || response.statusText);
+    }
+    const text = await response.text();
+    return text ? JSON.parse(text) : null;
+}
+
+function showError(e) {
+    document.getElementById("error").textContent = e ? e.message : "";
+}
+
+function cell(text) {
+    const td = document.createElement("td");
+    td.textContent = text ?? "";
+    return td;
+}
+
+function listEntry(text) {
+    const li = document.createElement("li");
+    li.textContent = text;
+    return li;
+}
+
+function renderItems(items) {
+    const rows = document.getElementById("item-rows");
+    rows.replaceChildren(...items.map(item => {
+        const tr = document.createElement("tr");
+        tr.append(cell(item.id), cell(item.name), cell(item.description), cell(item.quantity));
+
+        const remove = document.createElement("button");
+        remove.textContent = "Delete";
+        remove.onclick = () => api(`/item/${item.id}`, {method: "DELETE"})
+            .then(loadItems)
+            .catch(showError);
+        const actions = document.createElement("td");
+        actions.append(remove);
+        tr.append(actions);
+        return tr;
+    }));
+}
+
+async function loadItems() {
+    renderItems(await api("/item"));
+}
+
+async function loadCategories() {
+    const categories = await api("/category");
+    document.getElementById("category-list")
+        .replaceChildren(...categories.map(c => listEntry(c.name)));
+}
+
+async function loadLocations() {
+    const locations = await api("/location");
+    document.getElementById("location-list")
+        .replaceChildren(...locations.map(l => listEntry(l.name)));
+}
+
+async function loadStorage() {
+    const usage = await api("/admin/storage-usage");
+    document.getElementById("storage-list").replaceChildren(
+        ...usage.entities.map(e => listEntry(`${e.entity}: ${e.files} files, ${e.bytes} bytes`)),
+        listEntry(`total: ${usage.total_bytes} bytes`),
+    );
+}
+
+function onSubmit(id, handler) {
+    document.getElementById(id).addEventListener("submit", event => {
+        event.preventDefault();
+        const form = event.target;
+        handler(Object.fromEntries(new FormData(form)))
+            .then(() => {
+                form.reset();
+                showError(null);
+            })
+            .catch(showError);
+    });
+}
+
+onSubmit("search", async ({query}) => {
+    if (!query) {
+        return loadItems();
+    }
+    renderItems(await api(`/item/search/${encodeURIComponent(query)}`).catch(() => []));
+});
+
+onSubmit("new-item", async ({name, description}) => {
+    await api("/item", {
+        method: "POST",
+        body: JSON.stringify({name, description: description || null}),
+    });
+    await loadItems();
+});
+
+onSubmit("new-category", async ({name}) => {
+    await api("/category", {method: "POST", body: JSON.stringify({name})});
+    await loadCategories();
+});
+
+onSubmit("new-location", async ({name}) => {
+    await api("/location", {method: "POST", body: JSON.stringify({name})});
+    await loadLocations();
+});
+
+document.getElementById("logout").addEventListener("click", event => {
+    event.preventDefault();
+    api("/auth/logout", {method: "POST"})
+        .then(() => location.href = LOGIN_PAGE)
+        .catch(showError);
+});
+
+function loadAll() {
+    return Promise.all([loadItems(), loadCategories(), loadLocations(), loadStorage()]);
+}
+
+loadAll().catch(showError);
@@ -0,0 +1,156 @@
+//! Hot paths of search and item creation. Run with `cargo bench`, criterion keeps the results of
+//! previous runs in `target/criterion` and reports changes against them.
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use doc_search::{
+    Document, EmptyWordFilter, Index, MemoryStorage, OptionType, QueryOption, SimpleTokenizer,
+};
+use find_me_pls::{demo_items, Analyzer, BusinessRules, ConfigHandle, Item};
+use tokio::runtime::Runtime;
+
+type SearchIndex = Index<i64, MemoryStorage<i64>, PathBuf>;
+
+const SYLLABLES: [&str; 16] = [
+    "ka", "lo", "mi", "ne", "ru", "sa", "ti", "vo", "ba", "de", "fu", "go", "he", "ji", "po", "ze",
+];
+const WORDS_PER_DOCUMENT: usize = 8;
+
+/// Pronounceable, distinct words, so autocorrect has realistic neighbours to choose from
+fn vocabulary(size: usize) -> Vec<String> {
+    (0..size)
+        .map(|mut i| {
+            let mut word = String::new();
+            for _ in 0..4 {
+                word.push_str(SYLLABLES[i % SYLLABLES.len()]);
+                i /= SYLLABLES.len();
+            }
+            word
+        })
+        .collect()
+}
+
+/// Documents that together use every word of the vocabulary
+fn documents(vocabulary: &[String]) -> Vec<(i64, String)> {
+    vocabulary
+        .chunks(WORDS_PER_DOCUMENT)
+        .enumerate()
+        .map(|(id, words)| (id as i64, words.join(" ")))
+        .collect()
+}
+
+async fn build_index(documents: &[(i64, String)]) -> SearchIndex {
+    let storage = MemoryStorage::new("bench_storage.json");
+    let mut index = Index::new(None, storage);
+    for (id, text) in documents {
+        let document = Document::new(*id, text.clone(), &EmptyWordFilter {}, &SimpleTokenizer::new());
+        index.insert_document(document).await.unwrap();
+    }
+    index
+}
+
+/// Runs the benchmarks in a scratch directory, the business rules keep their database and
+/// files relative to the working directory.
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("find_me_pls_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+    dir
+}
+
+fn index_insert(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("index_insert");
+
+    for count in [100, 1_000, 10_000] {
+        let documents = documents(&vocabulary(count * WORDS_PER_DOCUMENT));
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &documents, |b, documents| {
+            b.to_async(&runtime).iter_batched(
+                || documents.clone(),
+                |documents| async move { build_index(&documents).await },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+/// Query latency by vocabulary size. An exact word is found directly, a misspelled one has to
+/// go through autocorrect first.
+fn query(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("query");
+
+    for size in [1_000, 10_000, 100_000] {
+        let vocabulary = vocabulary(size);
+        let index = runtime.block_on(build_index(&documents(&vocabulary)));
+        let exact = vocabulary[size / 2].clone();
+        // swap the first two letters
+        let misspelled = format!("{}{}{}", &exact[1..2], &exact[0..1], &exact[2..]);
+
+        for (name, term) in [("exact", &exact), ("misspelled", &misspelled)] {
+            group.bench_with_input(BenchmarkId::new(name, size), term, |b, term| {
+                b.to_async(&runtime).iter(|| async {
+                    index
+                        .query(
+                            term,
+                            &SimpleTokenizer::new(),
+                            &EmptyWordFilter {},
+                            Some(QueryOption::new().add(OptionType::TfIdf).build()),
+                        )
+                        .await
+                        .unwrap()
+                        .collect()
+                        .len()
+                });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// `add_item` end to end: validation, the database, the index and writing the thumbnail file
+fn add_item(c: &mut Criterion) {
+    let dir = scratch_dir();
+    std::fs::File::create(dir.join("db.sqlite")).unwrap();
+
+    let runtime = Runtime::new().unwrap();
+    let rules = runtime.block_on(async {
+        let index = Index::new(None, MemoryStorage::new("storage.json"));
+        let rules = BusinessRules::new(
+            Some(index),
+            Analyzer::default(),
+            ConfigHandle::load("config.json"),
+        )
+        .await;
+        rules.init_db().await;
+        rules
+    });
+
+    let demo = demo_items(1).unwrap().remove(0);
+    let item = Item {
+        name: demo.name,
+        description: Some(demo.description),
+        price: Some(demo.price),
+        thumbnail: Some(base64::engine::general_purpose::STANDARD.encode(demo.thumbnail)),
+        tags: demo.tags,
+        quantity: Some(demo.quantity),
+        ..Default::default()
+    };
+
+    c.bench_function("add_item", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { rules.add_item(item.clone()).await.unwrap() });
+    });
+
+    drop(rules);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+criterion_group!(benches, index_insert, query, add_item);
+criterion_main!(benches);
@@ -1,4 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/find_me_pls.proto")?;
+    tonic_build::configure().compile(
+        &[
+            "proto/find_me_pls/v1/find_me_pls.proto",
+            "proto/find_me_pls/v2/find_me_pls.proto",
+        ],
+        &["proto"],
+    )?;
     Ok(())
 }
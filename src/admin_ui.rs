@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use rust_embed::RustEmbed;
+
+use crate::BusinessRules;
+
+/// Static files of the admin ui, compiled into the binary
+#[derive(RustEmbed)]
+#[folder = "admin/"]
+struct Assets;
+
+/// Routes of the admin ui, to be nested below `/admin/ui`
+pub fn admin_ui_router() -> Router<Arc<BusinessRules>> {
+    Router::new()
+        .route("/", get(index))
+        .route("/*path", get(asset))
+}
+
+async fn index() -> Response {
+    serve("index.html")
+}
+
+async fn asset(Path(path): Path<String>) -> Response {
+    if Assets::get(&path).is_some() {
+        serve(&path)
+    } else if path.contains('.') {
+        StatusCode::NOT_FOUND.into_response()
+    } else {
+        // client side route of the spa
+        serve("index.html")
+    }
+}
+
+fn serve(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_owned())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use axum::http::StatusCode;
+
+use crate::{util, AnalyzerConfig, CustError, Result, TokenizerKind};
+
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "the", "to", "with",
+];
+const GERMAN_STOP_WORDS: &[&str] = &[
+    "am", "an", "auf", "aus", "das", "dem", "den", "der", "die", "ein", "eine", "einem", "einen",
+    "einer", "für", "im", "in", "ist", "mit", "und", "von", "zu", "zum", "zur",
+];
+
+/// Splits analyzed text into the terms that are indexed
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Decides which words of a text are indexed at all
+pub trait WordFilter: Send + Sync {
+    fn keep(&self, word: &str) -> bool;
+}
+
+/// Lowercased words, split at everything that isn't alphanumeric
+pub struct WordTokenizer;
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        util::search_tokens(text)
+    }
+}
+
+/// Overlapping character n-grams of every word, so parts of compound words match. Words shorter
+/// than `n` are kept whole.
+pub struct NgramTokenizer {
+    n: usize,
+}
+
+impl NgramTokenizer {
+    pub fn new(n: usize) -> Result<Self> {
+        if n < 2 {
+            return Err(CustError::new(
+                format!("n-grams must be at least 2 characters long, not {}", n),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        Ok(Self { n })
+    }
+}
+
+impl Tokenizer for NgramTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut terms = vec![];
+        for word in util::search_tokens(text) {
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len() <= self.n {
+                terms.push(word);
+                continue;
+            }
+            terms.extend(chars.windows(self.n).map(|gram| gram.iter().collect::<String>()));
+        }
+        terms
+    }
+}
+
+pub struct KeepAllWords;
+
+impl WordFilter for KeepAllWords {
+    fn keep(&self, _word: &str) -> bool {
+        true
+    }
+}
+
+/// Drops common words of a language, which would otherwise match nearly every item
+pub struct StopWords {
+    words: HashSet<&'static str>,
+}
+
+impl StopWords {
+    /// Stop words of a language by its code, `en` or `de`
+    pub fn for_language(language: &str) -> Result<Self> {
+        let words = match language {
+            "en" => ENGLISH_STOP_WORDS,
+            "de" => GERMAN_STOP_WORDS,
+            _ => {
+                return Err(CustError::new(
+                    format!("no stop words for language: {}", language),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        };
+        Ok(Self {
+            words: words.iter().copied().collect(),
+        })
+    }
+}
+
+impl WordFilter for StopWords {
+    fn keep(&self, word: &str) -> bool {
+        !self.words.contains(word)
+    }
+}
+
+/// Turns item and query text into the terms of the search index. Documents and queries go
+/// through the same analyzer, so both end up with comparable terms.
+pub struct Analyzer {
+    tokenizer: Box<dyn Tokenizer>,
+    filter: Box<dyn WordFilter>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new(WordTokenizer, KeepAllWords)
+    }
+}
+
+impl Analyzer {
+    pub fn new(tokenizer: impl Tokenizer + 'static, filter: impl WordFilter + 'static) -> Self {
+        Self {
+            tokenizer: Box::new(tokenizer),
+            filter: Box::new(filter),
+        }
+    }
+
+    pub fn from_config(config: &AnalyzerConfig) -> Result<Self> {
+        let tokenizer: Box<dyn Tokenizer> = match config.tokenizer {
+            TokenizerKind::Words => Box::new(WordTokenizer),
+            TokenizerKind::Ngram => Box::new(NgramTokenizer::new(config.ngram_size)?),
+        };
+        let filter: Box<dyn WordFilter> = match &config.stop_words {
+            Some(language) => Box::new(StopWords::for_language(language)?),
+            None => Box::new(KeepAllWords),
+        };
+        Ok(Self { tokenizer, filter })
+    }
+
+    /// Filters the words of `text` and tokenizes what is left, joined by spaces
+    pub fn analyze(&self, text: &str) -> String {
+        let words: Vec<String> = util::search_tokens(text)
+            .into_iter()
+            .filter(|word| self.filter.keep(word))
+            .collect();
+        self.tokenizer.tokenize(&words.join(" ")).join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test_analyzer {
+    use crate::{Analyzer, NgramTokenizer, StopWords, WordTokenizer};
+
+    #[test]
+    fn stop_words_are_dropped_before_tokenizing() {
+        let analyzer = Analyzer::new(WordTokenizer, StopWords::for_language("en").unwrap());
+        assert_eq!(analyzer.analyze("The Hammer of the shed"), "hammer shed");
+
+        let analyzer = Analyzer::new(
+            NgramTokenizer::new(3).unwrap(),
+            StopWords::for_language("de").unwrap(),
+        );
+        assert_eq!(analyzer.analyze("Akku für Bohrer"), "akk kku boh ohr hre rer");
+
+        assert!(StopWords::for_language("xx").is_err());
+        assert!(NgramTokenizer::new(1).is_err());
+    }
+}
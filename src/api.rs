@@ -0,0 +1,213 @@
+use axum::body::{Body, HttpBody};
+use axum::extract::Query;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::{CustError, Result};
+
+/// Entries of a listing page, if the request doesn't say
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest page of a listing, larger requested pages are cut to it with a warning
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Response header of listings that return fewer entries than they have, e.g. searches cut to
+/// their limit. v2 reports it as the `total` of the envelope.
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// Body of the successful JSON responses of the v2 api. v1 answers with `data` alone, which
+/// leaves no room for anything about the response itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub data: T,
+    /// Passed as `?cursor=` for the next page of a listing, `null` on its last page and for
+    /// anything that isn't a listing
+    pub next_cursor: Option<String>,
+    /// Entries of a listing on all of its pages, `null` for anything that isn't a listing
+    pub total: Option<usize>,
+    /// Things the client should know, but that didn't stop the request
+    pub warnings: Vec<String>,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            data,
+            next_cursor: None,
+            total: None,
+            warnings: vec![],
+        }
+    }
+}
+
+impl<T> Envelope<Vec<T>> {
+    /// The page of `entries` selected by `page`
+    pub fn page(mut entries: Vec<T>, page: &PageQuery) -> Result<Self> {
+        let mut warnings = vec![];
+        let mut page_size = page.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+        if page_size > MAX_PAGE_SIZE {
+            warnings.push(format!(
+                "page_size is limited to {}, the page has fewer entries",
+                MAX_PAGE_SIZE
+            ));
+            page_size = MAX_PAGE_SIZE;
+        }
+        let offset = match &page.cursor {
+            Some(cursor) => decode_cursor(cursor)?,
+            None => 0,
+        };
+
+        let total = entries.len();
+        let end = offset.saturating_add(page_size).min(total);
+        let data: Vec<T> = entries.drain(offset.min(total)..end).collect();
+        Ok(Self {
+            data,
+            next_cursor: (end < total).then(|| encode_cursor(end)),
+            total: Some(total),
+            warnings,
+        })
+    }
+}
+
+impl<T: Serialize> IntoResponse for Envelope<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self).into_response()
+    }
+}
+
+/// `?cursor=<cursor>&page_size=<n>`, the page of a listing in the v2 api
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageQuery {
+    /// `next_cursor` of the page before, the first page without one
+    pub cursor: Option<String>,
+    pub page_size: Option<usize>,
+}
+
+/// Cursors are opaque to clients, so what they hold can change without breaking them. They
+/// hold the offset of the next entry; a listing that changes between two pages may skip or
+/// repeat entries.
+fn encode_cursor(offset: usize) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("offset:{}", offset))
+}
+
+fn decode_cursor(cursor: &str) -> Result<usize> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|cursor| cursor.strip_prefix("offset:")?.parse().ok())
+        .ok_or_else(|| {
+            CustError::new("invalid cursor".to_string(), StatusCode::BAD_REQUEST)
+                .with_code("invalid_cursor")
+        })
+}
+
+/// Wraps the JSON bodies of the v2 api in an [`Envelope`], listings are paged. Errors stay
+/// problem documents and files, streams and empty bodies are passed through as they are.
+pub async fn envelope_middleware(
+    Query(page): Query<PageQuery>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value == "application/json");
+    if !response.status().is_success() || !is_json {
+        return response;
+    }
+
+    let (mut parts, mut body) = response.into_parts();
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(e) => {
+                return CustError::new(
+                    format!("could not read the response: {}", e),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                    .into_response()
+            }
+        }
+    }
+    let Ok(data) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Response::from_parts(parts, axum::body::boxed(Body::from(bytes)));
+    };
+
+    let total: Option<usize> = parts
+        .headers
+        .get(TOTAL_COUNT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let envelope = match data {
+        serde_json::Value::Array(entries) => match Envelope::page(entries, &page) {
+            Ok(envelope) => Envelope {
+                data: serde_json::Value::Array(envelope.data),
+                next_cursor: envelope.next_cursor,
+                total: total.or(envelope.total),
+                warnings: envelope.warnings,
+            },
+            Err(e) => return e.into_response(),
+        },
+        data => Envelope::new(data),
+    };
+    let body = match serde_json::to_vec(&envelope) {
+        Ok(body) => body,
+        Err(e) => return CustError::from(anyhow::Error::from(e)).into_response(),
+    };
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+    Response::from_parts(parts, axum::body::boxed(Body::from(body)))
+}
+
+#[cfg(test)]
+mod test_api {
+    use super::{Envelope, PageQuery, MAX_PAGE_SIZE};
+
+    #[test]
+    fn listings_are_paged_with_cursors() {
+        let entries: Vec<i32> = (0..5).collect();
+        let first = PageQuery {
+            cursor: None,
+            page_size: Some(2),
+        };
+        let page = Envelope::page(entries.clone(), &first).unwrap();
+        assert_eq!(page.data, [0, 1]);
+        assert_eq!(page.total, Some(5));
+
+        let mut data = page.data;
+        let mut cursor = page.next_cursor;
+        while let Some(next) = cursor {
+            let query = PageQuery {
+                cursor: Some(next),
+                ..first.clone()
+            };
+            let page = Envelope::page(entries.clone(), &query).unwrap();
+            data.extend(page.data);
+            cursor = page.next_cursor;
+        }
+        assert_eq!(data, entries);
+
+        let invalid = PageQuery {
+            cursor: Some("not a cursor".to_owned()),
+            page_size: None,
+        };
+        let error = Envelope::page(entries.clone(), &invalid).unwrap_err();
+        assert_eq!(error.code(), "invalid_cursor");
+
+        let huge = PageQuery {
+            cursor: None,
+            page_size: Some(MAX_PAGE_SIZE + 1),
+        };
+        let page = Envelope::page(entries, &huge).unwrap();
+        assert_eq!(page.data.len(), 5);
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.warnings.len(), 1);
+    }
+}
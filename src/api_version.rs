@@ -0,0 +1,90 @@
+use axum::http::{StatusCode, Uri};
+use axum::response::Redirect;
+
+use crate::{CustError, Result};
+
+/// Prefix of the first JSON api. Breaking changes ship under a new prefix, nested next to this
+/// one, while existing clients keep using v1.
+pub const API_V1: &str = "/api/v1";
+
+/// Prefix of the JSON api whose bodies are wrapped in an [`Envelope`](crate::Envelope). Its
+/// routes are the ones of v1.
+pub const API_V2: &str = "/api/v2";
+
+/// First path segments of the routes that were served without a version prefix
+const LEGACY_PREFIXES: [&str; 13] = [
+    "/item",
+    "/items",
+    "/category",
+    "/valuation",
+    "/location",
+    "/collection",
+    "/webhook",
+    "/search",
+    "/admin/storage-usage",
+    "/admin/reload-config",
+    "/admin/search-analytics",
+    "/public",
+    "/auth",
+];
+
+/// The path without its `/api/vN` prefix, paths without one are returned unchanged.
+pub fn unversioned_path(path: &str) -> &str {
+    let Some(rest) = path.strip_prefix("/api/v") else {
+        return path;
+    };
+    let version_len = rest.find('/').unwrap_or(rest.len());
+    if version_len == 0 || !rest[..version_len].bytes().all(|b| b.is_ascii_digit()) {
+        return path;
+    }
+    &rest[version_len..]
+}
+
+/// Fallback of the REST api: permanently redirects paths from before versioning to v1. The
+/// redirect is a 308, so clients repeat the request with the same method and body.
+pub async fn legacy_redirect(uri: Uri) -> Result<Redirect> {
+    let path = uri.path();
+    let legacy = LEGACY_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    if !legacy {
+        return Err(CustError::new("not found".to_string(), StatusCode::NOT_FOUND));
+    }
+
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or(path);
+    Ok(Redirect::permanent(&format!("{}{}", API_V1, path_and_query)))
+}
+
+#[cfg(test)]
+mod test_api_version {
+    use axum::http::{header, StatusCode, Uri};
+    use axum::response::IntoResponse;
+
+    use crate::{legacy_redirect, unversioned_path};
+
+    #[test]
+    fn version_prefix_is_stripped() {
+        assert_eq!(unversioned_path("/api/v1/auth/login"), "/auth/login");
+        assert_eq!(unversioned_path("/api/v12/item"), "/item");
+        assert_eq!(unversioned_path("/api/v1"), "");
+        assert_eq!(unversioned_path("/api/vx/item"), "/api/vx/item");
+        assert_eq!(unversioned_path("/auth/login"), "/auth/login");
+    }
+
+    #[tokio::test]
+    async fn legacy_paths_are_redirected() {
+        let uri: Uri = "/item/search/hammer?explain=true".parse().unwrap();
+        let response = legacy_redirect(uri).await.into_response();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/api/v1/item/search/hammer?explain=true"
+        );
+
+        for uri in ["/itemsxyz", "/admin/ui/"] {
+            let response = legacy_redirect(uri.parse().unwrap()).await.into_response();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+}
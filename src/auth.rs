@@ -0,0 +1,428 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tonic::server::NamedService;
+use tonic::service::Interceptor;
+use tonic::Status;
+use tower::Service;
+
+use crate::{unversioned_path, util, BusinessRules, CustError, Result, TokenScope};
+
+/// What a caller is allowed to do. Roles are ordered, a higher role includes the lower ones.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Role {
+    ReadOnly,
+    ReadWrite,
+    /// Also destructive bulk operations
+    Admin,
+}
+
+/// A static api token, configured in `config.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+    /// Restricts the token to some categories and collections, unrestricted if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<TokenScope>,
+}
+
+/// The authenticated caller of a request, attached to the request extensions
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthContext {
+    pub name: String,
+    pub role: Role,
+    /// Enforced by the [`crate::AccessScope`] of the business rules
+    pub scope: Option<TokenScope>,
+}
+
+impl AuthContext {
+    pub fn require(&self, role: Role) -> Result<()> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(CustError::new(
+                format!("{} is not allowed to do this", self.name),
+                StatusCode::FORBIDDEN,
+            ))
+        }
+    }
+}
+
+/// Name of the cookie holding the session of a logged in user
+pub const SESSION_COOKIE: &str = "findmepls_session";
+
+/// Resolves bearer tokens of REST and gRPC requests into an [`AuthContext`] and signs session
+/// cookies. Auth is disabled, i.e. every caller has full access, until tokens are configured or
+/// the first user account is created.
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    tokens: Arc<Vec<ApiToken>>,
+    session_secret: Arc<Vec<u8>>,
+    has_users: Arc<AtomicBool>,
+}
+
+impl Default for Authenticator {
+    fn default() -> Self {
+        Self::new(vec![], None)
+    }
+}
+
+impl Authenticator {
+    /// Without a configured secret a random one is used, so sessions end with a restart.
+    pub fn new(tokens: Vec<ApiToken>, session_secret: Option<&str>) -> Self {
+        let session_secret = match session_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => util::random_token().into_bytes(),
+        };
+
+        Self {
+            tokens: Arc::new(tokens),
+            session_secret: Arc::new(session_secret),
+            has_users: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_has_users(&self, has_users: bool) {
+        self.has_users.store(has_users, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || self.has_users.load(Ordering::Relaxed)
+    }
+
+    /// Resolves the value of an `authorization` header or metadata entry.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<AuthContext> {
+        if !self.enabled() {
+            return Ok(AuthContext {
+                name: "anonymous".to_owned(),
+                role: Role::Admin,
+                scope: None,
+            });
+        }
+
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                CustError::new("missing bearer token".to_string(), StatusCode::UNAUTHORIZED)
+            })?;
+
+        self.tokens
+            .iter()
+            .find(|t| constant_time_eq(t.token.as_bytes(), token.trim().as_bytes()))
+            .map(|t| AuthContext {
+                name: t.name.clone(),
+                role: t.role,
+                scope: t.scope.clone(),
+            })
+            .ok_or_else(|| CustError::new("invalid token".to_string(), StatusCode::UNAUTHORIZED))
+    }
+
+    /// Cookie value of a session: the session id followed by its signature
+    pub fn sign_session(&self, session_id: &str) -> String {
+        format!("{}.{}", session_id, self.session_signature(session_id))
+    }
+
+    /// Returns the session id of a cookie value, if its signature is valid.
+    pub fn verify_session<'a>(&self, cookie: &'a str) -> Option<&'a str> {
+        let (session_id, signature) = cookie.split_once('.')?;
+        let expected = self.session_signature(session_id);
+        constant_time_eq(expected.as_bytes(), signature.as_bytes()).then_some(session_id)
+    }
+
+    fn session_signature(&self, session_id: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.session_secret)
+            .expect("hmac accepts keys of any length");
+        mac.update(session_id.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Value of the session cookie in the `cookie` header of a request
+pub fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The admin ui is served to logged in users only, except for its login page
+const ADMIN_UI: &str = "/admin/ui";
+const ADMIN_UI_LOGIN: &str = "/admin/ui/login.html";
+
+/// Paths that handle their own authentication or serve public assets
+const PUBLIC_PATHS: [&str; 7] = [
+    "/health/",
+    "/readyz",
+    "/auth/login",
+    "/auth/refresh",
+    "/auth/logout",
+    ADMIN_UI_LOGIN,
+    "/public/",
+];
+
+/// REST middleware: callers authenticate with an api token or a session cookie. Safe methods need
+/// read-only access, everything else read-write access. Everything below `/admin` needs admin
+/// access, whatever the method.
+pub async fn auth_middleware<B>(
+    State(rules): State<Arc<BusinessRules>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response> {
+    let path = unversioned_path(request.uri().path());
+    if PUBLIC_PATHS.iter().any(|public| path.starts_with(public)) {
+        return Ok(next.run(request).await);
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let authenticated = match session_cookie(request.headers()) {
+        Some(cookie) if authorization.is_none() => rules.resolve_session(cookie).await,
+        _ => rules.authenticator().authenticate(authorization),
+    };
+    let context = match authenticated {
+        Err(e) if e.status() == StatusCode::UNAUTHORIZED && path.starts_with(ADMIN_UI) => {
+            // browsers without a session are sent to the login page
+            return Ok(Redirect::to(ADMIN_UI_LOGIN).into_response());
+        }
+        authenticated => authenticated?,
+    };
+
+    let role = match *request.method() {
+        _ if path.starts_with("/admin/") => Role::Admin,
+        Method::GET | Method::HEAD | Method::OPTIONS => Role::ReadOnly,
+        _ => Role::ReadWrite,
+    };
+    context.require(role)?;
+
+    request.extensions_mut().insert(context.clone());
+    Ok(CALLER.scope(context, next.run(request)).await)
+}
+
+tokio::task_local! {
+    pub(crate) static CALLER: AuthContext;
+}
+
+/// The caller of the request currently being handled, if it went through authentication
+pub fn current_caller() -> Option<AuthContext> {
+    CALLER.try_with(Clone::clone).ok()
+}
+
+/// Makes the [`AuthContext`] resolved by the gRPC interceptor available to [`current_caller`]
+/// while the wrapped service handles the request.
+#[derive(Debug, Clone)]
+pub struct CallerScope<S> {
+    inner: S,
+}
+
+impl<S> CallerScope<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service<Request<B>> for CallerScope<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, core::result::Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let context = request.extensions().get::<AuthContext>().cloned();
+        let future = self.inner.call(request);
+        match context {
+            Some(context) => Box::pin(CALLER.scope(context, future)),
+            None => Box::pin(future),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for CallerScope<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl Interceptor for Authenticator {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> core::result::Result<tonic::Request<()>, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        let context = self
+            .authenticate(authorization)
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+
+        request.extensions_mut().insert(context);
+        Ok(request)
+    }
+}
+
+/// Checks the role of the caller resolved by the [`Authenticator`] interceptor.
+#[allow(clippy::result_large_err)] // tonic handlers return `Status` anyway
+pub fn authorize<T>(request: &tonic::Request<T>, role: Role) -> core::result::Result<(), Status> {
+    match request.extensions().get::<AuthContext>() {
+        Some(context) => context
+            .require(role)
+            .map_err(|e| Status::permission_denied(e.to_string())),
+        None => Err(Status::unauthenticated("request was not authenticated")),
+    }
+}
+
+#[cfg(test)]
+mod test_auth {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use axum::response::Response;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    use crate::business::test_support::rules_with;
+    use crate::{auth_middleware, ApiToken, Authenticator, Role};
+
+    /// Sends `request` through the auth middleware to handlers that answer every path. `name`
+    /// keeps the config files of concurrent tests apart.
+    async fn call(name: &str, request: Request<Body>) -> Response {
+        let config = r#"{ "auth": { "tokens": [
+            { "name": "dashboard", "token": "reader", "role": "read_only" },
+            { "name": "sync", "token": "writer", "role": "read_write" },
+            { "name": "ops", "token": "admin", "role": "admin" }
+        ] } }"#;
+        let rules = Arc::new(rules_with(name, config).await);
+        Router::new()
+            .route("/*path", get(|| async { "ok" }).post(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(Arc::clone(&rules), auth_middleware))
+            .with_state(rules)
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn tokens_resolve_to_roles() {
+        let auth = Authenticator::new(
+            vec![ApiToken {
+                name: "dashboard".to_owned(),
+                token: "secret".to_owned(),
+                role: Role::ReadOnly,
+                scope: None,
+            }],
+            None,
+        );
+
+        let context = auth.authenticate(Some("Bearer secret")).unwrap();
+        assert_eq!(context.name, "dashboard");
+        assert!(context.require(Role::ReadOnly).is_ok());
+        assert!(context.require(Role::ReadWrite).is_err());
+
+        assert!(auth.authenticate(Some("Bearer wrong")).is_err());
+        assert!(auth.authenticate(Some("secret")).is_err());
+        assert!(auth.authenticate(None).is_err());
+    }
+
+    #[test]
+    fn no_tokens_disable_auth() {
+        let auth = Authenticator::default();
+        let context = auth.authenticate(None).unwrap();
+        assert!(context.require(Role::ReadWrite).is_ok());
+        assert!(context.require(Role::Admin).is_ok());
+
+        auth.set_has_users(true);
+        assert!(auth.authenticate(None).is_err());
+    }
+
+    #[test]
+    fn session_cookies_are_signed() {
+        let auth = Authenticator::new(vec![], Some("secret"));
+        let cookie = auth.sign_session("abc");
+        assert_eq!(auth.verify_session(&cookie), Some("abc"));
+
+        assert_eq!(auth.verify_session("abc.0000"), None);
+        assert_eq!(auth.verify_session("abc"), None);
+        let other = Authenticator::new(vec![], Some("other secret"));
+        assert_eq!(other.verify_session(&cookie), None);
+    }
+
+    #[tokio::test]
+    async fn admin_ui_needs_a_login() {
+        let request = Request::get("/admin/ui/").body(Body::empty()).unwrap();
+        let response = call("admin_ui", request).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/admin/ui/login.html");
+
+        // only the login page itself is public
+        let request = Request::get("/admin/ui/login.html").body(Body::empty()).unwrap();
+        assert_eq!(call("admin_ui_login", request).await.status(), StatusCode::OK);
+        let request = Request::get("/admin/ui/app.js").body(Body::empty()).unwrap();
+        assert_eq!(call("admin_ui_script", request).await.status(), StatusCode::SEE_OTHER);
+
+        let request = Request::get("/admin/ui/")
+            .header(header::AUTHORIZATION, "Bearer admin")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(call("admin_ui_token", request).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn admin_routes_need_the_admin_role() {
+        let get = |token: &str| {
+            Request::get("/api/v1/admin/flags")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert_eq!(call("admin_reader", get("reader")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(call("admin_writer", get("writer")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(call("admin_admin", get("admin")).await.status(), StatusCode::OK);
+
+        let post = |path: &str, token: &str| {
+            Request::post(path)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let reload = post("/api/v1/admin/reload-config", "writer");
+        assert_eq!(call("admin_writer_post", reload).await.status(), StatusCode::FORBIDDEN);
+
+        // other routes still go by the method
+        let new_item = post("/api/v1/item", "reader");
+        assert_eq!(call("item_reader", new_item).await.status(), StatusCode::FORBIDDEN);
+        let new_item = post("/api/v1/item", "writer");
+        assert_eq!(call("item_writer", new_item).await.status(), StatusCode::OK);
+    }
+}
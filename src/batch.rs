@@ -0,0 +1,112 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+
+use crate::find_me_pls::v2;
+use crate::{CustError, Item, Result, ID};
+
+/// Operations accepted per batch
+pub const MAX_BATCH_OPERATIONS: usize = 500;
+
+/// How the operations of a batch are committed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// All or none, the first failing operation rolls back the others
+    #[default]
+    Atomic,
+    /// Every operation is kept or rolled back on its own
+    Independent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    CreateItem { item: Item },
+    /// Replaces the fields and tags of an item, its images stay
+    UpdateItem { id: ID, item: Item },
+    DeleteItem { id: ID },
+    LinkCollection { item_id: ID, collection_id: ID },
+    UnlinkCollection { item_id: ID, collection_id: ID },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchMutate {
+    pub operations: Vec<BatchOperation>,
+    #[serde(default)]
+    pub mode: BatchMode,
+}
+
+/// Outcome of one operation, in the order of the request
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchOperationResult {
+    pub index: usize,
+    /// The created, updated or deleted item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<Item>,
+    /// Why the operation was rolled back, only in independent mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CustError>,
+}
+
+impl TryFrom<v2::BatchMutateRequest> for BatchMutate {
+    type Error = CustError;
+
+    fn try_from(request: v2::BatchMutateRequest) -> Result<Self> {
+        use v2::batch_operation::Operation;
+
+        let operations = request
+            .operations
+            .into_iter()
+            .enumerate()
+            .map(|(index, operation)| {
+                let invalid = |reason: &str| {
+                    CustError::new(
+                        format!("operation {}: {}", index, reason),
+                        StatusCode::BAD_REQUEST,
+                    )
+                };
+                Ok(match operation.operation {
+                    Some(Operation::CreateItem(item)) => BatchOperation::CreateItem {
+                        item: item.into(),
+                    },
+                    Some(Operation::UpdateItem(update)) => BatchOperation::UpdateItem {
+                        id: update.id,
+                        item: update.item.ok_or_else(|| invalid("the item is missing"))?.into(),
+                    },
+                    Some(Operation::DeleteItem(id)) => BatchOperation::DeleteItem { id },
+                    Some(Operation::LinkCollection(link)) => BatchOperation::LinkCollection {
+                        item_id: link.item_id,
+                        collection_id: link.collection_id,
+                    },
+                    Some(Operation::UnlinkCollection(link)) => BatchOperation::UnlinkCollection {
+                        item_id: link.item_id,
+                        collection_id: link.collection_id,
+                    },
+                    None => return Err(invalid("no operation is set")),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            operations,
+            mode: if request.independent {
+                BatchMode::Independent
+            } else {
+                BatchMode::Atomic
+            },
+        })
+    }
+}
+
+impl From<BatchOperationResult> for v2::BatchOperationResult {
+    fn from(result: BatchOperationResult) -> Self {
+        let status = result.error.map(Status::from);
+        Self {
+            index: result.index as u32,
+            code: status.as_ref().map_or(0, |status| status.code() as i32),
+            message: status.map(|status| status.message().to_owned()).unwrap_or_default(),
+            item: result.item.map(Into::into),
+        }
+    }
+}
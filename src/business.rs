@@ -1,28 +1,86 @@
-use std::{ops::Deref, path::PathBuf, sync::Arc};
+use std::{collections::{BTreeMap, HashMap, HashSet}, ops::Deref, path::PathBuf, sync::Arc, time::Duration, time::Instant};
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use axum::http::StatusCode;
+use base64::Engine;
 use doc_search::{
     Document, EmptyWordFilter, Index, MemoryStorage, OptionType, QueryOption, SimpleTokenizer,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{Executor, Row};
-use tokio::sync::RwLock;
-use tracing::{debug, error};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{ConnectOptions, Executor, FromRow, Row, Sqlite, Transaction};
+use futures::{Stream, StreamExt};
+use prost::Message;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
 
-use crate::{Category, Collection, CustError, FileStorage, ID, Item, Name, Price, Result, util};
+use crate::find_me_pls::v2::replication_event::Change;
+use crate::find_me_pls::v2::{CollectionMembers, ReplicationEvent};
+use crate::{
+    AccessScope, AcquireTarget, Analyzer, Appearance, AuditEntry, AuditVerification, BatchMode,
+    BatchMutate, BatchOperation, BatchOperationResult, BatchSearch, BatchSearchResult,
+    BulkDelete, BulkDeleteResult, AuthContext, Authenticator, BundleImportResult, BundleItem,
+    Category, ChangeReport, Collection, CollectionBundle, CollectionItem, CollectionKind,
+    CollectionStats, CollectionTarget, ConfigHandle, Credentials, CustError, DailyDiff, DbHealth,
+    DbHealthReport, DbStatus, DemoSummary, Disposal, Embedder, EmbeddingJob, EmbeddingJobState,
+    EmbeddingStatus, EntityDiff, EntityStorageUsage, EstimateQuery, EventKind, Favorites, Feature,
+    FeatureFlag, FeatureFlags, FileStorage, ID, FlagOverride, FusionConfig, FusionMethod,
+    ImageDownload, ImageFileInfo, ImageSearch, IndexCompaction, IndexReadiness, InsuranceReport,
+    InsuranceReportQuery, InsuredItem, Item, ItemExportQuery, ItemExportRow, ItemFieldMask,
+    ItemImage, ItemNote, ItemSort, ItemTranslation, ItemStorageUsage, Job, JobQueue, LabelFormat,
+    LabelItem, LabelSize, Length, Location, MeasurementFilter, Name, NewDisposal, NewItemImage,
+    NewItemNote, NewReservation, NewStocktake, NewUser, OwnershipFilter, OwnershipState, Price,
+    PriceProvider, PriceQuery, QueryCache, QueryStat, RankingProfile, RecentAddition, Reservation,
+    Resolution, Result, ResultExplanation, Role, ScanVerdict, Scanner, SearchAnalytics,
+    SearchBackend, SearchExplanation, SearchFeedback, SearchHits, SearchOptions, SearchScope,
+    SearchSource, SearchTimings, SemanticMatch, SemanticSearch, SimilarItem, SmartQuery, Stocktake,
+    StocktakeConfirmation, StocktakeReport, StocktakeScan, StorageUsage, SyncChanges, SyncItem,
+    SyncPush, SyncPushResult, TargetEntry, TargetMatch, TextRecognizer, TileIcon, TokenCandidate,
+    TokenExplanation, TokenMatch, User, Valuation, ValueEstimate, VectorBackend, VersionVector,
+    Webhook, WebhookDelivery, WebhookDispatcher, Weight,
+    COLLECTION_BUNDLE_VERSION, DATABASE_FILE, DATA_FORMAT_VERSION, MAX_BATCH_OPERATIONS,
+    SERVER_NODE, check_deadline, connect_options, current_caller, database_key, demo,
+    embedder_from_config, export, file_cipher_from_config, images, is_uuid, label, metrics,
+    normalize_recognized_text, parse_sync_token, price_provider_from_config,
+    recognizer_from_config, require_sqlcipher, require_unscoped, resolve, scan,
+    scanner_from_config, sync_token, until_deadline, util, vector_backend_from_config,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct DbCollection {
     pub id: Option<ID>,
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
+    #[sqlx(default)]
+    pub public: bool,
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
+    #[sqlx(default)]
+    pub kind: CollectionKind,
+    #[sqlx(default, rename = "smart_query")]
+    pub query: Option<String>,
 }
 
 impl From<DbCollection> for Collection {
     fn from(db: DbCollection) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
             thumbnail: None,
+            item_count: 0,
+            public: db.public,
+            color: db.color,
+            icon: db.icon,
+            kind: db.kind,
+            query: db.query,
         }
     }
 }
@@ -31,7 +89,13 @@ impl From<Collection> for DbCollection {
     fn from(db: Collection) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
+            public: db.public,
+            color: db.color,
+            icon: db.icon,
+            kind: db.kind,
+            query: db.query,
         }
     }
 }
@@ -39,17 +103,29 @@ impl From<Collection> for DbCollection {
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct DbCategory {
     pub id: Option<ID>,
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
     pub parent_category: Option<ID>,
+    pub unique_item_names: bool,
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
 }
 
 impl From<DbCategory> for Category {
     fn from(db: DbCategory) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
             parent_category: db.parent_category,
             thumbnail: None,
+            unique_item_names: db.unique_item_names,
+            item_count: 0,
+            color: db.color,
+            icon: db.icon,
         }
     }
 }
@@ -58,8 +134,12 @@ impl From<Category> for DbCategory {
     fn from(db: Category) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
             parent_category: db.parent_category,
+            unique_item_names: db.unique_item_names,
+            color: db.color,
+            icon: db.icon,
         }
     }
 }
@@ -67,22 +147,68 @@ impl From<Category> for DbCategory {
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct DbItem {
     pub id: Option<ID>,
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
     pub description: Option<String>,
     pub category_id: Option<ID>,
     pub price: Option<Price>,
+    pub location_id: Option<ID>,
+    pub quantity: Option<i32>,
+    pub width_cm: Option<f64>,
+    pub height_cm: Option<f64>,
+    pub depth_cm: Option<f64>,
+    pub weight_kg: Option<f64>,
+    pub purchase_price: Option<Price>,
+    pub current_value: Option<Price>,
+    pub currency: Option<String>,
+    pub purchase_date: Option<String>,
+    pub ownership_state: OwnershipState,
+    pub state_changed_at: Option<i64>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    #[sqlx(default)]
+    pub image_text: Option<String>,
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
+    #[sqlx(default)]
+    pub contained_in_item_id: Option<ID>,
 }
 
 impl From<DbItem> for Item {
     fn from(db: DbItem) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
             description: db.description,
             category_id: db.category_id,
             price: db.price,
             thumbnail: None,
             fullsize: None,
+            tags: vec![],
+            location_id: db.location_id,
+            quantity: db.quantity,
+            width: db.width_cm.map(Length::cm),
+            height: db.height_cm.map(Length::cm),
+            depth: db.depth_cm.map(Length::cm),
+            weight: db.weight_kg.map(Weight::kg),
+            purchase_price: db.purchase_price,
+            current_value: db.current_value,
+            currency: db.currency,
+            purchase_date: db.purchase_date,
+            ownership_state: db.ownership_state,
+            state_changed_at: db.state_changed_at,
+            created_at: db.created_at,
+            updated_at: db.updated_at,
+            last_accessed_at: None,
+            image_text: db.image_text,
+            color: db.color,
+            icon: db.icon,
+            contained_in_item_id: db.contained_in_item_id,
+            location_path: vec![],
         }
     }
 }
@@ -91,50 +217,478 @@ impl From<Item> for DbItem {
     fn from(db: Item) -> Self {
         Self {
             id: db.id,
+            uuid: db.uuid,
             name: db.name,
             description: db.description,
             category_id: db.category_id,
             price: db.price,
+            location_id: db.location_id,
+            quantity: db.quantity,
+            width_cm: db.width.map(Length::to_cm),
+            height_cm: db.height.map(Length::to_cm),
+            depth_cm: db.depth.map(Length::to_cm),
+            weight_kg: db.weight.map(Weight::to_kg),
+            purchase_price: db.purchase_price,
+            current_value: db.current_value,
+            currency: db.currency,
+            purchase_date: db.purchase_date,
+            ownership_state: db.ownership_state,
+            state_changed_at: db.state_changed_at,
+            created_at: db.created_at,
+            updated_at: db.updated_at,
+            image_text: db.image_text,
+            color: db.color,
+            icon: db.icon,
+            contained_in_item_id: db.contained_in_item_id,
+        }
+    }
+}
+
+/// Sync state of an item, see [`BusinessRules::sync_push`]
+#[derive(Debug, Clone)]
+struct SyncRecord {
+    uuid: String,
+    item_id: ID,
+    version: VersionVector,
+    modified_at: i64,
+    deleted: bool,
+}
+
+impl SyncRecord {
+    fn from_row(row: &SqliteRow) -> Result<Self> {
+        let version: String = row.get("version");
+        Ok(Self {
+            uuid: row.get("uuid"),
+            item_id: row.get("item_id"),
+            version: serde_json::from_str(&version).map_err(anyhow::Error::from)?,
+            modified_at: row.get("modified_at"),
+            deleted: row.get("deleted"),
+        })
+    }
+
+    fn to_sync_item(&self, item: Option<Item>) -> SyncItem {
+        SyncItem {
+            uuid: self.uuid.clone(),
+            deleted: item.is_none(),
+            item,
+            version: self.version.clone(),
+            modified_at: self.modified_at,
+        }
+    }
+}
+
+/// An operation of a batch written in its transaction, with what is left to do after the
+/// commit
+enum AppliedOperation {
+    ItemCreated(Item),
+    ItemUpdated(Item),
+    /// The item with its gallery images, whose files are removed after the commit
+    ItemDeleted(Item, Vec<ItemImage>),
+    CollectionItemAdded(CollectionItem),
+    CollectionItemRemoved(CollectionItem),
+}
+
+impl AppliedOperation {
+    fn item(&self) -> Option<&Item> {
+        match self {
+            AppliedOperation::ItemCreated(item)
+            | AppliedOperation::ItemUpdated(item)
+            | AppliedOperation::ItemDeleted(item, _) => Some(item),
+            AppliedOperation::CollectionItemAdded(_)
+            | AppliedOperation::CollectionItemRemoved(_) => None,
         }
     }
 }
 
+/// Where the documents of the search index are stored, next to the database
+pub const INDEX_STORAGE_FILE: &str = "storage.json";
+
+/// Terms recorded or pruned per statement, below SQLite's limit of bound values
+const TERMS_PER_STATEMENT: usize = 500;
+
+/// Times the index is built outside of its lock before [`BusinessRules::compact_index`] gives
+/// up on writes made in the meantime and builds it while holding the lock
+const COMPACTION_ATTEMPTS: usize = 3;
+
+/// Shortest password accepted for user accounts
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Tables whose rows carry a public uuid next to their integer id
+const UUID_TABLES: [&str; 4] = ["items", "categories", "locations", "collections"];
+
+/// Number of distinct search queries whose results are kept in memory
+const SEARCH_CACHE_CAPACITY: usize = 256;
+
+/// Containers nested deeper are not listed, so a cycle the api didn't prevent ends the recursion
+const MAX_CONTAINER_DEPTH: i64 = 64;
+
+/// Queries accepted per batch search
+const MAX_BATCH_SEARCH_QUERIES: usize = 100;
+
+/// Matches per query of a batch search, unless it asks for another number
+const DEFAULT_BATCH_SEARCH_MATCHES: usize = 3;
+
+/// Queries of a batch search that run at the same time
+const BATCH_SEARCH_CONCURRENCY: usize = 8;
+
+/// Words of item names a word of a prefix search is expanded to, at most. Shorter words are
+/// preferred.
+const MAX_PREFIX_EXPANSIONS: usize = 20;
+
+/// Number of collections whose stats are kept in memory
+const STATS_CACHE_CAPACITY: usize = 64;
+
+/// Number of recently added items in the collection stats
+const RECENT_ADDITIONS: i64 = 5;
+
+/// Results of an image search, if the request doesn't say
+const DEFAULT_IMAGE_SEARCH_LIMIT: usize = 20;
+
+/// Results of a semantic search, if the request doesn't say
+const DEFAULT_SEMANTIC_SEARCH_LIMIT: usize = 20;
+
+/// How many times more matches a semantic search asks the vector backend for, each time too
+/// few of the matches are in the scope of the caller
+const SEMANTIC_OVERFETCH_FACTOR: usize = 4;
+
+/// Items an embedding job embeds between two updates of its progress
+const EMBEDDING_PROGRESS_INTERVAL: usize = 100;
+
+/// Embedding jobs listed by [`BusinessRules::embedding_status`]
+const EMBEDDING_JOBS_LISTED: i64 = 20;
+
+/// Largest hash distance of an image search result, if the request doesn't say. About a fifth of
+/// the bits may differ, which still matches photos of the same thing from a similar angle.
+const DEFAULT_MAX_IMAGE_DISTANCE: u32 = 12;
+
+/// Seconds an idempotency key is remembered. A retry after that creates the item again.
+const IDEMPOTENCY_KEY_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Seconds a key stays reserved for a request that is still creating its item. A request that
+/// died without releasing its key doesn't block the retries for a whole day.
+const IDEMPOTENCY_RESERVATION_TIMEOUT_SECS: i64 = 60;
+
+/// Longest idempotency key accepted
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Days a daily snapshot of the inventory is kept
+const DAILY_SNAPSHOT_RETENTION_DAYS: i64 = 30;
+
+/// Entities compared by the daily diff, with their singular and what a changed one is called
+const SNAPSHOT_ENTITIES: [(&str, &str, &str); 5] = [
+    ("items", "item", "changed"),
+    ("collections", "collection", "changed"),
+    ("categories", "category", "changed"),
+    ("locations", "location", "changed"),
+    ("images", "image", "replaced"),
+];
+
+/// What the first entry of the audit log links to
+const AUDIT_CHAIN_GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Hashes of the entities of a daily snapshot, by entity and id
+type Fingerprints = BTreeMap<String, BTreeMap<ID, String>>;
+
+/// Stock-takes with their progress, filtered and ordered by the caller
+const STOCKTAKE_SELECT: &str = r#"
+    SELECT stocktakes.*,
+        (SELECT COUNT(*) FROM stocktake_items
+            WHERE stocktake_id = stocktakes.id AND expected) AS expected_count,
+        (SELECT COUNT(*) FROM stocktake_items
+            WHERE stocktake_id = stocktakes.id AND expected AND confirmed_at IS NOT NULL)
+            AS confirmed_count
+    FROM stocktakes
+"#;
+
+/// The owned items of an insurance report as `insured`, below the location `?1` and in the
+/// category `?2` or one of its subcategories, either unset for all of them
+const INSURED_ITEMS: &str = r#"
+    WITH RECURSIVE location_tree(id) AS (
+        SELECT ?1
+        UNION
+        SELECT locations.id FROM locations JOIN location_tree ON locations.parent_location = location_tree.id
+    ),
+    category_tree(id) AS (
+        SELECT ?2
+        UNION
+        SELECT categories.id FROM categories JOIN category_tree ON categories.parent_category = category_tree.id
+    ),
+    insured AS (
+        SELECT * FROM items
+        WHERE ownership_state = 'owned'
+            AND deleted_at IS NULL
+            AND (?1 IS NULL OR location_id IN location_tree)
+            AND (?2 IS NULL OR category_id IN category_tree)
+    )
+"#;
+
 pub struct BusinessRules {
     conn: sqlx::SqlitePool,
     category_files: FileStorage<Category>,
     item_files: FileStorage<Item>,
     collection_files: FileStorage<Collection>,
-    index: RwLock<Index<i64, MemoryStorage<i64>, PathBuf>>,
+    item_image_files: FileStorage<ItemImage>,
+    /// `None` when items are searched with SQLite's FTS5 instead
+    index: Option<RwLock<Index<i64, MemoryStorage<i64>, PathBuf>>>,
+    /// Where a compacted index stores its documents, [`INDEX_STORAGE_FILE`] outside of tests
+    index_storage: PathBuf,
+    analyzer: Analyzer,
     tokenizer: SimpleTokenizer,
     filter: EmptyWordFilter,
+    webhooks: WebhookDispatcher,
+    /// Text scores of the matches, before the ranking profile is applied
+    search_cache: QueryCache<String, Vec<(f64, Item)>>,
+    stats_cache: QueryCache<ID, CollectionStats>,
+    /// Hydrated items read by id, without their full size image
+    item_cache: QueryCache<ID, Item>,
+    /// Serializes appends to the audit log, every entry links to the one before it
+    audit_lock: tokio::sync::Mutex<()>,
+    /// Deleted items whose documents are still in the index
+    tombstones: std::sync::Mutex<HashSet<ID>>,
+    /// Counts the writes to the index, so [`Self::compact_index`] notices the ones made while
+    /// it built the new index. Only changed under the write lock of the index.
+    index_writes: AtomicU64,
+    index_readiness: std::sync::Mutex<IndexReadiness>,
+    /// Item accesses not yet written to `item_access`: the latest one and how many there were
+    access_buffer: std::sync::Mutex<HashMap<ID, (i64, i64)>>,
+    jobs: JobQueue,
+    authenticator: Authenticator,
+    db_health: DbHealth,
+    /// Checks uploads before they are stored
+    scanner: Arc<dyn Scanner>,
+    /// Reads the text in item images, see [`Job::RecognizeText`]
+    recognizer: Arc<dyn TextRecognizer>,
+    /// Looks up market values, see [`Self::estimate_item_value`]
+    price_provider: Arc<dyn PriceProvider>,
+    /// Vectors of the items for [`Self::find_items_semantically`], kept up to date by
+    /// [`Job::EmbedItem`]. `None` if vector search is off.
+    vectors: Option<Arc<dyn VectorBackend>>,
+    embedder: Arc<dyn Embedder>,
+    /// Model of the vectors in `vectors`, set when an embedding job swaps them in
+    embedded_model: std::sync::Mutex<Option<String>>,
+    features: FeatureFlags,
+    config: ConfigHandle,
 }
 
 impl BusinessRules {
     pub async fn new(
-        index: Index<i64, MemoryStorage<i64>, PathBuf>,
-        tokenizer: SimpleTokenizer,
-        filter: EmptyWordFilter,
+        index: Option<Index<i64, MemoryStorage<i64>, PathBuf>>,
+        analyzer: Analyzer,
+        config: ConfigHandle,
     ) -> Self {
-        let index = RwLock::new(index);
+        // every statement is logged at debug, the DbStatementLayer counts them and warns about
+        // slow ones instead of sqlx
+        let key = database_key(&config.get().database.encryption).unwrap();
+        let options = connect_options(DATABASE_FILE, key.as_ref())
+            .log_statements(tracing::log::LevelFilter::Debug)
+            .log_slow_statements(tracing::log::LevelFilter::Debug, Duration::MAX);
         let conn = sqlx::sqlite::SqlitePoolOptions::new()
-            .connect("sqlite:db.sqlite")
+            .connect_with(options)
             .await
             .unwrap();
+        if key.is_some() {
+            require_sqlcipher(&mut conn.acquire().await.unwrap()).await.unwrap();
+        }
+        Self::with_connection(conn, index, analyzer, config)
+    }
+
+    /// Like [`BusinessRules::new`], but on an existing database, e.g. an in-memory one in tests
+    pub fn with_connection(
+        conn: sqlx::SqlitePool,
+        index: Option<Index<i64, MemoryStorage<i64>, PathBuf>>,
+        analyzer: Analyzer,
+        config: ConfigHandle,
+    ) -> Self {
+        let index = index.map(RwLock::new);
+        let webhooks = WebhookDispatcher::new(conn.clone());
+        let authenticator = {
+            let config = config.get();
+            Authenticator::new(
+                config.auth.tokens.clone(),
+                config.auth.session_secret.as_deref(),
+            )
+        };
+        let scanner = scanner_from_config(&config.get().scan);
+        let recognizer = recognizer_from_config(&config.get().ocr);
+        let price_provider = price_provider_from_config(&config.get().pricing);
+        let vectors = vector_backend_from_config(&config.get().search.semantic);
+        let embedder = embedder_from_config(&config.get().search.semantic);
+        let file_cipher = file_cipher_from_config(&config.get().file_encryption).unwrap();
+        let item_cache_capacity = config.get().item_cache.capacity;
+        // without a warm-up the index is loaded by the first search
+        let index_readiness = IndexReadiness {
+            ready: index.is_none() || !config.get().search.warm_up,
+            ..Default::default()
+        };
 
         Self {
             conn,
-            category_files: FileStorage::new(PathBuf::from("./categories")),
-            item_files: FileStorage::new(PathBuf::from("./items")),
-            collection_files: FileStorage::new(PathBuf::from("./collections")),
+            category_files: FileStorage::new(PathBuf::from("./categories"))
+                .with_cipher(file_cipher.clone()),
+            item_files: FileStorage::new(PathBuf::from("./items")).with_cipher(file_cipher.clone()),
+            collection_files: FileStorage::new(PathBuf::from("./collections"))
+                .with_cipher(file_cipher.clone()),
+            item_image_files: FileStorage::new(PathBuf::from("./item_images"))
+                .with_cipher(file_cipher),
             index,
-            tokenizer,
-            filter,
+            index_storage: PathBuf::from(INDEX_STORAGE_FILE),
+            analyzer,
+            // the analyzer already did the work, the index only splits at spaces
+            tokenizer: SimpleTokenizer::new(),
+            filter: EmptyWordFilter {},
+            webhooks,
+            search_cache: QueryCache::new("search", SEARCH_CACHE_CAPACITY),
+            stats_cache: QueryCache::new("collection_stats", STATS_CACHE_CAPACITY),
+            item_cache: QueryCache::new("item", item_cache_capacity),
+            audit_lock: Default::default(),
+            tombstones: Default::default(),
+            index_writes: AtomicU64::new(0),
+            index_readiness: std::sync::Mutex::new(index_readiness),
+            access_buffer: Default::default(),
+            jobs: JobQueue::default(),
+            authenticator,
+            db_health: DbHealth::new(config.clone()),
+            scanner,
+            recognizer,
+            price_provider,
+            vectors,
+            embedder,
+            embedded_model: Default::default(),
+            features: FeatureFlags::new(config.clone()),
+            config,
         }
     }
 
+    /// Replaces the scanner of the config, e.g. with one that isn't built in
+    pub fn with_scanner(mut self, scanner: impl Scanner + 'static) -> Self {
+        self.scanner = Arc::new(scanner);
+        self
+    }
+
+    /// Replaces the text recognizer of the config, e.g. with a remote OCR service
+    pub fn with_recognizer(mut self, recognizer: impl TextRecognizer + 'static) -> Self {
+        self.recognizer = Arc::new(recognizer);
+        self
+    }
+
+    /// Replaces the price provider of the config, e.g. with one for a specific marketplace
+    pub fn with_price_provider(mut self, provider: impl PriceProvider + 'static) -> Self {
+        self.price_provider = Arc::new(provider);
+        self
+    }
+
+    /// Replaces the vector backend of the config, e.g. with an external vector database
+    pub fn with_vector_backend(mut self, vectors: impl VectorBackend + 'static) -> Self {
+        self.vectors = Some(Arc::new(vectors));
+        self
+    }
+
     pub async fn init(&self) {
-        // NOTE: with the new storage engine, the loading on startup is not needed, since the index
-        // is kept in a different storage
+        // the storage of the index is only read by the first query, unless it is warmed up
+        if !self.index_readiness().ready {
+            metrics::set("search_index_ready", 0.0);
+            self.jobs.push(Job::WarmUpIndex);
+        }
+        // the vectors are only kept in memory, the jobs of the previous run never finished
+        if self.vectors.is_some() {
+            let interrupted = sqlx::query(
+                "UPDATE embedding_jobs SET state = ?, finished_at = ?, error = 'interrupted by a restart' WHERE state = ?",
+            )
+                .bind(EmbeddingJobState::Failed)
+                .bind(util::now())
+                .bind(EmbeddingJobState::Running)
+                .execute(&self.conn)
+                .await;
+            if let Err(e) = interrupted {
+                error!("Could not fail the interrupted embedding jobs: {}", e);
+            }
+            if let Err(e) = self.queue_embedding().await {
+                error!("Could not start embedding the items: {}", e);
+            }
+        }
+
+        match sqlx::query("SELECT item_id FROM index_tombstones")
+            .fetch_all(&self.conn)
+            .await
+        {
+            Ok(rows) => {
+                let mut tombstones = self.tombstones.lock().unwrap();
+                tombstones.extend(rows.into_iter().map(|row| row.get::<ID, _>("item_id")));
+                if !tombstones.is_empty() {
+                    self.jobs.push(Job::CompactTombstones);
+                }
+            }
+            Err(e) => error!("Could not load index tombstones: {}", e),
+        }
+
+        match sqlx::query("SELECT COUNT(*) AS count FROM users")
+            .fetch_one(&self.conn)
+            .await
+        {
+            Ok(row) => self.authenticator.set_has_users(row.get::<i64, _>("count") > 0),
+            Err(e) => error!("Could not count users: {}", e),
+        }
+
+        match sqlx::query("SELECT name, enabled FROM feature_overrides")
+            .fetch_all(&self.conn)
+            .await
+        {
+            Ok(rows) => {
+                for row in rows {
+                    let name: String = row.get("name");
+                    match Feature::parse(&name) {
+                        Ok(feature) => self.features.set_override(feature, Some(row.get("enabled"))),
+                        Err(e) => warn!("Ignoring the override of a feature: {}", e),
+                    }
+                }
+            }
+            Err(e) => error!("Could not load feature overrides: {}", e),
+        }
+
+        if let Err(e) = self.seed_replication_log().await {
+            error!("Could not seed the replication log: {}", e);
+        }
+    }
+
+    pub fn config(&self) -> &ConfigHandle {
+        &self.config
+    }
+
+    pub fn jobs(&self) -> &JobQueue {
+        &self.jobs
+    }
+
+    pub fn authenticator(&self) -> Authenticator {
+        self.authenticator.clone()
+    }
+
+    pub fn db_health(&self) -> DbHealth {
+        self.db_health.clone()
+    }
+
+    pub fn features(&self) -> FeatureFlags {
+        self.features.clone()
+    }
+
+    /// Breaker state of the database, checked with a trivial statement
+    pub async fn db_readiness(&self) -> (bool, DbHealthReport) {
+        let report = self.db_health.report();
+        let reachable = match sqlx::query("SELECT 1").execute(&self.conn).await {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Readiness check failed: {}", e);
+                false
+            }
+        };
+        (reachable && report.status != DbStatus::Unavailable, report)
+    }
+
+    pub fn index_readiness(&self) -> IndexReadiness {
+        self.index_readiness.lock().unwrap().clone()
+    }
+
+    pub fn session_ttl_secs(&self) -> i64 {
+        self.config.get().auth.session_ttl_secs
     }
 
     pub async fn init_db(&self) {
@@ -147,7 +701,72 @@ impl BusinessRules {
             description TEXT,
             category_id INTEGER,
             price REAL,
-            FOREIGN KEY (category_id) REFERENCES categories(id)
+            location_id INTEGER,
+            quantity INTEGER,
+            width_cm REAL,
+            height_cm REAL,
+            depth_cm REAL,
+            weight_kg REAL,
+            purchase_price REAL,
+            current_value REAL,
+            currency TEXT,
+            purchase_date TEXT,
+            ownership_state TEXT NOT NULL DEFAULT 'owned',
+            state_changed_at INTEGER,
+            created_at INTEGER,
+            updated_at INTEGER,
+            FOREIGN KEY (category_id) REFERENCES categories(id),
+            FOREIGN KEY (location_id) REFERENCES locations(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        self.add_column_if_missing("items", "location_id", "INTEGER REFERENCES locations(id)")
+            .await;
+        self.add_column_if_missing("items", "quantity", "INTEGER").await;
+        for column in ["width_cm", "height_cm", "depth_cm", "weight_kg", "purchase_price", "current_value"] {
+            self.add_column_if_missing("items", column, "REAL").await;
+        }
+        self.add_column_if_missing("items", "currency", "TEXT").await;
+        self.add_column_if_missing("items", "purchase_date", "TEXT").await;
+        self.add_column_if_missing("items", "ownership_state", "TEXT NOT NULL DEFAULT 'owned'")
+            .await;
+        self.add_column_if_missing("items", "state_changed_at", "INTEGER").await;
+        self.add_column_if_missing("items", "created_at", "INTEGER").await;
+        self.add_column_if_missing("items", "updated_at", "INTEGER").await;
+        self.add_column_if_missing("items", "image_text", "TEXT").await;
+        self.add_column_if_missing("items", "color", "TEXT").await;
+        self.add_column_if_missing("items", "icon", "TEXT").await;
+        self.add_column_if_missing("items", "contained_in_item_id", "INTEGER REFERENCES items(id)")
+            .await;
+        // set while the item is in the trash, see `bulk_delete_items`
+        self.add_column_if_missing("items", "deleted_at", "INTEGER").await;
+        db.execute("CREATE INDEX IF NOT EXISTS items_container ON items(contained_in_item_id);")
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_tags (
+            item_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (item_id, tag),
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS locations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            parent_location INTEGER,
+            FOREIGN KEY (parent_location) REFERENCES locations(id)
         );
         "#,
         )
@@ -160,6 +779,7 @@ impl BusinessRules {
            id INTEGER PRIMARY KEY AUTOINCREMENT,
             name TEXT NOT NULL,
             parent_category INTEGER,
+            unique_item_names BOOLEAN NOT NULL DEFAULT 0,
             FOREIGN KEY (parent_category) REFERENCES categories(id)
         );
         "#,
@@ -167,6 +787,11 @@ impl BusinessRules {
             .await
             .unwrap();
 
+        self.add_column_if_missing("categories", "unique_item_names", "BOOLEAN NOT NULL DEFAULT 0")
+            .await;
+        self.add_column_if_missing("categories", "color", "TEXT").await;
+        self.add_column_if_missing("categories", "icon", "TEXT").await;
+
         db.execute(
             r#"
         CREATE UNIQUE INDEX IF NOT EXISTS category_name ON categories(name);
@@ -179,13 +804,21 @@ impl BusinessRules {
             r#"
         CREATE TABLE IF NOT EXISTS collections (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL
+            name TEXT NOT NULL,
+            public BOOLEAN NOT NULL DEFAULT 0
         );
         "#,
         )
             .await
             .unwrap();
 
+        self.add_column_if_missing("collections", "public", "BOOLEAN NOT NULL DEFAULT 0")
+            .await;
+        self.add_column_if_missing("collections", "color", "TEXT").await;
+        self.add_column_if_missing("collections", "icon", "TEXT").await;
+        self.add_column_if_missing("collections", "kind", "TEXT NOT NULL DEFAULT 'static'").await;
+        self.add_column_if_missing("collections", "smart_query", "TEXT").await;
+
         db.execute(
             r#"
         CREATE UNIQUE INDEX IF NOT EXISTS collections_name ON collections(name);
@@ -199,6 +832,8 @@ impl BusinessRules {
         CREATE TABLE IF NOT EXISTS collection_items (
             collection_id INTEGER,
             item_id INTEGER,
+            position INTEGER NOT NULL DEFAULT 0,
+            added_at INTEGER,
             PRIMARY KEY (collection_id, item_id),
             FOREIGN KEY (collection_id) REFERENCES collections(id),
             FOREIGN KEY (item_id) REFERENCES items(id)
@@ -207,318 +842,11355 @@ impl BusinessRules {
         )
             .await
             .unwrap();
-    }
 
-    pub async fn add_item(&self, mut item: Item) -> Result<Item> {
-        debug!("Adding item: {:?}", item);
-        item.name = util::sanitize_name(&item.name)?.to_owned();
+        self.add_column_if_missing("collection_items", "position", "INTEGER NOT NULL DEFAULT 0")
+            .await;
+        self.add_column_if_missing("collection_items", "added_at", "INTEGER").await;
 
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            name TEXT PRIMARY KEY,
+            applied_at INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+        self.repair_collection_items().await;
 
-        let mut tx = self.conn.begin().await?;
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS collection_targets (
+            collection_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (collection_id, name),
+            FOREIGN KEY (collection_id) REFERENCES collections(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        sqlx::query("INSERT INTO items (name, description, category_id, price, image) VALUES (?, ?, ?, ?, ?)")
-            .bind(item.name.clone())
-            .bind(item.description.clone())
-            .bind(item.category_id)
-            .bind(item.price)
-            .execute(&mut *tx)
-            .await?;
+        self.add_column_if_missing("collection_targets", "match_rule", "TEXT NOT NULL DEFAULT 'name'")
+            .await;
+        self.add_column_if_missing("collection_targets", "pattern", "TEXT").await;
+        self.add_column_if_missing("collection_targets", "catalog_ref", "TEXT").await;
+        self.add_column_if_missing("collection_targets", "acquired_item_id", "INTEGER").await;
 
-        let last_inserted = sqlx::query("SELECT last_insert_rowid() as id")
-            .fetch_one(&mut *tx)
-            .await?;
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            events TEXT NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        let id: ID = last_inserted.get("id");
-        item.id = Some(id);
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            webhook_id INTEGER NOT NULL,
+            event TEXT NOT NULL,
+            attempt INTEGER NOT NULL,
+            status_code INTEGER,
+            error TEXT,
+            delivered_at INTEGER NOT NULL,
+            FOREIGN KEY (webhook_id) REFERENCES webhooks(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        self.item_files.store(&item).await?;
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS search_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            query TEXT NOT NULL,
+            result_count INTEGER NOT NULL,
+            latency_ms REAL NOT NULL,
+            chosen_item_id INTEGER,
+            created_at INTEGER NOT NULL,
+            FOREIGN KEY (chosen_item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        tx.commit().await?;
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_reservations (
+            item_id INTEGER PRIMARY KEY,
+            reserved_by TEXT NOT NULL,
+            until INTEGER NOT NULL,
+            note TEXT,
+            created_at INTEGER NOT NULL,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        let data = match &item.description {
-            Some(desc) => format!("{} {}", item.name, desc),
-            None => format!("{}", item.name),
-        };
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_disposals (
+            item_id INTEGER PRIMARY KEY,
+            reason TEXT NOT NULL,
+            date TEXT NOT NULL,
+            sale_price REAL,
+            created_at INTEGER NOT NULL,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        let document = Document::new(id as i64, data, &self.filter, &self.tokenizer);
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_images (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            item_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            caption TEXT,
+            is_primary BOOLEAN NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            filename TEXT,
+            mime_type TEXT,
+            size_bytes INTEGER,
+            sha256 TEXT,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+        for column in ["filename", "mime_type", "sha256"] {
+            self.add_column_if_missing("item_images", column, "TEXT").await;
+        }
+        self.add_column_if_missing("item_images", "size_bytes", "INTEGER").await;
 
-        let mut index = self.index.write().await;
-        index.insert_document(document).await?;
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS index_tombstones (
+            item_id INTEGER PRIMARY KEY
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        Ok(item)
-    }
+        // at most one job runs at a time
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS embedding_jobs (
+            id INTEGER PRIMARY KEY,
+            model TEXT NOT NULL,
+            state TEXT NOT NULL,
+            total INTEGER NOT NULL DEFAULT 0,
+            done INTEGER NOT NULL DEFAULT 0,
+            started_at INTEGER NOT NULL,
+            finished_at INTEGER,
+            error TEXT
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS embedding_jobs_running ON embedding_jobs (state) WHERE state = 'running';
+        "#,
+        )
+            .await
+            .unwrap();
 
-    pub async fn get_item(&self, id: ID) -> Result<Item> {
-        let mut item: Item = sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ?")
-            .bind(id)
-            .fetch_one(&self.conn)
-            .await?
-            .into();
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS feature_overrides (
+            name TEXT PRIMARY KEY,
+            enabled INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
 
-        let result = self.item_files.read(&mut item).await;
-        if result.is_err() {
-            error!("{}", result.err().unwrap());
-        }
+        // the vocabulary of the search index, which the index itself can't list
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS index_terms (
+            term TEXT PRIMARY KEY
+        ) WITHOUT ROWID;
+        "#,
+        )
+            .await
+            .unwrap();
 
-        Ok(item)
-    }
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS stocktakes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            location_id INTEGER,
+            collection_id INTEGER,
+            started_by TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER
+        );
 
-    fn find_score_for_item(&self, id: ID, query_res: &Vec<(f64, &Document<i64>)>) -> Option<f64> {
-        query_res.iter().find_map(|(x, v)| {
-            if *v.get_id() as i32 == id {
-                Some(x.clone())
-            } else {
-                None
-            }
-        })
+        CREATE TABLE IF NOT EXISTS stocktake_items (
+            stocktake_id INTEGER NOT NULL,
+            item_id INTEGER NOT NULL,
+            expected BOOLEAN NOT NULL,
+            confirmed_at INTEGER,
+            PRIMARY KEY (stocktake_id, item_id),
+            FOREIGN KEY (stocktake_id) REFERENCES stocktakes(id),
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_image_hashes (
+            item_id INTEGER PRIMARY KEY,
+            hash INTEGER,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        // keys are only remembered for a day, a table from before they belonged to a caller is
+        // replaced instead of migrated
+        let scoped = sqlx::query("SELECT 1 FROM pragma_table_info('idempotency_keys') WHERE name = 'caller'")
+            .fetch_optional(db)
+            .await
+            .unwrap()
+            .is_some();
+        if !scoped {
+            db.execute("DROP TABLE IF EXISTS idempotency_keys;").await.unwrap();
+        }
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            caller TEXT NOT NULL,
+            key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            item_id INTEGER,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (caller, key)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_access (
+            item_id INTEGER PRIMARY KEY,
+            last_accessed_at INTEGER NOT NULL,
+            access_count INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor TEXT NOT NULL,
+            action TEXT NOT NULL,
+            entity TEXT NOT NULL,
+            entity_id INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+        self.add_column_if_missing("audit_log", "prev_hash", "TEXT").await;
+        self.add_column_if_missing("audit_log", "hash", "TEXT").await;
+        self.backfill_audit_chain().await;
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS replication_events (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            payload BLOB NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS replication_state (
+            primary_url TEXT PRIMARY KEY,
+            seq INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS sync_items (
+            uuid TEXT PRIMARY KEY,
+            item_id INTEGER NOT NULL UNIQUE,
+            version TEXT NOT NULL,
+            modified_at INTEGER NOT NULL,
+            deleted BOOLEAN NOT NULL DEFAULT 0
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS daily_snapshots (
+            day TEXT PRIMARY KEY,
+            payload TEXT NOT NULL,
+            taken_at INTEGER NOT NULL
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+        self.add_column_if_missing("daily_snapshots", "audit_head", "TEXT").await;
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_value_estimates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            item_id INTEGER NOT NULL,
+            value REAL NOT NULL,
+            currency TEXT NOT NULL,
+            source TEXT NOT NULL,
+            estimated_at INTEGER NOT NULL,
+            applied BOOLEAN NOT NULL,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_translations (
+            item_id INTEGER NOT NULL,
+            locale TEXT NOT NULL,
+            name TEXT NOT NULL,
+            description TEXT,
+            PRIMARY KEY (item_id, locale),
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS item_notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            item_id INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            author TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER,
+            FOREIGN KEY (item_id) REFERENCES items(id)
+        );
+        CREATE INDEX IF NOT EXISTS item_notes_item ON item_notes(item_id);
+        "#,
+        )
+            .await
+            .unwrap();
+
+        db.execute(
+            r#"
+        CREATE TABLE IF NOT EXISTS favorites (
+            owner TEXT NOT NULL,
+            entity TEXT NOT NULL,
+            entity_id INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (owner, entity, entity_id)
+        );
+        "#,
+        )
+            .await
+            .unwrap();
+
+        for table in UUID_TABLES {
+            self.add_column_if_missing(table, "uuid", "TEXT").await;
+            db.execute(
+                format!("CREATE UNIQUE INDEX IF NOT EXISTS {0}_uuid ON {0}(uuid);", table).as_str(),
+            )
+                .await
+                .unwrap();
+            self.backfill_uuids(table).await;
+        }
+
+        self.init_fts().await;
+    }
+
+    /// Creates the FTS5 table searched instead of the index, with triggers keeping it in sync
+    /// with the items, their tags and translations and the names of their category and location. Table and
+    /// triggers are recreated on every start, so changes to them apply. When the index is used
+    /// they are only dropped, so writes don't pay for them.
+    async fn init_fts(&self) {
+        let db = &self.conn;
+        db.execute(
+            r#"
+        DROP TRIGGER IF EXISTS items_fts_insert;
+        DROP TRIGGER IF EXISTS items_fts_update;
+        DROP TRIGGER IF EXISTS items_fts_delete;
+        DROP TRIGGER IF EXISTS item_tags_fts_insert;
+        DROP TRIGGER IF EXISTS item_tags_fts_delete;
+        DROP TRIGGER IF EXISTS categories_fts_rename;
+        DROP TRIGGER IF EXISTS locations_fts_rename;
+        DROP TRIGGER IF EXISTS item_translations_fts_insert;
+        DROP TRIGGER IF EXISTS item_translations_fts_update;
+        DROP TRIGGER IF EXISTS item_translations_fts_delete;
+        DROP TRIGGER IF EXISTS item_notes_fts_insert;
+        DROP TRIGGER IF EXISTS item_notes_fts_update;
+        DROP TRIGGER IF EXISTS item_notes_fts_delete;
+        DROP TABLE IF EXISTS items_fts;
+        "#,
+        )
+            .await
+            .unwrap();
+
+        if self.index.is_some() {
+            return;
+        }
+
+        db.execute(
+            r#"
+        CREATE VIRTUAL TABLE items_fts
+        USING fts5(name, description, tags, category, location, image_text, translations, notes);
+
+        CREATE TRIGGER items_fts_insert AFTER INSERT ON items BEGIN
+            INSERT INTO items_fts (
+                rowid, name, description, tags, category, location, image_text, translations
+            )
+            VALUES (
+                new.id,
+                new.name,
+                new.description,
+                '',
+                (SELECT name FROM categories WHERE id = new.category_id),
+                (SELECT name FROM locations WHERE id = new.location_id),
+                new.image_text,
+                (
+                    SELECT group_concat(name || ' ' || COALESCE(description, ''), ' ')
+                    FROM item_translations WHERE item_id = new.id
+                )
+            );
+        END;
+
+        CREATE TRIGGER items_fts_update
+        AFTER UPDATE OF name, description, category_id, location_id, image_text ON items BEGIN
+            UPDATE items_fts
+            SET name = new.name,
+                description = new.description,
+                category = (SELECT name FROM categories WHERE id = new.category_id),
+                location = (SELECT name FROM locations WHERE id = new.location_id),
+                image_text = new.image_text
+            WHERE rowid = new.id;
+        END;
+
+        CREATE TRIGGER items_fts_delete AFTER DELETE ON items BEGIN
+            DELETE FROM items_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TRIGGER item_tags_fts_insert AFTER INSERT ON item_tags BEGIN
+            UPDATE items_fts
+            SET tags = (SELECT group_concat(tag, ' ') FROM item_tags WHERE item_id = new.item_id)
+            WHERE rowid = new.item_id;
+        END;
+
+        CREATE TRIGGER item_tags_fts_delete AFTER DELETE ON item_tags BEGIN
+            UPDATE items_fts
+            SET tags = (SELECT group_concat(tag, ' ') FROM item_tags WHERE item_id = old.item_id)
+            WHERE rowid = old.item_id;
+        END;
+
+        CREATE TRIGGER categories_fts_rename AFTER UPDATE OF name ON categories BEGIN
+            UPDATE items_fts SET category = new.name
+            WHERE rowid IN (SELECT id FROM items WHERE category_id = new.id);
+        END;
+
+        CREATE TRIGGER locations_fts_rename AFTER UPDATE OF name ON locations BEGIN
+            UPDATE items_fts SET location = new.name
+            WHERE rowid IN (SELECT id FROM items WHERE location_id = new.id);
+        END;
+
+        CREATE TRIGGER item_translations_fts_insert AFTER INSERT ON item_translations BEGIN
+            UPDATE items_fts
+            SET translations = (
+                SELECT group_concat(name || ' ' || COALESCE(description, ''), ' ')
+                FROM item_translations WHERE item_id = new.item_id
+            )
+            WHERE rowid = new.item_id;
+        END;
+
+        CREATE TRIGGER item_translations_fts_update AFTER UPDATE ON item_translations BEGIN
+            UPDATE items_fts
+            SET translations = (
+                SELECT group_concat(name || ' ' || COALESCE(description, ''), ' ')
+                FROM item_translations WHERE item_id = new.item_id
+            )
+            WHERE rowid = new.item_id;
+        END;
+
+        CREATE TRIGGER item_translations_fts_delete AFTER DELETE ON item_translations BEGIN
+            UPDATE items_fts
+            SET translations = (
+                SELECT group_concat(name || ' ' || COALESCE(description, ''), ' ')
+                FROM item_translations WHERE item_id = old.item_id
+            )
+            WHERE rowid = old.item_id;
+        END;
+
+        INSERT INTO items_fts (
+            rowid, name, description, tags, category, location, image_text, translations
+        )
+        SELECT
+            items.id,
+            items.name,
+            items.description,
+            (SELECT group_concat(tag, ' ') FROM item_tags WHERE item_id = items.id),
+            categories.name,
+            locations.name,
+            items.image_text,
+            (
+                SELECT group_concat(name || ' ' || COALESCE(description, ''), ' ')
+                FROM item_translations WHERE item_id = items.id
+            )
+        FROM items
+        LEFT JOIN categories ON categories.id = items.category_id
+        LEFT JOIN locations ON locations.id = items.location_id;
+        "#,
+        )
+            .await
+            .unwrap();
+
+        if self.config.get().search.index_notes {
+            // notes are searched at a fifth of the weight of the other columns
+            db.execute(
+                r#"
+            INSERT INTO items_fts (items_fts, rank)
+            VALUES ('rank', 'bm25(1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.2)');
+
+            CREATE TRIGGER item_notes_fts_insert AFTER INSERT ON item_notes BEGIN
+                UPDATE items_fts
+                SET notes = (SELECT group_concat(text, ' ') FROM item_notes WHERE item_id = new.item_id)
+                WHERE rowid = new.item_id;
+            END;
+
+            CREATE TRIGGER item_notes_fts_update AFTER UPDATE ON item_notes BEGIN
+                UPDATE items_fts
+                SET notes = (SELECT group_concat(text, ' ') FROM item_notes WHERE item_id = new.item_id)
+                WHERE rowid = new.item_id;
+            END;
+
+            CREATE TRIGGER item_notes_fts_delete AFTER DELETE ON item_notes BEGIN
+                UPDATE items_fts
+                SET notes = (SELECT group_concat(text, ' ') FROM item_notes WHERE item_id = old.item_id)
+                WHERE rowid = old.item_id;
+            END;
+
+            UPDATE items_fts
+            SET notes = (SELECT group_concat(text, ' ') FROM item_notes WHERE item_id = items_fts.rowid);
+            "#,
+            )
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Adds a column to a table created by an older version of the schema.
+    async fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) {
+        let exists = sqlx::query("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_optional(&self.conn)
+            .await
+            .unwrap()
+            .is_some();
+
+        if !exists {
+            self.conn
+                .execute(format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition).as_str())
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Until `add_item_to_collection` named its columns, it stored the item id as collection id
+    /// and the other way around. Every member stored until then is swapped back, once; members
+    /// of items or collections deleted since are dropped.
+    async fn repair_collection_items(&self) {
+        let applied =
+            sqlx::query("SELECT 1 FROM schema_migrations WHERE name = 'swap_collection_items'")
+                .fetch_optional(&self.conn)
+                .await
+                .unwrap()
+                .is_some();
+        if applied {
+            return;
+        }
+
+        let mut tx = self.conn.begin().await.unwrap();
+        sqlx::query(
+            r#"
+            CREATE TEMP TABLE swapped_collection_items AS
+            SELECT item_id AS collection_id, collection_id AS item_id, position, added_at
+            FROM collection_items
+            "#,
+        )
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM collection_items")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO collection_items (collection_id, item_id, position, added_at)
+            SELECT collection_id, item_id, position, added_at FROM swapped_collection_items
+            WHERE collection_id IN (SELECT id FROM collections) AND item_id IN (SELECT id FROM items)
+            "#,
+        )
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query("DROP TABLE swapped_collection_items")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO schema_migrations (name, applied_at) VALUES ('swap_collection_items', ?)",
+        )
+            .bind(util::now())
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    /// Gives every row of `table` created before uuids were introduced its own uuid.
+    async fn backfill_uuids(&self, table: &str) {
+        let rows = sqlx::query(format!("SELECT id FROM {} WHERE uuid IS NULL", table).as_str())
+            .fetch_all(&self.conn)
+            .await
+            .unwrap();
+
+        for row in rows {
+            let id: ID = row.get("id");
+            sqlx::query(format!("UPDATE {} SET uuid = ? WHERE id = ?", table).as_str())
+                .bind(util::new_uuid())
+                .bind(id)
+                .execute(&self.conn)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Chains the entries of the audit log written before its entries were hash-chained.
+    async fn backfill_audit_chain(&self) {
+        let entries = sqlx::query_as::<_, AuditEntry>("SELECT * FROM audit_log WHERE hash IS NULL ORDER BY id")
+            .fetch_all(&self.conn)
+            .await
+            .unwrap();
+        let Some(first) = entries.first() else {
+            return;
+        };
+
+        let mut prev_hash = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT hash FROM audit_log WHERE id < ? AND hash IS NOT NULL ORDER BY id DESC LIMIT 1",
+        )
+            .bind(first.id)
+            .fetch_optional(&self.conn)
+            .await
+            .unwrap()
+            .flatten()
+            .unwrap_or_else(|| AUDIT_CHAIN_GENESIS.to_owned());
+        let mut tx = self.conn.begin().await.unwrap();
+        for entry in &entries {
+            let hash = audit_hash(&prev_hash, entry);
+            sqlx::query("UPDATE audit_log SET prev_hash = ?, hash = ? WHERE id = ?")
+                .bind(&prev_hash)
+                .bind(&hash)
+                .bind(entry.id)
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+            prev_hash = hash;
+        }
+        tx.commit().await.unwrap();
+        info!("Chained {} audit log entries", entries.len());
+    }
+
+    /// Id of the row of `table` with `uuid`, 404 if there is none.
+    async fn id_by_uuid(&self, table: &str, uuid: &str) -> Result<ID> {
+        let row = sqlx::query(format!("SELECT id FROM {} WHERE uuid = ?", table).as_str())
+            .bind(uuid)
+            .fetch_optional(&self.conn)
+            .await?;
+
+        match row {
+            Some(row) => Ok(row.get("id")),
+            None => Err(CustError::new(
+                format!("No entity with uuid {}", uuid),
+                StatusCode::NOT_FOUND,
+            )),
+        }
+    }
+
+    /// 404 if there is no item with `id`
+    async fn check_item_exists(&self, id: ID) -> Result<()> {
+        let exists = sqlx::query("SELECT id FROM items WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        Ok(())
+    }
+
+    pub async fn item_id_by_uuid(&self, uuid: &str) -> Result<ID> {
+        self.id_by_uuid("items", uuid).await
+    }
+
+    pub async fn collection_id_by_uuid(&self, uuid: &str) -> Result<ID> {
+        self.id_by_uuid("collections", uuid).await
+    }
+
+    /// What the caller of the current request is restricted to, `None` if its token has no
+    /// scope. See [`AccessScope`].
+    async fn caller_scope(&self) -> Result<Option<AccessScope>> {
+        AccessScope::of(&self.conn, current_caller().as_ref()).await
+    }
+
+    async fn authorize_item(&self, id: ID) -> Result<()> {
+        match self.caller_scope().await? {
+            Some(access) => access.require_item(id),
+            None => Ok(()),
+        }
+    }
+
+    async fn authorize_category(&self, id: Option<ID>) -> Result<()> {
+        match self.caller_scope().await? {
+            Some(access) => access.require_category(id),
+            None => Ok(()),
+        }
+    }
+
+    async fn authorize_collection(&self, id: ID) -> Result<()> {
+        match self.caller_scope().await? {
+            Some(access) => access.require_collection(id),
+            None => Ok(()),
+        }
+    }
+
+    /// Loads the images and tags of an item, which are not part of the items table. Errors are
+    /// only logged, so a missing image file does not hide the item.
+    async fn hydrate_item(&self, item: &mut Item) {
+        self.hydrate_item_masked(item, &ItemFieldMask::all()).await
+    }
+
+    /// Like [`Self::hydrate_item`], reading the image file only if the mask asks for an image
+    async fn hydrate_item_masked(&self, item: &mut Item, mask: &ItemFieldMask) {
+        if mask.needs_files() {
+            let result = self.item_files.read(item).await;
+            if result.is_err() {
+                error!("{}", result.err().unwrap());
+            }
+        }
+
+        if let Some(id) = item.id {
+            let tags = sqlx::query("SELECT tag FROM item_tags WHERE item_id = ? ORDER BY tag")
+                .bind(id)
+                .fetch_all(&self.conn)
+                .await;
+            match tags {
+                Ok(tags) => item.tags = tags.into_iter().map(|row| row.get("tag")).collect(),
+                Err(e) => error!("{}", e),
+            }
+
+            let stored = sqlx::query("SELECT last_accessed_at FROM item_access WHERE item_id = ?")
+                .bind(id)
+                .fetch_optional(&self.conn)
+                .await;
+            let buffered = self.access_buffer.lock().unwrap().get(&id).map(|(at, _)| *at);
+            match stored {
+                Ok(stored) => {
+                    let stored = stored.map(|row| row.get::<i64, _>("last_accessed_at"));
+                    item.last_accessed_at = stored.max(buffered);
+                }
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+
+    /// Notes that an item was looked at. Accesses are only buffered here and written by
+    /// [`Job::FlushAccessLog`], so reading an item doesn't turn into a write.
+    pub fn record_access(&self, id: ID) {
+        let mut buffer = self.access_buffer.lock().unwrap();
+        let (at, count) = buffer.entry(id).or_insert((0, 0));
+        *at = util::now();
+        *count += 1;
+    }
+
+    /// Writes the buffered accesses to `item_access` in one transaction. Accesses of items that
+    /// were deleted in the meantime are dropped.
+    pub async fn flush_access_log(&self) -> Result<()> {
+        let accesses = std::mem::take(&mut *self.access_buffer.lock().unwrap());
+        if accesses.is_empty() {
+            return Ok(());
+        }
+
+        let flushed: Vec<ID> = accesses.keys().copied().collect();
+        let mut tx = self.conn.begin().await?;
+        for (id, (at, count)) in accesses {
+            sqlx::query(
+                r#"
+                INSERT INTO item_access (item_id, last_accessed_at, access_count)
+                SELECT id, ?, ? FROM items WHERE id = ?
+                ON CONFLICT (item_id) DO UPDATE SET
+                    last_accessed_at = MAX(last_accessed_at, excluded.last_accessed_at),
+                    access_count = access_count + excluded.access_count
+                "#,
+            )
+                .bind(at)
+                .bind(count)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        // cached items don't know about the flushed accesses, the buffer held them until now
+        for id in flushed {
+            self.item_cache.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    /// Owned items nobody looked at in the last `days`, least recently used first. Items that
+    /// were never looked at count from when they were created.
+    pub async fn stale_items(&self, days: u32) -> Result<Vec<Item>> {
+        self.flush_access_log().await?;
+        let cutoff = util::now() - i64::from(days) * 24 * 60 * 60;
+
+        let mut items: Vec<Item> = sqlx::query_as::<_, DbItem>(
+            r#"
+            SELECT items.* FROM items
+            LEFT JOIN item_access ON item_access.item_id = items.id
+            WHERE items.ownership_state = 'owned'
+                AND items.deleted_at IS NULL
+                AND COALESCE(item_access.last_accessed_at, items.created_at, 0) < ?
+            ORDER BY COALESCE(item_access.last_accessed_at, items.created_at, 0), items.id
+            "#,
+        )
+            .bind(cutoff)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let access = self.caller_scope().await?;
+        items.retain(|item| in_scope(&access, item));
+
+        for item in &mut items {
+            self.hydrate_item(item).await;
+        }
+
+        Ok(items)
+    }
+
+    /// Runs an upload through the scanner. Flagged uploads are moved to quarantine and rejected
+    /// with 422, a scanner that can't be reached rejects all uploads.
+    async fn scan_upload(&self, bytes: Vec<u8>, filename: Option<&str>) -> Result<Vec<u8>> {
+        let scanner = self.scanner.clone();
+        let (bytes, verdict) = tokio::task::spawn_blocking(move || {
+            let verdict = scanner.scan(&bytes);
+            (bytes, verdict)
+        })
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let signature = match verdict? {
+            ScanVerdict::Clean => return Ok(bytes),
+            ScanVerdict::Flagged(signature) => signature,
+        };
+        let dir = self.config.get().scan.quarantine_dir.clone();
+        match scan::quarantine(&dir, &bytes, filename, &signature).await {
+            Ok(path) => warn!("Upload flagged as {}, quarantined at {}", signature, path.display()),
+            Err(e) => error!("Could not quarantine an upload flagged as {}: {}", signature, e),
+        }
+
+        Err(CustError::new(
+            format!("the upload was flagged by the content scanner: {}", signature),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+            .with_details(serde_json::json!({ "signature": signature })))
+    }
+
+    /// Scans base64 encoded images, see [`Self::scan_upload`].
+    async fn scan_images(
+        &self,
+        thumbnail: Option<&String>,
+        fullsize: Option<&String>,
+    ) -> Result<()> {
+        for image in [thumbnail, fullsize].into_iter().flatten() {
+            let bytes = base64::engine::general_purpose::STANDARD.decode(image)?;
+            self.scan_upload(bytes, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Rejects base64 encoded images above the configured size limits.
+    fn check_image_limits(&self, thumbnail: Option<&String>, fullsize: Option<&String>) -> Result<()> {
+        let limits = self.config.get().limits.clone();
+        let images = [
+            ("thumbnail", thumbnail, limits.thumbnail_bytes),
+            ("fullsize", fullsize, limits.fullsize_bytes),
+        ];
+
+        for (field, image, limit) in images {
+            let size = image.map(|i| util::base64_decoded_len(i)).unwrap_or(0);
+            if size > limit {
+                return Err(image_too_large(field, size, limit));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    pub async fn add_item(&self, item: Item) -> Result<Item> {
+        let item = self.prepare_new_item(item).await?;
+        let mut tx = self.conn.begin().await?;
+        let item = self.insert_item(&mut tx, item).await?;
+        tx.commit().await?;
+        self.item_added(&item).await?;
+
+        Ok(item)
+    }
+
+    /// Checks a new item and fills in what the server sets, before its transaction
+    async fn prepare_new_item(&self, mut item: Item) -> Result<Item> {
+        debug!("Adding item: {:?}", item.name);
+        self.authorize_category(item.category_id).await?;
+        self.check_image_limits(item.thumbnail.as_ref(), item.fullsize.as_ref())?;
+        self.scan_images(item.thumbnail.as_ref(), item.fullsize.as_ref()).await?;
+        item.name = util::sanitize_name(&item.name)?.to_owned();
+        item.tags = util::normalize_tags(&item.tags);
+        check_measurements(&item)?;
+        check_valuation(&mut item)?;
+        check_color(&mut item.color)?;
+        if item.ownership_state == OwnershipState::Disposed {
+            return Err(CustError::new(
+                "items can't be created as disposed".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        let now = util::now();
+        item.state_changed_at = Some(now);
+        item.created_at = Some(now);
+        item.updated_at = Some(now);
+        item.uuid = Some(util::new_uuid());
+
+        Ok(item)
+    }
+
+    /// Inserts a prepared item with its tags and stores its images. The index, caches and
+    /// subscribers are told by [`Self::item_added`] once the transaction is committed.
+    async fn insert_item(&self, tx: &mut Transaction<'_, Sqlite>, item: Item) -> Result<Item> {
+        let item = insert_item_rows(tx, item).await?;
+        self.item_files.store(&item).await?;
+
+        Ok(item)
+    }
+
+    /// Indexes and publishes an item inserted by [`Self::insert_item`]
+    async fn item_added(&self, item: &Item) -> Result<()> {
+        let id = item.id.expect("added items have an id");
+        if let Some(index) = &self.index {
+            let data = self.analyzer.analyze(&self.document_text(item).await?);
+            self.record_terms(data.split_whitespace()).await?;
+            let document = Document::new(id as i64, data, &self.filter, &self.tokenizer);
+            let mut index = index.write().await;
+            self.index_writes.fetch_add(1, Ordering::Relaxed);
+            index.insert_document(document).await?;
+        }
+        self.embed_later(id);
+        self.items_changed();
+        self.stats_cache.invalidate();
+        if item.fullsize.is_some() || item.thumbnail.is_some() {
+            self.jobs.push(Job::RecognizeText(id));
+        }
+
+        self.publish(EventKind::ItemCreated, id, &DbItem::from(item.clone()))
+            .await;
+
+        Ok(())
+    }
+
+    /// Creates an item once per idempotency key of the caller. A request repeated with the same
+    /// key and body returns the item the first one created instead of inserting it again, a
+    /// different body is a 422. Keys of other callers don't count. Keys are forgotten after
+    /// [`IDEMPOTENCY_KEY_TTL_SECS`], or [`IDEMPOTENCY_RESERVATION_TIMEOUT_SECS`] if their request
+    /// never created the item.
+    pub async fn add_item_idempotent(&self, item: Item, key: Option<&str>) -> Result<Item> {
+        let Some(key) = key else {
+            return self.add_item(item).await;
+        };
+        let key = key.trim();
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(CustError::new(
+                format!(
+                    "an idempotency key must have 1 to {} characters",
+                    MAX_IDEMPOTENCY_KEY_LENGTH
+                ),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let now = util::now();
+        sqlx::query(
+            "DELETE FROM idempotency_keys WHERE created_at < ? OR (item_id IS NULL AND created_at < ?)",
+        )
+            .bind(now - IDEMPOTENCY_KEY_TTL_SECS)
+            .bind(now - IDEMPOTENCY_RESERVATION_TIMEOUT_SECS)
+            .execute(&self.conn)
+            .await?;
+
+        let caller = current_caller().map(|caller| caller.name).unwrap_or_default();
+        let fingerprint = item_fingerprint(&item)?;
+        let reserved = sqlx::query(
+            "INSERT INTO idempotency_keys (caller, key, fingerprint, created_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT (caller, key) DO NOTHING",
+        )
+            .bind(&caller)
+            .bind(key)
+            .bind(&fingerprint)
+            .bind(now)
+            .execute(&self.conn)
+            .await?
+            .rows_affected()
+            == 1;
+
+        if !reserved {
+            let row = sqlx::query(
+                "SELECT fingerprint, item_id FROM idempotency_keys WHERE caller = ? AND key = ?",
+            )
+                .bind(&caller)
+                .bind(key)
+                .fetch_one(&self.conn)
+                .await?;
+            if row.get::<String, _>("fingerprint") != fingerprint {
+                return Err(CustError::new(
+                    "the idempotency key was already used for a different item".to_string(),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                ));
+            }
+            return match row.get::<Option<ID>, _>("item_id") {
+                Some(item_id) => self.get_item(item_id).await,
+                None => Err(CustError::new(
+                    "a request with this idempotency key is still in progress".to_string(),
+                    StatusCode::CONFLICT,
+                )),
+            };
+        }
+
+        match self.add_item(item).await {
+            Ok(item) => {
+                sqlx::query("UPDATE idempotency_keys SET item_id = ? WHERE caller = ? AND key = ?")
+                    .bind(item.id)
+                    .bind(&caller)
+                    .bind(key)
+                    .execute(&self.conn)
+                    .await?;
+                Ok(item)
+            }
+            Err(e) => {
+                // a failed request may be retried with the same key
+                sqlx::query("DELETE FROM idempotency_keys WHERE caller = ? AND key = ?")
+                    .bind(&caller)
+                    .bind(key)
+                    .execute(&self.conn)
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Text indexed for an item. Besides its own fields it contains the names of its category and
+    /// location, so e.g. searching "garage" finds the items located in the garage, and the text
+    /// recognized in its image.
+    async fn document_text(&self, item: &Item) -> Result<String> {
+        let mut data = match &item.description {
+            Some(desc) => format!("{} {}", item.name, desc),
+            None => item.name.to_string(),
+        };
+        for tag in &item.tags {
+            data.push(' ');
+            data.push_str(tag);
+        }
+        if let Some(text) = &item.image_text {
+            data.push(' ');
+            data.push_str(text);
+        }
+
+        let parents = sqlx::query(
+            r#"
+            SELECT (SELECT name FROM categories WHERE id = ?) AS category,
+                   (SELECT name FROM locations WHERE id = ?) AS location
+            "#,
+        )
+            .bind(item.category_id)
+            .bind(item.location_id)
+            .fetch_one(&self.conn)
+            .await?;
+        for parent in ["category", "location"] {
+            if let Some(name) = parents.get::<Option<String>, _>(parent) {
+                data.push(' ');
+                data.push_str(&name);
+            }
+        }
+
+        // every translation is indexed, so a search in any language finds the item
+        if let Some(id) = item.id {
+            let translations = sqlx::query_as::<_, ItemTranslation>(
+                "SELECT * FROM item_translations WHERE item_id = ? ORDER BY locale",
+            )
+                .bind(id)
+                .fetch_all(&self.conn)
+                .await?;
+            for translation in translations {
+                data.push(' ');
+                data.push_str(&translation.name);
+                if let Some(description) = &translation.description {
+                    data.push(' ');
+                    data.push_str(description);
+                }
+            }
+
+            if self.config.get().search.index_notes {
+                let notes: Vec<String> =
+                    sqlx::query_scalar("SELECT text FROM item_notes WHERE item_id = ? ORDER BY id")
+                        .bind(id)
+                        .fetch_all(&self.conn)
+                        .await?;
+                for note in notes {
+                    data.push(' ');
+                    data.push_str(&note);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Replaces the image of an item, generating a new thumbnail for it. The image becomes the
+    /// primary image of the gallery.
+    pub async fn set_item_image(&self, id: ID, image: Vec<u8>, filename: Option<String>) -> Result<Item> {
+        self.add_item_image(id, image, filename, None, true).await?;
+        self.get_item(id).await
+    }
+
+    pub async fn set_item_image_from_url(&self, id: ID, url: &str) -> Result<Item> {
+        // fail early, before downloading anything
+        let _item = self.get_item(id).await?;
+
+        let image = images::fetch_image(url).await?;
+        self.set_item_image(id, image, images::url_filename(url)).await
+    }
+
+    /// Adds an image to the gallery of an item. The first image of a gallery is always primary.
+    pub async fn add_item_image(
+        &self,
+        item_id: ID,
+        image: Vec<u8>,
+        filename: Option<String>,
+        caption: Option<String>,
+        primary: bool,
+    ) -> Result<ItemImage> {
+        self.authorize_item(item_id).await?;
+        let limit = self.config.get().limits.fullsize_bytes;
+        if image.len() > limit {
+            return Err(image_too_large("fullsize", image.len(), limit));
+        }
+        let info = images::image_file_info(&image, filename.as_deref());
+
+        let item = self.get_item(item_id).await?;
+        let image = self.scan_upload(image, info.filename.as_deref()).await?;
+        let processed = tokio::task::spawn_blocking(move || images::process_image(image))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        let mut tx = self.conn.begin().await?;
+        let count: i32 = sqlx::query("SELECT COUNT(*) AS count FROM item_images WHERE item_id = ?")
+            .bind(item_id)
+            .fetch_one(&mut *tx)
+            .await?
+            .get("count");
+
+        // images stored before galleries existed become the first gallery image
+        let has_legacy_image = item.fullsize.as_ref().is_some_and(|f| !f.is_empty());
+        let mut position = count;
+        if count == 0 && has_legacy_image {
+            let legacy_bytes = base64::engine::general_purpose::STANDARD
+                .decode(item.fullsize.as_deref().unwrap_or_default())?;
+            let legacy_info = images::image_file_info(&legacy_bytes, None);
+            let mut legacy = self
+                .insert_item_image(&mut tx, item_id, 0, None, true, &legacy_info)
+                .await?;
+            legacy.thumbnail = item.thumbnail.clone();
+            legacy.fullsize = item.fullsize.clone();
+            self.item_image_files.store(&legacy).await?;
+            position = 1;
+        }
+
+        let primary = primary || position == 0;
+        if primary {
+            sqlx::query("UPDATE item_images SET is_primary = 0 WHERE item_id = ?")
+                .bind(item_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut image = self
+            .insert_item_image(&mut tx, item_id, position, caption, primary, &info)
+            .await?;
+        image.thumbnail = Some(base64::engine::general_purpose::STANDARD.encode(processed.thumbnail));
+        image.fullsize = Some(base64::engine::general_purpose::STANDARD.encode(processed.fullsize));
+        self.item_image_files.store(&image).await?;
+
+        tx.commit().await?;
+
+        if primary {
+            self.sync_primary_image(item_id).await?;
+        }
+
+        Ok(image)
+    }
+
+    /// Adds an image that arrives in chunks, e.g. from a gRPC stream. The item is checked before
+    /// the first chunk is read and an upload is cancelled as soon as it exceeds the size limit.
+    pub async fn add_item_image_from_chunks<S>(
+        &self,
+        item_id: ID,
+        mut chunks: S,
+        filename: Option<String>,
+        caption: Option<String>,
+        primary: bool,
+    ) -> Result<ItemImage>
+    where
+        S: Stream<Item = Result<Vec<u8>>> + Unpin,
+    {
+        self.authorize_item(item_id).await?;
+        let _item = self.get_item(item_id).await?;
+
+        let limit = self.config.get().limits.fullsize_bytes;
+        let mut image = vec![];
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            if image.len() + chunk.len() > limit {
+                return Err(image_too_large("fullsize", image.len() + chunk.len(), limit));
+            }
+            image.extend_from_slice(&chunk);
+        }
+
+        self.add_item_image(item_id, image, filename, caption, primary).await
+    }
+
+    async fn insert_item_image(
+        &self,
+        tx: &mut Transaction<'_, Sqlite>,
+        item_id: ID,
+        position: i32,
+        caption: Option<String>,
+        primary: bool,
+        info: &ImageFileInfo,
+    ) -> Result<ItemImage> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO item_images (item_id, position, caption, is_primary, created_at, filename, mime_type, size_bytes, sha256)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+            .bind(item_id)
+            .bind(position)
+            .bind(caption.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+            .bind(primary)
+            .bind(util::now())
+            .bind(&info.filename)
+            .bind(&info.mime_type)
+            .bind(info.size_bytes)
+            .bind(&info.sha256)
+            .execute(&mut **tx)
+            .await?
+            .last_insert_rowid() as ID;
+
+        Ok(sqlx::query_as::<_, ItemImage>("SELECT * FROM item_images WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await?)
+    }
+
+    /// Gallery of an item in display order, with thumbnails only
+    pub async fn get_item_images(&self, item_id: ID) -> Result<Vec<ItemImage>> {
+        self.authorize_item(item_id).await?;
+        let mut images = sqlx::query_as::<_, ItemImage>(
+            "SELECT * FROM item_images WHERE item_id = ? ORDER BY position",
+        )
+            .bind(item_id)
+            .fetch_all(&self.conn)
+            .await?;
+
+        for image in &mut images {
+            if let Err(e) = self.item_image_files.read(image).await {
+                error!("{}", e);
+            }
+            image.fullsize = None;
+        }
+
+        Ok(images)
+    }
+
+    pub async fn get_item_image(&self, item_id: ID, image_id: ID) -> Result<ItemImage> {
+        self.authorize_item(item_id).await?;
+        let mut image = sqlx::query_as::<_, ItemImage>(
+            "SELECT * FROM item_images WHERE id = ? AND item_id = ?",
+        )
+            .bind(image_id)
+            .bind(item_id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("image not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        self.item_image_files.read(&mut image).await?;
+        Ok(image)
+    }
+
+    /// The full size image for serving it as a file. Images stored before their metadata was
+    /// recorded are described from their content.
+    pub async fn download_item_image(&self, item_id: ID, image_id: ID) -> Result<ImageDownload> {
+        self.authorize_item(item_id).await?;
+        let image = self.get_item_image(item_id, image_id).await?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(image.fullsize.as_deref().unwrap_or_default())?;
+
+        let info = match (image.mime_type, image.sha256) {
+            (Some(mime_type), Some(sha256)) => ImageFileInfo {
+                filename: image.filename,
+                mime_type,
+                size_bytes: bytes.len() as i64,
+                sha256,
+            },
+            _ => images::image_file_info(&bytes, image.filename.as_deref()),
+        };
+        let filename = info.filename.unwrap_or_else(|| {
+            let extension = image::ImageFormat::from_mime_type(&info.mime_type)
+                .and_then(|format| format.extensions_str().first())
+                .unwrap_or(&"bin");
+            format!("{}_{}.{}", item_id, image_id, extension)
+        });
+
+        Ok(ImageDownload {
+            bytes,
+            filename,
+            mime_type: info.mime_type,
+            sha256: info.sha256,
+        })
+    }
+
+    /// Removes an image from a gallery. If it was the primary image, the next one takes its place.
+    pub async fn delete_item_image(&self, item_id: ID, image_id: ID) -> Result<ItemImage> {
+        self.authorize_item(item_id).await?;
+        let image = self.get_item_image(item_id, image_id).await?;
+
+        let mut tx = self.conn.begin().await?;
+        sqlx::query("DELETE FROM item_images WHERE id = ?")
+            .bind(image_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE item_images SET position = position - 1 WHERE item_id = ? AND position > ?")
+            .bind(item_id)
+            .bind(image.position)
+            .execute(&mut *tx)
+            .await?;
+        if image.is_primary {
+            sqlx::query(
+                "UPDATE item_images SET is_primary = 1 WHERE item_id = ? AND position = 0",
+            )
+                .bind(item_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        self.item_image_files.delete(&image).await?;
+        if image.is_primary {
+            self.sync_primary_image(item_id).await?;
+        }
+
+        Ok(image)
+    }
+
+    pub async fn reorder_item_images(&self, item_id: ID, image_ids: Vec<ID>) -> Result<()> {
+        self.authorize_item(item_id).await?;
+        let mut tx = self.conn.begin().await?;
+
+        let mut current: Vec<ID> = sqlx::query("SELECT id FROM item_images WHERE item_id = ?")
+            .bind(item_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| row.get("id"))
+            .collect();
+        current.sort_unstable();
+
+        let mut requested = image_ids.clone();
+        requested.sort_unstable();
+
+        if current != requested {
+            return Err(CustError::new(
+                "order must contain every image of the item exactly once".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        for (position, image_id) in image_ids.iter().enumerate() {
+            sqlx::query("UPDATE item_images SET position = ? WHERE id = ?")
+                .bind(position as i32)
+                .bind(image_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn set_primary_item_image(&self, item_id: ID, image_id: ID) -> Result<()> {
+        self.authorize_item(item_id).await?;
+        // fails with 404 for images of other items
+        self.get_item_image(item_id, image_id).await?;
+
+        sqlx::query("UPDATE item_images SET is_primary = (id = ?) WHERE item_id = ?")
+            .bind(image_id)
+            .bind(item_id)
+            .execute(&self.conn)
+            .await?;
+
+        self.sync_primary_image(item_id).await
+    }
+
+    /// Mirrors the primary gallery image into the thumbnail and fullsize of the item, for clients
+    /// that don't know about galleries.
+    async fn sync_primary_image(&self, item_id: ID) -> Result<()> {
+        let primary = sqlx::query_as::<_, ItemImage>(
+            "SELECT * FROM item_images WHERE item_id = ? AND is_primary = 1",
+        )
+            .bind(item_id)
+            .fetch_optional(&self.conn)
+            .await?;
+
+        let mut item = self.get_item(item_id).await?;
+        match primary {
+            Some(mut primary) => {
+                self.item_image_files.read(&mut primary).await?;
+                item.thumbnail = primary.thumbnail;
+                item.fullsize = primary.fullsize;
+            }
+            None => {
+                item.thumbnail = None;
+                item.fullsize = None;
+            }
+        }
+
+        self.item_files.store(&item).await?;
+        sqlx::query("DELETE FROM item_image_hashes WHERE item_id = ?")
+            .bind(item_id)
+            .execute(&self.conn)
+            .await?;
+        self.items_changed();
+        self.jobs.push(Job::RecognizeText(item_id));
+        Ok(())
+    }
+
+    /// Reads the text in the primary image of an item, e.g. a model number, with the configured
+    /// recognizer. It is stored in `image_text` and searched like the other fields of the item.
+    /// Items without an image lose their text.
+    pub async fn recognize_item_text(&self, id: ID) -> Result<()> {
+        let item = self.get_item(id).await?;
+        let image = [item.fullsize, item.thumbnail]
+            .into_iter()
+            .flatten()
+            .find(|image| !image.is_empty());
+
+        let text = match image {
+            Some(image) => {
+                let bytes = base64::engine::general_purpose::STANDARD.decode(image)?;
+                let recognizer = self.recognizer.clone();
+                let text = tokio::task::spawn_blocking(move || recognizer.recognize(&bytes))
+                    .await
+                    .map_err(anyhow::Error::from)??;
+                normalize_recognized_text(&text)
+            }
+            None => None,
+        };
+        self.set_image_text(id, text).await
+    }
+
+    async fn set_image_text(&self, id: ID, text: Option<String>) -> Result<()> {
+        let updated =
+            sqlx::query("UPDATE items SET image_text = ?1 WHERE id = ?2 AND image_text IS NOT ?1")
+                .bind(&text)
+                .bind(id)
+                .execute(&self.conn)
+                .await?
+                .rows_affected();
+        if updated == 0 {
+            return Ok(());
+        }
+
+        let items =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_all(&self.conn)
+                .await?;
+        self.reindex_items(items).await?;
+
+        self.items_changed();
+        Ok(())
+    }
+
+    /// Items whose primary image looks like `search.image`, most similar first. Images are
+    /// compared by their [perceptual hash](images::perceptual_hash), so other photos of the
+    /// same thing match as long as they are taken from a similar angle.
+    pub async fn find_items_by_image(&self, search: ImageSearch) -> Result<Vec<SimilarItem>> {
+        self.features.require(Feature::ImageSearch)?;
+        self.check_image_limits(None, Some(&search.image))?;
+        let bytes = base64::engine::general_purpose::STANDARD.decode(&search.image)?;
+        let hash = tokio::task::spawn_blocking(move || images::perceptual_hash(&bytes))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        self.hash_item_images().await?;
+        let max_distance = search.max_distance.unwrap_or(DEFAULT_MAX_IMAGE_DISTANCE);
+        let access = self.caller_scope().await?;
+        let mut matches: Vec<(u32, ID)> = sqlx::query(
+            r#"
+            SELECT item_image_hashes.item_id, item_image_hashes.hash FROM item_image_hashes
+            JOIN items ON items.id = item_image_hashes.item_id
+            WHERE item_image_hashes.hash IS NOT NULL AND items.ownership_state != 'disposed'
+                AND items.deleted_at IS NULL
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let distance = images::hash_distance(hash, row.get::<i64, _>("hash") as u64);
+                (distance, row.get("item_id"))
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .filter(|(_, id)| access.as_ref().is_none_or(|access| access.allows_item(*id)))
+            .collect();
+        matches.sort_unstable();
+        matches.truncate(search.limit.unwrap_or(DEFAULT_IMAGE_SEARCH_LIMIT));
+
+        let mut similar = Vec::with_capacity(matches.len());
+        for (distance, id) in matches {
+            similar.push(SimilarItem {
+                item: self.get_item(id).await?,
+                distance,
+                similarity: 1.0 - f64::from(distance) / 64.0,
+            });
+        }
+
+        Ok(similar)
+    }
+
+    /// Items whose text is close to `search.query`, most similar first. Unlike the text search
+    /// it ranks by the [embedding](crate::Embedder) of the item, how close two texts are depends
+    /// on the embedder.
+    pub async fn find_items_semantically(
+        &self,
+        search: SemanticSearch,
+    ) -> Result<Vec<SemanticMatch>> {
+        let vectors = self.require_vectors()?;
+        let vector = self.embedder.embed(&search.query);
+        let limit = search.limit.unwrap_or(DEFAULT_SEMANTIC_SEARCH_LIMIT);
+        let access = self.caller_scope().await?;
+
+        // the backend doesn't know the scope of the caller, so it is asked for more matches until
+        // enough of them are in scope or there are no more
+        let mut fetched = limit;
+        let matches = loop {
+            let matches = vectors.search(&vector, fetched).await?;
+            let exhausted = matches.len() < fetched;
+            let mut matches: Vec<_> = matches
+                .into_iter()
+                .filter(|(id, _)| access.as_ref().is_none_or(|access| access.allows_item(*id)))
+                .collect();
+            if matches.len() >= limit || exhausted {
+                matches.truncate(limit);
+                break matches;
+            }
+            fetched = fetched.saturating_mul(SEMANTIC_OVERFETCH_FACTOR);
+        };
+
+        let mut found = Vec::with_capacity(matches.len());
+        for (id, similarity) in matches {
+            found.push(SemanticMatch {
+                item: self.get_item(id).await?,
+                similarity,
+            });
+        }
+        Ok(found)
+    }
+
+    /// The vector backend, if one is configured and semantic search is enabled
+    fn require_vectors(&self) -> Result<&Arc<dyn VectorBackend>> {
+        self.features.require(Feature::SemanticSearch)?;
+        self.vectors.as_ref().ok_or_else(|| {
+            CustError::new(
+                "no vector backend is configured".to_string(),
+                StatusCode::NOT_IMPLEMENTED,
+            )
+        })
+    }
+
+    /// Queues [`Job::EmbedItem`] for an item whose text changed or that was removed
+    fn embed_later(&self, id: ID) {
+        if self.vectors.is_some() {
+            self.jobs.push(Job::EmbedItem(id));
+        }
+    }
+
+    /// Updates the vector of one item, or removes it if the item is gone or disposed
+    pub async fn embed_item(&self, id: ID) -> Result<()> {
+        let Some(vectors) = &self.vectors else {
+            return Ok(());
+        };
+        match self.embedding_texts(Some(id)).await?.remove(&id) {
+            Some(text) => vectors.upsert(id, self.embedder.embed(&text)).await?,
+            None => vectors.remove(id).await?,
+        }
+        // fused search results were cached with the old vector
+        self.search_cache.invalidate();
+        Ok(())
+    }
+
+    /// Embeds all items again in the background, e.g. after the model of the embedder changed.
+    /// Searches keep using the current vectors until every item is embedded, then the new ones
+    /// replace them at once. Only one job runs at a time, its progress is listed by
+    /// [`Self::embedding_status`].
+    pub async fn start_embedding(&self) -> Result<EmbeddingJob> {
+        require_unscoped()?;
+        if self.vectors.is_none() {
+            return Err(CustError::new(
+                "no vector backend is configured".to_string(),
+                StatusCode::NOT_IMPLEMENTED,
+            ));
+        }
+        self.queue_embedding().await
+    }
+
+    async fn queue_embedding(&self) -> Result<EmbeddingJob> {
+        let job = sqlx::query_as::<_, EmbeddingJob>(
+            "INSERT INTO embedding_jobs (model, state, started_at) VALUES (?, ?, ?) RETURNING *",
+        )
+            .bind(self.embedder.model())
+            .bind(EmbeddingJobState::Running)
+            .bind(util::now())
+            .fetch_one(&self.conn)
+            .await;
+
+        match job {
+            Ok(job) => {
+                self.jobs.push(Job::EmbedAllItems(job.id));
+                Ok(job)
+            }
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(CustError::new(
+                "an embedding job is already running".to_string(),
+                StatusCode::CONFLICT,
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Runs the embedding job `job`: embeds every item and replaces all vectors with the new
+    /// ones at once. Items that change meanwhile are embedded again by their
+    /// [`Job::EmbedItem`], which is queued behind this job.
+    pub async fn embed_all_items(&self, job: ID) -> Result<()> {
+        let Some(vectors) = &self.vectors else {
+            return Ok(());
+        };
+        let result = self.run_embedding(vectors, job).await;
+
+        let (state, error) = match &result {
+            Ok(_) => (EmbeddingJobState::Completed, None),
+            Err(e) => (EmbeddingJobState::Failed, Some(e.to_string())),
+        };
+        sqlx::query("UPDATE embedding_jobs SET state = ?, finished_at = ?, error = ? WHERE id = ?")
+            .bind(state)
+            .bind(util::now())
+            .bind(error)
+            .bind(job)
+            .execute(&self.conn)
+            .await?;
+
+        result
+    }
+
+    async fn run_embedding(&self, vectors: &Arc<dyn VectorBackend>, job: ID) -> Result<()> {
+        let texts = self.embedding_texts(None).await?;
+        let count = texts.len();
+        sqlx::query("UPDATE embedding_jobs SET total = ? WHERE id = ?")
+            .bind(count as i64)
+            .bind(job)
+            .execute(&self.conn)
+            .await?;
+
+        let mut embedded = HashMap::with_capacity(count);
+        for (id, text) in texts {
+            embedded.insert(id, self.embedder.embed(&text));
+            if embedded.len() % EMBEDDING_PROGRESS_INTERVAL == 0 || embedded.len() == count {
+                sqlx::query("UPDATE embedding_jobs SET done = ? WHERE id = ?")
+                    .bind(embedded.len() as i64)
+                    .bind(job)
+                    .execute(&self.conn)
+                    .await?;
+            }
+        }
+
+        vectors.replace_all(embedded).await?;
+        *self.embedded_model.lock().unwrap() = Some(self.embedder.model());
+        self.search_cache.invalidate();
+        debug!("Embedded {} items", count);
+        Ok(())
+    }
+
+    /// The model of the embedder, the model the searched vectors were embedded with and the
+    /// latest embedding jobs
+    pub async fn embedding_status(&self) -> Result<EmbeddingStatus> {
+        require_unscoped()?;
+        let jobs = sqlx::query_as::<_, EmbeddingJob>(
+            "SELECT * FROM embedding_jobs ORDER BY id DESC LIMIT ?",
+        )
+            .bind(EMBEDDING_JOBS_LISTED)
+            .fetch_all(&self.conn)
+            .await?;
+
+        Ok(EmbeddingStatus {
+            model: self.embedder.model(),
+            embedded_model: self.embedded_model.lock().unwrap().clone(),
+            jobs,
+        })
+    }
+
+    /// Name, description and tags of the item with `id`, or of all items. Disposed and deleted
+    /// items have no text, they aren't found by vector searches.
+    async fn embedding_texts(&self, id: Option<ID>) -> Result<HashMap<ID, String>> {
+        let mut texts: HashMap<ID, String> = sqlx::query(
+            r#"
+            SELECT id, name, description FROM items
+            WHERE ownership_state != 'disposed' AND deleted_at IS NULL AND (?1 IS NULL OR id = ?1)
+            "#,
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let description: Option<String> = row.get("description");
+                let text = format!(
+                    "{} {}",
+                    row.get::<String, _>("name"),
+                    description.unwrap_or_default()
+                );
+                (row.get("id"), text)
+            })
+            .collect();
+        let tags = sqlx::query(
+            "SELECT item_id, tag FROM item_tags WHERE ?1 IS NULL OR item_id = ?1",
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?;
+        for row in tags {
+            if let Some(text) = texts.get_mut(&row.get::<ID, _>("item_id")) {
+                text.push(' ');
+                text.push_str(&row.get::<String, _>("tag"));
+            }
+        }
+        Ok(texts)
+    }
+
+    /// Hashes the images of items that weren't hashed since their image last changed. Items
+    /// without an image get a NULL hash, so their file isn't read again.
+    async fn hash_item_images(&self) -> Result<()> {
+        let ids: Vec<ID> = sqlx::query(
+            r#"
+            SELECT id FROM items
+            WHERE deleted_at IS NULL AND id NOT IN (SELECT item_id FROM item_image_hashes)
+            "#,
+
+        )
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        for id in ids {
+            let mut item = Item {
+                id: Some(id),
+                ..Default::default()
+            };
+            if let Err(e) = self.item_files.read(&mut item).await {
+                debug!("Item {} has no image file: {}", id, e);
+            }
+            let image = [item.thumbnail, item.fullsize]
+                .into_iter()
+                .flatten()
+                .find(|image| !image.is_empty());
+
+            let hash = match image {
+                Some(image) => {
+                    let bytes = base64::engine::general_purpose::STANDARD.decode(image)?;
+                    let hash = tokio::task::spawn_blocking(move || images::perceptual_hash(&bytes))
+                        .await
+                        .map_err(anyhow::Error::from)?;
+                    match hash {
+                        Ok(hash) => Some(hash as i64),
+                        Err(e) => {
+                            warn!("Could not hash the image of item {}: {}", id, e);
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            sqlx::query("INSERT OR REPLACE INTO item_image_hashes (item_id, hash) VALUES (?, ?)")
+                .bind(id)
+                .bind(hash)
+                .execute(&self.conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_item(&self, id: ID) -> Result<Item> {
+        self.get_item_masked(id, &ItemFieldMask::all()).await
+    }
+
+    /// Like [`Self::get_item`], without reading the image file unless the mask asks for an image
+    #[instrument(skip_all, fields(id = id))]
+    pub async fn get_item_masked(&self, id: ID, mask: &ItemFieldMask) -> Result<Item> {
+        self.authorize_item(id).await?;
+        if let Some(mut item) = self.item_cache.get(&id) {
+            if mask.includes("fullsize") {
+                if let Err(e) = self.item_files.read(&mut item).await {
+                    error!("{}", e);
+                }
+            }
+            let buffered = self.access_buffer.lock().unwrap().get(&id).map(|(at, _)| *at);
+            item.last_accessed_at = item.last_accessed_at.max(buffered);
+            return Ok(item);
+        }
+
+        let generation = self.item_cache.generation();
+        let mut item: Item =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_one(&self.conn)
+                .await?
+                .into();
+
+
+        self.hydrate_item_masked(&mut item, mask).await;
+        // without the files the thumbnail is missing, so only complete items are cached
+        if mask.needs_files() {
+            let cached = Item {
+                fullsize: None,
+                ..item.clone()
+            };
+            self.item_cache.insert(id, cached, generation);
+        }
+
+        Ok(item)
+    }
+
+    pub async fn get_item_by_uuid(&self, uuid: &str) -> Result<Item> {
+        self.get_item(self.item_id_by_uuid(uuid).await?).await
+    }
+
+    /// Sets the color and icon of the tile of an item
+    pub async fn set_item_appearance(&self, id: ID, mut appearance: Appearance) -> Result<Item> {
+        self.authorize_item(id).await?;
+        check_color(&mut appearance.color)?;
+        let result =
+            sqlx::query(
+                "UPDATE items SET color = ?1, icon = ?2, updated_at = ?3 WHERE id = ?4 AND deleted_at IS NULL",
+            )
+                .bind(&appearance.color)
+                .bind(appearance.icon)
+                .bind(util::now())
+                .bind(id)
+                .execute(&self.conn)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+        }
+
+        self.items_changed();
+        let item = self.get_item(id).await?;
+        self.publish(EventKind::ItemUpdated, id, &DbItem::from(item.clone()))
+            .await;
+        Ok(item)
+    }
+
+    /// Packs an item into the item `container`, e.g. a drill into a toolbox, or takes it out of
+    /// its container without one
+    pub async fn move_item(&self, id: ID, container: Option<ID>) -> Result<Item> {
+        self.authorize_item(id).await?;
+        if let Some(container) = container {
+            self.authorize_item(container).await?;
+        }
+        let mut tx = self.conn.begin().await?;
+        check_reference(&mut tx, "items", "container", container).await?;
+
+        if let Some(container) = container {
+            let inside = sqlx::query(
+                r#"
+                WITH RECURSIVE contents(id) AS (
+                    SELECT ?1
+                    UNION
+                    SELECT items.id FROM items
+                    JOIN contents ON items.contained_in_item_id = contents.id
+                )
+                SELECT 1 FROM contents WHERE id = ?2
+                "#,
+            )
+                .bind(id)
+                .bind(container)
+                .fetch_optional(&mut *tx)
+                .await?
+                .is_some();
+            if inside {
+                return Err(CustError::new(
+                    "an item can't be packed into itself or an item inside of it".to_string(),
+                    StatusCode::CONFLICT,
+                )
+                    .with_details(serde_json::json!({ "container": container })));
+            }
+        }
+
+        let result = sqlx::query(
+            "UPDATE items SET contained_in_item_id = ?1, updated_at = ?2 WHERE id = ?3 AND deleted_at IS NULL",
+        )
+            .bind(container)
+            .bind(util::now())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+        }
+        tx.commit().await?;
+
+        self.items_changed();
+        let item = self.get_item(id).await?;
+        self.publish(EventKind::ItemUpdated, id, &DbItem::from(item.clone()))
+            .await;
+        Ok(item)
+    }
+
+    /// Everything inside a container, including what is inside the items in it, outermost
+    /// first. Each item names the container it is in directly.
+    pub async fn get_item_contents(&self, id: ID) -> Result<Vec<Item>> {
+        self.authorize_item(id).await?;
+        self.check_item_exists(id).await?;
+        let mut items: Vec<Item> = sqlx::query_as::<_, DbItem>(
+            r#"
+            WITH RECURSIVE contents(id, depth) AS (
+                SELECT id, 1 FROM items WHERE contained_in_item_id = ? AND deleted_at IS NULL
+                UNION
+                SELECT items.id, contents.depth + 1 FROM items
+                JOIN contents ON items.contained_in_item_id = contents.id
+                WHERE contents.depth < ? AND items.deleted_at IS NULL
+            )
+
+            SELECT items.* FROM items
+            JOIN contents ON contents.id = items.id
+            ORDER BY contents.depth, items.name, items.id
+            "#,
+        )
+            .bind(id)
+            .bind(MAX_CONTAINER_DEPTH)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let access = self.caller_scope().await?;
+        items.retain(|item| in_scope(&access, item));
+
+        for item in &mut items {
+            check_deadline()?;
+            self.hydrate_item(item).await;
+        }
+        Ok(items)
+    }
+
+    /// Translations of the name and description of an item, ordered by locale
+    pub async fn get_item_translations(&self, id: ID) -> Result<Vec<ItemTranslation>> {
+        self.authorize_item(id).await?;
+        self.check_item_exists(id).await?;
+        Ok(sqlx::query_as::<_, ItemTranslation>(
+            "SELECT * FROM item_translations WHERE item_id = ? ORDER BY locale",
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    /// Adds or replaces the translation of an item into `locale`. The item is reindexed, so it
+    /// is found by the translated words as well.
+    pub async fn set_item_translation(
+        &self,
+        id: ID,
+        locale: &str,
+        mut translation: ItemTranslation,
+    ) -> Result<ItemTranslation> {
+        self.authorize_item(id).await?;
+        translation.locale = util::normalize_locale(locale).ok_or_else(|| {
+            CustError::new(format!("{} is not a language tag", locale), StatusCode::BAD_REQUEST)
+        })?;
+        let default_locale = util::normalize_locale(&self.config.get().default_locale);
+        if default_locale.as_ref() == Some(&translation.locale) {
+            return Err(CustError::new(
+                format!("{} is the default locale, set the name of the item instead", locale),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        translation.name = util::sanitize_name(&translation.name)?.to_owned();
+        translation.description = translation
+            .description
+            .map(|description| description.trim().to_owned())
+            .filter(|description| !description.is_empty());
+        self.check_item_exists(id).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO item_translations (item_id, locale, name, description) VALUES (?, ?, ?, ?)
+            ON CONFLICT (item_id, locale)
+            DO UPDATE SET name = excluded.name, description = excluded.description
+            "#,
+        )
+            .bind(id)
+            .bind(&translation.locale)
+            .bind(&translation.name)
+            .bind(&translation.description)
+            .execute(&self.conn)
+            .await?;
+
+        self.translations_changed(id).await?;
+        Ok(translation)
+    }
+
+    pub async fn delete_item_translation(&self, id: ID, locale: &str) -> Result<ItemTranslation> {
+        self.authorize_item(id).await?;
+        let locale = util::normalize_locale(locale).unwrap_or_default();
+        let translation = sqlx::query_as::<_, ItemTranslation>(
+            "DELETE FROM item_translations WHERE item_id = ? AND locale = ? RETURNING *",
+        )
+            .bind(id)
+            .bind(&locale)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| {
+                CustError::new("translation not found".to_string(), StatusCode::NOT_FOUND)
+            })?;
+
+        self.translations_changed(id).await?;
+        Ok(translation)
+    }
+
+    /// The FTS5 table follows translations by triggers, the index document is replaced here.
+    /// Listings show the translations, so the item counts as updated.
+    async fn translations_changed(&self, id: ID) -> Result<()> {
+        sqlx::query("UPDATE items SET updated_at = ? WHERE id = ?")
+            .bind(util::now())
+            .bind(id)
+            .execute(&self.conn)
+            .await?;
+        let items =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_all(&self.conn)
+                .await?;
+        self.reindex_items(items).await?;
+        self.items_changed();
+        Ok(())
+    }
+
+    /// Notes of an item, oldest first
+    pub async fn get_item_notes(&self, id: ID) -> Result<Vec<ItemNote>> {
+        self.authorize_item(id).await?;
+        self.check_item_exists(id).await?;
+        Ok(sqlx::query_as::<_, ItemNote>("SELECT * FROM item_notes WHERE item_id = ? ORDER BY id")
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    /// Adds a note to an item, written by the current caller
+    pub async fn add_item_note(&self, id: ID, note: NewItemNote) -> Result<ItemNote> {
+        self.authorize_item(id).await?;
+        let text = note_text(&note)?;
+        self.check_item_exists(id).await?;
+
+        let note = sqlx::query_as::<_, ItemNote>(
+            "INSERT INTO item_notes (item_id, text, author, created_at) VALUES (?, ?, ?, ?) RETURNING *",
+        )
+            .bind(id)
+            .bind(text)
+            .bind(current_caller().map(|caller| caller.name))
+            .bind(util::now())
+            .fetch_one(&self.conn)
+            .await?;
+
+        self.notes_changed(id).await?;
+        Ok(note)
+    }
+
+    /// Replaces the text of a note, it keeps its author
+    pub async fn update_item_note(&self, id: ID, note_id: ID, note: NewItemNote) -> Result<ItemNote> {
+        self.authorize_item(id).await?;
+        let text = note_text(&note)?;
+        let note = sqlx::query_as::<_, ItemNote>(
+            "UPDATE item_notes SET text = ?, updated_at = ? WHERE id = ? AND item_id = ? RETURNING *",
+        )
+            .bind(text)
+            .bind(util::now())
+            .bind(note_id)
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| note_not_found(note_id))?;
+
+        self.notes_changed(id).await?;
+        Ok(note)
+    }
+
+    pub async fn delete_item_note(&self, id: ID, note_id: ID) -> Result<ItemNote> {
+        self.authorize_item(id).await?;
+        let note = sqlx::query_as::<_, ItemNote>(
+            "DELETE FROM item_notes WHERE id = ? AND item_id = ? RETURNING *",
+        )
+            .bind(note_id)
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| note_not_found(note_id))?;
+
+        self.notes_changed(id).await?;
+        Ok(note)
+    }
+
+    /// Notes are only searched with `search.index_notes`. The FTS5 table follows them by
+    /// triggers, the index document is replaced here.
+    async fn notes_changed(&self, id: ID) -> Result<()> {
+        if !self.config.get().search.index_notes {
+            return Ok(());
+        }
+        let items =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_all(&self.conn)
+                .await?;
+        self.reindex_items(items).await?;
+        self.items_changed();
+        Ok(())
+    }
+
+    /// Replaces the names and descriptions of items with their translation into the most
+    /// preferred of `locales`, see [`util::accepted_locales`]. Locales after the default locale
+    /// are ignored, an item keeps its name for callers that prefer the default.
+    pub async fn localize_items(&self, items: &mut [Item], locales: &[String]) -> Result<()> {
+        let default_locale = self.config.get().default_locale.to_ascii_lowercase();
+        let default_language = default_locale.split('-').next().unwrap_or_default();
+        let wanted: Vec<&String> = locales
+            .iter()
+            .take_while(|locale| locale.split('-').next() != Some(default_language))
+            .collect();
+        if wanted.is_empty() || items.is_empty() {
+            return Ok(());
+        }
+
+        let query_str = format!(
+            "SELECT * FROM item_translations WHERE locale IN ({})",
+            util::placeholders(wanted.len())
+        );
+        let translations: HashMap<(ID, String), ItemTranslation> = wanted
+            .iter()
+            .fold(sqlx::query(&query_str), |query, locale| query.bind(locale.as_str()))
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let translation = ItemTranslation::from_row(&row)?;
+                Ok(((row.get("item_id"), translation.locale.clone()), translation))
+            })
+            .collect::<std::result::Result<_, sqlx::Error>>()?;
+
+        for item in items {
+            let Some(id) = item.id else {
+                continue;
+            };
+            let translation = wanted
+                .iter()
+                .find_map(|locale| translations.get(&(id, locale.to_string())));
+            if let Some(translation) = translation {
+                item.name = translation.name.clone();
+                if translation.description.is_some() {
+                    item.description = translation.description.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_score_for_item(&self, id: ID, query_res: &Vec<(f64, &Document<i64>)>) -> Option<f64> {
+        query_res.iter().find_map(|(x, v)| {
+            if *v.get_id() as i32 == id {
+                Some(x.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_items(
+        &self,
+        name: Name,
+        filter: &MeasurementFilter,
+        ownership: &OwnershipFilter,
+        scope: &SearchScope,
+        options: &SearchOptions,
+    ) -> Result<Vec<Item>> {
+        self.find_items_counted(name, filter, ownership, scope, options)
+            .await
+            .map(|hits| hits.items)
+    }
+
+    /// Like [`Self::find_items`], with the number of matches before the limit of the search
+    pub async fn find_items_counted(
+        &self,
+        name: Name,
+        filter: &MeasurementFilter,
+        ownership: &OwnershipFilter,
+        scope: &SearchScope,
+        options: &SearchOptions,
+    ) -> Result<SearchHits> {
+        if !self.is_searchable(&name) {
+            return Err(CustError::new(
+                "the search query has no words to search for".to_string(),
+                StatusCode::BAD_REQUEST,
+            )
+            .with_code("empty_query"));
+        }
+        let start = Instant::now();
+        let access = self.caller_scope().await?;
+        let source = self.search_source(options.source)?;
+        let query = if options.prefix {
+            self.expand_prefixes(&name).await?
+        } else {
+            name.clone()
+        };
+        // the sources of a search find different items for the same query
+        let key = format!("{:?} {}", source, util::normalize_query(&query));
+
+        // scoped results aren't cached, collection membership changes don't invalidate the cache
+        let candidates = if scope.is_empty() {
+            None
+        } else {
+            Some(self.scope_candidates(scope).await?)
+        };
+        let cached = match candidates {
+            None => self.search_cache.get(&key),
+            Some(_) => None,
+        };
+
+        let result = match cached {
+            Some(items) => Ok(items),
+            None if candidates.is_some() => {
+                self.search_index(&query, candidates.as_ref(), source).await
+            }
+            None => {
+                let generation = self.search_cache.generation();
+                let result = self.search_index(&query, None, source).await;
+                if let Ok(items) = &result {
+                    self.search_cache.insert(key, items.clone(), generation);
+                }
+                result
+            }
+        };
+
+        let (limit, min_score) = {
+            let config = self.config.get();
+            (
+                options.limit.unwrap_or(config.search.result_limit),
+                options.min_score.unwrap_or(config.search.min_score),
+            )
+        };
+        // reservations and recent use don't invalidate the cache, the ranking is applied to every
+        // search
+        let result = match result {
+            Ok(items) => self.apply_ranking(items).await.map(|items| {
+                items
+                    .into_iter()
+                    .filter(|(score, _)| *score >= min_score)
+                    .map(|(_, item)| item)
+                    .filter(|item| filter.matches(item) && ownership.matches(item))
+                    .filter(|item| in_scope(&access, item))
+                    .collect::<Vec<_>>()
+            }),
+            Err(e) => Err(e),
+        };
+        let total = result.as_ref().map(|items| items.len()).unwrap_or(0);
+        let result = result.map(|mut items| {
+            if limit > 0 {
+                items.truncate(limit);
+            }
+            items
+        });
+        // locations and containers move without invalidating the cache
+        let result = match result {
+            Ok(mut items) => self.fill_location_paths(&mut items).await.map(|_| items),
+            Err(e) => Err(e),
+        };
+
+        self.log_search(&name, total, start.elapsed()).await;
+
+        result.map(|items| SearchHits { items, total })
+    }
+
+    /// Whether a query has words the search backend can match. Queries of only whitespace,
+    /// punctuation or, with the index, stop words have none.
+    fn is_searchable(&self, query: &str) -> bool {
+        match self.index {
+            Some(_) => !self.analyzer.analyze(query).trim().is_empty(),
+            None => !util::fts_query(query).is_empty(),
+        }
+    }
+
+    /// Searches for every query of a packing or shopping list at once, a few of them at a time.
+    /// Each query gets its best matches, localized like [`Self::localize_items`].
+    #[instrument(skip_all, fields(queries = batch.queries.len()))]
+    pub async fn find_items_batch(
+        &self,
+        batch: BatchSearch,
+        locales: &[String],
+    ) -> Result<Vec<BatchSearchResult>> {
+        if batch.queries.len() > MAX_BATCH_SEARCH_QUERIES {
+            return Err(CustError::new(
+                format!("a batch search can have at most {} queries", MAX_BATCH_SEARCH_QUERIES),
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ));
+        }
+        if let Some(index) = batch.queries.iter().position(|query| query.trim().is_empty()) {
+            return Err(CustError::new(
+                format!("query {} is empty", index),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        let limit = batch.limit.unwrap_or(DEFAULT_BATCH_SEARCH_MATCHES);
+
+        let filter = MeasurementFilter::default();
+        let ownership = OwnershipFilter::default();
+        let scope = SearchScope::default();
+        let options = SearchOptions::default();
+        // the futures are created up front, a closure over `&String` makes the handler's future
+        // not `Send`
+        let searches: Vec<_> = batch
+            .queries
+            .iter()
+            .map(|query| {
+                self.find_items(query.trim().to_owned(), &filter, &ownership, &scope, &options)
+            })
+            .collect();
+        let found: Vec<Result<Vec<Item>>> = futures::stream::iter(searches)
+            .buffered(BATCH_SEARCH_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut results = Vec::with_capacity(found.len());
+        for (query, items) in batch.queries.into_iter().zip(found) {
+            let mut items = match items {
+                Ok(items) => items,
+                // e.g. an entry of only stop words
+                Err(e) if e.status() == StatusCode::NOT_FOUND || e.code() == "empty_query" => {
+                    vec![]
+                }
+                Err(e) => return Err(e.context(format!("query `{}`", query))),
+            };
+            items.truncate(limit);
+            self.localize_items(&mut items, locales).await?;
+            let matches = items.into_iter().map(|item| Item { fullsize: None, ..item }).collect();
+            results.push(BatchSearchResult { query, matches });
+        }
+
+        Ok(results)
+    }
+
+    /// Sets the [`Item::location_path`] of every item
+    async fn fill_location_paths(&self, items: &mut [Item]) -> Result<()> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let locations: HashMap<ID, Location> = self
+            .get_all_locations()
+            .await?
+            .into_iter()
+            .filter_map(|location| Some((location.id?, location)))
+            .collect();
+        let containers: HashMap<ID, (Name, Option<ID>, Option<ID>)> = sqlx::query(
+            r#"
+            SELECT id, name, location_id, contained_in_item_id FROM items
+            WHERE id IN (SELECT contained_in_item_id FROM items)
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let container = (
+                    row.get("name"),
+                    row.get("location_id"),
+                    row.get("contained_in_item_id"),
+                );
+                (row.get("id"), container)
+            })
+            .collect();
+
+        for item in items {
+            item.location_path = location_path(item, &locations, &containers);
+        }
+        Ok(())
+    }
+
+    /// Adds the words of item names that start with a word of `query` to it, so short searches
+    /// match before a whole word is typed. FTS5 matches every word as a prefix already.
+    async fn expand_prefixes(&self, query: &str) -> Result<String> {
+        if self.index.is_none() {
+            return Ok(query.to_owned());
+        }
+
+        let tokens = util::search_tokens(query);
+        let mut words = tokens.clone();
+        for token in &tokens {
+            let names: Vec<String> =
+                sqlx::query_scalar(
+                    "SELECT name FROM items WHERE instr(lower(name), ?) > 0 AND deleted_at IS NULL",
+                )
+                    .bind(token)
+                    .fetch_all(&self.conn)
+                    .await?;
+            let names = names.iter().map(String::as_str);
+            words.extend(util::prefix_expansions(token, names, MAX_PREFIX_EXPANSIONS));
+        }
+        Ok(words.join(" "))
+    }
+
+    /// Whether the item is in a collection that is shared publicly
+    pub async fn is_shared_publicly(&self, id: ID) -> Result<bool> {
+        Ok(sqlx::query(
+            r#"
+            SELECT 1 FROM collection_items
+            JOIN collections ON collections.id = collection_items.collection_id
+            WHERE collection_items.item_id = ? AND collections.public
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some())
+    }
+
+    /// Ids of the items a scoped search may return: those in the collection and below the
+    /// location of the scope, and in a public collection if the scope asks for it.
+    async fn scope_candidates(&self, scope: &SearchScope) -> Result<HashSet<ID>> {
+        let mut tx = self.conn.begin().await?;
+        check_reference(&mut tx, "collections", "collection", scope.collection).await?;
+        check_reference(&mut tx, "locations", "location", scope.location).await?;
+
+        let ids = sqlx::query(
+            r#"
+            WITH RECURSIVE subtree(id) AS (
+                SELECT ?2
+                UNION
+                SELECT locations.id FROM locations JOIN subtree ON locations.parent_location = subtree.id
+            )
+            SELECT id FROM items
+            WHERE deleted_at IS NULL
+                AND (?1 IS NULL OR id IN (SELECT item_id FROM collection_items WHERE collection_id = ?1))
+                AND (?2 IS NULL OR location_id IN subtree)
+
+                AND (NOT ?3 OR id IN (
+                    SELECT collection_items.item_id FROM collection_items
+                    JOIN collections ON collections.id = collection_items.collection_id
+                    WHERE collections.public
+                ))
+            "#,
+        )
+            .bind(scope.collection)
+            .bind(scope.location)
+            .bind(scope.public_only)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// The search a text search runs, `None` for both merged. Without vector search only the
+    /// text search runs, unless the vector search is asked for.
+    fn search_source(&self, source: Option<SearchSource>) -> Result<Option<SearchSource>> {
+        match source {
+            Some(SearchSource::Vector) => self.require_vectors().map(|_| source),
+            Some(SearchSource::Text) => Ok(source),
+            None if self.require_vectors().is_ok() => Ok(None),
+            None => Ok(Some(SearchSource::Text)),
+        }
+    }
+
+    /// Items whose vector is close to the one of `name`, with their similarity as the score,
+    /// most similar first
+    #[instrument(skip_all)]
+    async fn search_vectors(
+        &self,
+        name: &str,
+        candidates: Option<&HashSet<ID>>,
+    ) -> Result<Vec<(f64, Item)>> {
+        let vectors = self.require_vectors()?;
+        let fusion = self.config.get().search.fusion.clone();
+        let similarities: HashMap<ID, f32> = vectors
+            .search(&self.embedder.embed(name), fusion.vector_candidates)
+            .await?
+            .into_iter()
+            .filter(|(id, similarity)| {
+                *similarity >= fusion.min_similarity && candidates.is_none_or(|c| c.contains(id))
+            })
+            .collect();
+        if similarities.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let query_str = format!(
+            "SELECT * FROM items WHERE id IN ({}) AND deleted_at IS NULL",
+            util::placeholders(similarities.len())
+        );
+        let query = similarities
+            .keys()
+            .fold(sqlx::query_as::<_, DbItem>(&query_str), |query, id| query.bind(*id));
+        let mut items = vec![];
+        for item in query.fetch_all(&self.conn).await? {
+            check_deadline()?;
+            let mut item: Item = item.into();
+            self.hydrate_item(&mut item).await;
+            let similarity = similarities[&item.id.expect("stored items have an id")];
+            items.push((f64::from(similarity), item));
+        }
+        items.sort_by(|x, y| y.0.total_cmp(&x.0));
+
+        Ok(items)
+    }
+
+    async fn search_index(
+        &self,
+        name: &str,
+        candidates: Option<&HashSet<ID>>,
+        source: Option<SearchSource>,
+    ) -> Result<Vec<(f64, Item)>> {
+        let items = match source {
+            Some(SearchSource::Text) => self.search_scored(name, candidates).await?,
+            Some(SearchSource::Vector) => self.search_vectors(name, candidates).await?,
+            None => {
+                let (text, vectors) = tokio::join!(
+                    self.search_scored(name, candidates),
+                    self.search_vectors(name, candidates)
+                );
+                let fusion = self.config.get().search.fusion.clone();
+                fuse_matches(text?, vectors?, &fusion)
+            }
+        };
+        if items.is_empty() {
+            return Err(CustError::new(
+                "no items for search query".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        Ok(items)
+    }
+
+    /// Post-scoring stage of a search: multiplies the text scores with the modifiers of the
+    /// ranking profile and sorts the matches by the result, best first. Matches that score the
+    /// same keep their order.
+    async fn apply_ranking(&self, mut scored: Vec<(f64, Item)>) -> Result<Vec<(f64, Item)>> {
+        if scored.is_empty() {
+            return Ok(scored);
+        }
+        check_deadline()?;
+        let profile = self.config.get().search.ranking.clone();
+
+        let now = util::now();
+        let recent_since = now - i64::from(profile.recent_days) * 24 * 60 * 60;
+        let reserved: HashSet<ID> =
+            sqlx::query("SELECT item_id FROM item_reservations WHERE until > ?")
+                .bind(now)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|row| row.get("item_id"))
+                .collect();
+        let chosen: HashSet<ID> = sqlx::query(
+            r#"
+            SELECT DISTINCT chosen_item_id FROM search_log
+            WHERE chosen_item_id IS NOT NULL AND created_at >= ?
+            "#,
+        )
+            .bind(recent_since)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get("chosen_item_id"))
+            .collect();
+        let favorites: HashSet<ID> = self.favorite_ids("item").await?.into_iter().collect();
+
+        for (score, item) in &mut scored {
+            let Some(id) = item.id else {
+                continue;
+            };
+            let recently_used =
+                chosen.contains(&id) || item.updated_at.is_some_and(|at| at >= recent_since);
+            *score *= ranking_factor(
+                &profile,
+                item,
+                !reserved.contains(&id),
+                recently_used,
+                favorites.contains(&id),
+            );
+        }
+        scored.sort_by(|x, y| y.0.total_cmp(&x.0));
+
+        Ok(scored)
+    }
+
+    /// Matching items with their scores, best match first. With `candidates` only those items
+    /// are read and hydrated.
+    #[instrument(skip_all)]
+    async fn search_scored(
+        &self,
+        name: &str,
+        candidates: Option<&HashSet<ID>>,
+    ) -> Result<Vec<(f64, Item)>> {
+        debug!("Searching for: {:?}", name);
+        let Some(index) = &self.index else {
+            return self.search_fts(name, candidates).await;
+        };
+        let query = self.analyzer.analyze(name);
+        if query.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        let index = until_deadline(index.read()).await?;
+        let mut result = index
+            .query(
+                &query,
+                &self.tokenizer,
+                &self.filter,
+                Some(QueryOption::new().add(OptionType::TfIdf).build()),
+            )
+            .instrument(info_span!("index query"))
+            .await?.collect();
+
+        {
+            let tombstones = self.tombstones.lock().unwrap();
+            result.retain(|(_, doc)| {
+                let id = *doc.get_id() as ID;
+                !tombstones.contains(&id) && candidates.is_none_or(|c| c.contains(&id))
+            });
+        }
+
+        if result.is_empty() {
+            return Ok(vec![]);
+        }
+        debug!("Search result: {:?}", result);
+
+        result.sort_by(|(x, _), (y, _)| x.total_cmp(y));
+
+        let ids: Vec<Arc<i64>> = result.iter().map(|(_x, v)| v.get_id()).collect();
+        let query_str = format!(
+            "SELECT * FROM items WHERE id IN ({}) AND deleted_at IS NULL",
+            util::placeholders(ids.len())
+        );
+
+        let query = sqlx::query_as::<_, DbItem>(&query_str);
+        let query = ids
+            .into_iter()
+            .fold(query, |query, id| query.bind(id.deref().clone()));
+
+        let items = query.fetch_all(&self.conn).await?;
+        let mut items: Vec<(f64, Item)> = items
+            .into_iter()
+            .filter_map(|item| {
+                self.find_score_for_item(item.id.unwrap(), &result)
+                    .map(|score| (score, item.into()))
+            })
+            .collect();
+
+        for (_, item) in &mut items {
+            check_deadline()?;
+            self.hydrate_item(item).await;
+        }
+
+        items.sort_by(|x, y| y.0.total_cmp(&x.0));
+
+        Ok(items)
+    }
+
+    #[instrument(skip_all)]
+    async fn search_fts(
+        &self,
+        name: &str,
+        candidates: Option<&HashSet<ID>>,
+    ) -> Result<Vec<(f64, Item)>> {
+        let query = util::fts_query(name);
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // rank is the bm25 score, which is lower for better matches
+        let rows = sqlx::query(
+            "SELECT items.*, -items_fts.rank AS score FROM items_fts JOIN items ON items.id = items_fts.rowid WHERE items_fts MATCH ? AND items.deleted_at IS NULL ORDER BY items_fts.rank",
+
+        )
+            .bind(query)
+            .fetch_all(&self.conn)
+            .await?;
+
+        let mut items = vec![];
+        for row in rows {
+            let id: ID = row.get("id");
+            if candidates.is_some_and(|c| !c.contains(&id)) {
+                continue;
+            }
+            check_deadline()?;
+            let mut item: Item = DbItem::from_row(&row)?.into();
+            self.hydrate_item(&mut item).await;
+            items.push((row.get("score"), item));
+        }
+
+        Ok(items)
+    }
+
+    /// Runs a search like [`BusinessRules::find_items`], bypassing the cache, and reports how
+    /// the query was tokenized, how close each token came to the words of the matched items and
+    /// what each match scored.
+    pub async fn explain_search(
+        &self,
+        name: Name,
+        filter: &MeasurementFilter,
+        ownership: &OwnershipFilter,
+        options: &SearchOptions,
+    ) -> Result<SearchExplanation> {
+        require_unscoped()?;
+        let start = Instant::now();
+        let name = if options.prefix {
+            self.expand_prefixes(&name).await?
+        } else {
+            name
+        };
+        let scored = self.search_scored(&name, None).await?;
+        let scored = self.apply_ranking(scored).await?;
+        let search_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let tokens = util::search_tokens(&name);
+        let mut explained_tokens: Vec<TokenExplanation> = tokens
+            .iter()
+            .map(|token| TokenExplanation {
+                token: token.clone(),
+                candidates: vec![],
+            })
+            .collect();
+        let mut results = vec![];
+        let mut filtered_out = 0;
+
+        for (score, item) in scored {
+            if !filter.matches(&item) || !ownership.matches(&item) {
+                filtered_out += 1;
+                continue;
+            }
+            let Some(item_id) = item.id else {
+                continue;
+            };
+
+            let words = util::search_tokens(&self.document_text(&item).await?);
+            let mut matches = vec![];
+            for explained in &mut explained_tokens {
+                let closest = words
+                    .iter()
+                    .map(|word| (util::levenshtein(&explained.token, word), word))
+                    .min_by_key(|(distance, _)| *distance);
+                if let Some((distance, word)) = closest {
+                    explained.candidates.push(TokenCandidate {
+                        item_id,
+                        word: word.clone(),
+                        distance,
+                    });
+                    matches.push(TokenMatch {
+                        token: explained.token.clone(),
+                        word: word.clone(),
+                        distance,
+                    });
+                }
+            }
+
+            results.push(ResultExplanation {
+                rank: results.len() + 1,
+                item_id,
+                name: item.name,
+                score,
+                matches,
+            });
+        }
+
+        for explained in &mut explained_tokens {
+            explained.candidates.sort_by_key(|candidate| candidate.distance);
+        }
+
+        let total_ms = start.elapsed().as_secs_f64() * 1000.0;
+        Ok(SearchExplanation {
+            query: name,
+            backend: if self.index.is_some() {
+                SearchBackend::Index
+            } else {
+                SearchBackend::Fts5
+            },
+            tokens: explained_tokens,
+            results,
+            filtered_out,
+            timings: SearchTimings {
+                search_ms,
+                analysis_ms: total_ms - search_ms,
+                total_ms,
+            },
+        })
+    }
+
+    /// When the listing of `entity` (`item`, `category` or `collection`) last changed, from the
+    /// audit log and for items also from their `updated_at`. `None` if no change was recorded.
+    pub async fn last_modified(&self, entity: &str) -> Result<Option<i64>> {
+        let last_modified = sqlx::query_scalar::<_, Option<i64>>(
+            r#"
+            SELECT MAX(modified_at) FROM (
+                SELECT MAX(created_at) AS modified_at FROM audit_log WHERE entity = ?
+                UNION ALL
+                SELECT MAX(updated_at) FROM items WHERE ? = 'item'
+            )
+            "#,
+        )
+            .bind(entity)
+            .bind(entity)
+            .fetch_one(&self.conn)
+            .await?;
+        Ok(last_modified)
+    }
+
+    pub async fn get_all_items(
+        &self,
+        ownership: &OwnershipFilter,
+        sort: &ItemSort,
+    ) -> Result<Vec<Item>> {
+        self.get_all_items_masked(ownership, sort, &ItemFieldMask::all()).await
+    }
+
+    /// Like [`Self::get_all_items`], without reading image files unless the mask asks for images
+    #[instrument(skip_all)]
+    pub async fn get_all_items_masked(
+        &self,
+        ownership: &OwnershipFilter,
+        sort: &ItemSort,
+        mask: &ItemFieldMask,
+    ) -> Result<Vec<Item>> {
+        let query = self.all_items_query(sort);
+        let mut items: Vec<Item> =
+            sqlx::query_as::<_, DbItem>(&query)
+                .bind(ownership.state)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        let access = self.caller_scope().await?;
+        items.retain(|item| in_scope(&access, item));
+
+        for item in &mut items {
+            check_deadline()?;
+            self.hydrate_item_masked(item, mask).await;
+        }
+
+        Ok(items)
+    }
+
+    /// Fills an empty database with the demo dataset of [`demo::demo_items`], e.g. to try out
+    /// the ui or to benchmark. Databases that already contain items are left alone.
+    pub async fn seed_demo(&self, items: usize) -> Result<DemoSummary> {
+        require_unscoped()?;
+        if items > demo::MAX_DEMO_ITEMS {
+            return Err(CustError::new(
+                format!("at most {} demo items can be seeded", demo::MAX_DEMO_ITEMS),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let existing: i64 = sqlx::query("SELECT COUNT(*) AS count FROM items")
+            .fetch_one(&self.conn)
+            .await?
+            .get("count");
+        if existing > 0 {
+            return Err(CustError::new(
+                "demo data can only be seeded into an empty database".to_string(),
+                StatusCode::CONFLICT,
+            ));
+        }
+
+        let demo_items = tokio::task::spawn_blocking(move || demo::demo_items(items))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        let mut category_ids = vec![];
+        for (name, _) in demo::DEMO_CATEGORIES {
+            let category = self
+                .new_category(Category {
+                    id: None,
+                    uuid: None,
+                    name: name.to_owned(),
+                    parent_category: None,
+                    thumbnail: None,
+                    unique_item_names: false,
+                    item_count: 0,
+                    color: None,
+                    icon: None,
+                })
+                .await?;
+            category_ids.push(category.id);
+        }
+
+        let mut location_ids = vec![];
+        for name in demo::DEMO_LOCATIONS {
+            let location = self
+                .new_location(Location {
+                    id: None,
+                    uuid: None,
+                    name: name.to_owned(),
+                    parent_location: None,
+                })
+                .await?;
+            location_ids.push(location.id);
+        }
+
+        for item in demo_items {
+            self.add_item(Item {
+                name: item.name,
+                description: Some(item.description),
+                category_id: category_ids[item.category],
+                price: Some(item.price),
+                thumbnail: Some(base64::engine::general_purpose::STANDARD.encode(item.thumbnail)),
+                tags: item.tags,
+                location_id: location_ids[item.location],
+                quantity: Some(item.quantity),
+                ..Default::default()
+            })
+                .await?;
+        }
+
+        Ok(DemoSummary {
+            categories: category_ids.len(),
+            locations: location_ids.len(),
+            items,
+        })
+    }
+
+    /// Like [`BusinessRules::get_all_items`], but streams the items as they are read and
+    /// hydrated, one JSON document per line.
+    pub fn stream_all_items(
+        self: &Arc<Self>,
+        ownership: OwnershipFilter,
+        sort: ItemSort,
+    ) -> impl Stream<Item = Result<String>> {
+        let rules = Arc::clone(self);
+        let caller = current_caller();
+        let (sender, receiver) = mpsc::channel::<Result<String>>(64);
+
+        tokio::spawn(async move {
+            let access = match AccessScope::of(&rules.conn, caller.as_ref()).await {
+                Ok(access) => access,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+            let query = rules.all_items_query(&sort);
+            let mut rows = sqlx::query_as::<_, DbItem>(&query)
+                .bind(ownership.state)
+                .fetch(&rules.conn);
+
+            while let Some(row) = rows.next().await {
+                let line = match row {
+                    Ok(row) => {
+                        let mut item: Item = row.into();
+                        if !in_scope(&access, &item) {
+                            continue;
+                        }
+                        rules.hydrate_item(&mut item).await;
+                        serde_json::to_string(&item)
+                            .map(|json| json + "\n")
+                            .map_err(|e| anyhow::Error::from(e).into())
+                    }
+                    Err(e) => Err(e.into()),
+                };
+                let failed = line.is_err();
+                if sender.send(line).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|line| (line, receiver))
+        })
+    }
+
+    fn all_items_query(&self, sort: &ItemSort) -> String {
+        format!(
+            "SELECT * FROM items WHERE deleted_at IS NULL AND ownership_state = COALESCE(?1, ownership_state) AND (?1 IS NOT NULL OR ownership_state != 'disposed') ORDER BY {}",
+
+            sort.order_by(&self.config.get().listing)
+        )
+    }
+
+    /// Moves an item to another ownership state, e.g. a wishlist item that arrived to owned.
+    /// Disposing is done by [`BusinessRules::dispose_item`], disposed items keep their state.
+    pub async fn set_ownership_state(&self, id: ID, state: OwnershipState) -> Result<Item> {
+        self.authorize_item(id).await?;
+        let current: OwnershipState = sqlx::query(
+            "SELECT ownership_state FROM items WHERE id = ? AND deleted_at IS NULL",
+        )
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .map(|row| row.get("ownership_state"))
+            .ok_or_else(|| CustError::new("item not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        if current == OwnershipState::Disposed || state == OwnershipState::Disposed {
+            return Err(CustError::new(
+                "items are disposed with a disposal record and keep that state".to_string(),
+                StatusCode::CONFLICT,
+            ));
+        }
+
+        if current != state {
+            sqlx::query("UPDATE items SET ownership_state = ?1, state_changed_at = ?2, updated_at = ?2 WHERE id = ?3")
+                .bind(state)
+                .bind(util::now())
+                .bind(id)
+                .execute(&self.conn)
+                .await?;
+
+            self.embed_later(id);
+            self.items_changed();
+            self.stats_cache.invalidate();
+        }
+
+        let item = self.get_item(id).await?;
+        if current != state {
+            self.publish(EventKind::ItemStateChanged, id, &DbItem::from(item.clone()))
+                .await;
+        }
+
+        Ok(item)
+    }
+
+    /// Marks an item as sold or thrown away. The item and a record of the disposal are kept.
+    pub async fn dispose_item(&self, id: ID, disposal: NewDisposal) -> Result<Disposal> {
+        self.authorize_item(id).await?;
+        let reason = disposal.reason.trim().to_owned();
+        if reason.is_empty() {
+            return Err(CustError::new(
+                "a reason is required".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        let date = disposal.date.unwrap_or_else(|| util::iso_date(util::now()));
+        if !util::is_iso_date(&date) {
+            return Err(CustError::new(
+                "date must be formatted as YYYY-MM-DD".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        if matches!(disposal.sale_price, Some(price) if !price.is_finite() || price < 0.0) {
+            return Err(CustError::new(
+                "sale_price must be a non negative number".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let mut tx = self.conn.begin().await?;
+
+        let current: OwnershipState = sqlx::query(
+            "SELECT ownership_state FROM items WHERE id = ? AND deleted_at IS NULL",
+        )
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(|row| row.get("ownership_state"))
+            .ok_or_else(|| CustError::new("item not found".to_string(), StatusCode::NOT_FOUND))?;
+        if current == OwnershipState::Disposed {
+            return Err(CustError::new(
+                "item is already disposed".to_string(),
+                StatusCode::CONFLICT,
+            ));
+        }
+
+        let now = util::now();
+        sqlx::query("UPDATE items SET ownership_state = ?1, state_changed_at = ?2, updated_at = ?2 WHERE id = ?3")
+            .bind(OwnershipState::Disposed)
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let disposal = sqlx::query_as::<_, Disposal>(
+            "INSERT INTO item_disposals (item_id, reason, date, sale_price, created_at) VALUES (?, ?, ?, ?, ?) RETURNING *",
+        )
+            .bind(id)
+            .bind(reason)
+            .bind(date)
+            .bind(disposal.sale_price)
+            .bind(now)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        self.embed_later(id);
+        self.items_changed();
+        self.stats_cache.invalidate();
+        self.publish(EventKind::ItemDisposed, id, &disposal).await;
+
+        Ok(disposal)
+    }
+
+    pub async fn get_disposal(&self, id: ID) -> Result<Disposal> {
+        self.authorize_item(id).await?;
+        sqlx::query_as::<_, Disposal>("SELECT * FROM item_disposals WHERE item_id = ?")
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("item is not disposed".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Looks up the market value of an item with the configured [`PriceProvider`], by barcode if
+    /// one is given and by name. The estimate is recorded, and becomes the current value of the
+    /// item unless it is in another currency.
+    pub async fn estimate_item_value(&self, id: ID, query: EstimateQuery) -> Result<ValueEstimate> {
+        self.authorize_item(id).await?;
+        let item: Item =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_optional(&self.conn)
+                .await?
+                .ok_or_else(|| CustError::new("item not found".to_string(), StatusCode::NOT_FOUND))?
+                .into();
+
+        let lookup = PriceQuery {
+            name: item.name.clone(),
+            barcode: query
+                .barcode
+                .map(|barcode| barcode.trim().to_owned())
+                .filter(|barcode| !barcode.is_empty()),
+            currency: item.currency.clone(),
+        };
+        let quote = self.price_provider.lookup(&lookup).await?.ok_or_else(|| {
+            CustError::new(format!("no price found for {}", item.name), StatusCode::NOT_FOUND)
+        })?;
+
+        let currency = quote.currency.trim().to_uppercase();
+        let valid = quote.value.is_finite()
+            && quote.value >= 0.0
+            && currency.len() == 3
+            && currency.chars().all(|c| c.is_ascii_alphabetic());
+        if !valid {
+            return Err(CustError::new(
+                format!(
+                    "{} answered with an invalid price: {} {}",
+                    self.price_provider.name(),
+                    quote.value,
+                    quote.currency
+                ),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        let applied = item.currency.as_ref().is_none_or(|c| *c == currency);
+
+        let now = util::now();
+        let mut tx = self.conn.begin().await?;
+        let estimate = sqlx::query_as::<_, ValueEstimate>(
+            r#"
+            INSERT INTO item_value_estimates (item_id, value, currency, source, estimated_at, applied)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+            .bind(id)
+            .bind(quote.value)
+            .bind(&currency)
+            .bind(self.price_provider.name())
+            .bind(now)
+            .bind(applied)
+            .fetch_one(&mut *tx)
+            .await?;
+        if applied {
+            sqlx::query(
+                "UPDATE items SET current_value = ?, currency = ?, updated_at = ? WHERE id = ?",
+            )
+                .bind(quote.value)
+                .bind(&currency)
+                .bind(now)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        metrics::increment("price_estimates_total");
+
+        if applied {
+            // the valuation stats and cached search results hold the old value
+            self.stats_cache.invalidate();
+            self.items_changed();
+            let item = self.get_item(id).await?;
+            self.publish(EventKind::ItemUpdated, id, &DbItem::from(item)).await;
+        }
+        Ok(estimate)
+    }
+
+    /// Who a reservation is made or released by: the caller, or while auth is disabled whoever
+    /// the request names.
+    fn reservation_holder(&self, named: Option<String>) -> String {
+        let named = named
+            .map(|name| name.trim().to_owned())
+            .filter(|name| !name.is_empty());
+        match (self.authenticator.enabled(), current_caller()) {
+            (true, Some(caller)) => caller.name,
+            _ => named.unwrap_or_else(|| "anonymous".to_owned()),
+        }
+    }
+
+    /// Reserves an item until a point in time. The holder of an active reservation can extend it,
+    /// anyone else gets a conflict until it ends.
+    pub async fn reserve_item(&self, id: ID, reservation: NewReservation) -> Result<Reservation> {
+        self.authorize_item(id).await?;
+        let now = util::now();
+        if reservation.until <= now {
+            return Err(CustError::new(
+                "until must be in the future".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        let holder = self.reservation_holder(reservation.reserved_by);
+
+        let mut tx = self.conn.begin().await?;
+        let exists = sqlx::query("SELECT id FROM items WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+        }
+
+        // only expired reservations and those of the same holder are replaced, checked in the
+        // upsert itself so concurrent reservations can't both succeed
+        let reserved = sqlx::query_as::<_, Reservation>(
+            r#"
+            INSERT INTO item_reservations (item_id, reserved_by, until, note, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (item_id) DO UPDATE SET
+                reserved_by = excluded.reserved_by,
+                until = excluded.until,
+                note = excluded.note,
+                created_at = excluded.created_at
+            WHERE item_reservations.until <= ?5 OR item_reservations.reserved_by = excluded.reserved_by
+            RETURNING *
+            "#,
+        )
+            .bind(id)
+            .bind(holder)
+            .bind(reservation.until)
+            .bind(reservation.note)
+            .bind(now)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let Some(reservation) = reserved else {
+            let current = sqlx::query_as::<_, Reservation>(
+                "SELECT * FROM item_reservations WHERE item_id = ?",
+            )
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await?;
+            return Err(reserved_by_someone_else(&current));
+        };
+
+        tx.commit().await?;
+
+        self.publish(EventKind::ItemReserved, id, &reservation).await;
+
+        Ok(reservation)
+    }
+
+    /// The active reservation of an item
+    pub async fn get_reservation(&self, id: ID) -> Result<Reservation> {
+        self.authorize_item(id).await?;
+        sqlx::query_as::<_, Reservation>(
+            "SELECT * FROM item_reservations WHERE item_id = ? AND until > ?",
+        )
+            .bind(id)
+            .bind(util::now())
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("item is not reserved".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Ends the active reservation of an item early. With auth enabled only its holder can.
+    pub async fn release_reservation(&self, id: ID) -> Result<Reservation> {
+        let reservation = self.get_reservation(id).await?;
+        if self.authenticator.enabled() && reservation.reserved_by != self.reservation_holder(None) {
+            return Err(reserved_by_someone_else(&reservation));
+        }
+
+        sqlx::query("DELETE FROM item_reservations WHERE item_id = ?")
+            .bind(id)
+            .execute(&self.conn)
+            .await?;
+
+        self.publish(EventKind::ItemReservationReleased, id, &reservation)
+            .await;
+
+        Ok(reservation)
+    }
+
+    /// Starts a stock-take. The owned items in its scope are expected from now on, items moved
+    /// into or out of it later don't change what the report compares against.
+    pub async fn start_stocktake(&self, new: NewStocktake) -> Result<Stocktake> {
+        require_unscoped()?;
+        let started_by = current_caller()
+            .map(|caller| caller.name)
+            .unwrap_or_else(|| "anonymous".to_owned());
+
+        let mut tx = self.conn.begin().await?;
+        check_reference(&mut tx, "locations", "location", new.location_id).await?;
+        check_reference(&mut tx, "collections", "collection", new.collection_id).await?;
+
+        let id: ID = sqlx::query(
+            r#"
+            INSERT INTO stocktakes (location_id, collection_id, started_by, started_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+            .bind(new.location_id)
+            .bind(new.collection_id)
+            .bind(started_by)
+            .bind(util::now())
+            .fetch_one(&mut *tx)
+            .await?
+            .get("id");
+
+        sqlx::query(
+            r#"
+            WITH RECURSIVE subtree(id) AS (
+                SELECT ?2
+                UNION
+                SELECT locations.id FROM locations JOIN subtree ON locations.parent_location = subtree.id
+            )
+            INSERT INTO stocktake_items (stocktake_id, item_id, expected)
+            SELECT ?1, id, 1 FROM items
+            WHERE ownership_state = 'owned'
+                AND deleted_at IS NULL
+                AND (?2 IS NULL OR location_id IN subtree)
+                AND (?3 IS NULL OR id IN (SELECT item_id FROM collection_items WHERE collection_id = ?3))
+            "#,
+        )
+            .bind(id)
+            .bind(new.location_id)
+            .bind(new.collection_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        self.get_stocktake(id).await
+    }
+
+    /// All stock-takes, the latest first
+    pub async fn get_stocktakes(&self) -> Result<Vec<Stocktake>> {
+        require_unscoped()?;
+        let stocktakes = sqlx::query_as::<_, Stocktake>(&format!(
+            "{} ORDER BY stocktakes.started_at DESC, stocktakes.id DESC",
+            STOCKTAKE_SELECT
+        ))
+            .fetch_all(&self.conn)
+            .await?;
+        Ok(stocktakes)
+    }
+
+    pub async fn get_stocktake(&self, id: ID) -> Result<Stocktake> {
+        require_unscoped()?;
+        sqlx::query_as::<_, Stocktake>(&format!("{} WHERE stocktakes.id = ?", STOCKTAKE_SELECT))
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| stocktake_not_found(id))
+    }
+
+    /// Confirms that an item is present. Items that weren't expected are confirmed as well and
+    /// listed as unexpected in the report.
+    pub async fn confirm_stocktake_item(
+        &self,
+        id: ID,
+        scan: StocktakeScan,
+    ) -> Result<StocktakeConfirmation> {
+        require_unscoped()?;
+        let item_id = self.scanned_item_id(scan).await?;
+
+        let mut tx = self.conn.begin().await?;
+        let ended_at: Option<i64> = sqlx::query("SELECT ended_at FROM stocktakes WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| stocktake_not_found(id))?
+            .get("ended_at");
+        if ended_at.is_some() {
+            return Err(stocktake_ended(id));
+        }
+        check_reference(&mut tx, "items", "item", Some(item_id)).await?;
+
+        let previous = sqlx::query(
+            "SELECT expected, confirmed_at FROM stocktake_items WHERE stocktake_id = ? AND item_id = ?",
+        )
+            .bind(id)
+            .bind(item_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let expected = previous.as_ref().is_some_and(|row| row.get("expected"));
+        let already_confirmed = previous
+            .as_ref()
+            .is_some_and(|row| row.get::<Option<i64>, _>("confirmed_at").is_some());
+
+        if !already_confirmed {
+            sqlx::query(
+                r#"
+                INSERT INTO stocktake_items (stocktake_id, item_id, expected, confirmed_at)
+                VALUES (?, ?, 0, ?)
+                ON CONFLICT (stocktake_id, item_id) DO UPDATE SET confirmed_at = excluded.confirmed_at
+                "#,
+            )
+                .bind(id)
+                .bind(item_id)
+                .bind(util::now())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(StocktakeConfirmation {
+            item: self.get_item(item_id).await?,
+            expected,
+            already_confirmed,
+        })
+    }
+
+    /// The item a stock-take scan refers to. Barcode scanners may pass on the `*` start and stop
+    /// characters of code 39, they are ignored.
+    async fn scanned_item_id(&self, scan: StocktakeScan) -> Result<ID> {
+        if let Some(item_id) = scan.item_id {
+            return Ok(item_id);
+        }
+        let Some(code) = scan.code else {
+            return Err(CustError::new(
+                "either item_id or code is required".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        };
+
+        let code = code.trim().trim_matches('*');
+        if is_uuid(code) {
+            return self.item_id_by_uuid(code).await;
+        }
+        code.parse().map_err(|_| {
+            CustError::new(
+                format!("code {} is neither an item id nor a uuid", code),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        })
+    }
+
+    /// Ends a stock-take, no more items can be confirmed afterwards
+    pub async fn end_stocktake(&self, id: ID) -> Result<Stocktake> {
+        require_unscoped()?;
+        let ended = sqlx::query("UPDATE stocktakes SET ended_at = ? WHERE id = ? AND ended_at IS NULL")
+            .bind(util::now())
+            .bind(id)
+            .execute(&self.conn)
+            .await?
+            .rows_affected();
+
+        let stocktake = self.get_stocktake(id).await?;
+        if ended == 0 {
+            return Err(stocktake_ended(id));
+        }
+        Ok(stocktake)
+    }
+
+    /// Items expected but not confirmed and confirmed but not expected, ordered by location so
+    /// the missing ones can be looked for place by place
+    pub async fn stocktake_report(&self, id: ID) -> Result<StocktakeReport> {
+        require_unscoped()?;
+        let stocktake = self.get_stocktake(id).await?;
+
+        let mut items: Vec<(bool, Item)> = sqlx::query(
+            r#"
+            SELECT stocktake_items.expected, items.* FROM stocktake_items
+            JOIN items ON items.id = stocktake_items.item_id
+            WHERE stocktake_items.stocktake_id = ?
+                AND stocktake_items.expected = (stocktake_items.confirmed_at IS NULL)
+                AND items.deleted_at IS NULL
+            ORDER BY items.location_id, items.id
+
+            "#,
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| Ok((row.try_get("expected")?, DbItem::from_row(&row)?.into())))
+            .collect::<std::result::Result<_, sqlx::Error>>()?;
+
+        for (_, item) in &mut items {
+            self.hydrate_item(item).await;
+        }
+        let (missing, unexpected): (Vec<_>, Vec<_>) =
+            items.into_iter().partition(|(expected, _)| *expected);
+
+        Ok(StocktakeReport {
+            stocktake,
+            missing: missing.into_iter().map(|(_, item)| item).collect(),
+            unexpected: unexpected.into_iter().map(|(_, item)| item).collect(),
+        })
+    }
+
+    #[instrument(skip_all, fields(id = id))]
+    pub async fn delete_item(&self, id: ID) -> Result<Item> {
+        self.authorize_item(id).await?;
+        let item = self.remove_item(id).await?;
+
+        self.publish(EventKind::ItemDeleted, id, &DbItem::from(item.clone()))
+            .await;
+
+        // TODO: delete all connection before
+        Ok(item)
+    }
+
+    /// Deletes an item with its tags, images, disposal and reservation, without publishing it
+    async fn remove_item(&self, id: ID) -> Result<Item> {
+        let mut tx = self.conn.begin().await?;
+        let (item, gallery) = self
+            .delete_item_rows(&mut tx, id, &mut ChangeReport::default())
+            .await?;
+        tx.commit().await?;
+        self.item_removed(id, &gallery).await;
+
+        Ok(item)
+    }
+
+    /// Deletes the rows of an item, returning it and its gallery images, whose files are removed
+    /// by [`Self::item_removed`] once the transaction is committed. The rows are counted in
+    /// `changes`.
+    async fn delete_item_rows(
+        &self,
+        tx: &mut Transaction<'_, Sqlite>,
+        id: ID,
+        changes: &mut ChangeReport,
+    ) -> Result<(Item, Vec<ItemImage>)> {
+        let item: Item = sqlx::query_as::<_, DbItem>("SELECT * from items WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await?
+            .into();
+
+        for table in [
+            "item_tags",
+            "collection_items",
+            "item_translations",
+            "item_notes",
+            "item_value_estimates",
+            "item_disposals",
+            "item_reservations",
+            "item_access",
+            "item_image_hashes",
+            "stocktake_items",
+        ] {
+            let deleted = sqlx::query(&format!("DELETE FROM {} WHERE item_id = ?", table))
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+            changes.record_deleted(table, deleted.rows_affected());
+        }
+
+        // the contents of a deleted container are taken out of it
+        let updated =
+            sqlx::query("UPDATE items SET contained_in_item_id = NULL WHERE contained_in_item_id = ?")
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+        changes.record_updated("items", updated.rows_affected());
+
+        let deleted = sqlx::query("DELETE FROM favorites WHERE entity = 'item' AND entity_id = ?")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        changes.record_deleted("favorites", deleted.rows_affected());
+
+        let gallery = sqlx::query_as::<_, ItemImage>("DELETE FROM item_images WHERE item_id = ? RETURNING *")
+            .bind(id)
+            .fetch_all(&mut **tx)
+            .await?;
+        changes.record_deleted("item_images", gallery.len() as u64);
+
+        let deleted = sqlx::query("DELETE FROM items WHERE id = ?")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        changes.record_deleted("items", deleted.rows_affected());
+
+        // the index document is only tombstoned here, so the index lock isn't held during the
+        // transaction. It is removed by a background job. FTS5 rows are deleted by a trigger.
+        if self.index.is_some() {
+            sqlx::query("INSERT OR IGNORE INTO index_tombstones (item_id) VALUES (?)")
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        Ok((item, gallery))
+    }
+
+    async fn item_removed(&self, id: ID, gallery: &[ItemImage]) {
+        if self.index.is_some() {
+            self.tombstones.lock().unwrap().insert(id);
+            self.jobs.push(Job::CompactTombstones);
+        }
+        self.embed_later(id);
+        self.items_changed();
+        self.stats_cache.invalidate();
+
+        for image in gallery {
+            if let Err(e) = self.item_image_files.delete(image).await {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Moves the items listed in the request or matching its filter to the trash in one
+    /// transaction, only admins may do this. Items in the trash keep their rows and images, but
+    /// reads skip them; [`Self::restore_item`] takes them out again and [`Self::purge_trash`]
+    /// removes them for good. Index documents are removed right away, under a single lock of the
+    /// index. A dry run rolls the transaction back and only reports the rows it changed.
+    pub async fn bulk_delete_items(&self, request: BulkDelete) -> Result<BulkDeleteResult> {
+        require_unscoped()?;
+        if let Some(caller) = current_caller() {
+            caller.require(Role::Admin)?;
+        }
+
+        let ids = self.bulk_delete_selection(&request).await?;
+        if ids.is_empty() {
+            return Ok(BulkDeleteResult {
+                matched: 0,
+                deleted: 0,
+                dry_run: request.dry_run,
+                item_ids: ids,
+                changes: ChangeReport::default(),
+            });
+        }
+
+        let now = util::now();
+        let mut deleted = Vec::with_capacity(ids.len());
+        let mut changes = ChangeReport::default();
+        let mut tx = self.conn.begin().await?;
+        for &id in &ids {
+            let item: Item = sqlx::query_as::<_, DbItem>(
+                "UPDATE items SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2 RETURNING *",
+            )
+                .bind(now)
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await?
+                .into();
+            changes.record_updated("items", 1);
+
+            // the contents of a deleted container are taken out of it, and stay out on restore
+            let updated = sqlx::query(
+                "UPDATE items SET contained_in_item_id = NULL WHERE contained_in_item_id = ?",
+            )
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            changes.record_updated("items", updated.rows_affected());
+
+            if self.index.is_some() {
+                sqlx::query("INSERT OR IGNORE INTO index_tombstones (item_id) VALUES (?)")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            deleted.push(item);
+        }
+        if self.index.is_some() {
+            changes.index_removals = ids.len();
+        }
+
+        // a dry run does everything but keeping it
+        if request.dry_run {
+            tx.rollback().await?;
+            return Ok(BulkDeleteResult {
+                matched: ids.len(),
+                deleted: 0,
+                dry_run: true,
+                item_ids: ids,
+                changes,
+            });
+        }
+        tx.commit().await?;
+
+        if let Some(index) = &self.index {
+            let mut removed = Vec::with_capacity(ids.len());
+            {
+                let mut index = index.write().await;
+                self.index_writes.fetch_add(1, Ordering::Relaxed);
+                for &id in &ids {
+                    match index.remove_document(Arc::new(id as i64)).await {
+                        Ok(_) => removed.push(id),
+                        Err(e) => {
+                            error!("Could not remove the index document of item {}: {}", id, e);
+                            // its tombstone stays, compacting the index tries again
+                            self.tombstones.lock().unwrap().insert(id);
+                        }
+                    }
+                }
+            }
+            if removed.len() < ids.len() {
+                self.jobs.push(Job::CompactTombstones);
+            }
+            self.drop_tombstones(&removed).await;
+        }
+        for &id in &ids {
+            self.embed_later(id);
+        }
+        self.items_changed();
+        self.stats_cache.invalidate();
+
+        for item in deleted {
+            let id = item.id.expect("stored items have an id");
+            self.publish(EventKind::ItemDeleted, id, &DbItem::from(item)).await;
+        }
+
+        Ok(BulkDeleteResult {
+            matched: ids.len(),
+            deleted: ids.len(),
+            dry_run: false,
+            item_ids: ids,
+            changes,
+        })
+    }
+
+    /// Takes an item out of the trash, with everything it had when it was deleted except for
+    /// the items that were inside of it. Only admins may do this.
+    pub async fn restore_item(&self, id: ID) -> Result<Item> {
+        require_unscoped()?;
+        if let Some(caller) = current_caller() {
+            caller.require(Role::Admin)?;
+        }
+
+        let restored = sqlx::query(
+            "UPDATE items SET deleted_at = NULL, updated_at = ? WHERE id = ? AND deleted_at IS NOT NULL",
+        )
+            .bind(util::now())
+            .bind(id)
+            .execute(&self.conn)
+            .await?;
+        if restored.rows_affected() == 0 {
+            return Err(CustError::new(
+                "item is not in the trash".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        self.items_changed();
+        self.stats_cache.invalidate();
+        let item = self.get_item(id).await?;
+        self.reindex_item(&item, false).await?;
+        self.publish(EventKind::ItemRestored, id, &DbItem::from(item.clone()))
+            .await;
+        Ok(item)
+    }
+
+    /// Ids of the existing items a bulk delete applies to
+    async fn bulk_delete_selection(&self, request: &BulkDelete) -> Result<Vec<ID>> {
+        let rows = match (&request.filter, request.ids.is_empty()) {
+            (None, false) => {
+                let query_str = format!(
+                    "SELECT id FROM items WHERE id IN ({}) AND deleted_at IS NULL ORDER BY id",
+                    util::placeholders(request.ids.len())
+                );
+                let query = request
+                    .ids
+                    .iter()
+                    .fold(sqlx::query(&query_str), |query, id| query.bind(*id));
+                query.fetch_all(&self.conn).await?
+            }
+            (Some(filter), true) if !filter.is_empty() => {
+                sqlx::query(
+                    r#"
+                    SELECT id FROM items
+                    WHERE deleted_at IS NULL
+                        AND (?1 IS NULL OR category_id = ?1)
+                        AND (?2 IS NULL OR id IN (SELECT item_id FROM collection_items WHERE collection_id = ?2))
+                        AND (?3 IS NULL OR ownership_state = ?3)
+                    ORDER BY id
+                    "#,
+                )
+                    .bind(filter.category_id)
+                    .bind(filter.collection_id)
+                    .bind(filter.state)
+                    .fetch_all(&self.conn)
+                    .await?
+            }
+            _ => {
+                return Err(CustError::new(
+                    "a bulk delete needs either ids or a non-empty filter".to_string(),
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
+        };
+
+        Ok(rows.into_iter().map(|row| row.get("id")).collect())
+    }
+
+    /// Deletes the tombstones of items whose index documents were removed right away
+    async fn drop_tombstones(&self, ids: &[ID]) {
+        for chunk in ids.chunks(TERMS_PER_STATEMENT) {
+            let sql = format!(
+                "DELETE FROM index_tombstones WHERE item_id IN ({})",
+                util::placeholders(chunk.len())
+            );
+            let query = chunk.iter().fold(sqlx::query(&sql), |query, id| query.bind(*id));
+            if let Err(e) = query.execute(&self.conn).await {
+                error!("Could not delete index tombstones: {}", e);
+            }
+        }
+    }
+
+    pub async fn new_category(&self, mut category: Category) -> Result<Category> {
+        debug!("adding new category: {:?}", category.name);
+        self.authorize_category(category.parent_category).await?;
+        self.check_image_limits(category.thumbnail.as_ref(), None)?;
+        self.scan_images(category.thumbnail.as_ref(), None).await?;
+        category.name = util::sanitize_name(&category.name)?.to_owned();
+        check_color(&mut category.color)?;
+        category.id = None;
+        let mut tx = self.conn.begin().await?;
+
+        check_reference(&mut tx, "categories", "parent_category", category.parent_category).await?;
+
+        let tmp_cat: Option<Category> =
+            sqlx::query_as::<_, DbCategory>("SELECT * FROM categories WHERE name = ?")
+                .bind(category.name.clone())
+                .bind(category.parent_category)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(Into::into);
+        debug!("tmp_cat: {:?}", tmp_cat);
+
+        if tmp_cat.is_some() {
+            return Err(CustError::new(
+                "category already exists".to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+
+        let id = insert_category(&mut tx, &mut category).await?;
+        self.category_files.store(&category).await?;
+
+        tx.commit().await?;
+
+        debug!("added new category: {:?}", category);
+        self.publish(EventKind::CategoryCreated, id, &DbCategory::from(category.clone()))
+            .await;
+        Ok(category)
+    }
+
+    pub async fn get_all_categories(&self) -> Result<Vec<Category>> {
+        let mut categories: Vec<Category> =
+            sqlx::query_as::<_, DbCategory>("SELECT * FROM categories")
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|c| c.into())
+                .collect();
+        if let Some(access) = self.caller_scope().await? {
+            categories.retain(|c| c.id.is_some_and(|id| access.allows_category(id)));
+        }
+
+        // items of a category include the items of all of its subcategories
+        let counts: HashMap<ID, i64> = sqlx::query(
+            r#"
+            WITH RECURSIVE tree(root, id) AS (
+                SELECT id, id FROM categories
+                UNION
+                SELECT tree.root, categories.id FROM categories
+                JOIN tree ON categories.parent_category = tree.id
+            )
+            SELECT tree.root AS id, COUNT(items.id) AS item_count FROM tree
+            LEFT JOIN items ON items.category_id = tree.id AND items.deleted_at IS NULL
+            GROUP BY tree.root
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("id"), row.get("item_count")))
+            .collect();
+
+        for category in &mut categories {
+            let result = self.category_files.read(category).await;
+            if result.is_err() {
+                error!("{}", result.err().unwrap());
+            }
+            category.item_count = category
+                .id
+                .and_then(|id| counts.get(&id).copied())
+                .unwrap_or(0);
+        }
+
+        Ok(categories)
+    }
+
+    /// A category with its thumbnail and item count, like in [`Self::get_all_categories`].
+    pub async fn get_category_by_uuid(&self, uuid: &str) -> Result<Category> {
+        self.get_all_categories()
+            .await?
+            .into_iter()
+            .find(|category| category.uuid.as_deref() == Some(uuid))
+            .ok_or_else(|| CustError::new("category not found".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Renames a category. The index documents of its items are updated by a background job.
+    pub async fn rename_category(&self, id: ID, name: Name) -> Result<Category> {
+        self.authorize_category(Some(id)).await?;
+        let name = util::sanitize_name(&name)?.to_owned();
+        let mut tx = self.conn.begin().await?;
+
+        let existing = sqlx::query("SELECT id FROM categories WHERE name = ? AND id != ?")
+            .bind(&name)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(existing) = existing {
+            let existing_id: ID = existing.get("id");
+            return Err(CustError::new(
+                "category already exists".to_string(),
+                StatusCode::CONFLICT,
+            )
+                .with_details(serde_json::json!({ "existing_id": existing_id })));
+        }
+
+        let category = sqlx::query_as::<_, DbCategory>("UPDATE categories SET name = ? WHERE id = ? RETURNING *")
+            .bind(&name)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| CustError::new("category not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        tx.commit().await?;
+
+        self.items_changed();
+        if self.index.is_some() {
+            self.jobs.push(Job::ReindexCategory(id));
+        }
+        self.publish(EventKind::CategoryRenamed, id, &category).await;
+
+        let mut category: Category = category.into();
+        if let Err(e) = self.category_files.read(&mut category).await {
+            error!("{}", e);
+        }
+        Ok(category)
+    }
+
+    /// Moves a category with all of its subcategories below `new_parent`, or to the root. The
+    /// tree is only stored as parent references, so the subtree follows without being rewritten;
+    /// searches scoped to a category see the new tree once their cache is dropped.
+    pub async fn move_category(&self, id: ID, new_parent: Option<ID>) -> Result<Category> {
+        if let Some(access) = self.caller_scope().await? {
+            access.require_category(Some(id))?;
+            access.require_category(new_parent)?;
+        }
+        let mut tx = self.conn.begin().await?;
+
+        let old_parent: Option<ID> =
+            sqlx::query("SELECT parent_category FROM categories WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| {
+                    CustError::new("category not found".to_string(), StatusCode::NOT_FOUND)
+                })?
+                .get("parent_category");
+        check_reference(&mut tx, "categories", "parent_category", new_parent).await?;
+
+        if let Some(parent) = new_parent {
+            let in_subtree = sqlx::query(
+                r#"
+                WITH RECURSIVE subtree(id) AS (
+                    SELECT ?1
+                    UNION
+                    SELECT categories.id FROM categories
+                    JOIN subtree ON categories.parent_category = subtree.id
+                )
+                SELECT 1 FROM subtree WHERE id = ?2
+                "#,
+            )
+                .bind(id)
+                .bind(parent)
+                .fetch_optional(&mut *tx)
+                .await?
+                .is_some();
+            if in_subtree {
+                return Err(CustError::new(
+                    "a category can't be moved below itself or one of its subcategories"
+                        .to_string(),
+                    StatusCode::CONFLICT,
+                )
+                    .with_details(serde_json::json!({ "new_parent": parent })));
+            }
+        }
+
+        let category = sqlx::query_as::<_, DbCategory>(
+            "UPDATE categories SET parent_category = ? WHERE id = ? RETURNING *",
+        )
+            .bind(new_parent)
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        debug!("moved category {} from {:?} to {:?}", id, old_parent, new_parent);
+        self.items_changed();
+        self.publish(EventKind::CategoryMoved, id, &category).await;
+
+        let mut category: Category = category.into();
+        if let Err(e) = self.category_files.read(&mut category).await {
+            error!("{}", e);
+        }
+        Ok(category)
+    }
+
+    pub async fn new_collection(&self, mut coll: Collection) -> Result<Collection> {
+        require_unscoped()?;
+        self.check_image_limits(coll.thumbnail.as_ref(), None)?;
+        self.scan_images(coll.thumbnail.as_ref(), None).await?;
+        coll.name = util::sanitize_name(&coll.name)?.to_owned();
+        check_color(&mut coll.color)?;
+        check_smart_query(&mut coll)?;
+        let mut tx = self.conn.begin().await?;
+        let id = insert_collection(&mut tx, &mut coll).await?;
+        let collection = coll;
+
+        self.collection_files.store(&collection).await?;
+
+        tx.commit().await?;
+
+        self.publish(EventKind::CollectionCreated, id, &DbCollection::from(collection.clone()))
+            .await;
+
+        Ok(collection)
+    }
+
+    pub async fn get_all_collections(&self) -> Result<Vec<Collection>> {
+        let mut list = sqlx::query_as::<_, Collection>(
+            r#"
+            SELECT collections.*, COUNT(items.id) AS item_count FROM collections
+            LEFT JOIN collection_items ON collection_items.collection_id = collections.id
+            LEFT JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            GROUP BY collections.id
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+        if let Some(access) = self.caller_scope().await? {
+            list.retain(|c| c.id.is_some_and(|id| access.allows_collection(id)));
+        }
+
+        for c in &mut list {
+            let result = self.collection_files.read(c).await;
+            if result.is_err() {
+                error!("{}", result.err().unwrap());
+            }
+            self.count_smart_items(c).await?;
+        }
+
+        Ok(list)
+    }
+
+    /// Sets the color and icon of the tile of a category
+    pub async fn set_category_appearance(
+        &self,
+        id: ID,
+        mut appearance: Appearance,
+    ) -> Result<Category> {
+        self.authorize_category(Some(id)).await?;
+        check_color(&mut appearance.color)?;
+        let category = sqlx::query_as::<_, DbCategory>(
+            "UPDATE categories SET color = ?, icon = ? WHERE id = ? RETURNING *",
+        )
+            .bind(&appearance.color)
+            .bind(appearance.icon)
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("category not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        self.publish(EventKind::CategoryUpdated, id, &category).await;
+
+        let mut category: Category = category.into();
+        if let Err(e) = self.category_files.read(&mut category).await {
+            error!("{}", e);
+        }
+        Ok(category)
+    }
+
+    /// Replaces the thumbnail of a category with an uploaded image. It is checked and scanned
+    /// like an item image, and only its png preview is kept.
+    pub async fn set_category_thumbnail(
+        &self,
+        id: ID,
+        image: Vec<u8>,
+        filename: Option<String>,
+    ) -> Result<Category> {
+        self.authorize_category(Some(id)).await?;
+        let limit = self.config.get().limits.fullsize_bytes;
+        if image.len() > limit {
+            return Err(image_too_large("fullsize", image.len(), limit));
+        }
+        let info = images::image_file_info(&image, filename.as_deref());
+
+        let category = sqlx::query_as::<_, DbCategory>("SELECT * FROM categories WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("category not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        let image = self.scan_upload(image, info.filename.as_deref()).await?;
+        let processed = tokio::task::spawn_blocking(move || images::process_image(image))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        let mut updated: Category = category.clone().into();
+        updated.thumbnail = Some(base64::engine::general_purpose::STANDARD.encode(processed.thumbnail));
+        self.category_files.store(&updated).await?;
+
+        self.publish(EventKind::CategoryUpdated, id, &category).await;
+        Ok(updated)
+    }
+
+    /// The thumbnail of a category as a file, for clients that show it without decoding JSON
+    pub async fn category_thumbnail(&self, id: ID) -> Result<ImageDownload> {
+        self.authorize_category(Some(id)).await?;
+        let category = sqlx::query_as::<_, DbCategory>("SELECT * FROM categories WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("category not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        let mut category: Category = category.into();
+        self.category_files.read(&mut category).await?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(category.thumbnail.as_deref().unwrap_or_default())?;
+        if bytes.is_empty() {
+            return Err(CustError::new(
+                "the category has no thumbnail".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        let info = images::image_file_info(&bytes, None);
+        let extension = image::ImageFormat::from_mime_type(&info.mime_type)
+            .and_then(|format| format.extensions_str().first())
+            .unwrap_or(&"bin");
+        Ok(ImageDownload {
+            bytes,
+            filename: format!("category_{}.{}", id, extension),
+            mime_type: info.mime_type,
+            sha256: info.sha256,
+        })
+    }
+
+    /// Creates a collection named `name` with the items of another one, in the same order and
+    /// with the same tile, e.g. this year's packing list from last year's. A smart collection
+    /// keeps its query only if `smart_query` is set, otherwise its current items are copied.
+    /// The clone is never public.
+    pub async fn clone_collection(
+        &self,
+        id: ID,
+        name: Name,
+        smart_query: bool,
+    ) -> Result<Collection> {
+        require_unscoped()?;
+        let name = util::sanitize_name(&name)?.to_owned();
+        let exists = sqlx::query("SELECT 1 FROM collections WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CustError::new(
+                "collection not found".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+        let source = self.get_collection(id).await?;
+        let query = source.query.clone().filter(|_| smart_query);
+        // the members of a smart collection are only known by running its query
+        let smart_items = match &source.query {
+            Some(_) if query.is_none() => Some(
+                self.get_items_in_collection(id, &OwnershipFilter::default())
+                    .await?
+                    .into_iter()
+                    .filter_map(|item| item.id)
+                    .collect::<Vec<ID>>(),
+            ),
+            _ => None,
+        };
+        let kind = if query.is_some() {
+            CollectionKind::Smart
+        } else {
+            CollectionKind::Static
+        };
+
+        let mut tx = self.conn.begin().await?;
+        let uuid = util::new_uuid();
+        let clone_id: ID = sqlx::query(
+            "INSERT INTO collections (uuid, name, public, color, icon, kind, smart_query) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+            .bind(&uuid)
+            .bind(&name)
+            .bind(false)
+            .bind(&source.color)
+            .bind(source.icon)
+            .bind(kind)
+            .bind(&query)
+            .fetch_one(&mut *tx)
+            .await?
+            .get("id");
+
+        let now = util::now();
+        match &smart_items {
+            Some(items) => {
+                for (position, item_id) in items.iter().enumerate() {
+                    sqlx::query(
+                        "INSERT INTO collection_items (collection_id, item_id, position, added_at) VALUES (?, ?, ?, ?)",
+                    )
+                        .bind(clone_id)
+                        .bind(item_id)
+                        .bind(position as i64)
+                        .bind(now)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            None if kind == CollectionKind::Static => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO collection_items (collection_id, item_id, position, added_at)
+                    SELECT ?, item_id, position, ? FROM collection_items WHERE collection_id = ?
+                    "#,
+                )
+                    .bind(clone_id)
+                    .bind(now)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            None => {}
+        }
+
+        let clone = Collection {
+            id: Some(clone_id),
+            uuid: Some(uuid),
+            name,
+            thumbnail: source.thumbnail,
+            item_count: 0,
+            public: false,
+            color: source.color,
+            icon: source.icon,
+            kind,
+            query,
+        };
+        self.collection_files.store(&clone).await?;
+
+        tx.commit().await?;
+
+        self.stats_cache.invalidate();
+        self.publish(EventKind::CollectionCreated, clone_id, &DbCollection::from(clone))
+            .await;
+        self.get_collection(clone_id).await
+    }
+
+    /// Sets the color and icon of the tile of a collection
+    pub async fn set_collection_appearance(
+        &self,
+        id: ID,
+        mut appearance: Appearance,
+    ) -> Result<Collection> {
+        self.authorize_collection(id).await?;
+        check_color(&mut appearance.color)?;
+        let result = sqlx::query("UPDATE collections SET color = ?, icon = ? WHERE id = ?")
+            .bind(&appearance.color)
+            .bind(appearance.icon)
+            .bind(id)
+            .execute(&self.conn)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(CustError::new(
+                "collection not found".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        let collection = self.get_collection(id).await?;
+        self.publish(EventKind::CollectionUpdated, id, &DbCollection::from(collection.clone()))
+            .await;
+        Ok(collection)
+    }
+
+    /// Sets whether the public api lists a collection.
+    pub async fn set_collection_public(&self, id: ID, public: bool) -> Result<Collection> {
+        self.authorize_collection(id).await?;
+        // hiding a collection is always possible
+        if public {
+            self.features.require(Feature::PublicSharing)?;
+        }
+        let result = sqlx::query("UPDATE collections SET public = ? WHERE id = ?")
+            .bind(public)
+            .bind(id)
+            .execute(&self.conn)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(CustError::new(
+                "collection not found".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        self.get_collection(id).await
+    }
+
+    pub async fn get_collection(&self, id: ID) -> Result<Collection> {
+        self.authorize_collection(id).await?;
+        let mut collection = sqlx::query_as::<_, Collection>(
+            r#"
+            SELECT collections.*, COUNT(items.id) AS item_count FROM collections
+            LEFT JOIN collection_items ON collection_items.collection_id = collections.id
+            LEFT JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            WHERE collections.id = ?
+            GROUP BY collections.id
+            "#,
+        )
+            .bind(id)
+            .fetch_one(&self.conn)
+            .await?;
+
+        let result = self.collection_files.read(&mut collection).await;
+        if result.is_err() {
+            error!("{}", result.err().unwrap());
+        }
+        self.count_smart_items(&mut collection).await?;
+
+        Ok(collection)
+    }
+
+    /// Pins an item for the current caller, pinning it again does nothing
+    pub async fn favorite_item(&self, id: ID) -> Result<Item> {
+        self.authorize_item(id).await?;
+        self.set_favorite("item", id, true).await?;
+        self.get_item(id).await
+    }
+
+    pub async fn unfavorite_item(&self, id: ID) -> Result<Item> {
+        self.authorize_item(id).await?;
+        self.set_favorite("item", id, false).await?;
+        self.get_item(id).await
+    }
+
+    /// Pins a collection for the current caller, pinning it again does nothing
+    pub async fn favorite_collection(&self, id: ID) -> Result<Collection> {
+        self.authorize_collection(id).await?;
+        self.set_favorite("collection", id, true).await?;
+        self.get_collection(id).await
+    }
+
+    pub async fn unfavorite_collection(&self, id: ID) -> Result<Collection> {
+        self.authorize_collection(id).await?;
+        self.set_favorite("collection", id, false).await?;
+        self.get_collection(id).await
+    }
+
+    /// Favorites of the current caller. Those that left the scope of the caller are skipped.
+    pub async fn get_favorites(&self) -> Result<Favorites> {
+        let access = self.caller_scope().await?;
+        let mut favorites = Favorites::default();
+        for id in self.favorite_ids("item").await? {
+            if access.as_ref().is_none_or(|access| access.allows_item(id)) {
+                favorites.items.push(self.get_item(id).await?);
+            }
+        }
+        for id in self.favorite_ids("collection").await? {
+            if access.as_ref().is_none_or(|access| access.allows_collection(id)) {
+                favorites.collections.push(self.get_collection(id).await?);
+            }
+        }
+        Ok(favorites)
+    }
+
+    /// `entity` is `item` or `collection`
+    async fn set_favorite(&self, entity: &str, id: ID, favorite: bool) -> Result<()> {
+        let exists = sqlx::query(&format!("SELECT 1 FROM {}s WHERE id = ?", entity))
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CustError::new(
+                format!("{} {} does not exist", entity, id),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        if favorite {
+            sqlx::query(
+                r#"
+                INSERT INTO favorites (owner, entity, entity_id, created_at) VALUES (?, ?, ?, ?)
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+                .bind(favorites_owner())
+                .bind(entity)
+                .bind(id)
+                .bind(util::now())
+                .execute(&self.conn)
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM favorites WHERE owner = ? AND entity = ? AND entity_id = ?")
+                .bind(favorites_owner())
+                .bind(entity)
+                .bind(id)
+                .execute(&self.conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Ids of the favorite `entity`s of the current caller, most recently pinned first
+    async fn favorite_ids(&self, entity: &str) -> Result<Vec<ID>> {
+        Ok(sqlx::query_scalar(
+            r#"
+            SELECT entity_id FROM favorites WHERE owner = ? AND entity = ?
+                AND (entity != 'item' OR entity_id IN (SELECT id FROM items WHERE deleted_at IS NULL))
+            ORDER BY created_at DESC, rowid DESC
+
+            "#,
+        )
+            .bind(favorites_owner())
+            .bind(entity)
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    /// Sets the item count of a smart collection to the number of items matching its query
+    async fn count_smart_items(&self, collection: &mut Collection) -> Result<()> {
+        let Some(query) = collection.query.as_deref() else {
+            return Ok(());
+        };
+        let (condition, values) = SmartQuery::parse(query)?.to_sql();
+        let sql =
+            format!("SELECT COUNT(*) FROM items WHERE deleted_at IS NULL AND ({})", condition);
+        collection.item_count = values
+            .iter()
+            .fold(sqlx::query_scalar::<_, i64>(&sql), |query, value| query.bind(value))
+            .fetch_one(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_collection_by_uuid(&self, uuid: &str) -> Result<Collection> {
+        self.get_collection(self.collection_id_by_uuid(uuid).await?).await
+    }
+
+    /// Replaces the target list of a collection. Targets keep the item they were acquired with
+    /// if their name stays the same.
+    pub async fn set_collection_targets(
+        &self,
+        collection_id: ID,
+        entries: Vec<TargetEntry>,
+    ) -> Result<Vec<CollectionTarget>> {
+        self.authorize_collection(collection_id).await?;
+        let mut targets: Vec<CollectionTarget> = vec![];
+        for entry in entries {
+            let mut target = CollectionTarget::from(entry);
+            target.name = util::sanitize_name(&target.name)?.to_owned();
+            let trimmed = |value: Option<String>| {
+                value.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+            };
+            target.pattern = trimmed(target.pattern);
+            target.catalog_ref = trimmed(target.catalog_ref);
+
+            let name = util::normalize_name(&target.name);
+            if !targets.iter().any(|t| util::normalize_name(&t.name) == name) {
+                targets.push(target);
+            }
+        }
+
+        let mut tx = self.conn.begin().await?;
+        check_reference(&mut tx, "collections", "collection_id", Some(collection_id)).await?;
+
+        let acquired: HashMap<String, ID> = sqlx::query(
+            r#"
+            SELECT name, acquired_item_id FROM collection_targets
+            WHERE collection_id = ? AND acquired_item_id IS NOT NULL
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| (util::normalize_name(row.get("name")), row.get("acquired_item_id")))
+            .collect();
+
+        sqlx::query("DELETE FROM collection_targets WHERE collection_id = ?")
+            .bind(collection_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, target) in targets.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO collection_targets
+                    (collection_id, name, position, match_rule, pattern, catalog_ref, acquired_item_id)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+                .bind(collection_id)
+                .bind(&target.name)
+                .bind(position as i64)
+                .bind(target.match_rule)
+                .bind(&target.pattern)
+                .bind(&target.catalog_ref)
+                .bind(acquired.get(&util::normalize_name(&target.name)).copied())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        self.stats_cache.invalidate();
+
+        self.target_status(collection_id).await
+    }
+
+    /// The target list of a collection, with the item completing each target
+    pub async fn get_collection_targets(&self, collection_id: ID) -> Result<Vec<CollectionTarget>> {
+        self.authorize_collection(collection_id).await?;
+        let _collection = self.get_collection(collection_id).await?;
+        self.target_status(collection_id).await
+    }
+
+    /// Matches the owned items of a collection against its targets. The item a target was
+    /// acquired with completes it while it is owned and in the collection, other targets are
+    /// completed by the first item matching them.
+    async fn target_status(&self, collection_id: ID) -> Result<Vec<CollectionTarget>> {
+        let items: Vec<(ID, Name)> = sqlx::query(
+            r#"
+            SELECT items.id, items.name FROM collection_items
+            JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            WHERE collection_items.collection_id = ? AND items.ownership_state = 'owned'
+            ORDER BY collection_items.position, items.id
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("id"), row.get("name")))
+            .collect();
+
+        let mut tags: HashMap<ID, Vec<String>> = HashMap::new();
+        let rows = sqlx::query(
+            r#"
+            SELECT item_tags.item_id, item_tags.tag FROM item_tags
+            JOIN collection_items ON collection_items.item_id = item_tags.item_id
+            WHERE collection_items.collection_id = ?
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_all(&self.conn)
+            .await?;
+        for row in rows {
+            tags.entry(row.get("item_id")).or_default().push(row.get("tag"));
+        }
+
+        let rows = sqlx::query(
+            r#"
+            SELECT rowid AS id, name, match_rule, pattern, catalog_ref, acquired_item_id
+            FROM collection_targets
+            WHERE collection_id = ?
+            ORDER BY position
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_all(&self.conn)
+            .await?;
+
+        let targets = rows
+            .into_iter()
+            .map(|row| {
+                let mut target = CollectionTarget {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    match_rule: row.get("match_rule"),
+                    pattern: row.get("pattern"),
+                    catalog_ref: row.get("catalog_ref"),
+                    item_id: None,
+                    owned: false,
+                };
+                let acquired: Option<ID> = row.get("acquired_item_id");
+                let item_id = items
+                    .iter()
+                    .find(|(id, _)| Some(*id) == acquired)
+                    .or_else(|| {
+                        items.iter().find(|(id, name)| {
+                            let tags = tags.get(id).map(Vec::as_slice).unwrap_or_default();
+                            target.matches(name, tags)
+                        })
+                    })
+                    .map(|(id, _)| *id);
+                target.item_id = item_id;
+                target.owned = item_id.is_some();
+                target
+            })
+            .collect();
+
+        Ok(targets)
+    }
+
+    /// Creates the item of a target that was just acquired, e.g. bought, and adds it to the
+    /// collection. The item is named after the target and tagged with its catalog reference.
+    pub async fn acquire_collection_target(
+        &self,
+        collection_id: ID,
+        target_id: ID,
+        acquire: AcquireTarget,
+    ) -> Result<CollectionTarget> {
+        self.authorize_collection(collection_id).await?;
+        let target = self
+            .get_collection_targets(collection_id)
+            .await?
+            .into_iter()
+            .find(|target| target.id == Some(target_id))
+            .ok_or_else(|| {
+                CustError::new(
+                    format!("collection {} has no target {}", collection_id, target_id),
+                    StatusCode::NOT_FOUND,
+                )
+            })?;
+        if let Some(item_id) = target.item_id {
+            return Err(CustError::new(
+                format!("target {} is already owned", target.name),
+                StatusCode::CONFLICT,
+            )
+                .with_details(serde_json::json!({ "item_id": item_id })));
+        }
+
+        let mut tags: Vec<String> = target.catalog_ref.iter().cloned().collect();
+        if target.match_rule == TargetMatch::Tag {
+            tags.push(target.pattern.clone().unwrap_or_else(|| target.name.clone()));
+        }
+        let item = self
+            .add_item(Item {
+                name: target.name.clone(),
+                tags,
+                purchase_price: acquire.purchase_price,
+                currency: acquire.currency,
+                purchase_date: acquire.purchase_date,
+                category_id: acquire.category_id,
+                location_id: acquire.location_id,
+                ..Default::default()
+            })
+            .await?;
+        let item_id = item.id.expect("created items have an id");
+        self.add_item_to_collection(item_id, collection_id).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE collection_targets SET acquired_item_id = ?
+            WHERE rowid = ? AND collection_id = ?
+            "#,
+        )
+            .bind(item_id)
+            .bind(target_id)
+            .bind(collection_id)
+            .execute(&self.conn)
+            .await?;
+        self.stats_cache.invalidate();
+
+        Ok(CollectionTarget {
+            item_id: Some(item_id),
+            owned: true,
+            ..target
+        })
+    }
+
+    /// Item count, value, completion against the target list and recent additions of a
+    /// collection. Results are cached until items or the collection change.
+    pub async fn collection_stats(&self, collection_id: ID) -> Result<CollectionStats> {
+        self.authorize_collection(collection_id).await?;
+        if let Some(stats) = self.stats_cache.get(&collection_id) {
+            return Ok(stats);
+        }
+        let generation = self.stats_cache.generation();
+
+        let _collection = self.get_collection(collection_id).await?;
+
+        let item_count: i64 = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS count FROM collection_items
+            JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            WHERE collection_items.collection_id = ? AND items.ownership_state != 'disposed'
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_one(&self.conn)
+            .await?
+            .get("count");
+
+        let value = sqlx::query_as::<_, Valuation>(
+            r#"
+            SELECT collection_items.collection_id AS group_id, NULL AS name, items.currency AS currency,
+                COUNT(items.id) AS item_count,
+                TOTAL(items.purchase_price * COALESCE(items.quantity, 1)) AS total_purchase_price,
+                TOTAL(
+                    CASE WHEN items.ownership_state != 'disposed'
+                    THEN items.current_value * COALESCE(items.quantity, 1) END
+                ) AS total_current_value,
+                TOTAL(item_disposals.sale_price) AS total_proceeds
+            FROM collection_items
+            JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            LEFT JOIN item_disposals ON item_disposals.item_id = items.id
+            WHERE collection_items.collection_id = ?
+            GROUP BY items.currency
+            ORDER BY items.currency
+            "#,
+        )
+            .bind(collection_id)
+            .fetch_all(&self.conn)
+            .await?;
+
+        let targets = self.target_status(collection_id).await?;
+        let target_count = targets.len() as i64;
+        let missing_targets: Vec<Name> = targets
+            .iter()
+            .filter(|target| !target.owned)
+            .map(|target| target.name.clone())
+            .collect();
+        let targets_owned = target_count - missing_targets.len() as i64;
+
+        let recent_additions = sqlx::query_as::<_, RecentAddition>(
+            r#"
+            SELECT items.id AS item_id, items.name AS name, collection_items.added_at AS added_at
+            FROM collection_items
+            JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            WHERE collection_items.collection_id = ?
+            ORDER BY collection_items.added_at IS NULL, collection_items.added_at DESC, collection_items.position DESC
+            LIMIT ?
+            "#,
+        )
+            .bind(collection_id)
+            .bind(RECENT_ADDITIONS)
+            .fetch_all(&self.conn)
+            .await?;
+
+        let stats = CollectionStats {
+            collection_id,
+            item_count,
+            value: format_valuations(value, None),
+            target_count,
+            targets_owned,
+            completion_percent: (target_count > 0)
+                .then(|| targets_owned as f64 / target_count as f64 * 100.0),
+            missing_targets,
+            targets,
+            recent_additions,
+        };
+        self.stats_cache.insert(collection_id, stats.clone(), generation);
+
+        Ok(stats)
+    }
+
+    pub async fn add_item_to_collection(&self, item_id: ID, collection_id: ID) -> Result<()> {
+        self.authorize_item(item_id).await?;
+        self.authorize_collection(collection_id).await?;
+        let mut tx = self.conn.begin().await?;
+        link_collection_item(&mut tx, item_id, collection_id).await?;
+        tx.commit().await?;
+        self.collection_item_added(item_id, collection_id).await;
+
+        Ok(())
+    }
+
+    async fn collection_item_added(&self, item_id: ID, collection_id: ID) {
+        self.stats_cache.invalidate();
+
+        self.publish(
+            EventKind::CollectionItemAdded,
+            collection_id,
+            &CollectionItem {
+                collection_id,
+                item_id,
+            },
+        )
+            .await;
+    }
+
+    pub async fn remove_item_from_collection(&self, item_id: ID, collection_id: ID) -> Result<()> {
+        self.authorize_item(item_id).await?;
+        self.authorize_collection(collection_id).await?;
+        let mut tx = self.conn.begin().await?;
+        unlink_collection_item(&mut tx, item_id, collection_id).await?;
+        tx.commit().await?;
+        self.collection_item_removed(item_id, collection_id).await;
+
+        Ok(())
+    }
+
+    async fn collection_item_removed(&self, item_id: ID, collection_id: ID) {
+        self.stats_cache.invalidate();
+
+        self.publish(
+            EventKind::CollectionItemRemoved,
+            collection_id,
+            &CollectionItem {
+                collection_id,
+                item_id,
+            },
+        )
+            .await;
+    }
+
+    pub async fn new_location(&self, mut location: Location) -> Result<Location> {
+        location.name = util::sanitize_name(&location.name)?.to_owned();
+        let mut tx = self.conn.begin().await?;
+        let id = insert_location(&mut tx, &mut location).await?;
+        tx.commit().await?;
+
+        self.publish(EventKind::LocationCreated, id, &location).await;
+
+        Ok(location)
+    }
+
+    pub async fn get_all_locations(&self) -> Result<Vec<Location>> {
+        Ok(sqlx::query_as::<_, Location>("SELECT * FROM locations")
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    pub async fn get_location_by_uuid(&self, uuid: &str) -> Result<Location> {
+        sqlx::query_as::<_, Location>("SELECT * FROM locations WHERE uuid = ?")
+            .bind(uuid)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("location not found".to_string(), StatusCode::NOT_FOUND))
+    }
+
+    /// Renames a location. The index documents of its items are updated by a background job.
+    pub async fn rename_location(&self, id: ID, name: Name) -> Result<Location> {
+        let name = util::sanitize_name(&name)?.to_owned();
+
+        let location = sqlx::query_as::<_, Location>("UPDATE locations SET name = ? WHERE id = ? RETURNING *")
+            .bind(&name)
+            .bind(id)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| CustError::new("location not found".to_string(), StatusCode::NOT_FOUND))?;
+
+        self.items_changed();
+        if self.index.is_some() {
+            self.jobs.push(Job::ReindexLocation(id));
+        }
+        self.publish(EventKind::LocationRenamed, id, &location).await;
+
+        Ok(location)
+    }
+
+    /// Collections containing an item, without their thumbnails
+    pub async fn get_item_collections(&self, item_id: ID) -> Result<Vec<Collection>> {
+        self.authorize_item(item_id).await?;
+        let exists = sqlx::query("SELECT id FROM items WHERE id = ? AND deleted_at IS NULL")
+            .bind(item_id)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+        }
+
+        let collections = sqlx::query_as::<_, Collection>(
+            r#"
+            SELECT collections.*, COUNT(items.id) AS item_count FROM collections
+            JOIN collection_items AS member ON member.collection_id = collections.id
+            LEFT JOIN collection_items AS counted ON counted.collection_id = collections.id
+            LEFT JOIN items ON items.id = counted.item_id AND items.deleted_at IS NULL
+            WHERE member.item_id = ?
+            GROUP BY collections.id
+            ORDER BY collections.name
+            "#,
+        )
+            .bind(item_id)
+            .fetch_all(&self.conn)
+            .await?;
+
+        Ok(collections)
+    }
+
+    pub async fn get_items_in_collection(
+        &self,
+        collection_id: ID,
+        ownership: &OwnershipFilter,
+    ) -> Result<Vec<Item>> {
+        let collection = self.get_collection(collection_id).await?;
+
+        let items = match collection.query.as_deref() {
+            // smart collections are ordered by name, they have no positions
+            Some(query) => {
+                let (condition, values) = SmartQuery::parse(query)?.to_sql();
+                let sql = format!(
+                    r#"
+                    SELECT items.* FROM items
+                    WHERE ({})
+                    AND items.deleted_at IS NULL
+                    AND items.ownership_state = COALESCE(?, items.ownership_state)
+                    AND (? IS NOT NULL OR items.ownership_state != 'disposed')
+                    ORDER BY items.name, items.id
+                    "#,
+                    condition
+                );
+                values
+                    .iter()
+                    .fold(sqlx::query_as::<_, DbItem>(&sql), |query, value| query.bind(value))
+                    .bind(ownership.state)
+                    .bind(ownership.state)
+                    .fetch_all(&self.conn)
+                    .await?
+            }
+            None => {
+                sqlx::query_as::<_, DbItem>(
+                    r#"
+                    SELECT items.* FROM items
+                    JOIN collection_items ON items.id = collection_items.item_id
+                    WHERE collection_items.collection_id = ?
+                    AND items.deleted_at IS NULL
+                    AND items.ownership_state = COALESCE(?2, items.ownership_state)
+
+                    AND (?2 IS NOT NULL OR items.ownership_state != 'disposed')
+                    ORDER BY collection_items.position
+                    "#,
+                )
+                    .bind(collection_id)
+                    .bind(ownership.state)
+                    .fetch_all(&self.conn)
+                    .await?
+            }
+        };
+        let mut items: Vec<Item> = items.into_iter().map(Into::into).collect();
+
+        for item in &mut items {
+            self.hydrate_item(item).await;
+        }
+
+        Ok(items)
+    }
+
+    pub async fn export_collection_pdf(&self, collection_id: ID) -> Result<Vec<u8>> {
+        let collection = self.get_collection(collection_id).await?;
+        let items = self
+            .get_items_in_collection(collection_id, &OwnershipFilter::default())
+            .await?;
+        let locations: HashMap<ID, String> = self
+            .get_all_locations()
+            .await?
+            .into_iter()
+            .filter_map(|l| l.id.map(|id| (id, l.name)))
+            .collect();
+
+        tokio::task::spawn_blocking(move || {
+            export::collection_pdf(&collection.name, &items, &locations)
+        })
+            .await
+            .map_err(anyhow::Error::from)?
+    }
+
+    /// Everything needed to recreate a collection on another instance, see [`CollectionBundle`]
+    pub async fn export_collection_bundle(&self, collection_id: ID) -> Result<CollectionBundle> {
+        let collection = self.get_collection(collection_id).await?;
+        let items = self
+            .get_items_in_collection(collection_id, &OwnershipFilter::default())
+            .await?;
+
+        let mut categories: HashMap<ID, Category> = self
+            .get_all_categories()
+            .await?
+            .into_iter()
+            .filter_map(|c| c.id.map(|id| (id, c)))
+            .collect();
+        let parents = categories.iter().map(|(id, c)| (*id, c.parent_category)).collect();
+        let category_ids = with_ancestors(items.iter().filter_map(|i| i.category_id), &parents);
+
+        let mut locations: HashMap<ID, Location> = self
+            .get_all_locations()
+            .await?
+            .into_iter()
+            .filter_map(|l| l.id.map(|id| (id, l)))
+            .collect();
+        let parents = locations.iter().map(|(id, l)| (*id, l.parent_location)).collect();
+        let location_ids = with_ancestors(items.iter().filter_map(|i| i.location_id), &parents);
+
+        let mut bundle_items = Vec::with_capacity(items.len());
+        for mut item in items {
+            let id = item.id.expect("stored items have an id");
+            let mut images = vec![];
+            for image in self.get_item_images(id).await? {
+                let image = self.get_item_image(id, image.id).await?;
+                images.push(NewItemImage {
+                    image: image.fullsize.unwrap_or_default(),
+                    filename: image.filename,
+                    caption: image.caption,
+                    primary: image.is_primary,
+                });
+            }
+            // the image of the item is the primary image of its gallery, it comes back with it
+            if !images.is_empty() {
+                item.thumbnail = None;
+                item.fullsize = None;
+            }
+            bundle_items.push(BundleItem { item, images });
+        }
+
+        Ok(CollectionBundle {
+            version: COLLECTION_BUNDLE_VERSION,
+            exported_at: util::now(),
+            collection,
+            categories: category_ids.iter().filter_map(|id| categories.remove(id)).collect(),
+            locations: location_ids.iter().filter_map(|id| locations.remove(id)).collect(),
+            items: bundle_items,
+        })
+    }
+
+    /// Recreates an exported collection with new ids, in one transaction. Categories with the
+    /// same name and locations with the same name and parent are reused instead of created
+    /// again. The collection starts out private. A dry run rolls the transaction back and only
+    /// reports the rows it would have created.
+    pub async fn import_collection_bundle(
+        &self,
+        bundle: CollectionBundle,
+        dry_run: bool,
+    ) -> Result<BundleImportResult> {
+        require_unscoped()?;
+        if bundle.version != COLLECTION_BUNDLE_VERSION {
+            return Err(CustError::new(
+                format!(
+                    "unsupported bundle version {}, expected {}",
+                    bundle.version, COLLECTION_BUNDLE_VERSION
+                ),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        // check the whole bundle before the transaction, the images take a while
+        let categories: HashMap<ID, &Category> = bundle
+            .categories
+            .iter()
+            .filter_map(|c| c.id.map(|id| (id, c)))
+            .collect();
+        let parents = categories.iter().map(|(id, c)| (*id, c.parent_category)).collect();
+        let category_order = parents_first(&parents, "categories")?;
+
+        let locations: HashMap<ID, &Location> = bundle
+            .locations
+            .iter()
+            .filter_map(|l| l.id.map(|id| (id, l)))
+            .collect();
+        let parents = locations.iter().map(|(id, l)| (*id, l.parent_location)).collect();
+        let location_order = parents_first(&parents, "locations")?;
+
+        let mut new_categories = Vec::with_capacity(category_order.len());
+        for id in category_order {
+            let mut category = Category {
+                id: None,
+                uuid: None,
+                item_count: 0,
+                ..categories[&id].clone()
+            };
+            self.check_image_limits(category.thumbnail.as_ref(), None)?;
+            self.scan_images(category.thumbnail.as_ref(), None).await?;
+            category.name = util::sanitize_name(&category.name)?.to_owned();
+            check_color(&mut category.color)?;
+            new_categories.push((id, category));
+        }
+
+        let mut new_locations = Vec::with_capacity(location_order.len());
+        for id in location_order {
+            let location = locations[&id];
+            let name = util::sanitize_name(&location.name)?.to_owned();
+            new_locations.push((id, name, location.parent_location));
+        }
+
+        let limit = self.config.get().limits.fullsize_bytes;
+        let mut new_items = Vec::with_capacity(bundle.items.len());
+        for entry in bundle.items {
+            let item = entry.item;
+            let missing_category = item.category_id.is_some_and(|id| !categories.contains_key(&id));
+            let missing_location = item.location_id.is_some_and(|id| !locations.contains_key(&id));
+            if missing_category || missing_location {
+                return Err(CustError::new(
+                    format!(
+                        "item {:?} refers to a category or location that is not in the bundle",
+                        item.name
+                    ),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            let item = self.prepare_new_item(Item { id: None, ..item }).await?;
+
+            // the last image marked as primary wins, like when they are added one by one
+            let primary = entry.images.iter().rposition(|image| image.primary).unwrap_or(0);
+            let mut images = Vec::with_capacity(entry.images.len());
+            for (position, image) in entry.images.into_iter().enumerate() {
+                let bytes = base64::engine::general_purpose::STANDARD.decode(&image.image)?;
+                if bytes.len() > limit {
+                    return Err(image_too_large("fullsize", bytes.len(), limit));
+                }
+                let info = images::image_file_info(&bytes, image.filename.as_deref());
+                let bytes = self.scan_upload(bytes, info.filename.as_deref()).await?;
+                let processed = tokio::task::spawn_blocking(move || images::process_image(bytes))
+                    .await
+                    .map_err(anyhow::Error::from)??;
+                images.push((position, image.caption, position == primary, info, processed));
+            }
+            new_items.push((item, images));
+        }
+
+        let mut collection = Collection {
+            id: None,
+            uuid: None,
+            item_count: 0,
+            public: false,
+            ..bundle.collection
+        };
+        self.check_image_limits(collection.thumbnail.as_ref(), None)?;
+        self.scan_images(collection.thumbnail.as_ref(), None).await?;
+        collection.name = util::sanitize_name(&collection.name)?.to_owned();
+        check_color(&mut collection.color)?;
+        check_smart_query(&mut collection)?;
+
+        let mut changes = ChangeReport::default();
+        let mut tx = self.conn.begin().await?;
+
+        let mut category_ids: HashMap<ID, ID> = HashMap::new();
+        let mut created_categories = vec![];
+        for (id, mut category) in new_categories {
+            let existing: Option<ID> = sqlx::query("SELECT id FROM categories WHERE name = ?")
+                .bind(&category.name)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(|row| row.get("id"));
+            let new_id = match existing {
+                Some(existing) => existing,
+                None => {
+                    category.parent_category = category.parent_category.map(|p| category_ids[&p]);
+                    let new_id = insert_category(&mut tx, &mut category).await?;
+                    changes.record_created("categories", 1);
+                    created_categories.push(category);
+                    new_id
+                }
+            };
+            category_ids.insert(id, new_id);
+        }
+
+        let mut location_ids: HashMap<ID, ID> = HashMap::new();
+        let mut created_locations = vec![];
+        for (id, name, parent) in new_locations {
+            let parent = parent.map(|p| location_ids[&p]);
+            let existing: Option<ID> =
+                sqlx::query("SELECT id FROM locations WHERE name = ? AND parent_location IS ?")
+                    .bind(&name)
+                    .bind(parent)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .map(|row| row.get("id"));
+            let new_id = match existing {
+                Some(existing) => existing,
+                None => {
+                    let mut location = Location {
+                        id: None,
+                        uuid: None,
+                        name,
+                        parent_location: parent,
+                    };
+                    let new_id = insert_location(&mut tx, &mut location).await?;
+                    changes.record_created("locations", 1);
+                    created_locations.push(location);
+                    new_id
+                }
+            };
+            location_ids.insert(id, new_id);
+        }
+
+        let mut created_items = Vec::with_capacity(new_items.len());
+        for (mut item, new_images) in new_items {
+            item.category_id = item.category_id.map(|id| category_ids[&id]);
+            item.location_id = item.location_id.map(|id| location_ids[&id]);
+            let item = insert_item_rows(&mut tx, item).await?;
+            let id = item.id.expect("added items have an id");
+            changes.record_created("items", 1);
+            changes.record_created("item_tags", item.tags.len() as u64);
+
+            let mut gallery = Vec::with_capacity(new_images.len());
+            for (position, caption, primary, info, processed) in new_images {
+                let mut image = self
+                    .insert_item_image(&mut tx, id, position as i32, caption, primary, &info)
+                    .await?;
+                changes.record_created("item_images", 1);
+                image.thumbnail =
+                    Some(base64::engine::general_purpose::STANDARD.encode(processed.thumbnail));
+                image.fullsize =
+                    Some(base64::engine::general_purpose::STANDARD.encode(processed.fullsize));
+                gallery.push(image);
+            }
+            created_items.push((item, gallery));
+        }
+
+        let collection_id = insert_collection(&mut tx, &mut collection).await?;
+        changes.record_created("collections", 1);
+        // the items of a smart collection are those matching its query
+        let linked = collection.kind == CollectionKind::Static;
+        if linked {
+            for (item, _) in &created_items {
+                let id = item.id.expect("added items have an id");
+                link_collection_item(&mut tx, id, collection_id).await?;
+                changes.record_created("collection_items", 1);
+            }
+        }
+
+        // a dry run does everything but keeping it
+        if dry_run {
+            tx.rollback().await?;
+            return Ok(BundleImportResult {
+                collection: None,
+                dry_run: true,
+                changes,
+            });
+        }
+
+        for category in &created_categories {
+            self.category_files.store(category).await?;
+        }
+        for (item, gallery) in &created_items {
+            self.item_files.store(item).await?;
+            for image in gallery {
+                self.item_image_files.store(image).await?;
+            }
+        }
+        self.collection_files.store(&collection).await?;
+        tx.commit().await?;
+
+        // committed already, so the import succeeded even if e.g. the index lags behind
+        for category in created_categories {
+            let id = category.id.expect("created categories have an id");
+            self.publish(EventKind::CategoryCreated, id, &DbCategory::from(category))
+                .await;
+        }
+        for location in created_locations {
+            let id = location.id.expect("created locations have an id");
+            self.publish(EventKind::LocationCreated, id, &location).await;
+        }
+        for (item, gallery) in &created_items {
+            let id = item.id.expect("added items have an id");
+            let mut outcome = self.item_added(item).await;
+            if outcome.is_ok() && !gallery.is_empty() {
+                outcome = self.sync_primary_image(id).await;
+            }
+            if let Err(e) = outcome {
+                error!("Could not finish importing item {}: {}", id, e);
+            }
+        }
+        self.publish(EventKind::CollectionCreated, collection_id, &DbCollection::from(collection))
+            .await;
+        if linked {
+            for (item, _) in &created_items {
+                let id = item.id.expect("added items have an id");
+                self.collection_item_added(id, collection_id).await;
+            }
+        }
+
+        Ok(BundleImportResult {
+            collection: Some(self.get_collection(collection_id).await?),
+            dry_run: false,
+            changes,
+        })
+    }
+
+    /// Printable label of an item with its name, location and a barcode of its id
+    pub async fn item_label(
+        &self,
+        id: ID,
+        format: LabelFormat,
+        size: LabelSize,
+    ) -> Result<Vec<u8>> {
+        let item = self.get_item(id).await?;
+        self.render_labels(vec![item], format, size).await
+    }
+
+    /// Labels of all items of a collection in one batch, in the order of the collection
+    pub async fn collection_labels(
+        &self,
+        collection_id: ID,
+        format: LabelFormat,
+        size: LabelSize,
+    ) -> Result<Vec<u8>> {
+        let items = self
+            .get_items_in_collection(collection_id, &OwnershipFilter::default())
+            .await?;
+        self.render_labels(items, format, size).await
+    }
+
+    async fn render_labels(
+        &self,
+        items: Vec<Item>,
+        format: LabelFormat,
+        size: LabelSize,
+    ) -> Result<Vec<u8>> {
+        let locations: HashMap<ID, String> = self
+            .get_all_locations()
+            .await?
+            .into_iter()
+            .filter_map(|l| l.id.map(|id| (id, l.name)))
+            .collect();
+        let labels: Vec<LabelItem> = items
+            .into_iter()
+            .filter_map(|item| {
+                Some(LabelItem {
+                    id: item.id?,
+                    location: item.location_id.and_then(|id| locations.get(&id).cloned()),
+                    name: item.name,
+                })
+            })
+            .collect();
+
+        tokio::task::spawn_blocking(move || label::render_labels(&labels, format, size))
+            .await
+            .map_err(anyhow::Error::from)?
+    }
+
+    /// Streams the metadata of all matching items as csv, one line per chunk. The rows are read
+    /// from the database while the response is sent, so the export is never fully buffered.
+    pub fn export_items_csv(
+        &self,
+        query: ItemExportQuery,
+    ) -> Result<impl Stream<Item = Result<String>>> {
+        require_unscoped()?;
+        let columns = export::parse_csv_columns(query.columns.as_deref())?;
+        let conn = self.conn.clone();
+        let (sender, receiver) = mpsc::channel::<Result<String>>(64);
+
+        tokio::spawn(async move {
+            if sender.send(export::csv_record(&columns)).await.is_err() {
+                return;
+            }
+
+            let mut rows = sqlx::query_as::<_, ItemExportRow>(
+                r#"
+                SELECT items.id, items.name, items.description, items.category_id,
+                    categories.name AS category, items.price, items.location_id,
+                    locations.name AS location, items.quantity,
+                    (SELECT group_concat(tag, ';') FROM item_tags WHERE item_id = items.id) AS tags,
+                    items.color, items.icon
+                FROM items
+                LEFT JOIN categories ON categories.id = items.category_id
+                LEFT JOIN locations ON locations.id = items.location_id
+                WHERE items.deleted_at IS NULL
+                    AND (?1 IS NULL OR items.category_id = ?1)
+                    AND (?2 IS NULL OR items.id IN (
+                        SELECT item_id FROM collection_items WHERE collection_id = ?2
+                    ))
+                ORDER BY items.id
+                "#,
+            )
+                .bind(query.category)
+                .bind(query.collection)
+                .fetch(&conn);
+
+            while let Some(row) = rows.next().await {
+                let line = row
+                    .map_err(CustError::from)
+                    .and_then(|row| export::csv_row(&row, &columns));
+                let failed = line.is_err();
+                if sender.send(line).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        Ok(futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|line| (line, receiver))
+        }))
+    }
+
+    /// Sets the order of the items in a collection. `item_ids` has to contain every item of the
+    /// collection exactly once.
+    pub async fn reorder_collection(&self, collection_id: ID, item_ids: Vec<ID>) -> Result<()> {
+        self.authorize_collection(collection_id).await?;
+        let mut tx = self.conn.begin().await?;
+        check_static_collection(&mut tx, collection_id).await?;
+
+        let mut current: Vec<ID> =
+            sqlx::query("SELECT item_id FROM collection_items WHERE collection_id = ?")
+                .bind(collection_id)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|row| row.get("item_id"))
+                .collect();
+        current.sort_unstable();
+
+        let mut requested = item_ids.clone();
+        requested.sort_unstable();
+
+        if current != requested {
+            return Err(CustError::new(
+                "order must contain every item of the collection exactly once".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        for (position, item_id) in item_ids.iter().enumerate() {
+            sqlx::query(
+                "UPDATE collection_items SET position = ? WHERE collection_id = ? AND item_id = ?",
+            )
+                .bind(position as i32)
+                .bind(collection_id)
+                .bind(item_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        self.publish(
+            EventKind::CollectionReordered,
+            collection_id,
+            &serde_json::json!({ "collection_id": collection_id, "item_ids": item_ids }),
+        )
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn feature_flags(&self) -> Result<Vec<FeatureFlag>> {
+        require_unscoped()?;
+        Ok(self.features.list())
+    }
+
+    /// Overrides the config file for a feature until the override is removed, also across
+    /// restarts
+    pub async fn set_feature_flag(
+        &self,
+        name: &str,
+        flag: FlagOverride,
+    ) -> Result<Vec<FeatureFlag>> {
+        require_unscoped()?;
+        let feature = Feature::parse(name)?;
+        match flag.enabled {
+            Some(enabled) => {
+                sqlx::query(
+                    "INSERT INTO feature_overrides (name, enabled) VALUES (?, ?) ON CONFLICT (name) DO UPDATE SET enabled = excluded.enabled",
+                )
+                    .bind(feature.as_str())
+                    .bind(enabled)
+                    .execute(&self.conn)
+                    .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM feature_overrides WHERE name = ?")
+                    .bind(feature.as_str())
+                    .execute(&self.conn)
+                    .await?;
+            }
+        }
+        self.features.set_override(feature, flag.enabled);
+        info!("Feature {} overridden: {:?}", feature.as_str(), flag.enabled);
+        Ok(self.features.list())
+    }
+
+    pub async fn new_webhook(&self, mut webhook: Webhook) -> Result<Webhook> {
+        require_unscoped()?;
+        self.features.require(Feature::Webhooks)?;
+        webhook.url = webhook.url.trim().to_owned();
+        if !(webhook.url.starts_with("http://") || webhook.url.starts_with("https://")) {
+            return Err(CustError::new(
+                "webhook url must be an http(s) url".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        if webhook.events.trim().is_empty() {
+            webhook.events = "*".to_owned();
+        }
+
+        let result = sqlx::query("INSERT INTO webhooks (url, secret, events) VALUES (?, ?, ?)")
+            .bind(webhook.url.clone())
+            .bind(webhook.secret.clone())
+            .bind(webhook.events.clone())
+            .execute(&self.conn)
+            .await?;
+
+        webhook.id = Some(result.last_insert_rowid() as ID);
+        Ok(webhook)
+    }
+
+    pub async fn get_all_webhooks(&self) -> Result<Vec<Webhook>> {
+        require_unscoped()?;
+        self.features.require(Feature::Webhooks)?;
+        Ok(sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks")
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    pub async fn delete_webhook(&self, id: ID) -> Result<Webhook> {
+        require_unscoped()?;
+        self.features.require(Feature::Webhooks)?;
+        let mut tx = self.conn.begin().await?;
+
+        let webhook = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM webhooks WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn get_webhook_deliveries(&self, id: ID) -> Result<Vec<WebhookDelivery>> {
+        require_unscoped()?;
+        self.features.require(Feature::Webhooks)?;
+        Ok(sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT * FROM webhook_deliveries WHERE webhook_id = ? ORDER BY id DESC",
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    /// Records a search in the search log. Failures are only logged, a search should never fail
+    /// because of its analytics.
+    async fn log_search(&self, query: &str, result_count: usize, latency: Duration) {
+        let result = sqlx::query(
+            "INSERT INTO search_log (query, result_count, latency_ms, created_at) VALUES (?, ?, ?, ?)",
+        )
+            .bind(util::normalize_query(query))
+            .bind(result_count as i64)
+            .bind(latency.as_secs_f64() * 1000.0)
+            .bind(util::now())
+            .execute(&self.conn)
+            .await;
+
+        if let Err(e) = result {
+            error!("Could not log search: {}", e);
+        }
+    }
+
+    pub async fn search_feedback(&self, feedback: SearchFeedback) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE search_log SET chosen_item_id = ?
+            WHERE id = (
+                SELECT id FROM search_log
+                WHERE query = ? AND chosen_item_id IS NULL
+                ORDER BY id DESC LIMIT 1
+            )
+            "#,
+        )
+            .bind(feedback.item_id)
+            .bind(util::normalize_query(&feedback.query))
+            .execute(&self.conn)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(CustError::new(
+                "no open search for this query".to_string(),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+        self.record_access(feedback.item_id);
+
+        Ok(())
+    }
+
+    pub async fn search_analytics(&self) -> Result<SearchAnalytics> {
+        require_unscoped()?;
+        let total_searches: i64 = sqlx::query("SELECT COUNT(*) AS count FROM search_log")
+            .fetch_one(&self.conn)
+            .await?
+            .get("count");
+
+        let top_queries = sqlx::query_as::<_, QueryStat>(
+            r#"
+            SELECT query, COUNT(*) AS count, AVG(result_count) AS avg_results,
+                AVG(latency_ms) AS avg_latency_ms, COUNT(chosen_item_id) AS selections
+            FROM search_log
+            GROUP BY query
+            ORDER BY count DESC
+            LIMIT 20
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+
+        let zero_hit_queries = sqlx::query_as::<_, QueryStat>(
+            r#"
+            SELECT query, COUNT(*) AS count, AVG(result_count) AS avg_results,
+                AVG(latency_ms) AS avg_latency_ms, COUNT(chosen_item_id) AS selections
+            FROM search_log
+            WHERE result_count = 0
+            GROUP BY query
+            ORDER BY count DESC
+            LIMIT 20
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+
+        Ok(SearchAnalytics {
+            total_searches,
+            top_queries,
+            zero_hit_queries,
+        })
+    }
+
+    /// Records a mutation in the audit and replication logs, attributed to the current caller,
+    /// and notifies the webhooks about it.
+    async fn publish<T: Serialize>(&self, kind: EventKind, entity_id: ID, data: &T) {
+        let actor = current_caller()
+            .map(|caller| caller.name)
+            .unwrap_or_else(|| "system".to_owned());
+
+        let entry = AuditEntry {
+            id: 0,
+            actor,
+            action: kind.as_str().to_owned(),
+            entity: kind.entity().to_owned(),
+            entity_id,
+            created_at: util::now(),
+            prev_hash: None,
+            hash: None,
+        };
+        if let Err(e) = self.append_audit_entry(entry).await {
+            error!("Could not write audit log: {}", e);
+        }
+        if let Err(e) = self.record_replication_event(kind, entity_id).await {
+            error!("Could not write replication log: {}", e);
+        }
+        if kind.entity() == "item" {
+            self.item_cache.remove(&entity_id);
+            let deleted = kind == EventKind::ItemDeleted;
+            if let Err(e) = self.bump_sync_version(entity_id, deleted).await {
+                error!("Could not update the sync version of item {}: {}", entity_id, e);
+            }
+        }
+
+        if self.features.is_enabled(Feature::Webhooks) {
+            self.webhooks.fire(kind, data);
+        }
+    }
+
+    /// Appends an entry to the audit log, chained to the latest one by its hash
+    async fn append_audit_entry(&self, entry: AuditEntry) -> Result<()> {
+        // the entries are chained in the order they are written, two at once would have the
+        // same predecessor
+        let _guard = self.audit_lock.lock().await;
+        let prev_hash = self
+            .audit_head()
+            .await?
+            .unwrap_or_else(|| AUDIT_CHAIN_GENESIS.to_owned());
+        let hash = audit_hash(&prev_hash, &entry);
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (actor, action, entity, entity_id, created_at, prev_hash, hash)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+            .bind(entry.actor)
+            .bind(entry.action)
+            .bind(entry.entity)
+            .bind(entry.entity_id)
+            .bind(entry.created_at)
+            .bind(prev_hash)
+            .bind(hash)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Hash of the latest audit log entry, `None` while the log is empty
+    async fn audit_head(&self) -> Result<Option<String>> {
+        Ok(sqlx::query_scalar::<_, Option<String>>(
+            "SELECT hash FROM audit_log ORDER BY id DESC LIMIT 1",
+        )
+            .fetch_optional(&self.conn)
+            .await?
+            .flatten())
+    }
+
+    /// Walks the audit log from its first entry and checks that every entry still hashes to what
+    /// was recorded for it and links to the entry before it. Entries removed from the end of the
+    /// log don't break the chain, they are noticed by the heads recorded in the daily snapshots.
+    pub async fn verify_audit_chain(&self) -> Result<AuditVerification> {
+        require_unscoped()?;
+        let mut verification = AuditVerification {
+            valid: true,
+            ..Default::default()
+        };
+
+        let mut prev_hash = AUDIT_CHAIN_GENESIS.to_owned();
+        let mut entries =
+            sqlx::query_as::<_, AuditEntry>("SELECT * FROM audit_log ORDER BY id").fetch(&self.conn);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let hash = audit_hash(&prev_hash, &entry);
+            let intact = entry.prev_hash.as_deref() == Some(prev_hash.as_str())
+                && entry.hash.as_deref() == Some(hash.as_str());
+            if !intact && verification.first_broken_id.is_none() {
+                verification.first_broken_id = Some(entry.id);
+            }
+            // the next entry links to the hash that was recorded, so only the changed entry
+            // itself is reported
+            prev_hash = entry.hash.unwrap_or(hash);
+            verification.entries += 1;
+        }
+        drop(entries);
+        if verification.entries > 0 {
+            verification.head = Some(prev_hash);
+        }
+
+        verification.missing_snapshot_heads = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT day FROM daily_snapshots
+            WHERE audit_head IS NOT NULL
+                AND audit_head NOT IN (SELECT hash FROM audit_log WHERE hash IS NOT NULL)
+            ORDER BY day
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+
+        verification.valid =
+            verification.first_broken_id.is_none() && verification.missing_snapshot_heads.is_empty();
+        if !verification.valid {
+            metrics::increment("audit_chain_broken_total");
+            warn!(
+                "The audit log was tampered with, first changed entry: {:?}, snapshots whose head is missing: {:?}",
+                verification.first_broken_id, verification.missing_snapshot_heads
+            );
+        }
+        Ok(verification)
+    }
+
+    /// Drops the cached search results and items after a change to items, or to anything a
+    /// search matches them by
+    fn items_changed(&self) {
+        self.search_cache.invalidate();
+        self.item_cache.invalidate();
+    }
+
+    async fn item_tags(&self, id: ID) -> Result<Vec<String>> {
+        Ok(sqlx::query("SELECT tag FROM item_tags WHERE item_id = ? ORDER BY tag")
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get("tag"))
+            .collect())
+    }
+
+    /// An item with its tags but without its images, `None` if it doesn't exist or is in the
+    /// trash
+    async fn item_snapshot(&self, id: ID) -> Result<Option<Item>> {
+        let item =
+            sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE id = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_optional(&self.conn)
+                .await?;
+
+        match item {
+            Some(item) => {
+                let mut item: Item = item.into();
+                item.tags = self.item_tags(id).await?;
+                Ok(Some(item))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Appends the state of an entity after a mutation to the replication log.
+    async fn record_replication_event(&self, kind: EventKind, entity_id: ID) -> Result<()> {
+        let event = ReplicationEvent {
+            seq: 0,
+            kind: kind.as_str().to_owned(),
+            created_at: util::now(),
+            change: Some(self.replication_change(kind, entity_id).await?),
+        };
+
+        sqlx::query("INSERT INTO replication_events (kind, payload, created_at) VALUES (?, ?, ?)")
+            .bind(&event.kind)
+            .bind(event.encode_to_vec())
+            .bind(event.created_at)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Current state of an entity. Items are sent without their images, a missing item is sent
+    /// as deleted.
+    async fn replication_change(&self, kind: EventKind, entity_id: ID) -> Result<Change> {
+        Ok(match kind.entity() {
+            "item" => match self.item_snapshot(entity_id).await? {
+                Some(item) => Change::Item(item.into()),
+                None => Change::DeletedItemId(entity_id),
+            },
+            "category" => {
+                let category: Category =
+                    sqlx::query_as::<_, DbCategory>("SELECT * FROM categories WHERE id = ?")
+                        .bind(entity_id)
+                        .fetch_one(&self.conn)
+                        .await?
+                        .into();
+                Change::Category(category.into())
+            }
+            "location" => {
+                let location = sqlx::query_as::<_, Location>("SELECT * FROM locations WHERE id = ?")
+                    .bind(entity_id)
+                    .fetch_one(&self.conn)
+                    .await?;
+                Change::Location(location.into())
+            }
+            // collection
+            _ => {
+                let collection =
+                    sqlx::query_as::<_, DbCollection>("SELECT * FROM collections WHERE id = ?")
+                        .bind(entity_id)
+                        .fetch_one(&self.conn)
+                        .await?;
+                let item_ids = sqlx::query(
+                    "SELECT item_id FROM collection_items WHERE collection_id = ? ORDER BY position",
+                )
+                    .bind(entity_id)
+                    .fetch_all(&self.conn)
+                    .await?
+                    .into_iter()
+                    .map(|row| row.get("item_id"))
+                    .collect();
+                Change::Collection(CollectionMembers {
+                    public: collection.public,
+                    collection: Some(Collection::from(collection).into()),
+                    item_ids,
+                })
+            }
+        })
+    }
+
+    /// Fills an empty replication log with the current state of all entities, so replicas of a
+    /// database from before the log existed start out complete.
+    async fn seed_replication_log(&self) -> Result<()> {
+        let events: i64 = sqlx::query("SELECT COUNT(*) AS count FROM replication_events")
+            .fetch_one(&self.conn)
+            .await?
+            .get("count");
+        if events > 0 {
+            return Ok(());
+        }
+
+        // parents before the entities referencing them
+        let tables = [
+            (EventKind::LocationCreated, "locations"),
+            (EventKind::CategoryCreated, "categories"),
+            (EventKind::ItemCreated, "items"),
+            (EventKind::CollectionCreated, "collections"),
+        ];
+        let mut seeded = 0;
+        for (kind, table) in tables {
+            let ids: Vec<ID> = sqlx::query(&format!("SELECT id FROM {} ORDER BY id", table))
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|row| row.get("id"))
+                .collect();
+            for id in ids {
+                self.record_replication_event(kind, id).await?;
+                seeded += 1;
+            }
+        }
+
+        if seeded > 0 {
+            debug!("Seeded the replication log with {} entities", seeded);
+        }
+        Ok(())
+    }
+
+    /// Streams the replication log after `since`, each event length delimited. Like the csv
+    /// export, the rows are read from the database while the response is sent.
+    pub fn replication_events(
+        &self,
+        since: i64,
+        limit: u32,
+    ) -> impl Stream<Item = Result<Vec<u8>>> {
+        let conn = self.conn.clone();
+        let (sender, receiver) = mpsc::channel::<Result<Vec<u8>>>(64);
+
+        tokio::spawn(async move {
+            let mut rows = sqlx::query(
+                "SELECT seq, payload FROM replication_events WHERE seq > ? ORDER BY seq LIMIT ?",
+            )
+                .bind(since)
+                .bind(limit)
+                .fetch(&conn);
+
+            while let Some(row) = rows.next().await {
+                let event = row.map_err(CustError::from).and_then(|row| {
+                    let payload: Vec<u8> = row.get("payload");
+                    let mut event = ReplicationEvent::decode(payload.as_slice())
+                        .map_err(anyhow::Error::from)?;
+                    event.seq = row.get("seq");
+                    Ok(event.encode_length_delimited_to_vec())
+                });
+                let failed = event.is_err();
+                if sender.send(event).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|event| (event, receiver))
+        })
+    }
+
+    /// Items as they were at `until`, replayed from the replication log: items created later are
+    /// missing, deleted ones are back with the quantity and location they had. The log holds
+    /// items without their images, so they are returned without them.
+    pub async fn items_as_of(&self, until: i64, ownership: &OwnershipFilter) -> Result<Vec<Item>> {
+        require_unscoped()?;
+        let first: Option<i64> =
+            sqlx::query("SELECT MIN(created_at) AS first FROM replication_events")
+                .fetch_one(&self.conn)
+                .await?
+                .get("first");
+        if let Some(first) = first.filter(|first| until < *first) {
+            return Err(CustError::new(
+                format!(
+                    "the event log starts at {}, earlier states are unknown",
+                    util::iso_date(first)
+                ),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+                .with_details(serde_json::json!({ "log_starts_at": first })));
+        }
+
+        let mut items: BTreeMap<ID, Item> = BTreeMap::new();
+        let mut rows = sqlx::query(
+            r#"
+            SELECT payload FROM replication_events
+            WHERE kind LIKE 'item.%' AND created_at <= ?
+            ORDER BY seq
+            "#,
+        )
+            .bind(until)
+            .fetch(&self.conn);
+        while let Some(row) = rows.next().await {
+            let payload: Vec<u8> = row?.get("payload");
+            let event = ReplicationEvent::decode(payload.as_slice()).map_err(anyhow::Error::from)?;
+            match event.change {
+                Some(Change::Item(item)) => {
+                    let item: Item = item.into();
+                    if let Some(id) = item.id {
+                        items.insert(id, item);
+                    }
+                }
+                Some(Change::DeletedItemId(id)) => {
+                    items.remove(&id);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(items.into_values().filter(|item| ownership.matches(item)).collect())
+    }
+
+    /// Sequence number of the last event applied from `primary`, 0 before the first one
+    pub async fn replication_position(&self, primary: &str) -> Result<i64> {
+        Ok(sqlx::query("SELECT seq FROM replication_state WHERE primary_url = ?")
+            .bind(primary)
+            .fetch_optional(&self.conn)
+            .await?
+            .map(|row| row.get("seq"))
+            .unwrap_or(0))
+    }
+
+    pub async fn set_replication_position(&self, primary: &str, seq: i64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO replication_state (primary_url, seq) VALUES (?, ?)
+            ON CONFLICT (primary_url) DO UPDATE SET seq = excluded.seq
+            "#,
+        )
+            .bind(primary)
+            .bind(seq)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Applies an event from the replication log of the primary. Entities keep the ids of the
+    /// primary and are upserted, so applying an event twice does no harm. Applied events are
+    /// neither published nor logged again.
+    pub async fn apply_replication_event(&self, event: ReplicationEvent) -> Result<()> {
+        match event.change {
+            Some(Change::Item(item)) => self.upsert_item(item.into()).await?,
+            Some(Change::DeletedItemId(id)) => {
+                let exists = sqlx::query("SELECT id FROM items WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(&self.conn)
+                    .await?
+                    .is_some();
+                if exists {
+                    self.remove_item(id).await?;
+                }
+            }
+            Some(Change::Category(category)) => {
+                let category: Category = category.into();
+                sqlx::query(
+                    r#"
+                    INSERT INTO categories (id, uuid, name, parent_category, unique_item_names,
+                        color, icon)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT (id) DO UPDATE SET uuid = COALESCE(?, categories.uuid),
+                        name = excluded.name, parent_category = excluded.parent_category,
+                        unique_item_names = excluded.unique_item_names, color = excluded.color,
+                        icon = excluded.icon
+                    "#,
+                )
+                    .bind(category.id)
+                    .bind(category.uuid.clone().unwrap_or_else(util::new_uuid))
+                    .bind(&category.name)
+                    .bind(category.parent_category)
+                    .bind(category.unique_item_names)
+                    .bind(&category.color)
+                    .bind(category.icon)
+                    .bind(&category.uuid)
+                    .execute(&self.conn)
+                    .await?;
+                if let (Some(id), Some(_)) = (category.id, &self.index) {
+                    self.jobs.push(Job::ReindexCategory(id));
+                }
+            }
+            Some(Change::Location(location)) => {
+                let location: Location = location.into();
+                sqlx::query(
+                    r#"
+                    INSERT INTO locations (id, uuid, name, parent_location) VALUES (?, ?, ?, ?)
+                    ON CONFLICT (id) DO UPDATE SET uuid = COALESCE(?, locations.uuid),
+                        name = excluded.name, parent_location = excluded.parent_location
+                    "#,
+                )
+                    .bind(location.id)
+                    .bind(location.uuid.clone().unwrap_or_else(util::new_uuid))
+                    .bind(&location.name)
+                    .bind(location.parent_location)
+                    .bind(&location.uuid)
+                    .execute(&self.conn)
+                    .await?;
+                if let (Some(id), Some(_)) = (location.id, &self.index) {
+                    self.jobs.push(Job::ReindexLocation(id));
+                }
+            }
+            Some(Change::Collection(members)) => self.apply_replicated_collection(members).await?,
+            None => {}
+        }
+
+        self.items_changed();
+        self.stats_cache.invalidate();
+        Ok(())
+    }
+
+    /// Replaces the fields and tags of an item, its images stay. Checked like a new item.
+    async fn update_item(&self, id: ID, item: Item) -> Result<Item> {
+        let mut tx = self.conn.begin().await?;
+        let item = replace_item(&mut tx, id, item).await?;
+        tx.commit().await?;
+        self.item_updated(&item).await?;
+
+        Ok(item)
+    }
+
+    /// Reindexes and publishes an item replaced by [`replace_item`]
+    async fn item_updated(&self, item: &Item) -> Result<()> {
+        let id = item.id.expect("updated items have an id");
+        self.reindex_item(item, true).await?;
+        self.items_changed();
+        self.stats_cache.invalidate();
+
+        self.publish(EventKind::ItemUpdated, id, &DbItem::from(item.clone()))
+            .await;
+
+        Ok(())
+    }
+
+    /// Inserts or updates an item with its id and tags and updates its index document, without
+    /// any checks
+    async fn upsert_item(&self, mut item: Item) -> Result<()> {
+        if item.id.is_none() {
+            return Ok(());
+        }
+
+        let mut tx = self.conn.begin().await?;
+        let existed = write_item(&mut tx, &mut item).await?;
+        tx.commit().await?;
+
+        self.reindex_item(&item, existed).await
+    }
+
+    /// Replaces the index document of an item, or adds the first one
+    async fn reindex_item(&self, item: &Item, existed: bool) -> Result<()> {
+        let Some(id) = item.id else {
+            return Ok(());
+        };
+        if let Some(index) = &self.index {
+            let data = self.analyzer.analyze(&self.document_text(item).await?);
+            self.record_terms(data.split_whitespace()).await?;
+            let document = Document::new(id as i64, data, &self.filter, &self.tokenizer);
+            let mut index = index.write().await;
+            self.index_writes.fetch_add(1, Ordering::Relaxed);
+            // a restored item may still have the document it had before it was deleted
+            let tombstoned = self.tombstones.lock().unwrap().remove(&id);
+            if existed || tombstoned {
+                let _ = index.remove_document(Arc::new(id as i64)).await?;
+            }
+            index.insert_document(document).await?;
+            drop(index);
+            if tombstoned {
+                self.drop_tombstones(&[id]).await;
+            }
+        }
+        self.embed_later(id);
+        Ok(())
+
+    }
+
+    /// Upserts a collection and replaces its items, items that stay keep their `added_at`.
+    async fn apply_replicated_collection(&self, members: CollectionMembers) -> Result<()> {
+        let Some(collection) = members.collection else {
+            return Ok(());
+        };
+        let Some(id) = collection.id else {
+            return Ok(());
+        };
+
+        let mut tx = self.conn.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO collections (id, uuid, name, public, color, icon, kind, smart_query)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET uuid = COALESCE(?, collections.uuid),
+                name = excluded.name, public = excluded.public, color = excluded.color,
+                icon = excluded.icon, kind = excluded.kind, smart_query = excluded.smart_query
+            "#,
+        )
+            .bind(id)
+            .bind(collection.uuid.clone().unwrap_or_else(util::new_uuid))
+            .bind(&collection.name)
+            .bind(members.public)
+            .bind(&collection.color)
+            .bind(collection.icon.as_deref().and_then(|icon| icon.parse::<TileIcon>().ok()))
+            .bind(
+                collection
+                    .kind
+                    .as_deref()
+                    .and_then(|kind| kind.parse::<CollectionKind>().ok())
+                    .unwrap_or_default(),
+            )
+            .bind(&collection.query)
+            .bind(&collection.uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        let added_at: HashMap<ID, Option<i64>> =
+            sqlx::query("SELECT item_id, added_at FROM collection_items WHERE collection_id = ?")
+                .bind(id)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|row| (row.get("item_id"), row.get("added_at")))
+                .collect();
+        sqlx::query("DELETE FROM collection_items WHERE collection_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let now = util::now();
+        for (position, item_id) in members.item_ids.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO collection_items (collection_id, item_id, position, added_at) VALUES (?, ?, ?, ?)",
+            )
+                .bind(id)
+                .bind(item_id)
+                .bind(position as i32)
+                .bind(added_at.get(item_id).copied().flatten().unwrap_or(now))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn sync_record_by_uuid(&self, uuid: &str) -> Result<Option<SyncRecord>> {
+        sqlx::query("SELECT * FROM sync_items WHERE uuid = ?")
+            .bind(uuid)
+            .fetch_optional(&self.conn)
+            .await?
+            .map(|row| SyncRecord::from_row(&row))
+            .transpose()
+    }
+
+    async fn sync_record_of_item(&self, item_id: ID) -> Result<Option<SyncRecord>> {
+        sqlx::query("SELECT * FROM sync_items WHERE item_id = ?")
+            .bind(item_id)
+            .fetch_optional(&self.conn)
+            .await?
+            .map(|row| SyncRecord::from_row(&row))
+            .transpose()
+    }
+
+    async fn save_sync_record(&self, record: &SyncRecord) -> Result<()> {
+        let version = serde_json::to_string(&record.version).map_err(anyhow::Error::from)?;
+        sqlx::query(
+            r#"
+            INSERT INTO sync_items (uuid, item_id, version, modified_at, deleted)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (uuid) DO UPDATE SET item_id = excluded.item_id,
+                version = excluded.version, modified_at = excluded.modified_at,
+                deleted = excluded.deleted
+            "#,
+        )
+            .bind(&record.uuid)
+            .bind(record.item_id)
+            .bind(version)
+            .bind(record.modified_at)
+            .bind(record.deleted)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Counts a change made outside of sync in the version of an item clients know, so their
+    /// older copies can't overwrite it.
+    async fn bump_sync_version(&self, item_id: ID, deleted: bool) -> Result<()> {
+        let Some(mut record) = self.sync_record_of_item(item_id).await? else {
+            return Ok(());
+        };
+        record.version.increment(SERVER_NODE);
+        record.modified_at = util::now();
+        record.deleted = deleted;
+        self.save_sync_record(&record).await
+    }
+
+    async fn current_sync_item(&self, record: &SyncRecord) -> Result<SyncItem> {
+        let item = if record.deleted {
+            None
+        } else {
+            self.item_snapshot(record.item_id).await?
+        };
+        Ok(record.to_sync_item(item))
+    }
+
+    /// Changes since `token` for offline clients, taken from the replication log. Items are sent
+    /// in their current state. Items no client has seen yet get a uuid, with the server as the
+    /// only node in their version.
+    pub async fn sync_pull(&self, token: Option<&str>, limit: u32) -> Result<SyncChanges> {
+        require_unscoped()?;
+        let since = token.map(parse_sync_token).transpose()?.unwrap_or(0);
+        let mut rows = sqlx::query(
+            "SELECT seq, payload FROM replication_events WHERE seq > ? ORDER BY seq LIMIT ?",
+        )
+            .bind(since)
+            .bind(limit + 1)
+            .fetch_all(&self.conn)
+            .await?;
+        let more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+
+        let mut position = since;
+        let mut item_ids = HashSet::new();
+        // only the latest state of a category or location in the batch is sent
+        let mut categories: HashMap<ID, Category> = HashMap::new();
+        let mut locations: HashMap<ID, Location> = HashMap::new();
+        for row in rows {
+            position = row.get("seq");
+            let payload: Vec<u8> = row.get("payload");
+            let event = ReplicationEvent::decode(payload.as_slice()).map_err(anyhow::Error::from)?;
+            match event.change {
+                Some(Change::Item(item)) => item_ids.extend(item.id),
+                Some(Change::DeletedItemId(id)) => {
+                    item_ids.insert(id);
+                }
+                Some(Change::Category(category)) => {
+                    if let Some(id) = category.id {
+                        categories.insert(id, category.into());
+                    }
+                }
+                Some(Change::Location(location)) => {
+                    if let Some(id) = location.id {
+                        locations.insert(id, location.into());
+                    }
+                }
+                Some(Change::Collection(_)) | None => {}
+            }
+        }
+
+        let mut items = Vec::with_capacity(item_ids.len());
+        for item_id in item_ids {
+            let item = self.item_snapshot(item_id).await?;
+            let record = match self.sync_record_of_item(item_id).await? {
+                Some(record) => record,
+                // deleted before any client saw it
+                None if item.is_none() => continue,
+                None => {
+                    let mut record = SyncRecord {
+                        // clients get to know the item by its public uuid
+                        uuid: item
+                            .as_ref()
+                            .and_then(|item| item.uuid.clone())
+                            .unwrap_or_else(util::new_uuid),
+                        item_id,
+                        version: VersionVector::default(),
+                        modified_at: util::now(),
+                        deleted: false,
+                    };
+                    record.version.increment(SERVER_NODE);
+                    self.save_sync_record(&record).await?;
+                    record
+                }
+            };
+            items.push(record.to_sync_item(item));
+        }
+
+        Ok(SyncChanges {
+            items,
+            categories: categories.into_values().collect(),
+            locations: locations.into_values().collect(),
+            token: sync_token(position),
+            more,
+        })
+    }
+
+    /// Applies the item changes of an offline client. Every change is resolved on its own
+    /// against the server's version, see [`resolve`], and answered with the state the client
+    /// should keep. Pushing the same changes again has no effect, so a push that failed halfway
+    /// can simply be retried.
+    pub async fn sync_push(&self, push: SyncPush) -> Result<Vec<SyncPushResult>> {
+        require_unscoped()?;
+        let node = util::sanitize_name(&push.node)?;
+        if node == SERVER_NODE {
+            return Err(CustError::new(
+                format!("clients can't push as {}", SERVER_NODE),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        for pushed in &push.items {
+            if !is_uuid(&pushed.uuid) {
+                return Err(CustError::new(
+                    format!("{} is not a uuid", pushed.uuid),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            // every change of a client is counted under its name
+            if !pushed.version.0.contains_key(node) {
+                return Err(CustError::new(
+                    format!("the version of {} has no counter for {}", pushed.uuid, node),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+
+        let mut results = Vec::with_capacity(push.items.len());
+        for pushed in push.items {
+            results.push(self.sync_push_item(pushed).await?);
+        }
+        Ok(results)
+    }
+
+    async fn sync_push_item(&self, pushed: SyncItem) -> Result<SyncPushResult> {
+        let Some(mut record) = self.sync_record_by_uuid(&pushed.uuid).await? else {
+            return self.sync_new_item(pushed).await;
+        };
+
+        let resolution = resolve(
+            &record.version,
+            record.modified_at,
+            &pushed.version,
+            pushed.modified_at,
+        );
+        if resolution == Resolution::Apply {
+            match pushed.item.clone().filter(|_| !pushed.deleted) {
+                None if !record.deleted => {
+                    self.delete_item(record.item_id).await?;
+                }
+                None => {}
+                // changed on the client after it was deleted here, it comes back as a new item
+                Some(item) if record.deleted => {
+                    let item = self.add_item(without_images(item)).await?;
+                    record.item_id = item.id.expect("added items have an id");
+                }
+                Some(item) => {
+                    self.update_item(record.item_id, without_images(item)).await?;
+                }
+            }
+            record.deleted = pushed.deleted || pushed.item.is_none();
+            record.modified_at = pushed.modified_at;
+        }
+        if resolution != Resolution::Duplicate {
+            // the resolved state includes both, whichever won
+            record.version = record.version.merge(&pushed.version);
+            self.save_sync_record(&record).await?;
+        }
+
+        Ok(SyncPushResult {
+            uuid: pushed.uuid,
+            item_id: Some(record.item_id),
+            applied: resolution != Resolution::Keep,
+            current: self.current_sync_item(&record).await?,
+        })
+    }
+
+    async fn sync_new_item(&self, pushed: SyncItem) -> Result<SyncPushResult> {
+        let item = match pushed.item.clone() {
+            Some(item) if !pushed.deleted => item,
+            // created and deleted before it was ever pushed
+            _ => {
+                return Ok(SyncPushResult {
+                    uuid: pushed.uuid.clone(),
+                    item_id: None,
+                    applied: true,
+                    current: pushed,
+                })
+            }
+        };
+
+        let item = self.add_item(without_images(item)).await?;
+        let record = SyncRecord {
+            uuid: pushed.uuid.clone(),
+            item_id: item.id.expect("added items have an id"),
+            version: pushed.version,
+            modified_at: pushed.modified_at,
+            deleted: false,
+        };
+        self.save_sync_record(&record).await?;
+
+        Ok(SyncPushResult {
+            uuid: pushed.uuid,
+            item_id: Some(record.item_id),
+            applied: true,
+            current: self.current_sync_item(&record).await?,
+        })
+    }
+
+    /// Applies the operations of a batch in one transaction. An atomic batch stops at the first
+    /// failing operation and rolls back all of them, the error names the operation. In
+    /// independent mode every operation runs in a savepoint of its own, a failing one is rolled
+    /// back and reported in its result while the others are kept. The index, caches and
+    /// subscribers are told about the operations once the transaction is committed.
+    #[instrument(skip_all, fields(operations = batch.operations.len()))]
+    pub async fn batch_mutate(&self, batch: BatchMutate) -> Result<Vec<BatchOperationResult>> {
+        require_unscoped()?;
+        if batch.operations.len() > MAX_BATCH_OPERATIONS {
+            return Err(CustError::new(
+                format!("a batch can have at most {} operations", MAX_BATCH_OPERATIONS),
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ));
+        }
+
+        let mut applied = vec![];
+        let mut results = Vec::with_capacity(batch.operations.len());
+        let mut tx = self.conn.begin().await?;
+        for (index, operation) in batch.operations.into_iter().enumerate() {
+            // the whole batch is rolled back, even in independent mode
+            check_deadline()?;
+            let outcome = match batch.mode {
+                BatchMode::Atomic => Ok(self
+                    .apply_batch_operation(&mut tx, operation)
+                    .await
+                    .map_err(|e| e.context(format!("operation {}", index)))?),
+                BatchMode::Independent => {
+                    let mut savepoint = sqlx::Connection::begin(&mut *tx).await?;
+                    let outcome = self.apply_batch_operation(&mut savepoint, operation).await;
+                    if outcome.is_ok() {
+                        savepoint.commit().await?;
+                    } else {
+                        savepoint.rollback().await?;
+                    }
+                    outcome
+                }
+            };
+
+            results.push(match outcome {
+                Ok(operation) => {
+                    let item = operation.item().cloned();
+                    applied.push(operation);
+                    BatchOperationResult {
+                        index,
+                        item,
+                        error: None,
+                    }
+                }
+                Err(e) => BatchOperationResult {
+                    index,
+                    item: None,
+                    error: Some(e),
+                },
+            });
+        }
+        check_deadline()?;
+        tx.commit().await?;
+
+        for operation in applied {
+            // committed already, so the batch succeeded even if e.g. the index lags behind
+            let outcome = match operation {
+                AppliedOperation::ItemCreated(item) => self.item_added(&item).await,
+                AppliedOperation::ItemUpdated(item) => self.item_updated(&item).await,
+                AppliedOperation::ItemDeleted(item, gallery) => {
+                    let id = item.id.expect("stored items have an id");
+                    self.item_removed(id, &gallery).await;
+                    self.publish(EventKind::ItemDeleted, id, &DbItem::from(item)).await;
+                    Ok(())
+                }
+                AppliedOperation::CollectionItemAdded(link) => {
+                    self.collection_item_added(link.item_id, link.collection_id).await;
+                    Ok(())
+                }
+                AppliedOperation::CollectionItemRemoved(link) => {
+                    self.collection_item_removed(link.item_id, link.collection_id).await;
+                    Ok(())
+                }
+            };
+            if let Err(e) = outcome {
+                error!("Could not finish a batch operation: {}", e);
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn apply_batch_operation(
+        &self,
+        tx: &mut Transaction<'_, Sqlite>,
+        operation: BatchOperation,
+    ) -> Result<AppliedOperation> {
+        Ok(match operation {
+            BatchOperation::CreateItem { item } => {
+                let item = self.prepare_new_item(item).await?;
+                AppliedOperation::ItemCreated(self.insert_item(tx, item).await?)
+            }
+            BatchOperation::UpdateItem { id, item } => {
+                AppliedOperation::ItemUpdated(replace_item(tx, id, item).await?)
+            }
+            BatchOperation::DeleteItem { id } => {
+                check_reference(tx, "items", "item_id", Some(id)).await?;
+                let (item, gallery) = self
+                    .delete_item_rows(tx, id, &mut ChangeReport::default())
+                    .await?;
+                AppliedOperation::ItemDeleted(item, gallery)
+            }
+            BatchOperation::LinkCollection {
+                item_id,
+                collection_id,
+            } => {
+                link_collection_item(tx, item_id, collection_id).await?;
+                AppliedOperation::CollectionItemAdded(CollectionItem {
+                    collection_id,
+                    item_id,
+                })
+            }
+            BatchOperation::UnlinkCollection {
+                item_id,
+                collection_id,
+            } => {
+                unlink_collection_item(tx, item_id, collection_id).await?;
+                AppliedOperation::CollectionItemRemoved(CollectionItem {
+                    collection_id,
+                    item_id,
+                })
+            }
+        })
+    }
+
+    pub async fn get_item_history(&self, id: ID) -> Result<Vec<AuditEntry>> {
+        self.authorize_item(id).await?;
+        Ok(sqlx::query_as::<_, AuditEntry>(
+            "SELECT * FROM audit_log WHERE entity = 'item' AND entity_id = ? ORDER BY id",
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?)
+    }
+
+    /// Creates an account. Without accounts or api tokens anyone may create the first one, after
+    /// that only unscoped admins may add accounts, as they choose the role of the new account.
+    pub async fn new_user(&self, user: NewUser) -> Result<User> {
+        if self.authenticator.enabled() {
+            require_unscoped()?;
+            if let Some(caller) = current_caller() {
+                caller.require(Role::Admin)?;
+            }
+        }
+        let username = util::sanitize_name(&user.username)?.to_owned();
+        if user.password.len() < MIN_PASSWORD_LENGTH {
+            return Err(CustError::new(
+                format!("password needs at least {} characters", MIN_PASSWORD_LENGTH),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let password_hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(user.password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+        })
+            .await
+            .map_err(anyhow::Error::from)?
+            .map_err(|e| anyhow::anyhow!("could not hash password: {}", e))?;
+
+        let result = sqlx::query(
+            "INSERT INTO users (username, password_hash, role, created_at) VALUES (?, ?, ?, ?)",
+        )
+            .bind(&username)
+            .bind(password_hash)
+            .bind(user.role)
+            .bind(util::now())
+            .execute(&self.conn)
+            .await;
+
+        let id = match result {
+            Ok(result) => result.last_insert_rowid() as ID,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(CustError::new(
+                    "username is already taken".to_string(),
+                    StatusCode::CONFLICT,
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.authenticator.set_has_users(true);
+
+        Ok(sqlx::query_as::<_, User>(
+            "SELECT id, username, role, created_at FROM users WHERE id = ?",
+        )
+            .bind(id)
+            .fetch_one(&self.conn)
+            .await?)
+    }
+
+    /// Checks the credentials and starts a new session, returning the signed session cookie value.
+    pub async fn login(&self, credentials: Credentials) -> Result<(User, String)> {
+        let invalid = || {
+            CustError::new(
+                "invalid username or password".to_string(),
+                StatusCode::UNAUTHORIZED,
+            )
+        };
+
+        let row = sqlx::query("SELECT id, password_hash FROM users WHERE username = ?")
+            .bind(credentials.username.trim())
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(invalid)?;
+        let id: ID = row.get("id");
+        let password_hash: String = row.get("password_hash");
+
+        let valid = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&password_hash)
+                .map(|hash| {
+                    Argon2::default()
+                        .verify_password(credentials.password.as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false)
+        })
+            .await
+            .map_err(anyhow::Error::from)?;
+        if !valid {
+            return Err(invalid());
+        }
+
+        let user = sqlx::query_as::<_, User>(
+            "SELECT id, username, role, created_at FROM users WHERE id = ?",
+        )
+            .bind(id)
+            .fetch_one(&self.conn)
+            .await?;
+        let cookie = self.start_session(user.id).await?;
+
+        Ok((user, cookie))
+    }
+
+    async fn start_session(&self, user_id: ID) -> Result<String> {
+        let session_id = util::random_token();
+        let now = util::now();
+
+        sqlx::query("INSERT INTO sessions (id, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)")
+            .bind(&session_id)
+            .bind(user_id)
+            .bind(now + self.session_ttl_secs())
+            .bind(now)
+            .execute(&self.conn)
+            .await?;
+
+        Ok(self.authenticator.sign_session(&session_id))
+    }
+
+    /// Resolves a session cookie into the logged in user.
+    pub async fn resolve_session(&self, cookie: &str) -> Result<AuthContext> {
+        let expired = || CustError::new("session expired".to_string(), StatusCode::UNAUTHORIZED);
+        let session_id = self.authenticator.verify_session(cookie).ok_or_else(expired)?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT users.username, users.role FROM sessions
+            JOIN users ON users.id = sessions.user_id
+            WHERE sessions.id = ? AND sessions.expires_at > ?
+            "#,
+        )
+            .bind(session_id)
+            .bind(util::now())
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(expired)?;
+
+        Ok(AuthContext {
+            name: row.get("username"),
+            role: row.get("role"),
+            scope: None,
+        })
+    }
+
+    /// Replaces a valid session with a new one, returning its cookie value.
+    pub async fn refresh_session(&self, cookie: &str) -> Result<String> {
+        let expired = || CustError::new("session expired".to_string(), StatusCode::UNAUTHORIZED);
+        let session_id = self.authenticator.verify_session(cookie).ok_or_else(expired)?;
+
+        let user_id: ID =
+            sqlx::query("DELETE FROM sessions WHERE id = ? AND expires_at > ? RETURNING user_id")
+                .bind(session_id)
+                .bind(util::now())
+                .fetch_optional(&self.conn)
+                .await?
+                .ok_or_else(expired)?
+                .get("user_id");
+
+        self.start_session(user_id).await
+    }
+
+    pub async fn logout(&self, cookie: &str) -> Result<()> {
+        if let Some(session_id) = self.authenticator.verify_session(cookie) {
+            sqlx::query("DELETE FROM sessions WHERE id = ?")
+                .bind(session_id)
+                .execute(&self.conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Purchase cost and current value of all items per category and currency
+    pub async fn category_valuations(&self, locale: Option<&str>) -> Result<Vec<Valuation>> {
+        require_unscoped()?;
+        let valuations = sqlx::query_as::<_, Valuation>(
+            r#"
+            SELECT categories.id AS group_id, categories.name AS name, items.currency AS currency,
+                COUNT(items.id) AS item_count,
+                TOTAL(items.purchase_price * COALESCE(items.quantity, 1)) AS total_purchase_price,
+                TOTAL(
+                    CASE WHEN items.ownership_state != 'disposed'
+                    THEN items.current_value * COALESCE(items.quantity, 1) END
+                ) AS total_current_value,
+                TOTAL(item_disposals.sale_price) AS total_proceeds
+            FROM items
+            LEFT JOIN categories ON categories.id = items.category_id
+            LEFT JOIN item_disposals ON item_disposals.item_id = items.id
+            WHERE items.deleted_at IS NULL
+            GROUP BY categories.id, items.currency
+            ORDER BY categories.name, items.currency
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+
+        Ok(format_valuations(valuations, locale))
+    }
+
+    /// Purchase cost and current value of all items per collection and currency
+    pub async fn collection_valuations(&self, locale: Option<&str>) -> Result<Vec<Valuation>> {
+        require_unscoped()?;
+        let valuations = sqlx::query_as::<_, Valuation>(
+            r#"
+            SELECT collections.id AS group_id, collections.name AS name, items.currency AS currency,
+                COUNT(items.id) AS item_count,
+                TOTAL(items.purchase_price * COALESCE(items.quantity, 1)) AS total_purchase_price,
+                TOTAL(
+                    CASE WHEN items.ownership_state != 'disposed'
+                    THEN items.current_value * COALESCE(items.quantity, 1) END
+                ) AS total_current_value,
+                TOTAL(item_disposals.sale_price) AS total_proceeds
+            FROM collection_items
+            JOIN items ON items.id = collection_items.item_id AND items.deleted_at IS NULL
+            JOIN collections ON collections.id = collection_items.collection_id
+            LEFT JOIN item_disposals ON item_disposals.item_id = items.id
+            GROUP BY collections.id, items.currency
+            ORDER BY collections.name, items.currency
+            "#,
+        )
+            .fetch_all(&self.conn)
+            .await?;
+
+        Ok(format_valuations(valuations, locale))
+    }
+
+    /// Owned items with their photos, purchase details and value, below `location_id` and in
+    /// `category_id` or one of its subcategories if set. Rendered as pdf by
+    /// [`Self::insurance_report_pdf`].
+    pub async fn insurance_report(&self, query: &InsuranceReportQuery) -> Result<InsuranceReport> {
+        require_unscoped()?;
+        let mut tx = self.conn.begin().await?;
+        check_reference(&mut tx, "locations", "location", query.location_id).await?;
+        check_reference(&mut tx, "categories", "category", query.category_id).await?;
+
+        let items: Vec<DbItem> =
+            sqlx::query_as(&format!("{} SELECT * FROM insured ORDER BY name, id", INSURED_ITEMS))
+                .bind(query.location_id)
+                .bind(query.category_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        let totals = sqlx::query_as::<_, Valuation>(&format!(
+            r#"
+            {}
+            SELECT NULL AS group_id, NULL AS name, currency, COUNT(id) AS item_count,
+                TOTAL(purchase_price * COALESCE(quantity, 1)) AS total_purchase_price,
+                TOTAL(current_value * COALESCE(quantity, 1)) AS total_current_value,
+                0.0 AS total_proceeds
+            FROM insured
+            GROUP BY currency
+            ORDER BY currency
+            "#,
+            INSURED_ITEMS
+        ))
+            .bind(query.location_id)
+            .bind(query.category_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        let gallery_sizes: HashMap<ID, i64> =
+            sqlx::query("SELECT item_id, COUNT(*) AS images FROM item_images GROUP BY item_id")
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|row| (row.get("item_id"), row.get("images")))
+                .collect();
+        tx.commit().await?;
+
+        let categories: HashMap<ID, Name> = self
+            .get_all_categories()
+            .await?
+            .into_iter()
+            .filter_map(|c| Some((c.id?, c.name)))
+            .collect();
+        let mut items: Vec<Item> = items.into_iter().map(Into::into).collect();
+        self.fill_location_paths(&mut items).await?;
+
+        let locale = query.locale.as_deref().unwrap_or("en");
+        let mut insured = Vec::with_capacity(items.len());
+        for mut item in items {
+            check_deadline()?;
+            let id = item.id.expect("stored items have an id");
+            self.hydrate_item(&mut item).await;
+            let currency = item.currency.as_deref().unwrap_or("");
+            let format = |amount: Option<Price>| {
+                amount.map(|amount| util::format_money(amount as f64, currency, locale))
+            };
+            insured.push(InsuredItem {
+                id,
+                category: item.category_id.and_then(|id| categories.get(&id).cloned()),
+                formatted_purchase_price: format(item.purchase_price),
+                formatted_current_value: format(item.current_value),
+                photo_count: gallery_sizes
+                    .get(&id)
+                    .copied()
+                    .unwrap_or(i64::from(item.thumbnail.is_some())),
+                name: item.name,
+                description: item.description,
+                location_path: item.location_path,
+                quantity: item.quantity,
+                purchase_date: item.purchase_date,
+                purchase_price: item.purchase_price,
+                current_value: item.current_value,
+                currency: item.currency,
+                thumbnail: item.thumbnail,
+            });
+        }
+
+        Ok(InsuranceReport {
+            generated_at: util::now(),
+            location_id: query.location_id,
+            category_id: query.category_id,
+            items: insured,
+            totals: format_valuations(totals, query.locale.as_deref()),
+        })
+    }
+
+    /// [`Self::insurance_report`] as a printable document
+    pub async fn insurance_report_pdf(&self, query: &InsuranceReportQuery) -> Result<Vec<u8>> {
+        let report = self.insurance_report(query).await?;
+        tokio::task::spawn_blocking(move || export::insurance_report_pdf(&report))
+            .await
+            .map_err(anyhow::Error::from)?
+    }
+
+    /// Loads the documents of the search index, so the first search doesn't wait for them. The
+    /// index counts as ready afterwards even if loading failed, searches then load it lazily.
+    pub async fn warm_up_index(&self) -> Result<()> {
+        let result = self.load_index().await;
+        let mut readiness = self.index_readiness.lock().unwrap();
+        readiness.ready = true;
+        metrics::set("search_index_ready", 1.0);
+        let (documents, load_seconds) = result?;
+        info!("Loaded {} search index documents in {:.3}s", documents, load_seconds);
+        metrics::set("search_index_documents", documents as f64);
+        metrics::set("search_index_load_seconds", load_seconds);
+        readiness.documents = Some(documents);
+        readiness.load_seconds = Some(load_seconds);
+        Ok(())
+    }
+
+    async fn load_index(&self) -> Result<(i64, f64)> {
+        let Some(index) = &self.index else {
+            return Ok((0, 0.0));
+        };
+
+        let started = Instant::now();
+        {
+            let index = index.read().await;
+            // any query reads the whole storage
+            let query = self.analyzer.analyze("warm up");
+            let _ = index
+                .query(
+                    &query,
+                    &self.tokenizer,
+                    &self.filter,
+                    Some(QueryOption::new().add(OptionType::TfIdf).build()),
+                )
+                .await?;
+        }
+        let load_seconds = started.elapsed().as_secs_f64();
+
+        let items: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items WHERE deleted_at IS NULL")
+            .fetch_one(&self.conn)
+            .await?;
+        let tombstones = self.tombstones.lock().unwrap().len() as i64;
+        Ok((items + tombstones, load_seconds))
+    }
+
+    pub async fn reindex_category(&self, id: ID) -> Result<()> {
+        let items = sqlx::query_as::<_, DbItem>(
+            "SELECT * FROM items WHERE category_id = ? AND deleted_at IS NULL",
+        )
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?;
+        self.reindex_items(items).await
+    }
+
+    pub async fn reindex_location(&self, id: ID) -> Result<()> {
+        let items = sqlx::query_as::<_, DbItem>(
+            "SELECT * FROM items WHERE location_id = ? AND deleted_at IS NULL",
+        )
+
+            .bind(id)
+            .fetch_all(&self.conn)
+            .await?;
+        self.reindex_items(items).await
+    }
+
+    /// Replaces the index documents of `items`. The index lock is only held per item, so searches
+    /// aren't blocked for the whole run.
+    async fn reindex_items(&self, items: Vec<DbItem>) -> Result<()> {
+        let Some(index) = &self.index else {
+            return Ok(());
+        };
+
+        let count = items.len();
+        for item in items {
+            let mut item: Item = item.into();
+            let Some(id) = item.id else {
+                continue;
+            };
+            item.tags = self.item_tags(id).await?;
+
+            let data = self.analyzer.analyze(&self.document_text(&item).await?);
+            self.record_terms(data.split_whitespace()).await?;
+            let document = Document::new(id as i64, data, &self.filter, &self.tokenizer);
+            let mut index = index.write().await;
+            self.index_writes.fetch_add(1, Ordering::Relaxed);
+            let _ = index.remove_document(Arc::new(id as i64)).await?;
+            index.insert_document(document).await?;
+        }
+
+        self.items_changed();
+        debug!("Reindexed {} items", count);
+        Ok(())
+    }
+
+    /// Removes the documents of all tombstoned items from the index.
+    pub async fn compact_tombstones(&self) -> Result<()> {
+        // the tombstones are read under the lock of the index, so the document of an item that
+        // is restored in the meantime isn't removed
+        let mut index = match &self.index {
+            Some(index) => Some(index.write().await),
+            None => None,
+        };
+        self.index_writes.fetch_add(1, Ordering::Relaxed);
+        let ids: Vec<ID> = self.tombstones.lock().unwrap().iter().copied().collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        // tombstones left from running with the index are simply dropped when using FTS5
+        if let Some(index) = &mut index {
+            for id in &ids {
+                let _ = index.remove_document(Arc::new(*id as i64)).await?;
+            }
+        }
+        {
+            let mut tombstones = self.tombstones.lock().unwrap();
+            for id in &ids {
+                tombstones.remove(id);
+            }
+        }
+        drop(index);
+
+        for id in &ids {
+            sqlx::query("DELETE FROM index_tombstones WHERE item_id = ?")
+                .bind(id)
+                .execute(&self.conn)
+                .await?;
+        }
+        debug!("Compacted {} index tombstones", ids.len());
+
+        Ok(())
+    }
+
+    /// Rebuilds the search index from the items that exist. Removing a document leaves its terms
+    /// in the vocabulary of the index, where they keep growing the memory and get suggested by
+    /// the autocorrect; a rebuilt index only knows the terms of live documents. The documents
+    /// are built while searches and writes go on, only swapping the index waits for them. If
+    /// the index was written in the meantime, the documents are built again.
+    pub async fn compact_index(&self) -> Result<IndexCompaction> {
+        require_unscoped()?;
+        let started = Instant::now();
+        let Some(index) = &self.index else {
+            // FTS5 drops the terms of deleted rows itself, optimizing merges its segments
+            sqlx::query("INSERT INTO items_fts (items_fts) VALUES ('optimize')")
+                .execute(&self.conn)
+                .await?;
+            let documents: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+                .fetch_one(&self.conn)
+                .await?;
+            return Ok(IndexCompaction {
+                documents: documents as usize,
+                seconds: started.elapsed().as_secs_f64(),
+                ..Default::default()
+            });
+        };
+
+        let mut attempt = 1;
+        let (mut index, documents, terms) = loop {
+            // writes are counted under the lock after their rows are committed, so a write
+            // counted before this is in the rows read below
+            let writes = self.index_writes.load(Ordering::Relaxed);
+            if attempt == COMPACTION_ATTEMPTS {
+                let index = index.write().await;
+                let (documents, terms) = self.index_documents().await?;
+                break (index, documents, terms);
+            }
+            let (documents, terms) = self.index_documents().await?;
+            let index = index.write().await;
+            if self.index_writes.load(Ordering::Relaxed) == writes {
+                break (index, documents, terms);
+            }
+            debug!("The index was written while it was compacted, building it again");
+            attempt += 1;
+        };
+        let document_count = documents.len();
+        let indexed: HashSet<ID> = documents.iter().map(|(id, _)| *id).collect();
+
+        // a new storage would load the documents of the old one from its file
+        let previous = self.index_storage.with_extension("json.compacting");
+        let moved = match tokio::fs::rename(&self.index_storage, &previous).await {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        let mut rebuilt = Index::new(None, MemoryStorage::new(self.index_storage.clone()));
+        for (_, document) in documents {
+            if let Err(e) = rebuilt.insert_document(document).await {
+                if moved {
+                    tokio::fs::rename(&previous, &self.index_storage).await?;
+                }
+                return Err(e.into());
+            }
+        }
+        *index = rebuilt;
+        // an item deleted after its document was built keeps its tombstone
+        let (tombstones, kept): (Vec<ID>, Vec<ID>) = {
+            let mut tombstones = self.tombstones.lock().unwrap();
+            let (removed, kept) = tombstones.iter().partition(|id| !indexed.contains(*id));
+            tombstones.retain(|id| indexed.contains(id));
+            (removed, kept)
+        };
+        drop(index);
+        if !kept.is_empty() {
+            self.jobs.push(Job::CompactTombstones);
+        }
+        if moved {
+            if let Err(e) = tokio::fs::remove_file(&previous).await {
+                error!("Could not remove the storage of the old index: {}", e);
+            }
+        }
+
+        for chunk in tombstones.chunks(TERMS_PER_STATEMENT) {
+            let sql = format!(
+                "DELETE FROM index_tombstones WHERE item_id IN ({})",
+                util::placeholders(chunk.len())
+            );
+            let mut query = sqlx::query(&sql);
+            for id in chunk {
+                query = query.bind(id);
+            }
+            query.execute(&self.conn).await?;
+        }
+
+        let known: Vec<String> = sqlx::query_scalar("SELECT term FROM index_terms")
+            .fetch_all(&self.conn)
+            .await?;
+        let pruned: Vec<&String> = known.iter().filter(|term| !terms.contains(*term)).collect();
+        for chunk in pruned.chunks(TERMS_PER_STATEMENT) {
+            let sql = format!(
+                "DELETE FROM index_terms WHERE term IN ({})",
+                util::placeholders(chunk.len())
+            );
+            let mut query = sqlx::query(&sql);
+            for term in chunk {
+                query = query.bind(term.as_str());
+            }
+            query.execute(&self.conn).await?;
+        }
+        // terms indexed before they were recorded
+        self.record_terms(terms.iter().map(String::as_str)).await?;
+
+        self.items_changed();
+        let compaction = IndexCompaction {
+            documents: document_count,
+            removed_documents: tombstones.len(),
+            terms: terms.len(),
+            pruned_terms: pruned.len(),
+            seconds: started.elapsed().as_secs_f64(),
+        };
+        metrics::set("search_index_documents", document_count as f64);
+        metrics::add("search_index_pruned_terms_total", pruned.len() as f64);
+        info!(
+            "Compacted the search index in {:.3}s: {} documents, {} terms, pruned {} terms",
+            compaction.seconds, compaction.documents, compaction.terms, compaction.pruned_terms
+        );
+        Ok(compaction)
+    }
+
+    /// Documents of all items outside the trash, for a new index, and the terms in them
+    async fn index_documents(
+        &self,
+    ) -> Result<(Vec<(ID, Document<i64>)>, HashSet<String>)> {
+        let items = sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE deleted_at IS NULL")
+            .fetch_all(&self.conn)
+            .await?;
+        let mut documents = Vec::with_capacity(items.len());
+        let mut terms = HashSet::new();
+        for item in items {
+            let mut item: Item = item.into();
+            let Some(id) = item.id else {
+                continue;
+            };
+            item.tags = self.item_tags(id).await?;
+            let data = self.analyzer.analyze(&self.document_text(&item).await?);
+            terms.extend(data.split_whitespace().map(str::to_owned));
+            documents.push((id, Document::new(id as i64, data, &self.filter, &self.tokenizer)));
+        }
+        Ok((documents, terms))
+    }
+
+    /// Adds terms to the vocabulary of the index kept in `index_terms`
+    async fn record_terms<'a>(&self, terms: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let terms: Vec<&str> = terms.into_iter().collect::<HashSet<_>>().into_iter().collect();
+        for chunk in terms.chunks(TERMS_PER_STATEMENT) {
+            let sql = format!(
+                "INSERT OR IGNORE INTO index_terms (term) VALUES {}",
+                vec!["(?)"; chunk.len()].join(", ")
+            );
+            let mut query = sqlx::query(&sql);
+            for term in chunk {
+                query = query.bind(*term);
+            }
+            query.execute(&self.conn).await?;
+        }
+        Ok(())
+    }
+
+    /// Purges items that were deleted longer ago than the retention of the trash: their rows,
+    /// their files and what is left of them in the index. Returns the number of purged items.
+    pub async fn purge_trash(&self) -> Result<usize> {
+        let retention_days = self.config.get().trash.retention_days;
+        if retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = util::now() - i64::from(retention_days) * 24 * 60 * 60;
+
+        let ids: Vec<ID> = sqlx::query_scalar("SELECT id FROM items WHERE deleted_at < ?")
+            .bind(cutoff)
+            .fetch_all(&self.conn)
+            .await?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut purged = Vec::with_capacity(ids.len());
+        let mut tx = self.conn.begin().await?;
+        for &id in &ids {
+            purged.push(
+                self.delete_item_rows(&mut tx, id, &mut ChangeReport::default())
+                    .await?,
+            );
+        }
+        tx.commit().await?;
+
+        for (item, gallery) in &purged {
+            if let Err(e) = self.item_files.delete(item).await {
+                error!("Could not delete the file of purged item {:?}: {}", item.id, e);
+            }
+            for image in gallery {
+                if let Err(e) = self.item_image_files.delete(image).await {
+                    error!("{}", e);
+                }
+            }
+        }
+
+        // documents are removed when items go to the trash, this only catches the ones that
+        // could not be removed then. The rows are gone already, so a failure doesn't stop the
+        // purge.
+
+        let mut removed = Vec::with_capacity(ids.len());
+        if let Some(index) = &self.index {
+            let mut index = index.write().await;
+            self.index_writes.fetch_add(1, Ordering::Relaxed);
+            for &id in &ids {
+                match index.remove_document(Arc::new(id as i64)).await {
+                    Ok(_) => removed.push(id),
+                    Err(e) => {
+                        error!("Could not remove the index document of purged item {}: {}", id, e);
+                        // its tombstone stays, compacting the index tries again
+                        self.tombstones.lock().unwrap().insert(id);
+                    }
+                }
+            }
+            if removed.len() < ids.len() {
+                self.jobs.push(Job::CompactTombstones);
+            }
+        } else {
+            removed.extend(&ids);
+        }
+        self.drop_tombstones(&removed).await;
+        {
+            let mut tombstones = self.tombstones.lock().unwrap();
+            for id in &removed {
+                tombstones.remove(id);
+            }
+        }
+
+        metrics::add("trash_purged_items_total", ids.len() as f64);
+        info!("Purged {} items deleted more than {} days ago", ids.len(), retention_days);
+        Ok(ids.len())
+    }
+
+    /// Takes the snapshot of the inventory for today, unless it was taken already, and reports
+    /// what changed since the previous one in the log and as a webhook event.
+    pub async fn take_daily_snapshot(&self) -> Result<Option<DailyDiff>> {
+        let now = util::now();
+        let day = util::iso_date(now);
+        let taken = sqlx::query("SELECT 1 FROM daily_snapshots WHERE day = ?")
+            .bind(&day)
+            .fetch_optional(&self.conn)
+            .await?
+            .is_some();
+        if taken {
+            return Ok(None);
+        }
+
+        let fingerprints = self.inventory_fingerprints().await?;
+        let payload = serde_json::to_string(&fingerprints).map_err(anyhow::Error::from)?;
+        // with the head of the audit log, removing entries from its end is noticed as well
+        let audit_head = self.audit_head().await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO daily_snapshots (day, payload, taken_at, audit_head) VALUES (?, ?, ?, ?)",
+        )
+            .bind(&day)
+            .bind(payload)
+            .bind(now)
+            .bind(audit_head)
+            .execute(&self.conn)
+            .await?;
+        sqlx::query("DELETE FROM daily_snapshots WHERE taken_at < ?")
+            .bind(now - DAILY_SNAPSHOT_RETENTION_DAYS * 24 * 60 * 60)
+            .execute(&self.conn)
+            .await?;
+        metrics::increment("daily_snapshots_total");
+
+        let previous = self.daily_snapshot_before(&day).await?;
+        let diff = diff_snapshots(day, previous, &fingerprints);
+        match &diff.previous_day {
+            Some(previous_day) => {
+                info!("Inventory changes since {}: {}", previous_day, diff.summary);
+                self.webhooks.fire(EventKind::DailyDiffReported, &diff);
+            }
+            None => info!("Took the first daily snapshot of the inventory"),
+        }
+        Ok(Some(diff))
+    }
+
+    /// What changed between the snapshot of `day`, or the latest one, and the one before it
+    pub async fn daily_diff(&self, day: Option<&str>) -> Result<DailyDiff> {
+        require_unscoped()?;
+        if let Some(day) = day.filter(|day| !util::is_iso_date(day)) {
+            return Err(CustError::new(
+                format!("{} is not a YYYY-MM-DD date", day),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let row = sqlx::query(
+            "SELECT day, payload FROM daily_snapshots WHERE ? IS NULL OR day = ? ORDER BY day DESC LIMIT 1",
+        )
+            .bind(day)
+            .bind(day)
+            .fetch_optional(&self.conn)
+            .await?
+            .ok_or_else(|| {
+                CustError::new("no daily snapshot was taken".to_string(), StatusCode::NOT_FOUND)
+            })?;
+        let (day, fingerprints) = read_snapshot(&row)?;
+        let previous = self.daily_snapshot_before(&day).await?;
+        Ok(diff_snapshots(day, previous, &fingerprints))
+    }
+
+    async fn daily_snapshot_before(&self, day: &str) -> Result<Option<(String, Fingerprints)>> {
+        sqlx::query("SELECT day, payload FROM daily_snapshots WHERE day < ? ORDER BY day DESC LIMIT 1")
+            .bind(day)
+            .fetch_optional(&self.conn)
+            .await?
+            .map(|row| read_snapshot(&row))
+            .transpose()
+    }
+
+    /// Hashes of all items, collections, categories, locations and item images. Images are
+    /// hashed by their file, so a replaced file counts as a change.
+    async fn inventory_fingerprints(&self) -> Result<Fingerprints> {
+        let items = sqlx::query_as::<_, DbItem>("SELECT * FROM items WHERE deleted_at IS NULL")
+            .fetch_all(&self.conn)
+            .await?;
+        let collections = sqlx::query_as::<_, DbCollection>("SELECT * FROM collections")
+            .fetch_all(&self.conn)
+            .await?;
+        let categories = sqlx::query_as::<_, DbCategory>("SELECT * FROM categories")
+            .fetch_all(&self.conn)
+            .await?;
+        let locations = sqlx::query_as::<_, Location>("SELECT * FROM locations")
+            .fetch_all(&self.conn)
+            .await?;
+        let images: BTreeMap<ID, String> =
+            sqlx::query("SELECT id, COALESCE(sha256, filename, '') AS hash FROM item_images")
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|row| (row.get("id"), row.get("hash")))
+                .collect();
+
+        Ok(Fingerprints::from([
+            ("items".to_owned(), row_fingerprints(&items, |item| item.id)?),
+            ("collections".to_owned(), row_fingerprints(&collections, |c| c.id)?),
+            ("categories".to_owned(), row_fingerprints(&categories, |c| c.id)?),
+            ("locations".to_owned(), row_fingerprints(&locations, |l| l.id)?),
+            ("images".to_owned(), images),
+        ]))
+    }
+
+    /// Refuses stored files of a [`DATA_FORMAT_VERSION`] newer than this server, which would be
+    /// misread. Legacy files are still read and only reported. Checked on startup, only the
+    /// headers of the files are read.
+    pub async fn check_data_format(&self) -> Result<()> {
+        let outdated = [
+            ("categories", self.category_files.outdated().await?),
+            ("collections", self.collection_files.outdated().await?),
+            ("items", self.item_files.outdated().await?),
+            ("item_images", self.item_image_files.outdated().await?),
+        ];
+        let mut legacy = 0;
+        for (directory, files) in &outdated {
+            for (name, version) in files {
+                if *version != 0 {
+                    return Err(CustError::new(
+                        format!(
+                            "{}/{} has the unknown data format version {}, this version of the \
+                             server only knows up to {}",
+                            directory, name, version, DATA_FORMAT_VERSION
+                        ),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+                legacy += 1;
+            }
+        }
+        if legacy > 0 {
+            warn!(
+                "{} stored files predate the versioned data format, run `find_me_pls migrate-data` \
+                 to upgrade them",
+                legacy
+            );
+        }
+
+        let unencrypted = self.category_files.unencrypted().await?.len()
+            + self.collection_files.unencrypted().await?.len()
+            + self.item_files.unencrypted().await?.len()
+            + self.item_image_files.unencrypted().await?.len();
+        if unencrypted > 0 {
+            warn!(
+                "{} stored files are not encrypted yet and can't be read, run \
+                 `find_me_pls migrate-data` to encrypt them",
+                unencrypted
+            );
+        }
+        Ok(())
+    }
+
+    /// Upgrades the stored files to the current [`DATA_FORMAT_VERSION`] in place and encrypts
+    /// them if file encryption is enabled, see [`FileStorage::migrate`]. Returns the number of
+    /// upgraded files.
+    pub async fn migrate_data(&self) -> Result<usize> {
+        let migrated = self.category_files.migrate().await?
+            + self.collection_files.migrate().await?
+            + self.item_files.migrate().await?
+            + self.item_image_files.migrate().await?;
+        info!("Migrated {} stored files to data format version {}", migrated, DATA_FORMAT_VERSION);
+        Ok(migrated)
+    }
+
+    pub async fn storage_usage(&self) -> Result<StorageUsage> {
+        require_unscoped()?;
+        let item_files = self.item_files.usage().await?;
+        let item_image_files = self.item_image_files.usage().await?;
+        let entities = [
+            ("item", &item_files),
+            ("item_image", &item_image_files),
+            ("category", &self.category_files.usage().await?),
+            ("collection", &self.collection_files.usage().await?),
+        ]
+            .into_iter()
+            .map(|(entity, files)| EntityStorageUsage {
+                entity: entity.to_owned(),
+                files: files.len(),
+                bytes: files.iter().map(|(_, size)| size).sum(),
+            })
+            .collect::<Vec<_>>();
+
+        // item files are named `<item id>.dat`, gallery images `<item id>_<image id>.dat`
+        let mut per_item: HashMap<ID, u64> = HashMap::new();
+        for (name, bytes) in item_files.iter().chain(item_image_files.iter()) {
+            let item_id = name
+                .strip_suffix(".dat")
+                .and_then(|name| name.split('_').next())
+                .and_then(|id| id.parse().ok());
+            if let Some(item_id) = item_id {
+                *per_item.entry(item_id).or_default() += bytes;
+            }
+        }
+
+        let mut items: Vec<ItemStorageUsage> = per_item
+            .into_iter()
+            .map(|(item_id, bytes)| ItemStorageUsage { item_id, bytes })
+            .collect();
+        items.sort_by_key(|i| std::cmp::Reverse(i.bytes));
+
+        Ok(StorageUsage {
+            total_bytes: entities.iter().map(|e| e.bytes).sum(),
+            entities,
+            items,
+        })
+    }
+}
+
+/// Checks an item like a new one and replaces the fields and tags of the item `id` with it.
+/// Its uuid stays the one the server gave it.
+async fn replace_item(tx: &mut Transaction<'_, Sqlite>, id: ID, mut item: Item) -> Result<Item> {
+    item.name = util::sanitize_name(&item.name)?.to_owned();
+    check_measurements(&item)?;
+    check_valuation(&mut item)?;
+    check_color(&mut item.color)?;
+
+    check_reference(tx, "items", "item_id", Some(id)).await?;
+    check_reference(tx, "categories", "category_id", item.category_id).await?;
+    check_reference(tx, "locations", "location_id", item.location_id).await?;
+
+    item.id = Some(id);
+    item.uuid = None;
+    write_item(tx, &mut item).await?;
+    Ok(item)
+}
+
+/// Inserts or updates an item with its id and tags, without any checks. Returns whether it
+/// existed before.
+async fn write_item(tx: &mut Transaction<'_, Sqlite>, item: &mut Item) -> Result<bool> {
+    let id = item.id.expect("written items have an id");
+    // neither replicated nor synced, the database keeps its own
+    let now = util::now();
+    item.created_at = Some(now);
+    item.updated_at = Some(now);
+    item.tags = util::normalize_tags(&item.tags);
+
+    // an item in the trash is taken out of it
+    let existed = sqlx::query("SELECT id FROM items WHERE id = ? AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .is_some();
+
+    let db_item = DbItem::from(item.clone());
+    sqlx::query(
+        r#"
+        INSERT INTO items (id, uuid, name, description, category_id, price, location_id,
+            quantity, width_cm, height_cm, depth_cm, weight_kg, purchase_price, current_value,
+            currency, purchase_date, ownership_state, state_changed_at, created_at, updated_at,
+            color, icon)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (id) DO UPDATE SET uuid = COALESCE(?, items.uuid), name = excluded.name,
+            description = excluded.description, category_id = excluded.category_id,
+            price = excluded.price, location_id = excluded.location_id,
+            quantity = excluded.quantity, width_cm = excluded.width_cm,
+            height_cm = excluded.height_cm, depth_cm = excluded.depth_cm,
+            weight_kg = excluded.weight_kg, purchase_price = excluded.purchase_price,
+            current_value = excluded.current_value, currency = excluded.currency,
+            purchase_date = excluded.purchase_date, ownership_state = excluded.ownership_state,
+            state_changed_at = excluded.state_changed_at, updated_at = excluded.updated_at,
+            color = excluded.color, icon = excluded.icon, deleted_at = NULL
+        "#,
+
+    )
+        .bind(id)
+        .bind(db_item.uuid.clone().unwrap_or_else(util::new_uuid))
+        .bind(db_item.name)
+        .bind(db_item.description)
+        .bind(db_item.category_id)
+        .bind(db_item.price)
+        .bind(db_item.location_id)
+        .bind(db_item.quantity)
+        .bind(db_item.width_cm)
+        .bind(db_item.height_cm)
+        .bind(db_item.depth_cm)
+        .bind(db_item.weight_kg)
+        .bind(db_item.purchase_price)
+        .bind(db_item.current_value)
+        .bind(db_item.currency)
+        .bind(db_item.purchase_date)
+        .bind(db_item.ownership_state)
+        .bind(db_item.state_changed_at)
+        .bind(db_item.created_at)
+        .bind(db_item.updated_at)
+        .bind(db_item.color)
+        .bind(db_item.icon)
+        .bind(db_item.uuid)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query("DELETE FROM item_tags WHERE item_id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    for tag in &item.tags {
+        sqlx::query("INSERT INTO item_tags (item_id, tag) VALUES (?, ?)")
+            .bind(id)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(existed)
+}
+
+/// Inserts the rows of a prepared item and its tags and sets its id. Its file is stored by the
+/// caller.
+async fn insert_item_rows(tx: &mut Transaction<'_, Sqlite>, mut item: Item) -> Result<Item> {
+    check_reference(tx, "categories", "category_id", item.category_id).await?;
+    check_reference(tx, "locations", "location_id", item.location_id).await?;
+
+    if let Some(category_id) = item.category_id {
+        let unique_item_names: Option<bool> =
+            sqlx::query("SELECT unique_item_names FROM categories WHERE id = ?")
+                .bind(category_id)
+                .fetch_optional(&mut **tx)
+                .await?
+                .map(|row| row.get("unique_item_names"));
+
+        if unique_item_names == Some(true) {
+            let name = util::normalize_name(&item.name);
+            let existing = sqlx::query(
+                "SELECT id, name FROM items WHERE category_id = ? AND deleted_at IS NULL",
+            )
+                .bind(category_id)
+                .fetch_all(&mut **tx)
+                .await?
+                .into_iter()
+                .find(|row| util::normalize_name(row.get("name")) == name);
+
+            if let Some(existing) = existing {
+                let existing_id: ID = existing.get("id");
+                return Err(CustError::new(
+                    "an item with this name already exists in the category".to_string(),
+                    StatusCode::CONFLICT,
+                )
+                    .with_details(serde_json::json!({ "existing_id": existing_id })));
+            }
+        }
+    }
+
+    // only ever set by the text recognition job
+    item.image_text = None;
+    let db_item = DbItem::from(item.clone());
+    sqlx::query("INSERT INTO items (uuid, name, description, category_id, price, location_id, quantity, width_cm, height_cm, depth_cm, weight_kg, purchase_price, current_value, currency, purchase_date, ownership_state, state_changed_at, created_at, updated_at, color, icon) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(db_item.uuid)
+        .bind(db_item.name)
+        .bind(db_item.description)
+        .bind(db_item.category_id)
+        .bind(db_item.price)
+        .bind(db_item.location_id)
+        .bind(db_item.quantity)
+        .bind(db_item.width_cm)
+        .bind(db_item.height_cm)
+        .bind(db_item.depth_cm)
+        .bind(db_item.weight_kg)
+        .bind(db_item.purchase_price)
+        .bind(db_item.current_value)
+        .bind(db_item.currency)
+        .bind(db_item.purchase_date)
+        .bind(db_item.ownership_state)
+        .bind(db_item.state_changed_at)
+        .bind(db_item.created_at)
+        .bind(db_item.updated_at)
+        .bind(db_item.color)
+        .bind(db_item.icon)
+        .execute(&mut **tx)
+        .await?;
+
+    let last_inserted = sqlx::query("SELECT last_insert_rowid() as id")
+        .fetch_one(&mut **tx)
+        .await?;
+
+    let id: ID = last_inserted.get("id");
+    item.id = Some(id);
+
+    for tag in &item.tags {
+        sqlx::query("INSERT INTO item_tags (item_id, tag) VALUES (?, ?)")
+            .bind(id)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(item)
+}
+
+/// Inserts a checked category with a new uuid and sets its id, which is returned. Its file is
+/// stored by the caller.
+async fn insert_category(tx: &mut Transaction<'_, Sqlite>, category: &mut Category) -> Result<ID> {
+    category.uuid = Some(util::new_uuid());
+    let id = sqlx::query("INSERT INTO categories (uuid, name, parent_category, unique_item_names, color, icon) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(category.uuid.clone())
+        .bind(category.name.clone())
+        .bind(category.parent_category)
+        .bind(category.unique_item_names)
+        .bind(category.color.clone())
+        .bind(category.icon)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid() as ID;
+    category.id = Some(id);
+    Ok(id)
+}
+
+/// Like [`insert_category`], for a location. Its parent has to exist.
+async fn insert_location(tx: &mut Transaction<'_, Sqlite>, location: &mut Location) -> Result<ID> {
+    check_reference(tx, "locations", "parent_location", location.parent_location).await?;
+
+    location.uuid = Some(util::new_uuid());
+    let id = sqlx::query("INSERT INTO locations (uuid, name, parent_location) VALUES (?, ?, ?)")
+        .bind(location.uuid.clone())
+        .bind(location.name.clone())
+        .bind(location.parent_location)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid() as ID;
+    location.id = Some(id);
+    Ok(id)
+}
+
+/// Like [`insert_category`], for a collection
+async fn insert_collection(tx: &mut Transaction<'_, Sqlite>, coll: &mut Collection) -> Result<ID> {
+    coll.uuid = Some(util::new_uuid());
+    let id = sqlx::query("INSERT INTO COLLECTIONS (uuid, name, public, color, icon, kind, smart_query) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(coll.uuid.clone())
+        .bind(coll.name.clone())
+        .bind(coll.public)
+        .bind(coll.color.clone())
+        .bind(coll.icon)
+        .bind(coll.kind)
+        .bind(coll.query.clone())
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid() as ID;
+    coll.id = Some(id);
+    Ok(id)
+}
+
+/// Appends an item to a collection, a 409 if it already is in it
+async fn link_collection_item(
+    tx: &mut Transaction<'_, Sqlite>,
+    item_id: ID,
+    collection_id: ID,
+) -> Result<()> {
+    check_reference(tx, "items", "item_id", Some(item_id)).await?;
+    check_reference(tx, "collections", "collection_id", Some(collection_id)).await?;
+    check_static_collection(tx, collection_id).await?;
+
+    // new items are appended to the end of the collection
+    let result = sqlx::query(
+        r#"
+        INSERT INTO collection_items (collection_id, item_id, position, added_at) VALUES (
+            ?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM collection_items WHERE collection_id = ?1), ?3
+        )
+        "#,
+    )
+        .bind(collection_id)
+        .bind(item_id)
+        .bind(util::now())
+        .execute(&mut **tx)
+        .await;
+
+    match result {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(CustError::new(
+                "item is already in the collection".to_string(),
+                StatusCode::CONFLICT,
+            )
+                .with_details(serde_json::json!({
+                    "item_id": item_id,
+                    "collection_id": collection_id,
+                })))
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+/// Removes an item from a collection and closes the gap in the positions
+async fn unlink_collection_item(
+    tx: &mut Transaction<'_, Sqlite>,
+    item_id: ID,
+    collection_id: ID,
+) -> Result<()> {
+    check_reference(tx, "items", "item_id", Some(item_id)).await?;
+    check_reference(tx, "collections", "collection_id", Some(collection_id)).await?;
+    check_static_collection(tx, collection_id).await?;
+
+    let position: i32 = sqlx::query(
+        "SELECT position FROM collection_items WHERE item_id = ? AND collection_id = ?",
+    )
+        .bind(item_id)
+        .bind(collection_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .map(|row| row.get("position"))
+        .ok_or_else(|| {
+            CustError::new(
+                format!("item {} is not in collection {}", item_id, collection_id),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+                .with_details(serde_json::json!({
+                    "item_id": item_id,
+                    "collection_id": collection_id,
+                }))
+        })?;
+
+    sqlx::query("DELETE FROM collection_items WHERE item_id = ? AND collection_id = ?")
+        .bind(item_id)
+        .bind(collection_id)
+        .execute(&mut **tx)
+        .await?;
+
+    // close the gap, so positions stay compact
+    sqlx::query(
+        "UPDATE collection_items SET position = position - 1 WHERE collection_id = ? AND position > ?",
+    )
+        .bind(collection_id)
+        .bind(position)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Fails with 409 if the collection is a smart one, its items can't be changed by hand
+async fn check_static_collection(
+    tx: &mut Transaction<'_, Sqlite>,
+    collection_id: ID,
+) -> Result<()> {
+    let kind: Option<CollectionKind> =
+        sqlx::query_scalar("SELECT kind FROM collections WHERE id = ?")
+            .bind(collection_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    if kind != Some(CollectionKind::Smart) {
+        return Ok(());
+    }
+
+    Err(CustError::new(
+        format!("the items of smart collection {} are computed from its query", collection_id),
+        StatusCode::CONFLICT,
+    )
+        .with_details(serde_json::json!({ "collection_id": collection_id })))
+}
+
+/// A smart collection needs a valid query, a static one can't have one
+fn check_smart_query(collection: &mut Collection) -> Result<()> {
+    match collection.kind {
+        CollectionKind::Static if collection.query.is_some() => Err(CustError::new(
+            "only smart collections have a query".to_string(),
+            StatusCode::BAD_REQUEST,
+        )),
+        CollectionKind::Static => Ok(()),
+        CollectionKind::Smart => {
+            let query = collection.query.as_deref().map(str::trim).unwrap_or_default();
+            SmartQuery::parse(query)?;
+            collection.query = Some(query.to_owned());
+            Ok(())
+        }
+    }
+}
+
+/// Fails with 422, naming the field, if `id` is set but `table` has no row with it. SQLite
+/// doesn't enforce foreign keys by default, so references are checked here.
+async fn check_reference(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    field: &str,
+    id: Option<ID>,
+) -> Result<()> {
+    let Some(id) = id else {
+        return Ok(());
+    };
+
+    // items in the trash can't be referenced
+    let live = if table == "items" { " AND deleted_at IS NULL" } else { "" };
+    let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = ?{}", table, live))
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .is_some();
+    if exists {
+        return Ok(());
+    }
+
+    Err(CustError::new(
+        format!("{} {} does not exist", field, id),
+        StatusCode::UNPROCESSABLE_ENTITY,
+    )
+        .with_details(serde_json::json!({ "field": field, "id": id })))
+}
+
+fn format_valuations(mut valuations: Vec<Valuation>, locale: Option<&str>) -> Vec<Valuation> {
+    let locale = locale.unwrap_or("en");
+    for valuation in &mut valuations {
+        let currency = valuation.currency.as_deref().unwrap_or("");
+        valuation.formatted_purchase_price =
+            util::format_money(valuation.total_purchase_price, currency, locale);
+        valuation.formatted_current_value =
+            util::format_money(valuation.total_current_value, currency, locale);
+        valuation.formatted_proceeds =
+            util::format_money(valuation.total_proceeds, currency, locale);
+    }
+    valuations
+}
+
+/// Multiplier of the text score of `item`, see [`RankingProfile`]
+fn ranking_factor(
+    profile: &RankingProfile,
+    item: &Item,
+    available: bool,
+    recently_used: bool,
+    favorite: bool,
+) -> f64 {
+    let in_stock = item.ownership_state == OwnershipState::Owned
+        && item.quantity.is_none_or(|quantity| quantity > 0);
+
+    let mut factor = 1.0;
+    for (applies, weight) in [
+        (in_stock, profile.in_stock),
+        (available, profile.available),
+        (recently_used, profile.recently_used),
+        (favorite, profile.favorite),
+    ] {
+        if applies {
+            factor *= 1.0 + weight;
+        }
+    }
+    factor
+}
+
+/// `ids` and all of their ancestors in `parents`, sorted
+fn with_ancestors(ids: impl IntoIterator<Item = ID>, parents: &HashMap<ID, Option<ID>>) -> Vec<ID> {
+    let mut found = HashSet::new();
+    for mut id in ids {
+        while parents.contains_key(&id) && found.insert(id) {
+            match parents[&id] {
+                Some(parent) => id = parent,
+                None => break,
+            }
+        }
+    }
+
+    let mut found: Vec<ID> = found.into_iter().collect();
+    found.sort_unstable();
+    found
+}
+
+/// Orders the entries of a bundle so parents come before their children. Parents missing from
+/// the bundle and cycles are rejected.
+fn parents_first(parents: &HashMap<ID, Option<ID>>, kind: &str) -> Result<Vec<ID>> {
+    let mut ordered = vec![];
+    let mut placed = HashSet::new();
+    let mut pending: Vec<ID> = parents.keys().copied().collect();
+    pending.sort_unstable();
+
+    while !pending.is_empty() {
+        let before = pending.len();
+        pending.retain(|id| match parents[id] {
+            Some(parent) if !placed.contains(&parent) => true,
+            _ => {
+                ordered.push(*id);
+                placed.insert(*id);
+                false
+            }
+        });
+        if pending.len() == before {
+            return Err(CustError::new(
+                format!("the parents of the {} {:?} are missing or form a cycle", kind, pending),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Names from the outermost location down to the container `item` is in. Stops at a cycle,
+/// which the api prevents.
+fn location_path(
+    item: &Item,
+    locations: &HashMap<ID, Location>,
+    containers: &HashMap<ID, (Name, Option<ID>, Option<ID>)>,
+) -> Vec<Name> {
+    let mut path = vec![];
+    let mut location = item.location_id;
+    let mut container = item.contained_in_item_id;
+    while let Some((name, location_id, outer)) = container.and_then(|id| containers.get(&id)) {
+        if path.len() > containers.len() {
+            break;
+        }
+        path.push(name.clone());
+        location = *location_id;
+        container = *outer;
+    }
+    let mut depth = 0;
+    while let Some(found) = location.and_then(|id| locations.get(&id)) {
+        if depth > locations.len() {
+            break;
+        }
+        path.push(found.name.clone());
+        location = found.parent_location;
+        depth += 1;
+    }
+    path.reverse();
+    path
+}
+
+/// Pushed items only carry their fields, images are uploaded on their own
+fn without_images(mut item: Item) -> Item {
+    item.id = None;
+    item.thumbnail = None;
+    item.fullsize = None;
+    item
+}
+
+/// Whether an item is within the scope of the caller, if it has one
+fn in_scope(access: &Option<AccessScope>, item: &Item) -> bool {
+    match (access, item.id) {
+        (None, _) => true,
+        (Some(access), Some(id)) => access.allows_item(id),
+        (Some(_), None) => false,
+    }
+}
+
+/// Merges the matches of the text and the vector search, both best first, into one list scored
+/// by `fusion`. Items found by both get the sum of their two scores.
+fn fuse_matches(
+    text: Vec<(f64, Item)>,
+    vectors: Vec<(f64, Item)>,
+    fusion: &FusionConfig,
+) -> Vec<(f64, Item)> {
+    let text_weight = fusion.text_weight.clamp(0.0, 1.0);
+    // text scores have no upper bound, the weighted sum compares them to the best one
+    let best_text = text.iter().map(|(score, _)| *score).fold(0.0, f64::max);
+
+    let mut fused: Vec<(f64, Item)> = Vec::with_capacity(text.len() + vectors.len());
+    let mut positions: HashMap<ID, usize> = HashMap::new();
+    for (matches, is_text) in [(text, true), (vectors, false)] {
+        for (rank, (score, item)) in matches.into_iter().enumerate() {
+            let score = match fusion.method {
+                FusionMethod::Rrf => 1.0 / (fusion.rrf_k + rank as f64 + 1.0),
+                FusionMethod::Weighted if is_text && best_text > 0.0 => {
+                    text_weight * score / best_text
+                }
+                FusionMethod::Weighted if is_text => 0.0,
+                FusionMethod::Weighted => (1.0 - text_weight) * score.max(0.0),
+            };
+            let id = item.id.expect("stored items have an id");
+            match positions.get(&id) {
+                Some(&position) => fused[position].0 += score,
+                None => {
+                    positions.insert(id, fused.len());
+                    fused.push((score, item));
+                }
+            }
+        }
+    }
+    // ties keep the text order
+    fused.sort_by(|x, y| y.0.total_cmp(&x.0));
+    fused
+}
+
+/// Normalizes a tile color, see [`util::normalize_color`]
+fn check_color(color: &mut Option<String>) -> Result<()> {
+    let Some(value) = color.as_deref() else {
+        return Ok(());
+    };
+    match util::normalize_color(value) {
+        Some(normalized) => {
+            *color = Some(normalized);
+            Ok(())
+        }
+        None => Err(CustError::new(
+            format!("{} is not a hex color like #3a7bd5", value),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
+
+fn check_measurements(item: &Item) -> Result<()> {
+    let measurements = [
+        ("width", item.width.map(Length::to_cm)),
+        ("height", item.height.map(Length::to_cm)),
+        ("depth", item.depth.map(Length::to_cm)),
+        ("weight", item.weight.map(Weight::to_kg)),
+    ];
+
+    for (field, value) in measurements {
+        if let Some(value) = value {
+            if !value.is_finite() || value < 0.0 {
+                return Err(CustError::new(
+                    format!("{} must be a non negative number", field),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_valuation(item: &mut Item) -> Result<()> {
+    for (field, value) in [
+        ("purchase_price", item.purchase_price),
+        ("current_value", item.current_value),
+    ] {
+        if matches!(value, Some(value) if !value.is_finite() || value < 0.0) {
+            return Err(CustError::new(
+                format!("{} must be a non negative number", field),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+
+    if let Some(currency) = &item.currency {
+        let currency = currency.trim().to_uppercase();
+        if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(CustError::new(
+                "currency must be a three letter ISO 4217 code".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        item.currency = Some(currency);
+    }
+
+    if let Some(date) = &item.purchase_date {
+        if !util::is_iso_date(date) {
+            return Err(CustError::new(
+                "purchase_date must be formatted as YYYY-MM-DD".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn reserved_by_someone_else(reservation: &Reservation) -> CustError {
+    CustError::new(
+        "item is reserved by someone else".to_string(),
+        StatusCode::CONFLICT,
+    )
+        .with_details(serde_json::json!({
+            "reserved_by": reservation.reserved_by,
+            "until": reservation.until,
+        }))
+}
+
+/// Hash of an item as it was requested, to tell a retry from a different request reusing an
+/// idempotency key
+fn item_fingerprint(item: &Item) -> Result<String> {
+    let body = serde_json::to_vec(item).map_err(anyhow::Error::from)?;
+    Ok(hex::encode(Sha256::digest(body)))
+}
+
+/// Short hashes of rows by their id, rows without an id are left out
+fn row_fingerprints<T: Serialize>(
+    rows: &[T],
+    id: impl Fn(&T) -> Option<ID>,
+) -> Result<BTreeMap<ID, String>> {
+    let mut fingerprints = BTreeMap::new();
+    for row in rows {
+        if let Some(id) = id(row) {
+            let body = serde_json::to_vec(row).map_err(anyhow::Error::from)?;
+            fingerprints.insert(id, hex::encode(&Sha256::digest(body)[..8]));
+        }
+    }
+    Ok(fingerprints)
+}
+
+/// Hash of an audit log entry, covering the hash of the entry before it so changing or removing
+/// an entry breaks the chain
+fn audit_hash(prev_hash: &str, entry: &AuditEntry) -> String {
+    let fields = (
+        prev_hash,
+        &entry.actor,
+        &entry.action,
+        &entry.entity,
+        entry.entity_id,
+        entry.created_at,
+    );
+    // serialized as a JSON array, so no field can run into the next one
+    let body = serde_json::to_vec(&fields).expect("audit entries are serializable");
+    hex::encode(Sha256::digest(body))
+}
+
+fn read_snapshot(row: &SqliteRow) -> Result<(String, Fingerprints)> {
+    let payload: String = row.get("payload");
+    let fingerprints = serde_json::from_str(&payload).map_err(anyhow::Error::from)?;
+    Ok((row.get("day"), fingerprints))
+}
+
+/// Compares the snapshot of `day` with the previous one. Without a previous snapshot only the
+/// counts are known.
+fn diff_snapshots(
+    day: String,
+    previous: Option<(String, Fingerprints)>,
+    current: &Fingerprints,
+) -> DailyDiff {
+    let empty = BTreeMap::new();
+    let mut entities = vec![];
+    let mut changes = vec![];
+    for (entity, singular, changed_verb) in SNAPSHOT_ENTITIES {
+        let now = current.get(entity).unwrap_or(&empty);
+        let mut diff = EntityDiff {
+            entity: entity.to_owned(),
+            count: now.len(),
+            ..EntityDiff::default()
+        };
+        if let Some((_, previous)) = &previous {
+            let before = previous.get(entity).unwrap_or(&empty);
+            diff.added = now.keys().filter(|id| !before.contains_key(*id)).count();
+            diff.removed = before.keys().filter(|id| !now.contains_key(*id)).count();
+            diff.changed = now
+                .iter()
+                .filter(|(id, hash)| before.get(*id).is_some_and(|old| old != *hash))
+                .count();
+        }
+
+        let name = |count: usize| if count == 1 { singular } else { entity };
+        if diff.added > 0 {
+            changes.push(format!("+{} {}", diff.added, name(diff.added)));
+        }
+        if diff.removed > 0 {
+            changes.push(format!("-{} {}", diff.removed, name(diff.removed)));
+        }
+        if diff.changed > 0 {
+            changes.push(format!("{} {} {}", diff.changed, name(diff.changed), changed_verb));
+        }
+        entities.push(diff);
+    }
+
+    let summary = if previous.is_none() {
+        "no earlier snapshot to compare with".to_owned()
+    } else if changes.is_empty() {
+        "no changes".to_owned()
+    } else {
+        changes.join(", ")
+    };
+    DailyDiff {
+        day,
+        previous_day: previous.map(|(day, _)| day),
+        entities,
+        summary,
+    }
+}
+
+fn stocktake_not_found(id: ID) -> CustError {
+    CustError::new(format!("stock-take {} does not exist", id), StatusCode::NOT_FOUND)
+}
+
+fn stocktake_ended(id: ID) -> CustError {
+    CustError::new(format!("stock-take {} has ended", id), StatusCode::CONFLICT)
+}
+
+/// Whose favorites are read and changed: the api token of the request, everyone without auth
+fn favorites_owner() -> String {
+    current_caller().map(|caller| caller.name).unwrap_or_default()
+}
+
+/// Trimmed text of a note, which must not be empty
+fn note_text(note: &NewItemNote) -> Result<&str> {
+    let text = note.text.trim();
+    if text.is_empty() {
+        return Err(CustError::new("a note needs a text".to_string(), StatusCode::BAD_REQUEST));
+    }
+    Ok(text)
+}
+
+fn note_not_found(id: ID) -> CustError {
+    CustError::new(format!("note {} does not exist", id), StatusCode::NOT_FOUND)
+}
+
+fn image_too_large(field: &str, size: usize, limit: usize) -> CustError {
+    CustError::new(
+        format!("{} is {} bytes, the limit is {} bytes", field, size, limit),
+        StatusCode::PAYLOAD_TOO_LARGE,
+    )
+        .with_details(serde_json::json!({ "field": field, "size": size, "limit": limit }))
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::path::PathBuf;
+
+    use doc_search::{Index, MemoryStorage};
+
+    use crate::{Analyzer, BusinessRules, ConfigHandle};
+
+    /// Business rules on an in-memory database with three items and two collections. Items and
+    /// collections are inserted directly, to keep the tests off the file storage.
+    pub async fn rules() -> BusinessRules {
+        rules_with_config(ConfigHandle::load("/nonexistent/config.json"), None).await
+    }
+
+    /// Like [`rules`], with the config read from `json`
+    pub async fn rules_with(name: &str, json: &str) -> BusinessRules {
+        let path = std::env::temp_dir()
+            .join(format!("find_me_pls_config_{}_{}.json", name, std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let config = ConfigHandle::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        rules_with_config(config, None).await
+    }
+
+    /// Like [`rules`], with the items searched in an index instead of FTS5. The index keeps its
+    /// documents in memory, a compacted one stores them in a temporary file named after `name`.
+    pub async fn rules_with_index(name: &str) -> BusinessRules {
+        let storage = std::env::temp_dir()
+            .join(format!("find_me_pls_index_{}_{}.json", name, std::process::id()));
+        let index = Index::new(None, MemoryStorage::new(storage.clone()));
+        let config = ConfigHandle::load("/nonexistent/config.json");
+        let mut rules = rules_with_config(config, Some(index)).await;
+        rules.index_storage = storage;
+
+        for id in 1..=3 {
+            let item = rules.get_item(id).await.unwrap();
+            rules.reindex_item(&item, false).await.unwrap();
+        }
+        rules
+    }
+
+    async fn rules_with_config(
+        config: ConfigHandle,
+        index: Option<Index<i64, MemoryStorage<i64>, PathBuf>>,
+    ) -> BusinessRules {
+        let conn = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let rules = BusinessRules::with_connection(conn, index, Analyzer::default(), config);
+        rules.init_db().await;
+
+        sqlx::query("INSERT INTO items (name) VALUES ('hammer'), ('saw'), ('tent')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO collections (name) VALUES ('Toolbox'), ('Camping')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules
+    }
+}
+
+#[cfg(test)]
+mod test_collection_items {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::OwnershipFilter;
+
+    #[tokio::test]
+    async fn items_are_linked_to_the_right_collection() {
+        let rules = rules().await;
+        rules.add_item_to_collection(3, 2).await.unwrap();
+
+        let items = rules
+            .get_items_in_collection(2, &OwnershipFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(3)]);
+
+        let collections = rules.get_item_collections(3).await.unwrap();
+        assert_eq!(collections.iter().map(|c| c.id).collect::<Vec<_>>(), [Some(2)]);
+
+        // the swapped pair must not exist
+        assert!(rules.get_item_collections(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn adding_an_item_twice_conflicts() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+
+        let error = rules.add_item_to_collection(1, 1).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+
+        // the same item in another collection is fine
+        rules.add_item_to_collection(1, 2).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn members_stored_swapped_are_repaired() {
+        let rules = rules().await;
+        // as the old insert stored item 3 in collection 2, item 1 in collection 1 and item 2 in
+        // the since deleted collection 9, which the foreign keys would reject now
+        sqlx::query(
+            r#"
+            PRAGMA foreign_keys = OFF;
+            INSERT INTO collection_items (collection_id, item_id, position) VALUES (3, 2, 0), (1, 1, 0), (2, 9, 0);
+            PRAGMA foreign_keys = ON;
+            DELETE FROM schema_migrations WHERE name = 'swap_collection_items';
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        rules.repair_collection_items().await;
+        let collections = rules.get_item_collections(3).await.unwrap();
+        assert_eq!(collections.iter().map(|c| c.id).collect::<Vec<_>>(), [Some(2)]);
+        let items = rules
+            .get_items_in_collection(1, &OwnershipFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(1)]);
+        assert!(rules.get_item_collections(2).await.unwrap().is_empty());
+
+        // only once, members stored since are right
+        rules.repair_collection_items().await;
+        assert_eq!(rules.get_item_collections(3).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn deleted_items_leave_their_collections() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.add_item_to_collection(2, 1).await.unwrap();
+        rules.add_item_to_collection(1, 2).await.unwrap();
+
+        rules.delete_item(1).await.unwrap();
+        assert_eq!(rules.get_collection(1).await.unwrap().item_count, 1);
+        assert_eq!(rules.get_collection(2).await.unwrap().item_count, 0);
+        let toolbox = rules.get_item_collections(2).await.unwrap();
+        assert_eq!(toolbox[0].item_count, 1);
+
+        // members of items deleted without removing them aren't counted either
+        sqlx::query(
+            r#"
+            PRAGMA foreign_keys = OFF;
+            INSERT INTO collection_items (collection_id, item_id, position) VALUES (1, 9, 5);
+            PRAGMA foreign_keys = ON;
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        let collections = rules.get_all_collections().await.unwrap();
+        let toolbox = collections.iter().find(|c| c.id == Some(1)).unwrap();
+        assert_eq!(toolbox.item_count, 1);
+    }
+}
+
+#[cfg(test)]
+mod test_reservations {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{util, NewReservation};
+
+    fn new_reservation(by: &str, until: i64) -> NewReservation {
+        NewReservation {
+            until,
+            reserved_by: Some(by.to_owned()),
+            note: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn active_reservations_conflict() {
+        let rules = rules().await;
+        let later = util::now() + 3600;
+
+        rules.reserve_item(1, new_reservation("alice", later)).await.unwrap();
+        let error = rules.reserve_item(1, new_reservation("bob", later)).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+
+        // the holder extends, other items are unaffected
+        let extended = rules.reserve_item(1, new_reservation("alice", later + 60)).await.unwrap();
+        assert_eq!(extended.until, later + 60);
+        rules.reserve_item(2, new_reservation("bob", later)).await.unwrap();
+
+        rules.release_reservation(1).await.unwrap();
+        assert!(rules.get_reservation(1).await.is_err());
+        rules.reserve_item(1, new_reservation("bob", later)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn expired_reservations_are_replaced() {
+        let rules = rules().await;
+        sqlx::query("INSERT INTO item_reservations (item_id, reserved_by, until, created_at) VALUES (1, 'alice', 1, 0)")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        assert!(rules.get_reservation(1).await.is_err());
+        let reservation = rules.reserve_item(1, new_reservation("bob", util::now() + 60)).await.unwrap();
+        assert_eq!(reservation.reserved_by, "bob");
+
+        let in_past = new_reservation("bob", util::now() - 1);
+        let error = rules.reserve_item(1, in_past).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[cfg(test)]
+mod test_search_scope {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
+
+    #[tokio::test]
+    async fn scope_restricts_the_results() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO locations (name, parent_location) VALUES ('Garage', NULL), ('Shelf', 1);
+            INSERT INTO items (name, location_id) VALUES ('red hammer', 2), ('hammer drill', NULL);
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        // hammer and the hammer drill are in the toolbox
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.add_item_to_collection(5, 1).await.unwrap();
+
+        let search = |scope: SearchScope| {
+            let rules = &rules;
+            async move {
+                let mut ids: Vec<_> = rules
+                    .find_items(
+                        "hammer".to_owned(),
+                        &MeasurementFilter::default(),
+                        &OwnershipFilter::default(),
+                        &scope,
+                        &SearchOptions::default(),
+                    )
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter_map(|item| item.id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        assert_eq!(search(SearchScope::default()).await, [1, 4, 5]);
+        let collection = SearchScope { collection: Some(1), ..Default::default() };
+        assert_eq!(search(collection).await, [1, 5]);
+        // the shelf is below the garage
+        let location = SearchScope { location: Some(1), ..Default::default() };
+        assert_eq!(search(location).await, [4]);
+
+        // the public api only finds the red hammer of the shared collection
+        sqlx::query("UPDATE collections SET public = 1 WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.add_item_to_collection(4, 2).await.unwrap();
+        let public = SearchScope { public_only: true, ..Default::default() };
+        assert_eq!(search(public).await, [4]);
+        assert!(rules.is_shared_publicly(4).await.unwrap());
+        assert!(!rules.is_shared_publicly(1).await.unwrap());
+
+        let missing = SearchScope { collection: Some(9), ..Default::default() };
+        let err = rules
+            .find_items(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &missing,
+                &SearchOptions::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[cfg(test)]
+mod test_replication {
+    use futures::StreamExt;
+    use prost::Message;
+
+    use super::test_support::rules;
+    use crate::find_me_pls::v2::replication_event::Change;
+    use crate::find_me_pls::v2::ReplicationEvent;
+    use crate::OwnershipFilter;
+
+    #[tokio::test]
+    async fn replica_applies_the_log_of_the_primary() {
+        let primary = rules().await;
+        primary.add_item_to_collection(3, 2).await.unwrap();
+        primary.add_item_to_collection(1, 2).await.unwrap();
+
+        let mut events = vec![];
+        let mut stream = Box::pin(primary.replication_events(0, 100));
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            events.push(ReplicationEvent::decode_length_delimited(chunk.as_slice()).unwrap());
+        }
+        assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2]);
+        assert!(matches!(events[1].change, Some(Change::Collection(_))));
+
+        let replica = rules().await;
+        for event in events.into_iter().skip(1) {
+            replica.apply_replication_event(event).await.unwrap();
+        }
+        let items = replica
+            .get_items_in_collection(2, &OwnershipFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(3), Some(1)]);
+
+        // applying the deletion twice is fine
+        for _ in 0..2 {
+            let deleted = ReplicationEvent {
+                seq: 3,
+                kind: "item.deleted".to_owned(),
+                created_at: 0,
+                change: Some(Change::DeletedItemId(2)),
+            };
+            replica.apply_replication_event(deleted).await.unwrap();
+        }
+        assert!(replica.get_item(2).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_sync {
+    use super::test_support::rules;
+    use crate::{Item, OwnershipState, SyncItem, SyncPush, VersionVector};
+
+    fn pushed(uuid: &str, name: &str, version: &[(&str, u64)], modified_at: i64) -> SyncItem {
+        SyncItem {
+            uuid: uuid.to_owned(),
+            item: Some(Item {
+                name: name.to_owned(),
+                ..Default::default()
+            }),
+            version: VersionVector(version.iter().map(|(n, c)| (n.to_string(), *c)).collect()),
+            deleted: false,
+            modified_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_changes_lose_against_newer_ones() {
+        let rules = rules().await;
+        rules
+            .set_ownership_state(1, OwnershipState::Wishlist)
+            .await
+            .unwrap();
+
+        let changes = rules.sync_pull(None, 10).await.unwrap();
+        assert_eq!(changes.items.len(), 1);
+        let uuid = changes.items[0].uuid.clone();
+        assert_eq!(changes.items[0].version.0["server"], 1);
+
+        let push = SyncPush {
+            node: "phone".to_owned(),
+            items: vec![pushed(&uuid, "claw hammer", &[("server", 1), ("phone", 1)], 10)],
+        };
+        let results = rules.sync_push(push.clone()).await.unwrap();
+        assert!(results[0].applied);
+        assert_eq!(rules.get_item(1).await.unwrap().name, "claw hammer");
+        // a retried push changes nothing
+        assert!(rules.sync_push(push).await.unwrap()[0].applied);
+
+        // concurrent with the phone's change, but older
+        let push = SyncPush {
+            node: "tablet".to_owned(),
+            items: vec![pushed(&uuid, "mallet", &[("server", 1), ("tablet", 1)], 5)],
+        };
+        let results = rules.sync_push(push).await.unwrap();
+        assert!(!results[0].applied);
+        let current = &results[0].current;
+        assert_eq!(current.item.as_ref().unwrap().name, "claw hammer");
+        assert_eq!(current.version.0.len(), 3);
+
+        let changes = rules.sync_pull(Some(&changes.token), 10).await.unwrap();
+        assert_eq!(changes.items.len(), 1);
+        assert!(!changes.more);
+    }
+}
+
+#[cfg(test)]
+mod test_uuids {
+    use std::collections::HashSet;
+
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use sqlx::Row;
+
+    use super::test_support::rules;
+    use crate::{is_uuid, Location};
+
+    #[tokio::test]
+    async fn rows_from_before_uuids_get_one() {
+        let rules = rules().await;
+        // the fixture inserts its items without uuids, like an older schema
+        rules.backfill_uuids("items").await;
+
+        let uuids: Vec<String> = sqlx::query("SELECT uuid FROM items ORDER BY id")
+            .fetch_all(&rules.conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.get("uuid"))
+            .collect();
+        assert!(uuids.iter().all(|uuid| is_uuid(uuid)));
+        assert_eq!(uuids.iter().collect::<HashSet<_>>().len(), 3);
+
+        let item = rules.get_item_by_uuid(&uuids[1]).await.unwrap();
+        assert_eq!(item.id, Some(2));
+        assert_eq!(item.uuid.as_ref(), Some(&uuids[1]));
+    }
+
+    #[tokio::test]
+    async fn uuids_are_generated_by_the_server() {
+        let rules = rules().await;
+        let location = rules
+            .new_location(Location {
+                id: None,
+                uuid: Some("chosen-by-the-client".to_owned()),
+                name: "Garage".to_owned(),
+                parent_location: None,
+            })
+            .await
+            .unwrap();
+        let uuid = location.uuid.unwrap();
+        assert!(is_uuid(&uuid));
+
+        let found = rules.get_location_by_uuid(&uuid).await.unwrap();
+        assert_eq!(found.id, location.id);
+
+        let error = rules.get_location_by_uuid("chosen-by-the-client").await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[cfg(test)]
+mod test_ranking {
+    use super::test_support::rules;
+    use crate::{util, Item, NewReservation, OwnershipState};
+
+    fn scored(id: i32, quantity: Option<i32>, state: OwnershipState) -> (f64, Item) {
+        let item = Item {
+            id: Some(id),
+            quantity,
+            ownership_state: state,
+            ..Default::default()
+        };
+        (1.0, item)
+    }
+
+    #[tokio::test]
+    async fn usable_items_rank_first() {
+        let rules = rules().await;
+        rules
+            .reserve_item(
+                3,
+                NewReservation {
+                    until: util::now() + 3600,
+                    reserved_by: Some("alice".to_owned()),
+                    note: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let ranked = rules
+            .apply_ranking(vec![
+                scored(1, Some(0), OwnershipState::Owned),
+                scored(2, Some(0), OwnershipState::Disposed),
+                scored(3, Some(2), OwnershipState::Owned),
+                scored(4, None, OwnershipState::Owned),
+            ])
+            .await
+            .unwrap();
+        let ids: Vec<_> = ranked.iter().filter_map(|(_, item)| item.id).collect();
+        // in stock beats available, empty and disposed items keep their order
+        assert_eq!(ids, [4, 3, 1, 2]);
+        assert!(ranked[0].0 > ranked[1].0);
+    }
+
+    #[tokio::test]
+    async fn favorites_rank_first() {
+        let rules = rules().await;
+        rules.favorite_item(2).await.unwrap();
+
+        let ranked = rules
+            .apply_ranking(vec![
+                scored(1, None, OwnershipState::Owned),
+                scored(2, None, OwnershipState::Owned),
+            ])
+            .await
+            .unwrap();
+        let ids: Vec<_> = ranked.iter().filter_map(|(_, item)| item.id).collect();
+        assert_eq!(ids, [2, 1]);
+    }
+}
+
+#[cfg(test)]
+mod test_collection_bundle {
+    use std::collections::HashMap;
+
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use super::{parents_first, with_ancestors};
+    use crate::{
+        BundleItem, Category, Collection, CollectionBundle, CollectionKind, Item,
+        COLLECTION_BUNDLE_VERSION,
+    };
+
+    fn bundle(items: Vec<Item>) -> CollectionBundle {
+        CollectionBundle {
+            version: COLLECTION_BUNDLE_VERSION,
+            exported_at: 0,
+            collection: Collection {
+                id: Some(1),
+                uuid: None,
+                name: "Toolbox".to_owned(),
+                thumbnail: None,
+                item_count: 0,
+                public: false,
+                color: None,
+                icon: None,
+                kind: CollectionKind::Static,
+                query: None,
+            },
+            categories: vec![],
+            locations: vec![],
+            items: items
+                .into_iter()
+                .map(|item| BundleItem { item, images: vec![] })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ancestors_are_included() {
+        let parents = HashMap::from([(1, None), (2, Some(1)), (3, Some(2)), (4, None)]);
+        assert_eq!(with_ancestors([3], &parents), [1, 2, 3]);
+        assert_eq!(with_ancestors([4, 2], &parents), [1, 2, 4]);
+    }
+
+    #[test]
+    fn parents_come_first() {
+        let parents = HashMap::from([(1, Some(3)), (2, None), (3, Some(2))]);
+        assert_eq!(parents_first(&parents, "categories").unwrap(), [2, 3, 1]);
+
+        let cycle = HashMap::from([(1, Some(2)), (2, Some(1))]);
+        assert!(parents_first(&cycle, "categories").is_err());
+        let missing = HashMap::from([(1, Some(5))]);
+        assert!(parents_first(&missing, "locations").is_err());
+    }
+
+    #[tokio::test]
+    async fn broken_bundles_create_nothing() {
+        let rules = rules().await;
+        let item = Item {
+            name: "drill".to_owned(),
+            category_id: Some(7),
+            ..Default::default()
+        };
+
+        let error = rules.import_collection_bundle(bundle(vec![item]), false).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let mut old = bundle(vec![]);
+        old.version = COLLECTION_BUNDLE_VERSION + 1;
+        let error = rules.import_collection_bundle(old, false).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(rules.get_all_collections().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn dry_runs_report_and_roll_back() {
+        let rules = rules().await;
+        let item = Item {
+            name: "drill".to_owned(),
+            category_id: Some(7),
+            tags: vec!["power".to_owned()],
+            ..Default::default()
+        };
+        let mut bundle = bundle(vec![item]);
+        bundle.collection.name = "Workshop".to_owned();
+        bundle.categories.push(Category {
+            id: Some(7),
+            uuid: None,
+            name: "Power tools".to_owned(),
+            parent_category: None,
+            thumbnail: None,
+            unique_item_names: false,
+            item_count: 0,
+            color: None,
+            icon: None,
+        });
+        let categories = rules.get_all_categories().await.unwrap().len();
+
+        let result = rules.import_collection_bundle(bundle.clone(), true).await.unwrap();
+        assert!(result.dry_run);
+        assert!(result.collection.is_none());
+        let created: Vec<_> =
+            result.changes.created.iter().map(|(t, n)| (t.as_str(), *n)).collect();
+        assert_eq!(
+            created,
+            [
+                ("categories", 1),
+                ("collection_items", 1),
+                ("collections", 1),
+                ("item_tags", 1),
+                ("items", 1),
+            ]
+        );
+        assert_eq!(rules.get_all_collections().await.unwrap().len(), 2);
+        assert_eq!(rules.get_all_categories().await.unwrap().len(), categories);
+
+        let result = rules.import_collection_bundle(bundle, false).await.unwrap();
+        assert!(!result.dry_run);
+        let collection = result.collection.unwrap();
+        assert_eq!(collection.item_count, 1);
+        assert_eq!(rules.get_all_collections().await.unwrap().len(), 3);
+        assert_eq!(rules.get_all_categories().await.unwrap().len(), categories + 1);
+    }
+}
+
+#[cfg(test)]
+mod test_access_log {
+    use sqlx::Row;
+
+    use super::test_support::rules;
+
+    #[tokio::test]
+    async fn accesses_are_buffered_until_flushed() {
+        let rules = rules().await;
+        rules.record_access(1);
+        rules.record_access(1);
+        assert!(rules.get_item(1).await.unwrap().last_accessed_at.is_some());
+
+        let logged: i64 = sqlx::query("SELECT COUNT(*) AS count FROM item_access")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap()
+            .get("count");
+        assert_eq!(logged, 0);
+
+        rules.flush_access_log().await.unwrap();
+        let count: i64 = sqlx::query("SELECT access_count FROM item_access WHERE item_id = 1")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap()
+            .get("access_count");
+        assert_eq!(count, 2);
+        assert!(rules.get_item(2).await.unwrap().last_accessed_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn stale_items_skip_recently_accessed() {
+        let rules = rules().await;
+        rules.record_access(2);
+
+        let stale = rules.stale_items(365).await.unwrap();
+        let ids: Vec<_> = stale.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn accesses_of_deleted_items_are_dropped() {
+        let rules = rules().await;
+        rules.record_access(3);
+        rules.delete_item(3).await.unwrap();
+        rules.flush_access_log().await.unwrap();
+
+        let logged: i64 = sqlx::query("SELECT COUNT(*) AS count FROM item_access")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap()
+            .get("count");
+        assert_eq!(logged, 0);
+    }
+}
+
+#[cfg(test)]
+mod test_bulk_delete {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use sqlx::Row;
+
+    use super::BusinessRules;
+    use super::test_support::rules;
+    use crate::{BulkDelete, BulkDeleteFilter, ChangeReport, ID};
+
+    fn by_ids(ids: &[ID]) -> BulkDelete {
+        BulkDelete {
+            ids: ids.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Result of a query that selects a `count`
+    async fn count(rules: &BusinessRules, sql: &str) -> i64 {
+        sqlx::query(sql).fetch_one(&rules.conn).await.unwrap().get("count")
+    }
+
+    fn in_collection(collection_id: i32, dry_run: bool) -> BulkDelete {
+        BulkDelete {
+            filter: Some(BulkDeleteFilter {
+                collection_id: Some(collection_id),
+                ..Default::default()
+            }),
+            dry_run,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_and_rolls_back() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.add_item_to_collection(2, 1).await.unwrap();
+        rules.add_item_to_collection(2, 2).await.unwrap();
+
+        let result = rules.bulk_delete_items(in_collection(1, true)).await.unwrap();
+        assert_eq!((result.matched, result.deleted), (2, 0));
+        assert_eq!(result.item_ids, [1, 2]);
+        assert_eq!(result.changes.updated["items"], 2);
+        assert!(result.changes.deleted.is_empty());
+        assert!(rules.get_item(1).await.is_ok());
+        let sql = "SELECT COUNT(*) AS count FROM items WHERE deleted_at IS NOT NULL";
+        assert_eq!(count(&rules, sql).await, 0);
+
+        let result = rules.bulk_delete_items(in_collection(1, false)).await.unwrap();
+        assert_eq!((result.matched, result.deleted), (2, 2));
+        assert!(rules.get_item(1).await.is_err());
+        assert!(rules.get_item(3).await.is_ok());
+        assert_eq!(rules.get_collection(2).await.unwrap().item_count, 0);
+
+        // the rows are kept in the trash
+        assert_eq!(count(&rules, sql).await, 2);
+        let sql = "SELECT COUNT(*) AS count FROM collection_items";
+        assert_eq!(count(&rules, sql).await, 3);
+    }
+
+    #[tokio::test]
+    async fn restored_items_come_back() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.bulk_delete_items(by_ids(&[1])).await.unwrap();
+        assert!(rules.get_item(1).await.is_err());
+        assert_eq!(rules.get_collection(1).await.unwrap().item_count, 0);
+
+        let item = rules.restore_item(1).await.unwrap();
+        assert_eq!(item.id, Some(1));
+        assert_eq!(rules.get_collection(1).await.unwrap().item_count, 1);
+        assert!(rules.get_item(1).await.is_ok());
+
+        // only items in the trash can be restored
+        let error = rules.restore_item(1).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn unknown_ids_are_not_counted() {
+        let rules = rules().await;
+        let request = BulkDelete {
+            ids: vec![3, 42],
+            ..Default::default()
+        };
+        let result = rules.bulk_delete_items(request).await.unwrap();
+        assert_eq!(result.item_ids, [3]);
+    }
+
+    #[tokio::test]
+    async fn everything_is_not_a_filter() {
+        let rules = rules().await;
+        let request = BulkDelete {
+            filter: Some(BulkDeleteFilter::default()),
+            ..Default::default()
+        };
+        let error = rules.bulk_delete_items(request).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Every table with rows of an item, the column referencing the item, or the condition
+    /// ending in it, and a statement adding a row for the item bound to `?`
+    const ITEM_ROWS: [(&str, &str, &str); 12] = [
+        ("item_tags", "item_id", "INSERT INTO item_tags (item_id, tag) VALUES (?, 'tools')"),
+        (
+            "collection_items",
+            "item_id",
+            "INSERT INTO collection_items (collection_id, item_id) VALUES (1, ?)",
+        ),
+        (
+            "item_translations",
+            "item_id",
+            "INSERT INTO item_translations (item_id, locale, name) VALUES (?, 'de', 'Hammer')",
+        ),
+        (
+            "item_notes",
+            "item_id",
+            "INSERT INTO item_notes (item_id, text, created_at) VALUES (?, 'handle is loose', 0)",
+        ),
+        (
+            "item_value_estimates",
+            "item_id",
+            r#"
+            INSERT INTO item_value_estimates
+                (item_id, value, currency, source, estimated_at, applied)
+            VALUES (?, 12.5, 'EUR', 'test', 0, 1)
+            "#,
+        ),
+        (
+            "item_disposals",
+            "item_id",
+            "INSERT INTO item_disposals (item_id, reason, date, created_at) VALUES (?, 'sold', '2024-01-01', 0)",
+        ),
+        (
+            "item_reservations",
+            "item_id",
+            "INSERT INTO item_reservations (item_id, reserved_by, until, created_at) VALUES (?, 'admin', 0, 0)",
+        ),
+        (
+            "item_access",
+            "item_id",
+            "INSERT INTO item_access (item_id, last_accessed_at, access_count) VALUES (?, 0, 1)",
+        ),
+        ("item_image_hashes", "item_id", "INSERT INTO item_image_hashes (item_id, hash) VALUES (?, 42)"),
+        (
+            "stocktake_items",
+            "item_id",
+            "INSERT INTO stocktake_items (stocktake_id, item_id, expected) VALUES (1, ?, 1)",
+        ),
+        (
+            "item_images",
+            "item_id",
+            "INSERT INTO item_images (item_id, position, created_at) VALUES (?, 0, 0)",
+        ),
+        (
+            "favorites",
+            "entity = 'item' AND entity_id",
+            "INSERT INTO favorites (owner, entity, entity_id, created_at) VALUES ('admin', 'item', ?, 0)",
+        ),
+    ];
+
+    #[tokio::test]
+    async fn rows_of_deleted_items_are_dropped() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO stocktakes (id, started_by, started_at) VALUES (1, 'admin', 0);
+            INSERT INTO favorites (owner, entity, entity_id, created_at)
+            VALUES ('admin', 'collection', 1, 0);
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        for (_, _, insert) in ITEM_ROWS {
+            for id in [1, 2] {
+                sqlx::query(insert).bind(id).execute(&rules.conn).await.unwrap();
+            }
+        }
+
+        let mut changes = ChangeReport::default();
+        let mut tx = rules.conn.begin().await.unwrap();
+        rules.delete_item_rows(&mut tx, 1, &mut changes).await.unwrap();
+        tx.commit().await.unwrap();
+
+        for (table, column, _) in ITEM_ROWS {
+            assert_eq!(changes.deleted[table], 1, "{}", table);
+            let sql = format!("SELECT COUNT(*) AS count FROM {} WHERE {} = 1", table, column);
+            assert_eq!(count(&rules, &sql).await, 0, "{}", table);
+            // the rows of other items are kept
+            let sql = format!("SELECT COUNT(*) AS count FROM {} WHERE {} = 2", table, column);
+            assert_eq!(count(&rules, &sql).await, 1, "{}", table);
+        }
+        // a collection with the same id stays pinned
+        let sql = "SELECT COUNT(*) AS count FROM favorites WHERE entity = 'collection'";
+        assert_eq!(count(&rules, sql).await, 1);
+        assert!(rules.get_item(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn contents_of_deleted_containers_are_taken_out() {
+        let rules = rules().await;
+        sqlx::query("UPDATE items SET contained_in_item_id = 1 WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let result = rules.bulk_delete_items(by_ids(&[1])).await.unwrap();
+        // the container went to the trash, its contents were taken out
+        assert_eq!(result.changes.updated["items"], 2);
+
+        assert_eq!(rules.get_item(2).await.unwrap().contained_in_item_id, None);
+        let sql = "SELECT COUNT(*) AS count FROM items WHERE contained_in_item_id IS NOT NULL";
+        assert_eq!(count(&rules, sql).await, 0);
+    }
+}
+
+#[cfg(test)]
+mod test_image_search {
+    use std::io::Cursor;
+
+    use base64::Engine;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+
+    use super::test_support::rules;
+    use crate::{images, ImageSearch};
+
+    fn gradient() -> Vec<u8> {
+        let image = RgbImage::from_fn(64, 48, |x, _| Rgb([(x * 4) as u8; 3]));
+        let mut png = Cursor::new(vec![]);
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    #[tokio::test]
+    async fn similar_images_are_found() {
+        let rules = rules().await;
+        let png = gradient();
+        let hash = images::perceptual_hash(&png).unwrap();
+        // hashed directly, the items of the fixture have no image files
+        sqlx::query(
+            "INSERT INTO item_image_hashes (item_id, hash) VALUES (1, ?), (2, ?), (3, NULL)",
+        )
+            .bind(hash as i64)
+            .bind(!hash as i64)
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let search = ImageSearch {
+            image: base64::engine::general_purpose::STANDARD.encode(&png),
+            limit: None,
+            max_distance: None,
+        };
+        let similar = rules.find_items_by_image(search.clone()).await.unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].item.id, Some(1));
+        assert_eq!(similar[0].distance, 0);
+        assert_eq!(similar[0].similarity, 1.0);
+
+        let everything = ImageSearch {
+            max_distance: Some(64),
+            ..search
+        };
+        let similar = rules.find_items_by_image(everything).await.unwrap();
+        let ids: Vec<_> = similar.iter().filter_map(|s| s.item.id).collect();
+        assert_eq!(ids, [1, 2]);
+    }
+}
+
+#[cfg(test)]
+mod test_semantic_search {
+    use axum::http::StatusCode;
+
+    use super::test_support::{rules, rules_with};
+    use super::{fuse_matches, BusinessRules};
+    use crate::auth::CALLER;
+    use crate::{
+        AuthContext, EmbeddingJobState, FusionConfig, FusionMethod, Item, MeasurementFilter,
+        OwnershipFilter, Role, SearchOptions, SearchScope, SearchSource, SemanticSearch,
+        TokenScope, ID,
+    };
+
+    fn search(query: &str) -> SemanticSearch {
+        SemanticSearch {
+            query: query.to_owned(),
+            limit: None,
+        }
+    }
+
+    async fn embedded_rules(name: &str) -> BusinessRules {
+        let config = r#"{ "search": { "semantic": { "backend": "memory" } } }"#;
+        let rules = rules_with(name, config).await;
+        let job = rules.start_embedding().await.unwrap();
+        rules.embed_all_items(job.id).await.unwrap();
+        rules
+    }
+
+    #[tokio::test]
+    async fn embedding_jobs_are_tracked() {
+        let config = r#"{ "search": { "semantic": { "backend": "memory" } } }"#;
+        let rules = rules_with("embedding_jobs", config).await;
+        let status = rules.embedding_status().await.unwrap();
+        assert_eq!(status.model, "hashing-v1-256");
+        assert_eq!(status.embedded_model, None);
+
+        let job = rules.start_embedding().await.unwrap();
+        assert_eq!(job.state, EmbeddingJobState::Running);
+        // one job at a time
+        let error = rules.start_embedding().await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        // the vectors are only swapped in once all items are embedded
+        assert!(rules.find_items_semantically(search("hammers")).await.unwrap().is_empty());
+
+        rules.embed_all_items(job.id).await.unwrap();
+        let status = rules.embedding_status().await.unwrap();
+        assert_eq!(status.embedded_model.as_deref(), Some("hashing-v1-256"));
+        let job = &status.jobs[0];
+        assert_eq!(job.state, EmbeddingJobState::Completed);
+        assert_eq!((job.total, job.done), (3, 3));
+        assert!(job.finished_at.is_some());
+        let found = rules.find_items_semantically(search("hammers")).await.unwrap();
+        assert_eq!(found[0].item.id, Some(1));
+
+        assert!(rules.start_embedding().await.is_ok());
+        let error = rules_with("embedding_jobs_off", "{}").await.start_embedding().await;
+        assert_eq!(error.unwrap_err().status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    #[tokio::test]
+    async fn items_are_found_by_similar_words() {
+        let rules = embedded_rules("semantic_search").await;
+        let found = rules.find_items_semantically(search("hammers")).await.unwrap();
+        assert_eq!(found[0].item.id, Some(1));
+
+        // the job queued by the delete removes the vector of the item
+        rules.delete_item(1).await.unwrap();
+        rules.embed_item(1).await.unwrap();
+        let found = rules.find_items_semantically(search("hammers")).await.unwrap();
+        assert!(found.iter().all(|m| m.item.id != Some(1)));
+        assert_eq!(found.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn scoped_callers_get_as_many_results_as_they_ask_for() {
+        let rules = embedded_rules("semantic_search_scope").await;
+        rules.add_item_to_collection(2, 1).await.unwrap();
+        let caller = AuthContext {
+            name: "roommate".to_owned(),
+            role: Role::ReadOnly,
+            scope: Some(TokenScope {
+                categories: vec![],
+                collections: vec![1],
+            }),
+        };
+
+        // the hammer is the best match, but out of scope
+        let search = SemanticSearch {
+            limit: Some(1),
+            ..search("hammers")
+        };
+        let found = CALLER
+            .scope(caller, rules.find_items_semantically(search))
+            .await
+            .unwrap();
+        assert_eq!(found.iter().map(|m| m.item.id).collect::<Vec<_>>(), [Some(2)]);
+    }
+
+    #[tokio::test]
+    async fn semantic_search_needs_a_backend_and_the_feature() {
+        let rules = rules().await;
+        let error = rules.find_items_semantically(search("tent")).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_IMPLEMENTED);
+
+        let config = r#"{
+            "search": { "semantic": { "backend": "memory" } },
+            "features": { "semantic_search": false }
+        }"#;
+        let rules = rules_with("semantic_search_disabled", config).await;
+        let error = rules.find_items_semantically(search("tent")).await.unwrap_err();
+        assert_eq!(error.code(), "feature_disabled");
+    }
+
+    async fn find(rules: &BusinessRules, query: &str, source: Option<SearchSource>) -> Vec<ID> {
+        let options = SearchOptions {
+            source,
+            ..Default::default()
+        };
+        rules
+            .find_items(
+                query.to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &options,
+            )
+            .await
+            .map(|items| items.into_iter().filter_map(|item| item.id).collect())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn text_searches_merge_both_sources() {
+        let embedded = embedded_rules("semantic_search_fusion").await;
+        // FTS5 has no stemming, only the vectors match the plural
+        assert!(find(&embedded, "hammers", Some(SearchSource::Text)).await.is_empty());
+        assert_eq!(find(&embedded, "hammers", Some(SearchSource::Vector)).await[0], 1);
+        assert_eq!(find(&embedded, "hammers", None).await[0], 1);
+        assert_eq!(find(&embedded, "tent", None).await[0], 3);
+
+        // without a vector backend only the text search runs, unless the vectors are asked for
+        let rules = rules().await;
+        assert!(find(&rules, "hammers", None).await.is_empty());
+        assert_eq!(find(&rules, "hammer", None).await, [1]);
+        let options = SearchOptions {
+            source: Some(SearchSource::Vector),
+            ..Default::default()
+        };
+        let error = rules
+            .find_items(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &options,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    fn matches(ids: &[ID], scores: &[f64]) -> Vec<(f64, Item)> {
+        ids.iter()
+            .zip(scores)
+            .map(|(id, score)| {
+                let item = Item {
+                    id: Some(*id),
+                    ..Default::default()
+                };
+                (*score, item)
+            })
+            .collect()
+    }
+
+    fn fused_ids(fused: Vec<(f64, Item)>) -> Vec<ID> {
+        fused.into_iter().filter_map(|(_, item)| item.id).collect()
+    }
+
+    #[test]
+    fn matches_of_both_sources_rank_first() {
+        let text = matches(&[1, 2], &[12.0, 3.0]);
+        let vectors = matches(&[2, 3], &[0.9, 0.8]);
+        let rrf = FusionConfig::default();
+        let fused = fuse_matches(text.clone(), vectors.clone(), &rrf);
+        assert_eq!(fused_ids(fused), [2, 1, 3]);
+
+        // only the text score counts with all of the weight on it
+        let weighted = FusionConfig {
+            method: FusionMethod::Weighted,
+            text_weight: 1.0,
+            ..Default::default()
+        };
+        let fused = fuse_matches(text.clone(), vectors.clone(), &weighted);
+        assert_eq!(fused[0].0, 1.0);
+        assert_eq!(fused_ids(fused), [1, 2, 3]);
+
+        let weighted = FusionConfig {
+            text_weight: 0.5,
+            ..weighted
+        };
+        let fused = fuse_matches(text, vectors, &weighted);
+        assert_eq!(fused_ids(fused), [2, 1, 3]);
+    }
+}
+
+#[cfg(test)]
+mod test_image_text {
+    use super::test_support::rules;
+    use crate::{MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
+
+    #[tokio::test]
+    async fn recognized_text_is_searchable() {
+        let rules = rules().await;
+        rules
+            .set_image_text(2, Some("BOSCH GSR 12V-15".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(
+            rules.get_item(2).await.unwrap().image_text.as_deref(),
+            Some("BOSCH GSR 12V-15")
+        );
+
+        let found = rules
+            .find_items(
+                "gsr".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await
+            .unwrap();
+        let ids: Vec<_> = found.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [2]);
+    }
+
+    #[tokio::test]
+    async fn items_without_an_image_lose_their_text() {
+        let rules = rules().await;
+        rules.set_image_text(1, Some("STANLEY".to_owned())).await.unwrap();
+        rules.recognize_item_text(1).await.unwrap();
+        assert_eq!(rules.get_item(1).await.unwrap().image_text, None);
+    }
+}
+
+#[cfg(test)]
+mod test_stocktake {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{Item, NewStocktake, StocktakeScan};
+
+    fn scan(item_id: Option<i32>, code: Option<&str>) -> StocktakeScan {
+        StocktakeScan {
+            item_id,
+            code: code.map(str::to_owned),
+        }
+    }
+
+    #[tokio::test]
+    async fn report_lists_missing_and_unexpected_items() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.add_item_to_collection(2, 1).await.unwrap();
+
+        let new = NewStocktake {
+            collection_id: Some(1),
+            ..Default::default()
+        };
+        let stocktake = rules.start_stocktake(new).await.unwrap();
+        assert_eq!(stocktake.expected_count, 2);
+
+        // scanners may pass on the start and stop characters of code 39
+        let (rules, id) = (&rules, stocktake.id);
+        let confirm = move |item_id, code| rules.confirm_stocktake_item(id, scan(item_id, code));
+        let found = confirm(None, Some("*1*")).await.unwrap();
+        assert!(found.expected && !found.already_confirmed);
+        assert!(confirm(Some(1), None).await.unwrap().already_confirmed);
+        assert!(!confirm(Some(3), None).await.unwrap().expected);
+
+        let report = rules.stocktake_report(stocktake.id).await.unwrap();
+        assert_eq!(report.stocktake.confirmed_count, 1);
+        let ids = |items: &[Item]| items.iter().filter_map(|item| item.id).collect::<Vec<_>>();
+        assert_eq!(ids(&report.missing), [2]);
+        assert_eq!(ids(&report.unexpected), [3]);
+    }
+
+    #[tokio::test]
+    async fn ended_stocktakes_reject_confirmations() {
+        let rules = rules().await;
+        let stocktake = rules.start_stocktake(NewStocktake::default()).await.unwrap();
+        assert_eq!(stocktake.expected_count, 3);
+        rules.end_stocktake(stocktake.id).await.unwrap();
+
+        let error = rules
+            .confirm_stocktake_item(stocktake.id, scan(Some(1), None))
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+        let error = rules.end_stocktake(stocktake.id).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+
+        let error = rules.confirm_stocktake_item(1, scan(None, Some("drill"))).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[cfg(test)]
+mod test_collection_targets {
+    use super::test_support::rules;
+    use crate::TargetEntry;
+
+    #[tokio::test]
+    async fn targets_are_matched_by_their_rule() {
+        let rules = rules().await;
+        rules.add_item_to_collection(1, 1).await.unwrap();
+        rules.add_item_to_collection(2, 1).await.unwrap();
+        sqlx::query("INSERT INTO item_tags (item_id, tag) VALUES (1, 'isbn:123')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        // plain names still work next to targets with a match rule
+        let entries: Vec<TargetEntry> = serde_json::from_value(serde_json::json!([
+            "Hammer",
+            { "name": "Saw set", "match_rule": "contains", "pattern": "saw" },
+            { "name": "Manual", "match_rule": "tag", "pattern": "manual", "catalog_ref": "ISBN:123" },
+            "Tent",
+        ]))
+            .unwrap();
+        let targets = rules.set_collection_targets(1, entries).await.unwrap();
+        let owned: Vec<_> = targets.iter().map(|target| target.item_id).collect();
+        assert_eq!(owned, [Some(1), Some(2), Some(1), None]);
+
+        let stats = rules.collection_stats(1).await.unwrap();
+        assert_eq!(stats.target_count, 4);
+        assert_eq!(stats.targets_owned, 3);
+        assert_eq!(stats.missing_targets, ["Tent"]);
+    }
+}
+
+#[cfg(test)]
+mod test_items_as_of {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{util, EventKind, OwnershipFilter};
+
+    #[tokio::test]
+    async fn items_are_replayed_from_the_log() {
+        let rules = rules().await;
+        for id in 1..=3 {
+            rules.record_replication_event(EventKind::ItemCreated, id).await.unwrap();
+        }
+        sqlx::query("UPDATE items SET quantity = 4 WHERE id = 1")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.record_replication_event(EventKind::ItemUpdated, 1).await.unwrap();
+        sqlx::query("UPDATE replication_events SET created_at = 1000 + seq * 1000")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.delete_item(2).await.unwrap();
+
+        let ownership = OwnershipFilter::default();
+        let before_update = rules.items_as_of(4500, &ownership).await.unwrap();
+        let ids: Vec<_> = before_update.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(before_update[0].quantity, None);
+
+        let now = rules.items_as_of(util::now(), &ownership).await.unwrap();
+        let ids: Vec<_> = now.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(now[0].quantity, Some(4));
+
+        let error = rules.items_as_of(1000, &ownership).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[cfg(test)]
+mod test_idempotency {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use super::{item_fingerprint, IDEMPOTENCY_RESERVATION_TIMEOUT_SECS};
+    use crate::auth::CALLER;
+    use crate::{util, AuthContext, Item, Role};
+
+    fn hammer() -> Item {
+        Item {
+            name: "hammer".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_keys_return_the_first_item() {
+        let rules = rules().await;
+        sqlx::query(
+            "INSERT INTO idempotency_keys (caller, key, fingerprint, item_id, created_at) \
+             VALUES ('', 'retry', ?, 1, ?), ('', 'pending', ?, NULL, ?)",
+        )
+            .bind(item_fingerprint(&hammer()).unwrap())
+            .bind(util::now())
+            .bind(item_fingerprint(&hammer()).unwrap())
+            .bind(util::now())
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let item = rules.add_item_idempotent(hammer(), Some("retry")).await.unwrap();
+        assert_eq!(item.id, Some(1));
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+
+        let mut saw = hammer();
+        saw.name = "saw".to_owned();
+        let error = rules.add_item_idempotent(saw, Some("retry")).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let error = rules.add_item_idempotent(hammer(), Some("pending")).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+
+        let error = rules.add_item_idempotent(hammer(), Some(" ")).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_forgotten() {
+        let rules = rules().await;
+        sqlx::query(
+            "INSERT INTO idempotency_keys (caller, key, fingerprint, item_id, created_at) \
+             VALUES ('', 'old', 'other', 1, 0)",
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        // a stale key with a different body would be a 422 if it were still remembered
+        let mut invalid = hammer();
+        invalid.name = String::new();
+        let error = rules.add_item_idempotent(invalid, Some("old")).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM idempotency_keys")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn abandoned_reservations_expire() {
+        let rules = rules().await;
+        sqlx::query(
+            "INSERT INTO idempotency_keys (caller, key, fingerprint, item_id, created_at) \
+             VALUES ('', 'crashed', ?, NULL, ?)",
+        )
+            .bind(item_fingerprint(&hammer()).unwrap())
+            .bind(util::now() - 2 * IDEMPOTENCY_RESERVATION_TIMEOUT_SECS)
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let item = rules.add_item_idempotent(hammer(), Some("crashed")).await.unwrap();
+        assert_eq!(item.id, Some(4));
+    }
+
+    #[tokio::test]
+    async fn keys_belong_to_their_caller() {
+        let rules = rules().await;
+        let caller = |name: &str| AuthContext {
+            name: name.to_owned(),
+            role: Role::ReadWrite,
+            scope: None,
+        };
+        let first = CALLER
+            .scope(caller("alice"), rules.add_item_idempotent(hammer(), Some("order-1")))
+            .await
+            .unwrap();
+
+        // the same key of another caller creates another item
+        let other = CALLER
+            .scope(caller("bob"), rules.add_item_idempotent(hammer(), Some("order-1")))
+            .await
+            .unwrap();
+        assert_ne!(first.id, other.id);
+
+        let retry = CALLER
+            .scope(caller("alice"), rules.add_item_idempotent(hammer(), Some("order-1")))
+            .await
+            .unwrap();
+        assert_eq!(retry.id, first.id);
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{BatchMode, BatchMutate, BatchOperation, Item};
+
+    async fn count(rules: &crate::BusinessRules, query: &str) -> i64 {
+        sqlx::query_scalar(query).fetch_one(&rules.conn).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn independent_operations_fail_on_their_own() {
+        let rules = rules().await;
+        let batch = BatchMutate {
+            operations: vec![
+                BatchOperation::LinkCollection {
+                    item_id: 1,
+                    collection_id: 1,
+                },
+                BatchOperation::LinkCollection {
+                    item_id: 1,
+                    collection_id: 9,
+                },
+                BatchOperation::UpdateItem {
+                    id: 2,
+                    item: Item {
+                        name: "Saw".to_owned(),
+                        tags: vec!["Wood".to_owned()],
+                        ..Default::default()
+                    },
+                },
+                BatchOperation::DeleteItem { id: 9 },
+            ],
+            mode: BatchMode::Independent,
+        };
+
+        let results = rules.batch_mutate(batch).await.unwrap();
+        let failed: Vec<_> = results.iter().map(|result| result.error.is_some()).collect();
+        assert_eq!(failed, [false, true, false, true]);
+        assert_eq!(results[2].item.as_ref().unwrap().name, "Saw");
+
+        assert_eq!(count(&rules, "SELECT COUNT(*) FROM collection_items").await, 1);
+        let item = rules.get_item(2).await.unwrap();
+        assert_eq!(item.name, "Saw");
+        assert_eq!(item.tags, ["wood"]);
+    }
+
+    #[tokio::test]
+    async fn atomic_batches_roll_back_everything() {
+        let rules = rules().await;
+        let batch = BatchMutate {
+            operations: vec![
+                BatchOperation::LinkCollection {
+                    item_id: 1,
+                    collection_id: 1,
+                },
+                BatchOperation::DeleteItem { id: 3 },
+                BatchOperation::UnlinkCollection {
+                    item_id: 2,
+                    collection_id: 1,
+                },
+            ],
+            mode: BatchMode::Atomic,
+        };
+
+        let error = rules.batch_mutate(batch).await.unwrap_err();
+        assert!(error.to_string().starts_with("operation 2:"));
+        assert_eq!(error.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        assert_eq!(count(&rules, "SELECT COUNT(*) FROM collection_items").await, 0);
+        assert_eq!(count(&rules, "SELECT COUNT(*) FROM items").await, 3);
+    }
+}
+
+#[cfg(test)]
+mod test_appearance {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{Appearance, TileIcon};
+
+    #[tokio::test]
+    async fn colors_are_normalized_and_validated() {
+        let rules = rules().await;
+        let collection = rules
+            .set_collection_appearance(
+                1,
+                Appearance {
+                    color: Some("#FA0".to_owned()),
+                    icon: Some(TileIcon::Tool),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(collection.color.as_deref(), Some("#ffaa00"));
+        assert_eq!(collection.icon, Some(TileIcon::Tool));
+
+        let invalid = Appearance {
+            color: Some("orange".to_owned()),
+            icon: None,
+        };
+        let error = rules.set_item_appearance(1, invalid).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[cfg(test)]
+mod test_category_move {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+
+    #[tokio::test]
+    async fn subtrees_move_without_cycles() {
+        let rules = rules().await;
+        // tools > power tools > drills, garden
+        sqlx::query(
+            r#"
+            INSERT INTO categories (id, name, parent_category) VALUES
+                (1, 'tools', NULL), (2, 'power tools', 1), (3, 'drills', 2), (4, 'garden', NULL)
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE items SET category_id = 3 WHERE id = 1")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let moved = rules.move_category(2, Some(4)).await.unwrap();
+        assert_eq!(moved.parent_category, Some(4));
+        let garden = rules
+            .get_all_categories()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|c| c.id == Some(4))
+            .unwrap();
+        // the drills came along with their items
+        assert_eq!(garden.item_count, 1);
+
+        let error = rules.move_category(4, Some(3)).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+        let error = rules.move_category(4, Some(4)).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+
+        let moved = rules.move_category(2, None).await.unwrap();
+        assert_eq!(moved.parent_category, None);
+        assert!(rules.move_category(9, None).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_index_warm_up {
+    use super::test_support::{rules, rules_with_index};
+
+    #[tokio::test]
+    async fn fts5_is_ready_without_a_warm_up() {
+        let rules = rules().await;
+        assert!(rules.index_readiness().ready);
+
+        rules.warm_up_index().await.unwrap();
+        let readiness = rules.index_readiness();
+        assert!(readiness.ready);
+        assert_eq!(readiness.documents, Some(0));
+    }
+
+    #[tokio::test]
+    async fn the_index_is_ready_after_its_warm_up() {
+        let rules = rules_with_index("warm_up").await;
+        assert!(!rules.index_readiness().ready);
+
+        rules.warm_up_index().await.unwrap();
+        let readiness = rules.index_readiness();
+        assert!(readiness.ready);
+        assert_eq!(readiness.documents, Some(3));
+        assert!(readiness.load_seconds.is_some());
+    }
+}
+
+#[cfg(test)]
+mod test_smart_collections {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::OwnershipFilter;
+
+    #[tokio::test]
+    async fn items_are_computed_from_the_query() {
+        let rules = rules().await;
+        sqlx::query("INSERT INTO item_tags (item_id, tag) VALUES (1, 'power'), (2, 'power')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO collections (name, kind, smart_query)
+            VALUES ('Power', 'smart', 'tag:power AND name:a')
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let items = rules
+            .get_items_in_collection(3, &OwnershipFilter::default())
+            .await
+            .unwrap();
+        let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["hammer", "saw"]);
+        assert_eq!(rules.get_collection(3).await.unwrap().item_count, 2);
+
+        let error = rules.add_item_to_collection(3, 3).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn clones_need_a_name_and_a_source() {
+        let rules = rules().await;
+        let error = rules
+            .clone_collection(1, "  ".to_owned(), false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let error = rules
+            .clone_collection(9, "Camping 2025".to_owned(), false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+
+        // nothing was created
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM collections")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
+
+#[cfg(test)]
+mod test_trash {
+    use super::test_support::rules;
+    use crate::{util, BulkDelete};
+
+    #[tokio::test]
+    async fn old_deleted_items_are_purged() {
+        let rules = rules().await;
+        let request = BulkDelete {
+            ids: vec![1, 2],
+            ..Default::default()
+        };
+        rules.bulk_delete_items(request).await.unwrap();
+        sqlx::query("UPDATE items SET deleted_at = ? WHERE id = 1")
+            .bind(util::now() - 31 * 24 * 60 * 60)
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        assert_eq!(rules.purge_trash().await.unwrap(), 1);
+        let kept: Vec<i32> = sqlx::query_scalar("SELECT id FROM items ORDER BY id")
+            .fetch_all(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(kept, [2, 3]);
+        assert_eq!(rules.purge_trash().await.unwrap(), 0);
+        assert!(rules.restore_item(2).await.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_users {
+    use axum::http::StatusCode;
+
+    use super::test_support::rules;
+    use crate::auth::CALLER;
+    use crate::{AuthContext, NewUser, Role};
+
+    fn new_user(username: &str, role: Role) -> NewUser {
+        NewUser {
+            username: username.to_owned(),
+            password: "correct horse".to_owned(),
+            role,
+        }
+    }
+
+    fn caller(role: Role) -> AuthContext {
+        AuthContext {
+            name: "session".to_owned(),
+            role,
+            scope: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn only_admins_add_accounts_after_the_first_one() {
+        let rules = rules().await;
+        // the first account bootstraps the server, nobody can log in yet
+        rules.new_user(new_user("owner", Role::Admin)).await.unwrap();
+
+        let error = CALLER
+            .scope(caller(Role::ReadWrite), rules.new_user(new_user("mallory", Role::Admin)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::FORBIDDEN);
+
+        CALLER
+            .scope(caller(Role::Admin), rules.new_user(new_user("guest", Role::ReadOnly)))
+            .await
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_token_scope {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use super::BusinessRules;
+    use crate::auth::CALLER;
+    use crate::{AuthContext, BulkDelete, ItemSort, OwnershipFilter, Role, TokenScope};
+
+    /// Kitchen > Spices and Garage, with the tent among the spices, the hammer in the garage
+    /// and the uncategorized saw in the toolbox
+    async fn scoped_rules() -> BusinessRules {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO categories (id, name, parent_category)
+            VALUES (1, 'Kitchen', NULL), (2, 'Spices', 1), (3, 'Garage', NULL)
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE items SET category_id = CASE id WHEN 1 THEN 3 WHEN 3 THEN 2 END")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.add_item_to_collection(2, 1).await.unwrap();
+        rules
+    }
+
+    fn caller(categories: Vec<i32>, collections: Vec<i32>) -> AuthContext {
+        AuthContext {
+            name: "roommate".to_owned(),
+            role: Role::ReadWrite,
+            scope: Some(TokenScope {
+                categories,
+                collections,
+            }),
+        }
+    }
+
+    fn status(error: crate::CustError) -> StatusCode {
+        error.into_response().status()
+    }
+
+    #[tokio::test]
+    async fn categories_include_their_subtree() {
+        let rules = scoped_rules().await;
+        CALLER
+            .scope(caller(vec![1], vec![]), async {
+                let items = rules
+                    .get_all_items(&OwnershipFilter::default(), &ItemSort::default())
+                    .await
+                    .unwrap();
+                assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(3)]);
+                assert!(rules.get_item(3).await.is_ok());
+                assert_eq!(status(rules.get_item(1).await.unwrap_err()), StatusCode::FORBIDDEN);
+
+                let categories = rules.get_all_categories().await.unwrap();
+                let mut ids: Vec<_> = categories.iter().map(|c| c.id).collect();
+                ids.sort();
+                assert_eq!(ids, [Some(1), Some(2)]);
+                assert!(rules.get_all_collections().await.unwrap().is_empty());
+
+                let error = rules.rename_category(3, "Workshop".to_owned()).await.unwrap_err();
+                assert_eq!(status(error), StatusCode::FORBIDDEN);
+                let error = rules.move_category(2, None).await.unwrap_err();
+                assert_eq!(status(error), StatusCode::FORBIDDEN);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn collections_grant_their_items() {
+        let rules = scoped_rules().await;
+        CALLER
+            .scope(caller(vec![], vec![1]), async {
+                assert!(rules.get_collection(1).await.is_ok());
+                assert!(rules.get_item(2).await.is_ok());
+                assert_eq!(status(rules.get_item(3).await.unwrap_err()), StatusCode::FORBIDDEN);
+                let error = rules.get_collection(2).await.unwrap_err();
+                assert_eq!(status(error), StatusCode::FORBIDDEN);
+                let error = rules.add_item_to_collection(3, 1).await.unwrap_err();
+                assert_eq!(status(error), StatusCode::FORBIDDEN);
+
+                let request = BulkDelete {
+                    ids: vec![2],
+                    filter: None,
+                    dry_run: true,
+                };
+                let error = rules.bulk_delete_items(request).await.unwrap_err();
+                assert_eq!(status(error), StatusCode::FORBIDDEN);
+            })
+            .await;
+
+        // callers without a scope reach everything
+        assert!(rules.get_item(3).await.is_ok());
+        assert_eq!(rules.get_all_collections().await.unwrap().len(), 2);
+    }
+}
+
+#[cfg(test)]
+mod test_translations {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use super::BusinessRules;
+    use crate::{
+        ItemTranslation, MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope,
+    };
+
+    fn zelt() -> ItemTranslation {
+        ItemTranslation {
+            locale: String::new(),
+            name: "Zelt".to_owned(),
+            description: Some("Für zwei Personen".to_owned()),
+        }
+    }
+
+    async fn found(rules: &BusinessRules, query: &str) -> Vec<i32> {
+        let found = rules
+            .find_items(
+                query.to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await;
+        match found {
+            Ok(items) => items.iter().filter_map(|item| item.id).collect(),
+            Err(e) if e.status() == StatusCode::NOT_FOUND => vec![],
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn translations_are_searchable_and_localized() {
+        let rules = rules().await;
+        let translation = rules.set_item_translation(3, "DE", zelt()).await.unwrap();
+        assert_eq!(translation.locale, "de");
+        assert_eq!(found(&rules, "zelt").await, [3]);
+        assert_eq!(found(&rules, "tent").await, [3]);
+
+        let mut items = vec![rules.get_item(3).await.unwrap()];
+        rules
+            .localize_items(&mut items, &["de-at".to_owned(), "de".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(items[0].name, "Zelt");
+
+        // the default locale comes first, the item keeps its name
+        let mut items = vec![rules.get_item(3).await.unwrap()];
+        rules
+            .localize_items(&mut items, &["en".to_owned(), "de".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(items[0].name, "tent");
+
+        rules.delete_item_translation(3, "de").await.unwrap();
+        assert!(found(&rules, "zelt").await.is_empty());
+        assert!(rules.get_item_translations(3).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_translations_are_rejected() {
+        let rules = rules().await;
+        let status = |e: crate::CustError| e.into_response().status();
+
+        let error = rules.set_item_translation(3, "en", zelt()).await.unwrap_err();
+        assert_eq!(status(error), StatusCode::BAD_REQUEST);
+        let error = rules.set_item_translation(3, "german", zelt()).await.unwrap_err();
+        assert_eq!(status(error), StatusCode::BAD_REQUEST);
+        let error = rules.set_item_translation(42, "de", zelt()).await.unwrap_err();
+        assert_eq!(status(error), StatusCode::NOT_FOUND);
+        let error = rules.delete_item_translation(3, "fr").await.unwrap_err();
+        assert_eq!(status(error), StatusCode::NOT_FOUND);
+    }
+}
+
+#[cfg(test)]
+mod test_price_estimates {
+    use axum::async_trait;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{EstimateQuery, PriceProvider, PriceQuery, PriceQuote, Result};
+
+    /// Knows the price of tents, in euros
+    struct TentPrices;
+
+    #[async_trait]
+    impl PriceProvider for TentPrices {
+        fn name(&self) -> &str {
+            "tent-prices"
+        }
+
+        async fn lookup(&self, query: &PriceQuery) -> Result<Option<PriceQuote>> {
+            Ok((query.name == "tent").then(|| PriceQuote {
+                value: 120.0,
+                currency: "eur".to_owned(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn estimates_become_the_current_value() {
+        let rules = rules().await.with_price_provider(TentPrices);
+        let estimate = rules.estimate_item_value(3, EstimateQuery::default()).await.unwrap();
+        assert_eq!(estimate.source, "tent-prices");
+        assert_eq!(estimate.currency, "EUR");
+        assert!(estimate.applied);
+
+        let item = rules.get_item(3).await.unwrap();
+        assert_eq!(item.current_value, Some(120.0));
+        assert_eq!(item.currency.as_deref(), Some("EUR"));
+    }
+
+    #[tokio::test]
+    async fn estimates_in_another_currency_are_only_recorded() {
+        let rules = rules().await.with_price_provider(TentPrices);
+        sqlx::query("UPDATE items SET current_value = 90, currency = 'USD' WHERE id = 3")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let estimate = rules.estimate_item_value(3, EstimateQuery::default()).await.unwrap();
+        assert!(!estimate.applied);
+        assert_eq!(rules.get_item(3).await.unwrap().current_value, Some(90.0));
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM item_value_estimates")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn missing_prices_are_errors() {
+        let status = |e: crate::CustError| e.into_response().status();
+
+        let error = rules().await.estimate_item_value(3, EstimateQuery::default()).await;
+        assert_eq!(status(error.unwrap_err()), StatusCode::NOT_IMPLEMENTED);
+
+        let rules = rules().await.with_price_provider(TentPrices);
+        let error = rules.estimate_item_value(1, EstimateQuery::default()).await.unwrap_err();
+        assert_eq!(status(error), StatusCode::NOT_FOUND);
+        let error = rules.estimate_item_value(42, EstimateQuery::default()).await.unwrap_err();
+        assert_eq!(status(error), StatusCode::NOT_FOUND);
+    }
+}
+
+#[cfg(test)]
+mod test_daily_diff {
+    use super::test_support::rules;
+    use crate::util;
+
+    #[tokio::test]
+    async fn changes_since_yesterday_are_summarized() {
+        let rules = rules().await;
+        let diff = rules.take_daily_snapshot().await.unwrap().unwrap();
+        assert_eq!(diff.previous_day, None);
+        assert_eq!(diff.entities[0].count, 3);
+        assert!(rules.take_daily_snapshot().await.unwrap().is_none());
+
+        // pretend the snapshot was taken yesterday
+        let yesterday = util::iso_date(util::now() - 24 * 60 * 60);
+        sqlx::query("UPDATE daily_snapshots SET day = ?")
+            .bind(&yesterday)
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO items (name) VALUES ('drill'), ('ladder')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE items SET name = 'big saw' WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM collections WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let diff = rules.take_daily_snapshot().await.unwrap().unwrap();
+        assert_eq!(diff.previous_day.as_deref(), Some(yesterday.as_str()));
+        assert_eq!(diff.summary, "+2 items, 1 item changed, -1 collection");
+        assert_eq!(rules.daily_diff(None).await.unwrap().summary, diff.summary);
+        assert!(rules.daily_diff(Some(&yesterday)).await.unwrap().previous_day.is_none());
+        assert!(rules.daily_diff(Some("yesterday")).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_last_modified {
+    use super::test_support::rules;
+    use crate::{util, ItemTranslation};
+
+    #[tokio::test]
+    async fn listings_change_with_their_entities() {
+        let rules = rules().await;
+        assert_eq!(rules.last_modified("item").await.unwrap(), None);
+        assert_eq!(rules.last_modified("collection").await.unwrap(), None);
+
+        sqlx::query("UPDATE items SET updated_at = 100 WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(rules.last_modified("item").await.unwrap(), Some(100));
+
+        let before = util::now();
+        let translation = ItemTranslation {
+            locale: String::new(),
+            name: "Säge".to_owned(),
+            description: None,
+        };
+        rules.set_item_translation(2, "de", translation).await.unwrap();
+        assert!(rules.last_modified("item").await.unwrap().unwrap() >= before);
+
+        rules.add_item_to_collection(1, 2).await.unwrap();
+        assert!(rules.last_modified("collection").await.unwrap().unwrap() >= before);
+        assert_eq!(rules.last_modified("category").await.unwrap(), None);
+    }
+}
+
+#[cfg(test)]
+mod test_item_notes {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::{rules, rules_with};
+    use super::BusinessRules;
+    use crate::{MeasurementFilter, NewItemNote, OwnershipFilter, SearchOptions, SearchScope};
+
+    fn note(text: &str) -> NewItemNote {
+        NewItemNote {
+            text: text.to_owned(),
+        }
+    }
+
+    async fn found(rules: &BusinessRules, query: &str) -> Vec<i32> {
+        let found = rules
+            .find_items(
+                query.to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await;
+        match found {
+            Ok(items) => items.iter().filter_map(|item| item.id).collect(),
+            Err(e) if e.status() == StatusCode::NOT_FOUND => vec![],
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn notes_are_added_edited_and_removed() {
+        let rules = rules().await;
+        let first = rules.add_item_note(1, note(" replaced the handle ")).await.unwrap();
+        assert_eq!(first.text, "replaced the handle");
+        assert_eq!(first.updated_at, None);
+        let second = rules.add_item_note(1, note("left it at Mum's")).await.unwrap();
+
+        let edited = rules
+            .update_item_note(1, first.id, note("replaced the handle 2024-03"))
+            .await
+            .unwrap();
+        assert!(edited.updated_at.is_some());
+        assert_eq!(edited.created_at, first.created_at);
+
+        rules.delete_item_note(1, second.id).await.unwrap();
+        assert_eq!(rules.get_item_notes(1).await.unwrap(), [edited]);
+        assert!(rules.get_item_notes(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_notes_are_rejected() {
+        let rules = rules().await;
+        let e = rules.add_item_note(1, note("  ")).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::BAD_REQUEST);
+        let e = rules.add_item_note(42, note("lost")).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::NOT_FOUND);
+
+        // a note is only reachable through its own item
+        let added = rules.add_item_note(1, note("oiled")).await.unwrap();
+        let e = rules.update_item_note(2, added.id, note("rusty")).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::NOT_FOUND);
+        let e = rules.delete_item_note(2, added.id).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn notes_are_only_searched_when_indexed() {
+        let rules = rules().await;
+        rules.add_item_note(3, note("borrowed by the neighbours")).await.unwrap();
+        assert!(found(&rules, "neighbours").await.is_empty());
+
+        let rules = rules_with("notes", r#"{ "search": { "index_notes": true } }"#).await;
+        rules.add_item_note(3, note("borrowed by the neighbours")).await.unwrap();
+        assert_eq!(found(&rules, "neighbours").await, [3]);
+
+        // a match in the name ranks above one in a note
+        rules.add_item_note(1, note("used to pitch the tent")).await.unwrap();
+        assert_eq!(found(&rules, "tent").await, [3, 1]);
+
+        let notes = rules.get_item_notes(3).await.unwrap();
+        rules.delete_item_note(3, notes[0].id).await.unwrap();
+        assert!(found(&rules, "neighbours").await.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_favorites {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+
+    #[tokio::test]
+    async fn items_and_collections_are_pinned() {
+        let rules = rules().await;
+        rules.favorite_item(1).await.unwrap();
+        rules.favorite_item(3).await.unwrap();
+        rules.favorite_item(3).await.unwrap();
+        rules.favorite_collection(2).await.unwrap();
+
+        let favorites = rules.get_favorites().await.unwrap();
+        let ids: Vec<_> = favorites.items.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [3, 1]);
+        assert_eq!(favorites.collections[0].name, "Camping");
+
+        rules.unfavorite_item(3).await.unwrap();
+        rules.unfavorite_collection(2).await.unwrap();
+        let favorites = rules.get_favorites().await.unwrap();
+        assert_eq!(favorites.items.len(), 1);
+        assert!(favorites.collections.is_empty());
+
+        let e = rules.favorite_item(42).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[cfg(test)]
+mod test_deadlines {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{Deadline, ItemFieldMask, ItemSort, OwnershipFilter};
+
+    #[tokio::test]
+    async fn listings_stop_after_the_deadline() {
+        let rules = rules().await;
+        let (ownership, sort, mask) =
+            (OwnershipFilter::default(), ItemSort::default(), ItemFieldMask::all());
+        let list = || rules.get_all_items_masked(&ownership, &sort, &mask);
+
+        let items = Deadline::after(Duration::from_secs(60)).scope(list()).await.unwrap();
+        assert_eq!(items.len(), 3);
+
+        let e = Deadline::after(Duration::ZERO).scope(list()).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod test_item_cache {
+    use super::test_support::rules;
+    use crate::Appearance;
+
+    #[tokio::test]
+    async fn items_are_cached_until_they_change() {
+        let rules = rules().await;
+        assert_eq!(rules.get_item(1).await.unwrap().name, "hammer");
+
+        // bypasses the business rules, so the cached item stays
+        sqlx::query("UPDATE items SET name = 'mallet' WHERE id = 1")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(rules.get_item(1).await.unwrap().name, "hammer");
+
+        let appearance = Appearance {
+            color: Some("#ff0000".to_owned()),
+            icon: None,
+        };
+        let item = rules.set_item_appearance(1, appearance).await.unwrap();
+        assert_eq!(item.name, "mallet");
+        assert_eq!(rules.get_item(1).await.unwrap().color.as_deref(), Some("#ff0000"));
+    }
+
+    #[tokio::test]
+    async fn accesses_show_on_cached_items() {
+        let rules = rules().await;
+        assert_eq!(rules.get_item(2).await.unwrap().last_accessed_at, None);
+
+        rules.record_access(2);
+        assert!(rules.get_item(2).await.unwrap().last_accessed_at.is_some());
+        rules.flush_access_log().await.unwrap();
+        assert!(rules.get_item(2).await.unwrap().last_accessed_at.is_some());
+    }
+}
+
+#[cfg(test)]
+mod test_batch_search {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::BatchSearch;
+
+    #[tokio::test]
+    async fn every_query_gets_its_matches_with_their_location() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO locations (name, parent_location) VALUES ('Garage', NULL), ('Shelf', 1);
+            UPDATE items SET location_id = 2 WHERE id = 3;
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+
+        let batch = BatchSearch {
+            queries: vec!["tent".to_owned(), "unicorn".to_owned(), " hammer ".to_owned()],
+            limit: None,
+        };
+        let results = rules.find_items_batch(batch, &[]).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].matches[0].id, Some(3));
+        assert_eq!(results[0].matches[0].location_path, ["Garage", "Shelf"]);
+        assert!(results[1].matches.is_empty());
+        assert_eq!(results[2].query, " hammer ");
+        assert_eq!(results[2].matches[0].id, Some(1));
+        assert!(results[2].matches[0].location_path.is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_queries_are_rejected() {
+        let batch = BatchSearch {
+            queries: vec!["tent".to_owned(), " ".to_owned()],
+            limit: None,
+        };
+        let e = rules().await.find_items_batch(batch, &[]).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[cfg(test)]
+mod test_containers {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    use super::test_support::rules;
+    use crate::{MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
+
+    #[tokio::test]
+    async fn contents_are_listed_recursively() {
+        let rules = rules().await;
+        sqlx::query("INSERT INTO items (name) VALUES ('toolbox'), ('drawer')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.move_item(5, Some(4)).await.unwrap();
+        rules.move_item(1, Some(5)).await.unwrap();
+        let saw = rules.move_item(2, Some(4)).await.unwrap();
+        assert_eq!(saw.contained_in_item_id, Some(4));
+
+        let contents = rules.get_item_contents(4).await.unwrap();
+        let ids: Vec<_> = contents.iter().filter_map(|item| item.id).collect();
+        assert_eq!(ids, [5, 2, 1]);
+        assert_eq!(contents[2].contained_in_item_id, Some(5));
+
+        rules.move_item(2, None).await.unwrap();
+        assert_eq!(rules.get_item_contents(4).await.unwrap().len(), 2);
     }
 
-    pub async fn find_items(&self, name: Name) -> Result<Vec<Item>> {
-        debug!("Searching for: {:?}", name);
-        let index = self.index.read().await;
-        let mut result = index
-            .query(
-                name.as_str(),
-                &self.tokenizer,
-                &self.filter,
-                Some(QueryOption::new().add(OptionType::TfIdf).build()),
+    #[tokio::test]
+    async fn items_are_not_packed_into_themselves() {
+        let rules = rules().await;
+        rules.move_item(2, Some(1)).await.unwrap();
+
+        let e = rules.move_item(1, Some(2)).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::CONFLICT);
+        let e = rules.move_item(1, Some(1)).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::CONFLICT);
+        let e = rules.move_item(1, Some(42)).await.unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn search_results_report_the_physical_path() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO locations (name, parent_location) VALUES ('House', NULL), ('Garage', 1);
+            INSERT INTO items (name, location_id) VALUES ('red toolbox', 2);
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.move_item(1, Some(4)).await.unwrap();
+
+        let found = rules
+            .find_items(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
             )
-            .await?.collect();
+            .await
+            .unwrap();
+        assert_eq!(found[0].location_path, ["House", "Garage", "red toolbox"]);
+    }
+}
 
-        if result.is_empty() {
-            return Err(CustError::new(
-                "no items for search query".to_string(),
-                StatusCode::NOT_FOUND,
-            ));
-        }
-        debug!("Search result: {:?}", result);
+#[cfg(test)]
+mod test_audit_chain {
+    use super::test_support::rules;
 
-        result.sort_by(|(x, _), (y, _)| x.total_cmp(y));
+    #[tokio::test]
+    async fn an_untouched_chain_is_valid() {
+        let rules = rules().await;
+        let empty = rules.verify_audit_chain().await.unwrap();
+        assert!(empty.valid);
+        assert_eq!(empty.head, None);
 
-        let ids: Vec<Arc<i64>> = result.iter().map(|(_x, v)| v.get_id()).collect();
-        let params = format!("?{}", ", ?".repeat(ids.len() - 1));
-        let query_str = format!("SELECT * FROM items WHERE id IN ({})", params);
+        rules.move_item(1, Some(3)).await.unwrap();
+        rules.move_item(2, Some(3)).await.unwrap();
+        rules.move_item(1, None).await.unwrap();
 
-        let query = sqlx::query_as::<_, DbItem>(&query_str);
-        let query = ids
-            .into_iter()
-            .fold(query, |query, id| query.bind(id.deref().clone()));
+        let verification = rules.verify_audit_chain().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 3);
+        assert_eq!(verification.head, rules.audit_head().await.unwrap());
+    }
 
-        let items = query.fetch_all(&self.conn).await?;
-        let items: Vec<(Option<f64>, Item)> = items
-            .into_iter()
-            .map(|item| {
-                (
-                    self.find_score_for_item(item.id.unwrap(), &result),
-                    item.into(),
-                )
-            })
-            .collect();
+    #[tokio::test]
+    async fn changed_entries_are_found() {
+        let rules = rules().await;
+        rules.move_item(1, Some(3)).await.unwrap();
+        rules.move_item(2, Some(3)).await.unwrap();
+        rules.move_item(1, None).await.unwrap();
+        sqlx::query("UPDATE audit_log SET actor = 'someone else' WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
 
-        let mut items: Vec<_> = items.into_iter().filter(|x| x.0.is_some()).collect();
-        for (_, item) in &mut items {
-            let result = self.item_files.read(item).await;
-            if result.is_err() {
-                error!("{}", result.err().unwrap());
-            }
-        }
+        let verification = rules.verify_audit_chain().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_broken_id, Some(2));
+    }
+
+    #[tokio::test]
+    async fn removed_entries_are_found() {
+        let rules = rules().await;
+        rules.move_item(1, Some(3)).await.unwrap();
+        rules.move_item(2, Some(3)).await.unwrap();
+        rules.take_daily_snapshot().await.unwrap();
 
-        items.sort_by(|x, y| x.0.unwrap().total_cmp(&y.0.unwrap()));
+        // the snapshot remembers the head, so even the latest entry can't vanish
+        sqlx::query("DELETE FROM audit_log WHERE id = 2")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        let verification = rules.verify_audit_chain().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_broken_id, None);
+        assert_eq!(verification.missing_snapshot_heads.len(), 1);
 
-        Ok(items.into_iter().map(|x| x.1).rev().collect())
+        // an emptied log starts a new chain, only the snapshot notices
+        sqlx::query("DELETE FROM audit_log")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.move_item(1, None).await.unwrap();
+        let verification = rules.verify_audit_chain().await.unwrap();
+        assert_eq!(verification.first_broken_id, None);
+        assert!(!verification.valid);
     }
 
-    pub async fn get_all_items(&self) -> Result<Vec<Item>> {
-        let mut items: Vec<Item> = sqlx::query_as::<_, DbItem>("SELECT * FROM items")
-            .fetch_all(&self.conn)
-            .await?
-            .into_iter()
-            .map(Into::into)
-            .collect();
+    #[tokio::test]
+    async fn entries_written_before_the_chain_are_chained_on_startup() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (actor, action, entity, entity_id, created_at)
+            VALUES ('admin', 'item.updated', 'item', 1, 1), ('admin', 'item.deleted', 'item', 2, 2)
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        assert!(!rules.verify_audit_chain().await.unwrap().valid);
 
-        for item in &mut items {
-            let result = self.item_files.read(item).await;
-            if result.is_err() {
-                error!("{}", result.err().unwrap());
-            }
+        rules.backfill_audit_chain().await;
+        rules.move_item(1, Some(3)).await.unwrap();
+        let verification = rules.verify_audit_chain().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 3);
+    }
+}
+
+#[cfg(test)]
+mod test_search_queries {
+    use axum::http::StatusCode;
+    use proptest::prelude::*;
+    use proptest::test_runner::{Config, TestRunner};
+
+    use super::test_support::rules;
+    use crate::{BatchSearch, MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
+
+    #[tokio::test]
+    async fn queries_without_words_are_rejected() {
+        let rules = rules().await;
+        for query in ["", "   ", "?!", " -*- "] {
+            let err = rules
+                .find_items(
+                    query.to_owned(),
+                    &MeasurementFilter::default(),
+                    &OwnershipFilter::default(),
+                    &SearchScope::default(),
+                    &SearchOptions::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(err.code(), "empty_query");
         }
 
-        Ok(items)
+        // in a list the entry just finds nothing
+        let batch = BatchSearch {
+            queries: vec!["hammer".to_owned(), "???".to_owned()],
+            limit: None,
+        };
+        let results = rules.find_items_batch(batch, &[]).await.unwrap();
+        assert_eq!(results[0].matches.len(), 1);
+        assert!(results[1].matches.is_empty());
     }
 
-    pub async fn delete_item(&self, id: ID) -> Result<Item> {
-        let mut tx = self.conn.begin().await?;
+    #[test]
+    fn odd_queries_never_fail_the_server() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let rules = runtime.block_on(rules());
+        let queries = prop_oneof!["\\PC{0,30}", "[ \"*()^:+.,!?a-zA-Z-]{0,20}"];
 
-        let item: Item = sqlx::query_as::<_, DbItem>("SELECT * from items WHERE id = ?")
-            .bind(id)
-            .fetch_one(&mut *tx)
-            .await?
-            .into();
+        let mut runner = TestRunner::new(Config {
+            cases: 200,
+            ..Config::default()
+        });
+        runner
+            .run(&queries, |query| {
+                let result = runtime.block_on(rules.find_items(
+                    query,
+                    &MeasurementFilter::default(),
+                    &OwnershipFilter::default(),
+                    &SearchScope::default(),
+                    &SearchOptions::default(),
+                ));
+                if let Err(e) = result {
+                    prop_assert!(
+                        [StatusCode::NOT_FOUND, StatusCode::BAD_REQUEST].contains(&e.status()),
+                        "{:?}",
+                        e
+                    );
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+}
 
-        sqlx::query("DELETE FROM items WHERE id = ?")
-            .bind(id)
-            .execute(&mut *tx)
-            .await?;
+#[cfg(test)]
+mod test_category_thumbnail {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
 
-        // NOTE: This is to release the future faster
-        {
-            let mut index = self.index.write().await;
-            let _ = index.remove_document(Arc::new(id as i64)).await?;
-        }
+    use super::test_support::rules;
 
-        tx.commit().await?;
+    #[tokio::test]
+    async fn uploads_are_checked_before_they_are_stored() {
+        let rules = rules().await;
+        sqlx::query("INSERT INTO categories (name) VALUES ('Tools')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
 
-        // TODO: delete all connection before
-        Ok(item)
+        let error = rules
+            .set_category_thumbnail(1, b"not an image".to_vec(), Some("tools.png".to_owned()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let error = rules
+            .set_category_thumbnail(2, b"not an image".to_vec(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+
+        let error = rules.category_thumbnail(2).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
     }
+}
 
-    pub async fn new_category(&self, mut category: Category) -> Result<Category> {
-        debug!("adding new category: {:?}", category);
-        category.name = util::sanitize_name(&category.name)?.to_owned();
-        category.id = None;
-        let mut tx = self.conn.begin().await?;
+#[cfg(test)]
+mod test_index_compaction {
+    use super::test_support::{rules, rules_with_index};
+    use crate::{MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
 
-        let tmp_cat: Option<Category> =
-            sqlx::query_as::<_, DbCategory>("SELECT * FROM categories WHERE name = ?")
-                .bind(category.name.clone())
-                .bind(category.parent_category)
-                .fetch_optional(&mut *tx)
-                .await?
-                .map(Into::into);
-        debug!("tmp_cat: {:?}", tmp_cat);
+    #[tokio::test]
+    async fn fts_compaction_keeps_the_items_searchable() {
+        let rules = rules().await;
+        rules.delete_item(2).await.unwrap();
 
-        if tmp_cat.is_some() {
-            return Err(CustError::new(
-                "category already exists".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ));
-        }
+        let compaction = rules.compact_index().await.unwrap();
+        assert_eq!(compaction.documents, 2);
+        assert_eq!(compaction.pruned_terms, 0);
 
-        sqlx::query("INSERT INTO categories (name, parent_category) VALUES (?, ?)")
-            .bind(category.name.clone())
-            .bind(category.parent_category)
-            .execute(&mut *tx)
-            .await?;
+        let items = rules
+            .find_items(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(1)]);
+    }
 
-        let last_inserted = sqlx::query("SELECT last_insert_rowid() as id")
-            .fetch_one(&mut *tx)
-            .await?;
+    #[tokio::test]
+    async fn terms_are_recorded_once() {
+        let rules = rules().await;
+        rules.record_terms("red hammer red".split_whitespace()).await.unwrap();
+        rules.record_terms(["hammer", "saw"]).await.unwrap();
 
-        let id: ID = last_inserted.get("id");
-        category.id = Some(id);
-        self.category_files.store(&category).await?;
+        let terms: Vec<String> = sqlx::query_scalar("SELECT term FROM index_terms ORDER BY term")
+            .fetch_all(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(terms, ["hammer", "red", "saw"]);
+    }
 
-        tx.commit().await?;
+    #[tokio::test]
+    async fn index_compaction_prunes_the_terms_of_deleted_items() {
+        let rules = rules_with_index("compaction").await;
+        rules.delete_item(3).await.unwrap();
 
-        debug!("added new category: {:?}", category);
-        Ok(category)
+        let compaction = rules.compact_index().await.unwrap();
+        assert_eq!(compaction.documents, 2);
+        assert_eq!(compaction.removed_documents, 1);
+        assert_eq!(compaction.pruned_terms, 1);
+        let terms: Vec<String> = sqlx::query_scalar("SELECT term FROM index_terms ORDER BY term")
+            .fetch_all(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(terms, ["hammer", "saw"]);
+
+        let items = rules
+            .find_items(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), [Some(1)]);
+        let _ = std::fs::remove_file(&rules.index_storage);
     }
+}
 
-    pub async fn get_all_categories(&self) -> Result<Vec<Category>> {
-        let mut categories: Vec<Category> =
-            sqlx::query_as::<_, DbCategory>("SELECT * FROM categories")
-                .fetch_all(&self.conn)
-                .await?
-                .into_iter()
-                .map(|c| c.into())
-                .collect();
+#[cfg(test)]
+mod test_tombstones {
+    use axum::http::StatusCode;
+    use doc_search::{OptionType, QueryOption};
 
-        for category in &mut categories {
-            let result = self.category_files.read(category).await;
-            if result.is_err() {
-                error!("{}", result.err().unwrap());
-            }
-        }
+    use super::test_support::rules_with_index;
+    use super::BusinessRules;
+    use crate::{MeasurementFilter, OwnershipFilter, SearchOptions, SearchScope};
 
-        Ok(categories)
+    async fn found(rules: &BusinessRules, query: &str) -> Vec<i32> {
+        let found = rules
+            .find_items(
+                query.to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &SearchOptions::default(),
+            )
+            .await;
+        match found {
+            Ok(items) => items.iter().filter_map(|item| item.id).collect(),
+            Err(e) if e.status() == StatusCode::NOT_FOUND => vec![],
+            Err(e) => panic!("{:?}", e),
+        }
     }
 
-    pub async fn new_collection(&self, mut coll: Collection) -> Result<Collection> {
-        coll.name = util::sanitize_name(&coll.name)?.to_owned();
-        let mut tx = self.conn.begin().await?;
+    /// Ids of the documents in the index matching `query`, tombstoned or not
+    async fn indexed(rules: &BusinessRules, query: &str) -> Vec<i64> {
+        let index = rules.index.as_ref().unwrap().read().await;
+        let query = rules.analyzer.analyze(query);
+        let result = index
+            .query(
+                &query,
+                &rules.tokenizer,
+                &rules.filter,
+                Some(QueryOption::new().add(OptionType::TfIdf).build()),
+            )
+            .await
+            .unwrap()
+            .collect();
+        result.iter().map(|(_, doc)| *doc.get_id()).collect()
+    }
 
-        sqlx::query("INSERT INTO COLLECTIONS (name) VALUES (?)")
-            .bind(coll.name.clone())
-            .execute(&mut *tx)
-            .await?;
+    /// Tombstones item 3 like deleting it does, its row and its document are kept
+    async fn tombstone_tent(rules: &BusinessRules) {
+        sqlx::query("INSERT INTO index_tombstones (item_id) VALUES (3)")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+        rules.tombstones.lock().unwrap().insert(3);
+    }
 
-        let last_inserted = sqlx::query("SELECT last_insert_rowid() as id")
-            .fetch_one(&mut *tx)
-            .await?;
+    #[tokio::test]
+    async fn tombstoned_documents_are_not_found() {
+        let rules = rules_with_index("tombstones_found").await;
+        tombstone_tent(&rules).await;
 
-        let id: ID = last_inserted.get("id");
+        assert_eq!(indexed(&rules, "tent").await, [3]);
+        assert!(found(&rules, "tent").await.is_empty());
+        assert_eq!(found(&rules, "hammer").await, [1]);
+    }
 
-        let mut collection = coll;
-        collection.id = Some(id);
+    #[tokio::test]
+    async fn tombstoned_documents_are_compacted() {
+        let rules = rules_with_index("tombstones_compacted").await;
+        tombstone_tent(&rules).await;
 
-        self.collection_files.store(&collection).await?;
+        rules.compact_tombstones().await.unwrap();
+        assert!(indexed(&rules, "tent").await.is_empty());
+        assert!(rules.tombstones.lock().unwrap().is_empty());
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM index_tombstones")
+            .fetch_one(&rules.conn)
+            .await
+            .unwrap();
+        assert_eq!(left, 0);
+        assert_eq!(indexed(&rules, "hammer").await, [1]);
+    }
 
-        tx.commit().await?;
+    #[tokio::test]
+    async fn deleted_items_are_tombstoned() {
+        let rules = rules_with_index("tombstones_deleted").await;
+        rules.delete_item(3).await.unwrap();
 
-        Ok(collection)
+        assert!(rules.tombstones.lock().unwrap().contains(&3));
+        assert!(found(&rules, "tent").await.is_empty());
+        rules.compact_tombstones().await.unwrap();
+        assert!(indexed(&rules, "tent").await.is_empty());
     }
+}
 
-    pub async fn get_all_collections(&self) -> Result<Vec<Collection>> {
-        let mut list = sqlx::query_as::<_, Collection>("SELECT * from collections")
-            .fetch_all(&self.conn)
-            .await?;
+#[cfg(test)]
+mod test_feature_flags {
+    use axum::http::StatusCode;
 
-        for c in &mut list {
-            let result = self.collection_files.read(c).await;
-            if result.is_err() {
-                error!("{}", result.err().unwrap());
-            }
-        }
+    use super::test_support::rules;
+    use crate::{Feature, FlagOverride};
 
-        Ok(list)
+    #[tokio::test]
+    async fn disabled_features_are_not_found() {
+        let rules = rules().await;
+        assert!(rules.get_all_webhooks().await.is_ok());
+
+        let off = FlagOverride { enabled: Some(false) };
+        let flags = rules.set_feature_flag("webhooks", off).await.unwrap();
+        assert!(!flags.iter().any(|flag| flag.name == Feature::Webhooks && flag.enabled));
+        let error = rules.get_all_webhooks().await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error.code(), "feature_disabled");
+
+        let stored: bool =
+            sqlx::query_scalar("SELECT enabled FROM feature_overrides WHERE name = 'webhooks'")
+                .fetch_one(&rules.conn)
+                .await
+                .unwrap();
+        assert!(!stored);
+
+        rules
+            .set_feature_flag("webhooks", FlagOverride { enabled: None })
+            .await
+            .unwrap();
+        assert!(rules.get_all_webhooks().await.is_ok());
+
+        let error = rules
+            .set_feature_flag("teleport", FlagOverride { enabled: Some(true) })
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
     }
+}
 
-    pub async fn get_collection(&self, id: ID) -> Result<Collection> {
-        let mut collection = sqlx::query_as::<_, Collection>("SELECT * FROM collections WHERE id = ?")
-            .bind(id)
-            .fetch_one(&self.conn)
-            .await?;
+#[cfg(test)]
+mod test_search_limits {
+    use super::test_support::{rules, rules_with};
+    use super::BusinessRules;
+    use crate::{MeasurementFilter, OwnershipFilter, SearchHits, SearchOptions, SearchScope};
 
-        let result = self.collection_files.read(&mut collection).await;
-        if result.is_err() {
-            error!("{}", result.err().unwrap());
-        }
+    /// Three items match `hammer`
+    async fn add_hammers(rules: &BusinessRules) {
+        sqlx::query("INSERT INTO items (name) VALUES ('red hammer'), ('hammer drill')")
+            .execute(&rules.conn)
+            .await
+            .unwrap();
+    }
 
-        Ok(collection)
+    async fn search(rules: &BusinessRules, options: SearchOptions) -> SearchHits {
+        rules
+            .find_items_counted(
+                "hammer".to_owned(),
+                &MeasurementFilter::default(),
+                &OwnershipFilter::default(),
+                &SearchScope::default(),
+                &options,
+            )
+            .await
+            .unwrap()
     }
 
-    pub async fn add_item_to_collection(&self, item_id: ID, collection_id: ID) -> Result<()> {
-        let mut tx = self.conn.begin().await?;
+    #[tokio::test]
+    async fn results_are_cut_after_ranking() {
+        let rules = rules().await;
+        add_hammers(&rules).await;
+        let all = search(&rules, SearchOptions::default()).await;
+        assert_eq!(all.items.len(), 3);
+        assert_eq!(all.total, 3);
 
-        let _item = self.get_item(item_id).await?;
-        let _colletion = self.get_collection(collection_id).await?;
+        let options = SearchOptions {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let limited = search(&rules, options).await;
+        let ids: Vec<_> = limited.items.iter().map(|item| item.id).collect();
+        let best: Vec<_> = all.items.iter().take(2).map(|item| item.id).collect();
+        assert_eq!(ids, best);
+        assert_eq!(limited.total, 3);
 
-        sqlx::query("INSERT INTO collection_items VALUES (?, ?)")
-            .bind(item_id)
-            .bind(collection_id)
-            .execute(&mut *tx)
-            .await?;
+        let options = SearchOptions {
+            min_score: Some(f64::MAX),
+            ..Default::default()
+        };
+        let none = search(&rules, options).await;
+        assert!(none.items.is_empty());
+        assert_eq!(none.total, 0);
+    }
 
-        tx.commit().await?;
+    #[tokio::test]
+    async fn the_config_sets_the_default_limit() {
+        let rules = rules_with("search_limits", r#"{ "search": { "result_limit": 1 } }"#).await;
+        add_hammers(&rules).await;
+        let hits = search(&rules, SearchOptions::default()).await;
+        assert_eq!(hits.items.len(), 1);
+        assert_eq!(hits.total, 3);
 
-        Ok(())
+        let options = SearchOptions {
+            limit: Some(0),
+            ..Default::default()
+        };
+        let all = search(&rules, options).await;
+        assert_eq!(all.items.len(), 3);
     }
+}
 
-    pub async fn remove_item_from_collection(&self, item_id: ID, collection_id: ID) -> Result<()> {
-        let mut tx = self.conn.begin().await?;
+#[cfg(test)]
+mod test_insurance_report {
+    use axum::http::StatusCode;
 
-        // TODO: find a way to use tx here
-        let _item = self.get_item(item_id).await?;
-        let _collection = self.get_collection(collection_id).await?;
+    use super::test_support::rules;
+    use crate::InsuranceReportQuery;
 
-        sqlx::query("DELETE FROM collection_items WHERE item_id = ? AND collection_id = ?")
-            .bind(item_id)
-            .bind(collection_id)
-            .execute(&mut *tx)
-            .await?;
+    #[tokio::test]
+    async fn reports_owned_items_with_their_value() {
+        let rules = rules().await;
+        sqlx::query(
+            r#"
+            INSERT INTO locations (name, parent_location) VALUES ('House', NULL), ('Garage', 1);
+            INSERT INTO categories (name) VALUES ('Tools');
+            UPDATE items SET location_id = 2, category_id = 1, purchase_price = 20, current_value = 15,
+                quantity = 2, currency = 'EUR' WHERE id = 1;
+            UPDATE items SET location_id = 1, purchase_price = 30, current_value = 25, currency = 'EUR'
+                WHERE id = 2;
+            UPDATE items SET ownership_state = 'wishlist' WHERE id = 3;
+            "#,
+        )
+            .execute(&rules.conn)
+            .await
+            .unwrap();
 
-        tx.commit().await?;
+        let report = rules.insurance_report(&InsuranceReportQuery::default()).await.unwrap();
+        let ids: Vec<_> = report.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(report.items[0].location_path, ["House", "Garage"]);
+        assert_eq!(report.items[0].category.as_deref(), Some("Tools"));
+        assert_eq!(report.totals.len(), 1);
+        assert_eq!(report.totals[0].total_purchase_price, 70.0);
+        assert_eq!(report.totals[0].total_current_value, 55.0);
 
-        Ok(())
+        // the garage is in the house
+        let in_house = InsuranceReportQuery {
+            location_id: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(rules.insurance_report(&in_house).await.unwrap().items.len(), 2);
+        let tools = InsuranceReportQuery {
+            category_id: Some(1),
+            ..Default::default()
+        };
+        let report = rules.insurance_report(&tools).await.unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert_eq!(report.totals[0].item_count, 1);
+
+        let pdf = rules.insurance_report_pdf(&tools).await.unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+
+        let missing = InsuranceReportQuery {
+            location_id: Some(9),
+            ..Default::default()
+        };
+        let error = rules.insurance_report(&missing).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
     }
 }
@@ -0,0 +1,94 @@
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+use crate::metrics;
+
+/// LRU cache for query results. Every mutation that could change a result calls
+/// [`QueryCache::invalidate`], which also bumps a generation counter, so a query that was running
+/// during the invalidation can't store its outdated result afterwards.
+pub struct QueryCache<K: Hash + Eq, V: Clone> {
+    name: &'static str,
+    entries: Mutex<LruCache<K, V>>,
+    generation: AtomicU64,
+}
+
+impl<K: Hash + Eq, V: Clone> QueryCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            name,
+            entries: Mutex::new(LruCache::new(capacity)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let value = self.entries.lock().unwrap().get(key).cloned();
+        let result = if value.is_some() { "hit" } else { "miss" };
+        metrics::increment(&format!("{}_cache_total{{result=\"{}\"}}", self.name, result));
+        value
+    }
+
+    /// Generation to pass to [`QueryCache::insert`], read before running the query
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn insert(&self, key: K, value: V, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if generation == self.generation() {
+            entries.put(key, value);
+        }
+    }
+
+    /// Drops the entry of one key, like [`QueryCache::invalidate`] for everything else
+    pub fn remove(&self, key: &K) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.pop(key);
+    }
+
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+        metrics::increment(&format!("{}_cache_invalidations_total", self.name));
+    }
+}
+
+#[cfg(test)]
+mod test_query_cache {
+    use crate::QueryCache;
+
+    #[test]
+    fn stale_results_are_not_stored() {
+        let cache: QueryCache<String, i32> = QueryCache::new("test", 4);
+
+        let generation = cache.generation();
+        cache.insert("a".to_owned(), 1, generation);
+        assert_eq!(cache.get(&"a".to_owned()), Some(1));
+
+        let generation = cache.generation();
+        cache.invalidate();
+        cache.insert("b".to_owned(), 2, generation);
+        assert_eq!(cache.get(&"a".to_owned()), None);
+        assert_eq!(cache.get(&"b".to_owned()), None);
+    }
+
+    #[test]
+    fn removed_keys_are_not_stored_by_running_queries() {
+        let cache: QueryCache<i32, i32> = QueryCache::new("test", 4);
+        cache.insert(1, 1, cache.generation());
+        cache.insert(2, 2, cache.generation());
+
+        let generation = cache.generation();
+        cache.remove(&1);
+        cache.insert(1, 10, generation);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some(2));
+    }
+}
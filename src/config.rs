@@ -0,0 +1,902 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::{reload, Registry};
+
+use crate::{Analyzer, ApiToken, CustError, Feature, ItemSort, Result};
+
+/// Runtime configuration, read from `config.json`. Every field has a default, so the file and
+/// each of its keys are optional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Maximum level of log messages, e.g. `info` or `debug`
+    pub log_level: String,
+    pub logging: LoggingConfig,
+    pub telemetry: TelemetryConfig,
+    pub limits: ImageLimits,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub search: SearchConfig,
+    pub public: PublicApiConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub database: DatabaseConfig,
+    pub replication: ReplicationConfig,
+    pub scan: ScanConfig,
+    pub ocr: OcrConfig,
+    pub pricing: PricingConfig,
+    pub trash: TrashConfig,
+    pub item_cache: ItemCacheConfig,
+    pub grpc_web: GrpcWebConfig,
+    pub file_encryption: FileEncryptionConfig,
+    pub features: FeaturesConfig,
+    /// Default order of item listings, requests override it with `?sort=` and `?dir=`
+    pub listing: ItemSort,
+    /// Language items are named and described in, e.g. `en`. Translations into other languages
+    /// are added per item and chosen by `Accept-Language`.
+    pub default_locale: String,
+    /// Reject request bodies with fields the api doesn't know instead of dropping them. Requests
+    /// override it with the `X-Strict-Json` header.
+    pub strict_json: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "debug".to_owned(),
+            logging: LoggingConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            limits: ImageLimits::default(),
+            auth: AuthConfig::default(),
+            cors: CorsConfig::default(),
+            search: SearchConfig::default(),
+            public: PublicApiConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            database: DatabaseConfig::default(),
+            replication: ReplicationConfig::default(),
+            scan: ScanConfig::default(),
+            ocr: OcrConfig::default(),
+            pricing: PricingConfig::default(),
+            trash: TrashConfig::default(),
+            item_cache: ItemCacheConfig::default(),
+            grpc_web: GrpcWebConfig::default(),
+            file_encryption: FileEncryptionConfig::default(),
+            features: FeaturesConfig::default(),
+            listing: ItemSort::default(),
+            default_locale: "en".to_owned(),
+            strict_json: false,
+        }
+    }
+}
+
+/// Where and how log messages are written. The outputs are only read on startup, the levels of
+/// `modules` are applied on reload like `log_level`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Format of the messages on stdout
+    pub format: LogFormat,
+    /// Levels of modules that differ from `log_level`, by module path, e.g.
+    /// `{"sqlx": "warn", "find_me_pls::webhooks": "trace"}`
+    pub modules: BTreeMap<String, String>,
+    pub file: LogFileConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per message, for log collectors
+    Json,
+}
+
+/// Log files next to stdout, rotated and deleted after a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFileConfig {
+    /// Directory of the log files, none are written while it is not set
+    pub directory: Option<PathBuf>,
+    pub format: LogFormat,
+    pub rotation: LogRotation,
+    /// Rotated files kept, older ones are deleted
+    pub max_files: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            format: LogFormat::Json,
+            rotation: LogRotation::Daily,
+            max_files: 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    /// A single file that grows forever
+    Never,
+}
+
+/// Export of the spans of requests to an OpenTelemetry collector over OTLP, e.g. Jaeger or
+/// Tempo. Only read on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// gRPC endpoint of the collector
+    pub endpoint: String,
+    /// Reported as `service.name`
+    pub service_name: String,
+    /// Share of the traces started here that are exported, between 0 and 1. Traces continued
+    /// from a client follow its decision.
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_owned(),
+            service_name: "find_me_pls".to_owned(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+/// Unauthenticated, read-only api under `/public`, e.g. to embed the catalog on a website
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublicApiConfig {
+    pub enabled: bool,
+    /// Requests a client may make per minute
+    pub requests_per_minute: u32,
+    /// Identify clients by `X-Forwarded-For`, only enable this behind a reverse proxy
+    pub trust_forwarded_for: bool,
+    /// Identifiers shown to and accepted from public clients
+    pub ids: IdStrategy,
+}
+
+impl Default for PublicApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requests_per_minute: 30,
+            trust_forwarded_for: false,
+            ids: IdStrategy::Integer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdStrategy {
+    /// The integer ids of the database
+    #[default]
+    Integer,
+    /// Only uuids, so ids can't be enumerated and don't reveal how many entities there are
+    Uuid,
+}
+
+/// Requests that may run at the same time on expensive routes, 0 disables a limit. Requests over
+/// a limit are answered with 503 right away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Item searches, including the public api
+    pub search: usize,
+    /// CSV and PDF exports
+    pub export: usize,
+    /// Sent as `Retry-After` with shed requests
+    pub retry_after_secs: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            search: 16,
+            export: 4,
+            retry_after_secs: 1,
+        }
+    }
+}
+
+/// Statements slower than `slow_query_ms` are logged as warnings, 0 disables the warning. Every
+/// statement is counted in the metrics regardless.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub slow_query_ms: u64,
+    /// Times a GET request is retried after the database was busy, locked or unreachable
+    pub retry_attempts: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub retry_base_delay_ms: u64,
+    /// Transient failures in a row after which api requests are rejected right away, 0 disables
+    /// the circuit breaker
+    pub breaker_threshold: u32,
+    /// How long requests are rejected once the breaker opened
+    pub breaker_open_secs: u64,
+    pub encryption: EncryptionConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            slow_query_ms: 100,
+            retry_attempts: 3,
+            retry_base_delay_ms: 50,
+            breaker_threshold: 5,
+            breaker_open_secs: 30,
+            encryption: EncryptionConfig::default(),
+        }
+    }
+}
+
+/// Encryption of `db.sqlite` at rest. Only read on startup, an existing plaintext database is
+/// encrypted with `find_me_pls encrypt-db`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub kind: DatabaseEncryption,
+    /// Environment variable holding the passphrase, preferred over `key_file`
+    pub key_env: String,
+    /// File holding the passphrase, surrounding whitespace is ignored
+    pub key_file: Option<PathBuf>,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            kind: DatabaseEncryption::None,
+            key_env: "FIND_ME_PLS_DB_KEY".to_owned(),
+            key_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseEncryption {
+    /// The database is a plain SQLite file
+    #[default]
+    None,
+    /// The whole file is encrypted by SQLCipher, the server has to be built with the
+    /// `sqlcipher` feature
+    Sqlcipher,
+}
+
+/// Encryption of the stored image files with AES-256-GCM. Only read on startup. Files written
+/// before it was enabled can't be read until `find_me_pls migrate-data` encrypts them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileEncryptionConfig {
+    pub enabled: bool,
+    /// Environment variable holding the key as 64 hex digits, preferred over `key_file`
+    pub key_env: String,
+    /// File holding the key as 64 hex digits, e.g. written by `openssl rand -hex 32`
+    pub key_file: Option<PathBuf>,
+}
+
+impl Default for FileEncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_env: "FIND_ME_PLS_FILE_KEY".to_owned(),
+            key_file: None,
+        }
+    }
+}
+
+/// Mirrors another server, e.g. on a laptop to search the inventory offline. Local changes to
+/// entities of the primary are overwritten by its next event about them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    /// Base url of the primary like `http://nas:8080`, its events are applied while this is set
+    pub primary: Option<String>,
+    /// Api token for the primary, read access is enough
+    pub token: Option<String>,
+    /// Pause between pulls once the replica caught up
+    pub poll_secs: u64,
+    /// Events fetched per request
+    pub batch_size: u32,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            primary: None,
+            token: None,
+            poll_secs: 30,
+            batch_size: 500,
+        }
+    }
+}
+
+/// Lookup of the market value of items by `POST /item/:id/estimate-value`. Only read on
+/// startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    pub provider: PriceProviderKind,
+    /// Endpoint of the http provider, asked with `?name=`, `?barcode=` and `?currency=`
+    pub url: Option<String>,
+    /// Sent as bearer token to the http provider
+    pub token: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            provider: PriceProviderKind::None,
+            url: None,
+            token: None,
+            timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceProviderKind {
+    /// Values are not looked up
+    #[default]
+    None,
+    /// A JSON api at `url`, see [`crate::HttpPriceProvider`]
+    Http,
+}
+
+/// How long bulk deleted items are kept in the trash. Older ones are purged with their rows
+/// and files by a background task.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// 0 keeps deleted items forever
+    pub retention_days: u32,
+    /// Pause between purges, only read on startup
+    pub purge_interval_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 30,
+            purge_interval_secs: 60 * 60,
+        }
+    }
+}
+
+/// Subsystems enabled on this instance, see [`FeatureFlags`](crate::FeatureFlags). Admins
+/// override them at runtime with `/admin/flags`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeaturesConfig {
+    pub webhooks: bool,
+    /// The `/public` routes also need `public.enabled`
+    pub public_sharing: bool,
+    pub image_search: bool,
+    pub semantic_search: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: true,
+            public_sharing: true,
+            image_search: true,
+            semantic_search: true,
+        }
+    }
+}
+
+impl FeaturesConfig {
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Webhooks => self.webhooks,
+            Feature::PublicSharing => self.public_sharing,
+            Feature::ImageSearch => self.image_search,
+            Feature::SemanticSearch => self.semantic_search,
+        }
+    }
+}
+
+/// Items read by id are kept in memory without their full size image, so hot items don't hit the
+/// database and the disk on every read. Only read on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ItemCacheConfig {
+    /// Number of items kept, at least one
+    pub capacity: usize,
+}
+
+impl Default for ItemCacheConfig {
+    fn default() -> Self {
+        Self { capacity: 1024 }
+    }
+}
+
+/// gRPC-Web for browsers, served over HTTP/1.1 on a port of its own next to native gRPC.
+/// Browsers on the origins allowed by the cors config may call it. Only read on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcWebConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for GrpcWebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 50052,
+        }
+    }
+}
+
+/// Scanning of uploaded images before they are stored. Flagged uploads are rejected with 422 and
+/// kept in quarantine. Only read on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanConfig {
+    pub scanner: ScannerKind,
+    /// `host:port` of clamd, or the path of its unix socket
+    pub clamd_address: String,
+    pub timeout_secs: u64,
+    /// Where flagged uploads are kept for inspection
+    pub quarantine_dir: PathBuf,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            scanner: ScannerKind::None,
+            clamd_address: "127.0.0.1:3310".to_owned(),
+            timeout_secs: 30,
+            quarantine_dir: PathBuf::from("./quarantine"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScannerKind {
+    /// Uploads are not scanned
+    #[default]
+    None,
+    /// A ClamAV daemon, see `clamd_address`
+    Clamd,
+}
+
+/// Recognition of the text in item images, e.g. model numbers. The text is searchable like the
+/// other fields of the item. Only read on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrConfig {
+    pub recognizer: RecognizerKind,
+    /// The `tesseract` executable, looked up in `PATH` unless it is a path
+    pub tesseract_path: PathBuf,
+    /// Tesseract language codes, e.g. `eng+deu`
+    pub languages: String,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            recognizer: RecognizerKind::None,
+            tesseract_path: PathBuf::from("tesseract"),
+            languages: "eng".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecognizerKind {
+    /// Images are not read
+    #[default]
+    None,
+    /// The tesseract command line tool, see `tesseract_path`
+    Tesseract,
+}
+
+/// How items are searched. Only read on startup, changing the backend needs a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub backend: SearchBackend,
+    /// Only used by the index backend. Items keep the terms they were indexed with until they
+    /// are indexed again, e.g. when they are edited.
+    pub analyzer: AnalyzerConfig,
+    /// Unlike the rest of the search config, this is read on every search
+    pub ranking: RankingProfile,
+    /// Load the documents of the index right after startup, instead of on the first search.
+    /// The service reports itself as not ready until they are loaded.
+    pub warm_up: bool,
+    /// Find items by the text of their notes as well. With the FTS5 backend a match in a note
+    /// ranks below matches in any other field.
+    pub index_notes: bool,
+    /// Pause between rebuilds of the index that prune the terms of deleted documents, 0 only
+    /// compacts when an admin asks for it. Only read on startup.
+    pub compact_interval_secs: u64,
+    /// Most items a search returns unless it asks for another number, 0 returns all of them
+    pub result_limit: usize,
+    /// Matches ranked below this score are left out, unless a search asks for another
+    /// threshold. Scores depend on the backend and the ranking profile, 0 keeps every match.
+    pub min_score: f64,
+    pub semantic: SemanticConfig,
+    pub fusion: FusionConfig,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            backend: SearchBackend::default(),
+            analyzer: AnalyzerConfig::default(),
+            ranking: RankingProfile::default(),
+            warm_up: true,
+            index_notes: false,
+            compact_interval_secs: 24 * 60 * 60,
+            result_limit: 100,
+            min_score: 0.0,
+            semantic: SemanticConfig::default(),
+            fusion: FusionConfig::default(),
+        }
+    }
+}
+
+/// Score modifiers applied after the text match, so items that can be used right away rank above
+/// empty, lent out or forgotten ones. The score of a match is multiplied by `1 + weight` for
+/// every modifier that applies to it, a weight of 0 turns a modifier off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingProfile {
+    /// Owned items whose quantity is above 0 or not recorded
+    pub in_stock: f64,
+    /// Items nobody has reserved right now
+    pub available: f64,
+    /// Items chosen from a search result or changed in the last `recent_days`
+    pub recently_used: f64,
+    pub recent_days: u32,
+    /// Items the caller pinned as a favorite
+    pub favorite: f64,
+}
+
+impl Default for RankingProfile {
+    fn default() -> Self {
+        Self {
+            in_stock: 0.5,
+            available: 0.2,
+            recently_used: 0.2,
+            recent_days: 30,
+            favorite: 1.0,
+        }
+    }
+}
+
+/// How item text is split into index terms, see [`Analyzer`](crate::Analyzer)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyzerConfig {
+    pub tokenizer: TokenizerKind,
+    /// Length of the n-grams of the `ngram` tokenizer
+    pub ngram_size: usize,
+    /// Language whose stop words are not indexed, `en` or `de`
+    pub stop_words: Option<String>,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        Self {
+            tokenizer: TokenizerKind::default(),
+            ngram_size: 3,
+            stop_words: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerKind {
+    /// Whole words
+    #[default]
+    Words,
+    /// Character n-grams of the words, matches parts of compound words
+    Ngram,
+}
+
+/// Vector search with `POST /item/search/semantic`, next to the text search of `backend`. How
+/// close the results are in meaning depends on the [embedder](crate::Embedder). Only read on
+/// startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SemanticConfig {
+    pub backend: VectorBackendKind,
+    /// Length of the item vectors, longer ones have fewer collisions but take more memory
+    pub dimensions: usize,
+}
+
+impl Default for SemanticConfig {
+    fn default() -> Self {
+        Self {
+            backend: VectorBackendKind::None,
+            dimensions: 256,
+        }
+    }
+}
+
+/// How text searches merge the matches of the text backend and of the vector backend. Both run
+/// at once if a vector backend is configured and semantic search is enabled, a search can ask
+/// for only one of them with `source`. Read on every search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FusionConfig {
+    pub method: FusionMethod,
+    /// Added to the rank of a match by reciprocal rank fusion, larger values make the first
+    /// ranks count less
+    pub rrf_k: f64,
+    /// Share of the text score in a weighted sum, the vector similarity gets the rest
+    pub text_weight: f64,
+    /// Vector matches less similar to the query than this are left out
+    pub min_similarity: f32,
+    /// Most matches asked from the vector backend per search
+    pub vector_candidates: usize,
+}
+
+impl Default for FusionConfig {
+    fn default() -> Self {
+        Self {
+            method: FusionMethod::default(),
+            rrf_k: 60.0,
+            text_weight: 0.5,
+            min_similarity: 0.2,
+            vector_candidates: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion, `1 / (rrf_k + rank)` summed over both result lists. Only the
+    /// order within each list counts, not how far apart the scores are.
+    #[default]
+    Rrf,
+    /// `text_weight` times the text score relative to the best text match, plus the rest times
+    /// the vector similarity
+    Weighted,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorBackendKind {
+    /// Semantic searches fail with 501
+    #[default]
+    None,
+    /// [`MemoryVectorIndex`](crate::MemoryVectorIndex) with the
+    /// [`HashingEmbedder`](crate::HashingEmbedder), no external service or model file is needed
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchBackend {
+    /// The in-memory document index, persisted to `storage.json`
+    #[default]
+    Index,
+    /// SQLite's FTS5, kept in sync with the items table by triggers. Meant for small
+    /// deployments, there is no fuzzy matching.
+    Fts5,
+}
+
+/// Origins allowed to call the REST api from a browser. CORS headers are only sent for these.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Allowed origins like `https://example.com`, or `*` for all
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|o| o == "*" || o == origin)
+    }
+}
+
+/// Api tokens accepted by the REST and gRPC apis and the lifetime of user sessions. Auth is
+/// disabled while there are neither tokens nor user accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub tokens: Vec<ApiToken>,
+    /// Key used to sign session cookies, random on every start if not set
+    pub session_secret: Option<String>,
+    pub session_ttl_secs: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            tokens: vec![],
+            session_secret: None,
+            session_ttl_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Maximum decoded size of uploaded images in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageLimits {
+    pub thumbnail_bytes: usize,
+    pub fullsize_bytes: usize,
+    /// Largest accepted collection bundle, with its base64 encoded images
+    pub bundle_bytes: usize,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self {
+            thumbnail_bytes: 512 * 1024,
+            fullsize_bytes: 10 * 1024 * 1024,
+            bundle_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl ImageLimits {
+    /// Largest accepted request body: both images base64 encoded plus room for the metadata
+    pub fn request_body_bytes(&self) -> usize {
+        (self.thumbnail_bytes + self.fullsize_bytes) / 3 * 4 + 1024 * 1024
+    }
+}
+
+impl Config {
+    /// Reads and validates a config file, unlike [`Config::load`] errors are returned.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let config: Config = serde_json::from_str(&content).map_err(|e| {
+            CustError::new(format!("Invalid config: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        config.log_filter()?;
+        Analyzer::from_config(&config.search.analyzer)?;
+        Ok(config)
+    }
+
+    pub fn log_level_filter(&self) -> Result<LevelFilter> {
+        parse_log_level(&self.log_level)
+    }
+
+    /// `log_level` with the levels of the modules that differ from it
+    pub fn log_filter(&self) -> Result<Targets> {
+        let mut filter = Targets::new().with_default(self.log_level_filter()?);
+        for (module, level) in &self.logging.modules {
+            filter = filter.with_target(module.clone(), parse_log_level(level)?);
+        }
+        Ok(filter)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(config) => {
+                    info!("Loaded config from {}", path.display());
+                    config
+                }
+                Err(e) => {
+                    error!("Invalid config {}, using defaults: {}", path.display(), e);
+                    Self::default()
+                }
+            },
+            Err(_) => {
+                info!("No config at {}, using defaults", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Shared access to the current config. Long-lived components [`subscribe`](Self::subscribe) to
+/// it and pick up changes when the config is reloaded.
+#[derive(Debug, Clone)]
+pub struct ConfigHandle {
+    path: Arc<PathBuf>,
+    sender: Arc<watch::Sender<Config>>,
+}
+
+impl ConfigHandle {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let config = Config::load(&path);
+        Self {
+            path: Arc::new(path),
+            sender: Arc::new(watch::channel(config).0),
+        }
+    }
+
+    /// The current config. The returned guard must not be held across an await point.
+    pub fn get(&self) -> watch::Ref<'_, Config> {
+        self.sender.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Config> {
+        self.sender.subscribe()
+    }
+
+    /// Re-reads the config file. An invalid file leaves the current config untouched.
+    pub fn reload(&self) -> Result<Config> {
+        let config = Config::read(self.path.as_ref())?;
+        self.sender.send_replace(config.clone());
+        info!("Reloaded config from {}", self.path.display());
+        Ok(config)
+    }
+}
+
+fn parse_log_level(level: &str) -> Result<LevelFilter> {
+    level.parse().map_err(|_| {
+        CustError::new(
+            format!("Invalid log level: {}", level),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })
+}
+
+/// Applies the log levels of the config now and whenever it is reloaded.
+pub fn watch_log_level(config: &ConfigHandle, handle: reload::Handle<Targets, Registry>) {
+    let mut updates = config.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let level = updates.borrow_and_update().log_filter();
+            match level {
+                Ok(level) => {
+                    if let Err(e) = handle.modify(|filter| *filter = level) {
+                        error!("Could not change log level: {}", e);
+                    }
+                }
+                Err(e) => error!("{}", e),
+            }
+
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Reloads the config whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_hangup(config: ConfigHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("Could not listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            if let Err(e) = config.reload() {
+                error!("Could not reload config: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,70 @@
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::{ConfigHandle, CorsConfig, TOTAL_COUNT_HEADER};
+
+const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, DELETE, OPTIONS";
+const ALLOWED_HEADERS: &str = "authorization, content-type, if-modified-since, x-strict-json";
+
+/// Adds CORS headers for the origins allowed in the current config and answers preflight
+/// requests. The config is read on every request, so reloads apply immediately.
+pub async fn cors_middleware<B>(
+    State(config): State<ConfigHandle>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .filter(|origin| config.get().cors.allows(origin))
+        .and_then(|origin| HeaderValue::from_str(origin).ok());
+
+    let Some(origin) = origin else {
+        return next.run(request).await;
+    };
+
+    let preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    if preflight {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOWED_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOWED_HEADERS),
+        );
+    } else {
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(TOTAL_COUNT_HEADER),
+        );
+    }
+
+    response
+}
+
+/// CORS of the gRPC-Web server for the origins of the config it started with. Unlike the REST
+/// api, reloads don't change it.
+pub fn grpc_web_cors(cors: &CorsConfig) -> tonic_web::Config {
+    if cors.allowed_origins.iter().any(|origin| origin == "*") {
+        tonic_web::config().allow_all_origins()
+    } else {
+        tonic_web::config().allow_origins(cors.allowed_origins.clone())
+    }
+}
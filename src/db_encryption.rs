@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use axum::http::StatusCode;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection};
+use tracing::info;
+
+use crate::{CustError, DatabaseEncryption, EncryptionConfig, Result};
+
+/// The database of the server, next to `config.json`
+pub const DATABASE_FILE: &str = "db.sqlite";
+
+/// Passphrase of an encrypted database. Never printed, not even in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(String);
+
+impl DatabaseKey {
+    pub fn new(passphrase: impl Into<String>) -> Result<Self> {
+        let passphrase = passphrase.into();
+        if passphrase.trim().is_empty() {
+            return Err(key_error("the database key is empty".to_owned()));
+        }
+        Ok(Self(passphrase))
+    }
+
+    /// Value of `PRAGMA key`, a quoted string literal
+    fn pragma_value(&self) -> String {
+        format!("'{}'", self.0.replace('\'', "''"))
+    }
+}
+
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+/// The key the database is encrypted with, `None` for a plaintext database. Read from the
+/// environment variable of the config, or else from its key file.
+pub fn database_key(config: &EncryptionConfig) -> Result<Option<DatabaseKey>> {
+    if config.kind == DatabaseEncryption::None {
+        return Ok(None);
+    }
+    match read_secret(&config.key_env, config.key_file.as_deref())? {
+        Some(passphrase) => DatabaseKey::new(passphrase).map(Some),
+        None => Err(key_error(format!(
+            "the database is encrypted, but neither ${} nor a key file is set",
+            config.key_env
+        ))),
+    }
+}
+
+/// A secret from the environment variable `key_env`, or else from `key_file` without its
+/// surrounding whitespace. `None` if neither is set.
+pub fn read_secret(key_env: &str, key_file: Option<&Path>) -> Result<Option<String>> {
+    if let Ok(secret) = std::env::var(key_env) {
+        return Ok(Some(secret));
+    }
+    key_file
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .map(|secret| secret.trim().to_owned())
+                .map_err(|e| {
+                    key_error(format!("could not read the key from {}: {}", path.display(), e))
+                })
+        })
+        .transpose()
+}
+
+/// Options to open the database at `path`, decrypting it with `key`. SQLCipher needs the key
+/// before anything else is read, sqlx sends `PRAGMA key` first.
+pub fn connect_options(path: impl AsRef<Path>, key: Option<&DatabaseKey>) -> SqliteConnectOptions {
+    let options = SqliteConnectOptions::new().filename(path);
+    match key {
+        Some(key) => options.pragma("key", key.pragma_value()),
+        None => options,
+    }
+}
+
+/// Fails if this build can't encrypt databases. Without SQLCipher `PRAGMA key` is silently
+/// ignored, and the database would be written in plaintext.
+pub async fn require_sqlcipher(conn: &mut SqliteConnection) -> Result<()> {
+    let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(&mut *conn)
+        .await?;
+    match version {
+        Some(version) => {
+            info!("The database is encrypted with SQLCipher {}", version);
+            Ok(())
+        }
+        None => Err(key_error(
+            "the database is configured to be encrypted, but the server was built without \
+             SQLCipher, build it with `--features sqlcipher`"
+                .to_owned(),
+        )),
+    }
+}
+
+/// Encrypts the plaintext database at `path` with `key`. The plaintext database is kept next
+/// to it as `<path>.plaintext`, to be deleted once the server started on the encrypted one.
+/// Returns the path of the plaintext copy.
+pub async fn encrypt_database(path: &Path, key: &DatabaseKey) -> Result<PathBuf> {
+    let encrypted = path.with_extension("sqlite.encrypted");
+    let plaintext = path.with_extension("sqlite.plaintext");
+    if plaintext.exists() {
+        return Err(key_error(format!(
+            "{} exists, the database was encrypted already",
+            plaintext.display()
+        )));
+    }
+    if encrypted.exists() {
+        tokio::fs::remove_file(&encrypted).await?;
+    }
+
+    let mut conn = connect_options(path, None).connect().await?;
+    require_sqlcipher(&mut conn).await?;
+    // fails with "file is not a database" if it is encrypted already
+    sqlx::query("SELECT COUNT(*) FROM sqlite_master")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("ATTACH DATABASE ? AS encrypted KEY ?")
+        .bind(encrypted.to_string_lossy())
+        .bind(&key.0)
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('encrypted')")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DETACH DATABASE encrypted")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    // the copy has to open with the key before it replaces the original
+    let mut conn = connect_options(&encrypted, Some(key)).connect().await?;
+    sqlx::query("SELECT COUNT(*) FROM sqlite_master")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    tokio::fs::rename(path, &plaintext).await?;
+    tokio::fs::rename(&encrypted, path).await?;
+    Ok(plaintext)
+}
+
+fn key_error(message: String) -> CustError {
+    CustError::new(message, StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod test_db_encryption {
+    use super::{database_key, DatabaseKey};
+    use crate::{DatabaseEncryption, EncryptionConfig};
+
+    #[test]
+    fn keys_are_read_from_the_environment_or_a_file() {
+        let mut config = EncryptionConfig {
+            key_env: "FIND_ME_PLS_TEST_DB_KEY".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(database_key(&config).unwrap(), None);
+
+        config.kind = DatabaseEncryption::Sqlcipher;
+        assert!(database_key(&config).is_err());
+
+        let path = std::env::temp_dir().join(format!("find_me_pls_db_key_{}", std::process::id()));
+        std::fs::write(&path, "file secret\n").unwrap();
+        config.key_file = Some(path.clone());
+        assert_eq!(database_key(&config).unwrap(), Some(DatabaseKey("file secret".to_owned())));
+
+        std::env::set_var("FIND_ME_PLS_TEST_DB_KEY", "env secret");
+        assert_eq!(database_key(&config).unwrap(), Some(DatabaseKey("env secret".to_owned())));
+        std::env::remove_var("FIND_ME_PLS_TEST_DB_KEY");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keys_are_quoted_and_never_printed() {
+        let key = DatabaseKey::new("it's secret").unwrap();
+        assert_eq!(key.pragma_value(), "'it''s secret'");
+        assert_eq!(format!("{:?}", key), "DatabaseKey(..)");
+        assert!(DatabaseKey::new(" ").is_err());
+    }
+}
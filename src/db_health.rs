@@ -0,0 +1,296 @@
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use tower::{Service, ServiceExt};
+use tracing::warn;
+
+use crate::{ConfigHandle, CustError, API_V1, API_V2};
+
+/// Marks responses that failed because the database was busy, locked or unreachable
+#[derive(Debug, Clone, Copy)]
+pub struct TransientDbError;
+
+/// Whether an error is likely to go away on its own, e.g. a lock held by another connection
+/// or a network filesystem that is briefly gone.
+pub fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            // extended result codes carry the primary code in their lowest byte
+            let code = e.code().and_then(|code| code.parse::<i32>().ok()).unwrap_or(0);
+            matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED | SQLITE_IOERR)
+        }
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        _ => false,
+    }
+}
+
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_IOERR: i32 = 10;
+
+/// Circuit breaker over the database. After `breaker_threshold` transient failures in a row the
+/// api answers 503 right away for `breaker_open_secs`, then lets requests through again to find
+/// out whether the database recovered.
+#[derive(Clone)]
+pub struct DbHealth {
+    config: ConfigHandle,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbStatus {
+    Healthy,
+    /// Recent requests failed, but not enough to open the breaker
+    Degraded,
+    /// The breaker is open
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DbHealthReport {
+    pub status: DbStatus,
+    pub consecutive_failures: u32,
+    /// Seconds until requests are let through again, while the breaker is open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_secs: Option<u64>,
+}
+
+impl DbHealth {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self {
+            config,
+            state: Default::default(),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    pub fn record_failure(&self) {
+        let (threshold, open_secs) = {
+            let config = self.config.get();
+            (config.database.breaker_threshold, config.database.breaker_open_secs)
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if threshold != 0 && state.consecutive_failures >= threshold {
+            if state.open_until.is_none() {
+                warn!(
+                    "Database failed {} times in a row, rejecting requests for {}s",
+                    state.consecutive_failures, open_secs
+                );
+            }
+            state.open_until = Some(Instant::now() + Duration::from_secs(open_secs));
+        }
+    }
+
+    /// Time until requests are let through again, `None` while the breaker is closed
+    pub fn retry_in(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        let open_until = state.open_until?;
+        open_until.checked_duration_since(Instant::now())
+    }
+
+    pub fn report(&self) -> DbHealthReport {
+        let retry_in = self.retry_in();
+        let consecutive_failures = self.state.lock().unwrap().consecutive_failures;
+        let status = match (retry_in, consecutive_failures) {
+            (Some(_), _) => DbStatus::Unavailable,
+            (None, 0) => DbStatus::Healthy,
+            (None, _) => DbStatus::Degraded,
+        };
+        DbHealthReport {
+            status,
+            consecutive_failures,
+            retry_in_secs: retry_in.map(whole_secs),
+        }
+    }
+
+    /// Delay before retry `attempt` (starting at 1): exponential backoff, of which a random
+    /// half is skipped, so concurrent requests don't retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.config.get().database.retry_base_delay_ms;
+        let delay = base.saturating_mul(1 << attempt.min(16).saturating_sub(1));
+        let jitter = RandomState::new().build_hasher().finish() % (delay / 2 + 1);
+        Duration::from_millis(delay - jitter)
+    }
+}
+
+/// Retries safe requests of the REST api that failed with a [`TransientDbError`] and rejects
+/// api requests while the [`DbHealth`] breaker is open. Wraps the whole router, so a retry goes
+/// through routing and all middleware again.
+#[derive(Clone)]
+pub struct DbRetry<S> {
+    inner: S,
+    health: DbHealth,
+}
+
+impl<S> DbRetry<S> {
+    pub fn new(inner: S, health: DbHealth) -> Self {
+        Self { inner, health }
+    }
+}
+
+impl<S> Service<Request<Body>> for DbRetry<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, core::result::Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), Infallible>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // the ready service is the one that has to handle the request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let health = self.health.clone();
+
+        Box::pin(async move {
+            let path = request.uri().path();
+            if !path.starts_with(API_V1) && !path.starts_with(API_V2) {
+                return inner.call(request).await;
+            }
+            if let Some(retry_in) = health.retry_in() {
+                return Ok(unavailable(retry_in));
+            }
+
+            // requests with a body can't be replayed and might not be idempotent
+            let replay = matches!(*request.method(), Method::GET | Method::HEAD)
+                .then(|| Replay::of(&request));
+
+            let mut response = inner.call(request).await?;
+            if let Some(replay) = replay {
+                let retries = health.config.get().database.retry_attempts;
+                for attempt in 1..=retries {
+                    if response.extensions().get::<TransientDbError>().is_none() {
+                        break;
+                    }
+                    tokio::time::sleep(health.backoff(attempt)).await;
+                    response = inner.ready().await?.call(replay.request()).await?;
+                }
+            }
+
+            if response.extensions().get::<TransientDbError>().is_some() {
+                health.record_failure();
+            } else {
+                health.record_success();
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// What is needed to send a request without a body again
+struct Replay {
+    method: Method,
+    uri: axum::http::Uri,
+    version: axum::http::Version,
+    headers: axum::http::HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+}
+
+impl Replay {
+    fn of(request: &Request<Body>) -> Self {
+        Self {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            version: request.version(),
+            headers: request.headers().clone(),
+            connect_info: request.extensions().get::<ConnectInfo<SocketAddr>>().cloned(),
+        }
+    }
+
+    fn request(&self) -> Request<Body> {
+        let mut request = Request::new(Body::empty());
+        *request.method_mut() = self.method.clone();
+        *request.uri_mut() = self.uri.clone();
+        *request.version_mut() = self.version;
+        *request.headers_mut() = self.headers.clone();
+        if let Some(connect_info) = self.connect_info {
+            request.extensions_mut().insert(connect_info);
+        }
+        request
+    }
+}
+
+/// Seconds rounded up, so clients don't come back too early
+fn whole_secs(duration: Duration) -> u64 {
+    (duration.as_millis() as u64).div_ceil(1000).max(1)
+}
+
+fn unavailable(retry_in: Duration) -> Response {
+    let mut response = CustError::new(
+        "database is unavailable, try again later".to_string(),
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+    .into_response();
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(whole_secs(retry_in)),
+    );
+    response
+}
+
+#[cfg(test)]
+mod test_db_health {
+    use std::time::Duration;
+
+    use crate::{ConfigHandle, DbHealth, DbStatus};
+
+    #[test]
+    fn breaker_opens_after_repeated_failures() {
+        // defaults: open after 5 failures for 30 seconds
+        let health = DbHealth::new(ConfigHandle::load("/nonexistent/config.json"));
+        for _ in 0..4 {
+            health.record_failure();
+        }
+        assert_eq!(health.report().status, DbStatus::Degraded);
+        assert!(health.retry_in().is_none());
+
+        health.record_failure();
+        let report = health.report();
+        assert_eq!(report.status, DbStatus::Unavailable);
+        assert_eq!(report.retry_in_secs, Some(30));
+
+        health.record_success();
+        assert_eq!(health.report().status, DbStatus::Healthy);
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter() {
+        let health = DbHealth::new(ConfigHandle::load("/nonexistent/config.json"));
+        for attempt in 1..4 {
+            // 50ms base, doubled per attempt, at most half of it skipped
+            let full = Duration::from_millis(50 << (attempt - 1));
+            let delay = health.backoff(attempt);
+            assert!(delay <= full && delay >= full / 2, "{:?}", delay);
+        }
+    }
+}
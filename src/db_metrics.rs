@@ -0,0 +1,218 @@
+use std::fmt::{Debug, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::field::{Field, Visit};
+use tracing::{warn, Event, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::{metrics, ConfigHandle};
+
+/// Target of the statements sqlx logs
+const SQLX_TARGET: &str = "sqlx::query";
+
+/// Counts and times every SQL statement and warns about statements slower than the configured
+/// threshold. Reads the events sqlx emits after each statement, so the pool must log its
+/// statements, see [`BusinessRules::new`](crate::BusinessRules::new).
+///
+/// The SQLite driver runs statements on a worker thread, outside of any request span, so
+/// statements are counted by their sanitized SQL rather than by route.
+pub struct DbStatementLayer {
+    slow_query_ms: Arc<AtomicU64>,
+}
+
+/// Changes the slow query threshold of a running [`DbStatementLayer`]
+#[derive(Clone)]
+pub struct SlowQueryThreshold(Arc<AtomicU64>);
+
+impl DbStatementLayer {
+    pub fn new() -> (Self, SlowQueryThreshold) {
+        let slow_query_ms = Arc::new(AtomicU64::new(0));
+        let handle = SlowQueryThreshold(Arc::clone(&slow_query_ms));
+        (Self { slow_query_ms }, handle)
+    }
+
+    /// Only the statements are of interest, independent of the log level
+    pub fn filter() -> Targets {
+        Targets::new().with_target(SQLX_TARGET, LevelFilter::TRACE)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for DbStatementLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SQLX_TARGET {
+            return;
+        }
+
+        let mut fields = StatementFields::default();
+        event.record(&mut fields);
+        let Some(elapsed) = fields.elapsed.as_deref().and_then(parse_debug_duration) else {
+            return;
+        };
+        // short statements are only logged as their summary
+        let sql = match fields.statement.as_deref() {
+            Some(statement) if !statement.trim().is_empty() => statement,
+            _ => fields.summary.as_deref().unwrap_or_default(),
+        };
+        let sql = sanitize_sql(sql);
+
+        let labels = format!("{{statement=\"{}\"}}", escape_label(&sql));
+        metrics::increment(&format!("db_statements_total{}", labels));
+        metrics::add(&format!("db_statement_seconds_total{}", labels), elapsed.as_secs_f64());
+
+        let threshold = self.slow_query_ms.load(Ordering::Relaxed);
+        if threshold > 0 && elapsed >= Duration::from_millis(threshold) {
+            metrics::increment(&format!("db_slow_statements_total{}", labels));
+            warn!(elapsed_ms = elapsed.as_secs_f64() * 1000.0, "Slow query: {}", sql);
+        }
+    }
+}
+
+#[derive(Default)]
+struct StatementFields {
+    summary: Option<String>,
+    statement: Option<String>,
+    elapsed: Option<String>,
+}
+
+impl Visit for StatementFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+impl StatementFields {
+    fn record(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "summary" => self.summary = Some(value),
+            "db.statement" => self.statement = Some(value),
+            "elapsed" => self.elapsed = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Applies the slow query threshold of the config now and whenever it is reloaded.
+pub fn watch_slow_query_threshold(config: &ConfigHandle, threshold: SlowQueryThreshold) {
+    let mut updates = config.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let slow_query_ms = updates.borrow_and_update().database.slow_query_ms;
+            threshold.0.store(slow_query_ms, Ordering::Relaxed);
+
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Parses the `Debug` output of a [`Duration`], e.g. `1.5ms` or `250µs`
+fn parse_debug_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ns" => number / 1e9,
+        "µs" => number / 1e6,
+        "ms" => number / 1e3,
+        "s" => number,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// Reduces a statement to its shape: whitespace is collapsed and literals and lists of
+/// parameters become a single `?`, so statements only differing in values are counted together
+/// and no values end up in logs or metrics.
+fn sanitize_sql(sql: &str) -> String {
+    let mut out = String::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // '' is an escaped quote inside of a literal
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                push_parameter(&mut out);
+            }
+            '?' => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                push_parameter(&mut out);
+            }
+            c if c.is_ascii_digit() && !out.ends_with(|p: char| p.is_alphanumeric() || p == '_') => {
+                while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+                push_parameter(&mut out);
+            }
+            c if c.is_whitespace() => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.trim_end().to_owned()
+}
+
+/// Appends a `?`, unless it continues a list like `?, ?`
+fn push_parameter(out: &mut String) {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with("?,") {
+        out.truncate(trimmed.len() - 1);
+        return;
+    }
+    out.push('?');
+}
+
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '"' => {
+                let _ = write!(escaped, "\\{}", c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test_db_metrics {
+    use std::time::Duration;
+
+    use super::{parse_debug_duration, sanitize_sql};
+
+    #[test]
+    fn literals_and_parameter_lists_are_removed() {
+        assert_eq!(
+            sanitize_sql("SELECT *\n    FROM items\n    WHERE id IN (?, ?, ?) AND name = 'O''Brien'"),
+            "SELECT * FROM items WHERE id IN (?) AND name = ?"
+        );
+        assert_eq!(
+            sanitize_sql("UPDATE items SET quantity = 3 WHERE id = ?1 AND item2 = ?2"),
+            "UPDATE items SET quantity = ? WHERE id = ? AND item2 = ?"
+        );
+    }
+
+    #[test]
+    fn debug_durations_are_parsed() {
+        let micros = |value| parse_debug_duration(value).map(|d: Duration| d.as_secs_f64() * 1e6);
+        assert_eq!(micros("1.5ms").map(f64::round), Some(1500.0));
+        assert_eq!(micros("250µs").map(f64::round), Some(250.0));
+        assert_eq!(parse_debug_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_debug_duration("soon"), None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::future::Future;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::http::{Request, StatusCode};
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+use tonic::server::NamedService;
+use tower::Service;
+
+use crate::{metrics, CustError, Result};
+
+/// When the client of the current request stops waiting for the answer. Tonic gives up on the
+/// request at the transport, business methods check it so they stop working for nobody.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// From the `grpc-timeout` header a client sends along with its deadline
+    pub fn from_grpc_timeout(value: &str) -> Option<Self> {
+        parse_grpc_timeout(value).map(Self::after)
+    }
+
+    pub fn exceeded(&self) -> bool {
+        Instant::now() >= self.0
+    }
+
+    /// Runs `future` with this deadline available to [`check_deadline`]
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        DEADLINE.scope(self, future).await
+    }
+}
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/// Deadline of the request currently being handled, if its client set one
+pub fn current_deadline() -> Option<Deadline> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Fails once the deadline of the current request passed. Expensive work calls it between its
+/// steps, outside of a request or without a deadline it never fails.
+pub fn check_deadline() -> Result<()> {
+    match current_deadline() {
+        Some(deadline) if deadline.exceeded() => Err(deadline_exceeded()),
+        _ => Ok(()),
+    }
+}
+
+/// Awaits `future` until the deadline of the current request, e.g. to stop waiting for a lock
+pub async fn until_deadline<F: Future>(future: F) -> Result<F::Output> {
+    match current_deadline() {
+        Some(Deadline(at)) => {
+            tokio::time::timeout_at(at, future).await.map_err(|_| deadline_exceeded())
+        }
+        None => Ok(future.await),
+    }
+}
+
+/// Mapped to `DEADLINE_EXCEEDED` for gRPC clients
+fn deadline_exceeded() -> CustError {
+    metrics::increment("deadline_exceeded_total");
+    CustError::new(
+        "the deadline of the request passed".to_string(),
+        StatusCode::GATEWAY_TIMEOUT,
+    )
+}
+
+/// `grpc-timeout` is up to 8 digits followed by a unit: `H`, `M`, `S`, `m` for milliseconds,
+/// `u` for microseconds or `n` for nanoseconds
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if !(2..=9).contains(&value.len()) || !value.is_ascii() {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// Makes the deadline of a gRPC request available to [`check_deadline`] while the wrapped
+/// service handles it
+#[derive(Debug, Clone)]
+pub struct DeadlineScope<S> {
+    inner: S,
+}
+
+impl<S> DeadlineScope<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service<Request<B>> for DeadlineScope<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, core::result::Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let deadline = request
+            .headers()
+            .get("grpc-timeout")
+            .and_then(|value| value.to_str().ok())
+            .and_then(Deadline::from_grpc_timeout);
+        let future = self.inner.call(request);
+        match deadline {
+            Some(deadline) => Box::pin(deadline.scope(future)),
+            None => Box::pin(future),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for DeadlineScope<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+#[cfg(test)]
+mod test_deadline {
+    use std::time::Duration;
+
+    use super::{check_deadline, parse_grpc_timeout, until_deadline, Deadline};
+
+    #[test]
+    fn grpc_timeouts_are_parsed() {
+        assert_eq!(parse_grpc_timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_grpc_timeout("1500m"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_grpc_timeout("99999999n"), Some(Duration::from_nanos(99999999)));
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+        assert_eq!(parse_grpc_timeout("-1S"), None);
+        assert_eq!(parse_grpc_timeout("10s"), None);
+    }
+
+    #[tokio::test]
+    async fn work_stops_after_the_deadline() {
+        assert!(check_deadline().is_ok());
+
+        Deadline::after(Duration::from_secs(60))
+            .scope(async {
+                assert!(check_deadline().is_ok());
+                assert_eq!(until_deadline(async { 1 }).await.unwrap(), 1);
+            })
+            .await;
+
+        Deadline::after(Duration::ZERO)
+            .scope(async {
+                assert!(check_deadline().is_err());
+                assert!(until_deadline(std::future::pending::<()>()).await.is_err());
+            })
+            .await;
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Cursor;
+
+use image::{ImageOutputFormat, Rgb, RgbImage};
+
+use crate::{Price, Result};
+
+/// Edge length of the generated thumbnails in pixels
+const THUMBNAIL_SIZE: u32 = 64;
+/// Items seeded when no count is given
+pub const DEFAULT_DEMO_ITEMS: usize = 300;
+pub const MAX_DEMO_ITEMS: usize = 5000;
+
+/// Demo categories with the nouns their items are named after
+pub const DEMO_CATEGORIES: [(&str, [&str; 6]); 6] = [
+    ("Books", ["Novel", "Atlas", "Cookbook", "Comic", "Almanac", "Poetry Collection"]),
+    ("Vinyl Records", ["LP", "Single", "Live Album", "Soundtrack", "Box Set", "EP"]),
+    ("Coins", ["Penny", "Silver Dollar", "Commemorative Coin", "Token", "Sovereign", "Half Crown"]),
+    ("Board Games", ["Strategy Game", "Card Game", "Puzzle", "Chess Set", "Dice Game", "Party Game"]),
+    ("Cameras", ["Rangefinder", "Box Camera", "SLR", "Lens", "Light Meter", "Instant Camera"]),
+    ("Tools", ["Hand Plane", "Chisel", "Hammer", "Saw", "Spirit Level", "Wrench"]),
+];
+
+pub const DEMO_LOCATIONS: [&str; 6] = [
+    "Living Room Shelf",
+    "Attic",
+    "Garage",
+    "Basement",
+    "Office Cabinet",
+    "Storage Unit",
+];
+
+const ADJECTIVES: [&str; 12] = [
+    "Vintage", "Signed", "Rare", "Worn", "Mint", "Faded", "Restored", "Limited", "Antique",
+    "Pocket", "Illustrated", "Travel",
+];
+
+const TAGS: [&str; 8] = [
+    "gift", "favorite", "fragile", "duplicate", "to-sell", "inherited", "flea-market", "mint",
+];
+
+/// An item of the demo dataset, referring to categories and locations by their position in
+/// [`DEMO_CATEGORIES`] and [`DEMO_LOCATIONS`].
+#[derive(Debug, Clone)]
+pub struct DemoItem {
+    pub name: String,
+    pub description: String,
+    pub category: usize,
+    pub location: usize,
+    pub price: Price,
+    pub quantity: i32,
+    pub tags: Vec<String>,
+    /// png
+    pub thumbnail: Vec<u8>,
+}
+
+/// Xorshift generator with a fixed seed, so every seeded database contains the same items
+struct DemoRng(u64);
+
+impl DemoRng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Generates `count` demo items. The dataset only depends on `count`, the first items are the
+/// same for every count.
+pub fn demo_items(count: usize) -> Result<Vec<DemoItem>> {
+    let mut rng = DemoRng(0x2545_f491_4f6c_dd1d);
+    let mut items = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let category = rng.below(DEMO_CATEGORIES.len());
+        let (category_name, nouns) = DEMO_CATEGORIES[category];
+        let adjective = ADJECTIVES[rng.below(ADJECTIVES.len())];
+        let noun = nouns[rng.below(nouns.len())];
+        let location = rng.below(DEMO_LOCATIONS.len());
+
+        let mut tags: Vec<String> = (0..rng.below(3))
+            .map(|_| TAGS[rng.below(TAGS.len())].to_owned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        items.push(DemoItem {
+            name: format!("{} {} #{}", adjective, noun, i + 1),
+            description: format!(
+                "A {} {} from the demo {} collection.",
+                adjective.to_lowercase(),
+                noun.to_lowercase(),
+                category_name.to_lowercase()
+            ),
+            category,
+            location,
+            price: (rng.below(50_000) as Price) / 100.0 + 1.0,
+            quantity: rng.below(3) as i32 + 1,
+            tags,
+            thumbnail: demo_thumbnail(rng.next())?,
+        });
+    }
+
+    Ok(items)
+}
+
+/// A diagonal gradient between two colors derived from `seed`
+fn demo_thumbnail(seed: u64) -> Result<Vec<u8>> {
+    let [r1, g1, b1, r2, g2, b2, ..] = seed.to_le_bytes();
+    let mix = |a: u8, b: u8, t: u32| -> u8 {
+        let max = 2 * (THUMBNAIL_SIZE - 1);
+        ((a as u32 * (max - t) + b as u32 * t) / max) as u8
+    };
+
+    let image = RgbImage::from_fn(THUMBNAIL_SIZE, THUMBNAIL_SIZE, |x, y| {
+        let t = x + y;
+        Rgb([mix(r1, r2, t), mix(g1, g2, t), mix(b1, b2, t)])
+    });
+
+    let mut png = Cursor::new(vec![]);
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+mod test_demo {
+    use crate::{demo_items, DEMO_CATEGORIES, DEMO_LOCATIONS};
+
+    #[test]
+    fn demo_items_are_deterministic() {
+        let items = demo_items(20).unwrap();
+        let again = demo_items(30).unwrap();
+        assert_eq!(items.len(), 20);
+
+        for (a, b) in items.iter().zip(&again) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.thumbnail, b.thumbnail);
+            assert!(a.category < DEMO_CATEGORIES.len());
+            assert!(a.location < DEMO_LOCATIONS.len());
+            assert!(image::load_from_memory(&a.thumbnail).is_ok());
+        }
+    }
+}
@@ -1,13 +1,27 @@
+use std::collections::HashMap;
 use std::io;
 
 use axum::{
     body,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
 use tracing::warn;
 use thiserror::Error;
 
+use crate::{
+    is_transient, problem_context, problem_title, status_code_name, TransientDbError,
+    PROBLEM_TYPE_PREFIX,
+};
+
+/// Media type of RFC 7807 problem documents
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Domain of the `ErrorInfo` attached to gRPC statuses
+const ERROR_DOMAIN: &str = "find-me-pls";
+
 
 #[derive(Error, Debug)]
 pub enum NameError {
@@ -22,11 +36,75 @@ pub struct CustError {
     message: String,
     #[serde(skip)]
     status: StatusCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+    /// The database was busy or unreachable, the same request may succeed later
+    #[serde(skip)]
+    transient: bool,
+    /// Stable, machine readable kind of the error, see [`CustError::code`]
+    #[serde(skip)]
+    code: Option<&'static str>,
 }
 
 impl CustError {
     pub fn new(message: String, status: StatusCode) -> Self {
-        Self { message, status }
+        Self {
+            message,
+            status,
+            details: None,
+            transient: false,
+            code: None,
+        }
+    }
+
+    /// Gives the error a more specific code than the one of its status, e.g. to look up a more
+    /// specific title in the message catalog.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// HTTP status the error is answered with
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The code set with [`CustError::with_code`], or the one of the status, e.g. `not_found`
+    pub fn code(&self) -> &'static str {
+        self.code.unwrap_or_else(|| status_code_name(self.status))
+    }
+
+    /// RFC 7807 problem document, with the title in the language of the current request. The
+    /// message is the `detail`, [details](CustError::with_details) are an extension member.
+    pub fn problem(&self) -> serde_json::Value {
+        let context = problem_context();
+        let code = self.code();
+        let mut problem = serde_json::json!({
+            "type": format!("{}{}", PROBLEM_TYPE_PREFIX, code),
+            "title": problem_title(code, self.status, context.locale),
+            "status": self.status.as_u16(),
+            "detail": self.message,
+            "code": code,
+        });
+        if let Some(instance) = context.instance {
+            problem["instance"] = instance.into();
+        }
+        if let Some(details) = &self.details {
+            problem["details"] = details.clone();
+        }
+        problem
+    }
+
+    /// Attaches machine readable context (e.g. the id of a conflicting entity) to the error.
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Prefixes the message with where the error happened, e.g. `operation 3: ...`
+    pub fn context(mut self, context: impl std::fmt::Display) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
     }
 }
 
@@ -39,52 +117,84 @@ impl std::fmt::Display for CustError {
 impl IntoResponse for CustError {
     fn into_response(self) -> axum::response::Response {
         warn!("Generating error: {}", self.message);
-        let msg = serde_json::to_string(&self).unwrap();
+        let msg = self.problem().to_string();
 
-        Response::builder()
+        let mut response = Response::builder()
             .status(self.status)
-            .header("Content-Type", "application/json")
-            .body(body::boxed(msg))
-            .unwrap()
+            .header(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)
+            .header(header::CONTENT_LANGUAGE, problem_context().locale.tag());
+        if self.transient {
+            response = response
+                .header("Retry-After", "1")
+                .extension(TransientDbError);
+        }
+        response.body(body::boxed(msg)).unwrap()
     }
 }
 
 impl std::error::Error for CustError {}
 
+/// gRPC status of an error, with an `ErrorInfo` carrying its code and a `LocalizedMessage` in
+/// the language the request asked for
+impl From<CustError> for Status {
+    fn from(e: CustError) -> Self {
+        let locale = problem_context().locale;
+        let code = e.code();
+        let mut details = ErrorDetails::with_error_info(code, ERROR_DOMAIN, HashMap::new());
+        details.set_localized_message(locale.tag(), problem_title(code, e.status, locale));
+        Status::with_error_details(grpc_code(e.status), e.message, details)
+    }
+}
+
+fn grpc_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ if status.is_client_error() => Code::FailedPrecondition,
+        _ => Code::Internal,
+    }
+}
+
 impl From<sqlx::Error> for CustError {
     fn from(e: sqlx::Error) -> Self {
         dbg!(&e);
-        Self {
-            message: format!("Database error: {}", e),
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+        if is_transient(&e) {
+            let mut error = Self::new(
+                format!("Database is busy: {}", e),
+                StatusCode::SERVICE_UNAVAILABLE,
+            );
+            error.transient = true;
+            return error;
         }
+        Self::new(
+            format!("Database error: {}", e),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
     }
 }
 
 impl From<base64::DecodeError> for CustError {
     fn from(e: base64::DecodeError) -> Self {
-        Self {
-            message: format!("Parsing error: {}", e),
-            status: StatusCode::BAD_REQUEST,
-        }
+        Self::new(format!("Parsing error: {}", e), StatusCode::BAD_REQUEST)
     }
 }
 
 impl From<io::Error> for CustError {
     fn from(e: io::Error) -> Self {
-        Self {
-            message: format!("IO error: {}", e),
-            status: StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        Self::new(format!("IO error: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
     }
 }
 
 impl From<anyhow::Error> for CustError {
     fn from(e: anyhow::Error) -> Self {
-        Self {
-            message: format!("Error: {}", e),
-            status: StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        Self::new(format!("Error: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
     }
 }
 
@@ -93,3 +203,15 @@ impl From<NameError> for CustError {
         Self::new(value.to_string(), StatusCode::BAD_REQUEST)
     }
 }
+
+impl From<image::ImageError> for CustError {
+    fn from(e: image::ImageError) -> Self {
+        Self::new(format!("Image error: {}", e), StatusCode::BAD_REQUEST)
+    }
+}
+
+impl From<reqwest::Error> for CustError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::new(format!("Fetch error: {}", e), StatusCode::BAD_GATEWAY)
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use axum::http::StatusCode;
+use base64::Engine;
+use printpdf::{
+    BuiltinFont, Color, Image, ImageTransform, Mm, PdfDocument, PdfLayerReference, Rect, Rgb,
+};
+
+use crate::{util, CustError, InsuranceReport, Item, ItemExportRow, Result, ID};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+const ROW_HEIGHT: f32 = 30.0;
+const THUMBNAIL_BOX: f32 = 24.0;
+const BARCODE_NARROW: f32 = 0.4;
+const BARCODE_HEIGHT: f32 = 12.0;
+
+/// Renders an inventory sheet of a collection: one row per item with thumbnail, name, location,
+/// quantity and a code 39 barcode of the item id.
+pub fn collection_pdf(
+    title: &str,
+    items: &[Item],
+    locations: &HashMap<ID, String>,
+) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Items");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(pdf_error)?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(pdf_error)?;
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    layer.use_text(title, 18.0, Mm(MARGIN), Mm(PAGE_HEIGHT - MARGIN - 6.0), &bold);
+    let mut top = PAGE_HEIGHT - MARGIN - 12.0;
+
+    for item in items {
+        if top - ROW_HEIGHT < MARGIN {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Items");
+            layer = doc.get_page(page).get_layer(new_layer);
+            top = PAGE_HEIGHT - MARGIN;
+        }
+
+        if let Some(thumbnail) = decode_thumbnail(item) {
+            add_thumbnail(&layer, &thumbnail, top);
+        } else if let Some((r, g, b)) = item.color.as_deref().and_then(util::color_rgb) {
+            // items without a photo get a tile of their color
+            let rgb = |c: u8| c as f32 / 255.0;
+            layer.set_fill_color(Color::Rgb(Rgb::new(rgb(r), rgb(g), rgb(b), None)));
+            layer.add_rect(Rect::new(
+                Mm(MARGIN),
+                Mm(top - THUMBNAIL_BOX - 2.0),
+                Mm(MARGIN + THUMBNAIL_BOX),
+                Mm(top - 2.0),
+            ));
+            layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        }
+
+        let text_x = Mm(MARGIN + THUMBNAIL_BOX + 4.0);
+        layer.use_text(item.name.as_str(), 12.0, text_x, Mm(top - 8.0), &bold);
+
+        let location = item
+            .location_id
+            .and_then(|id| locations.get(&id))
+            .map(String::as_str)
+            .unwrap_or("-");
+        layer.use_text(format!("Location: {}", location), 9.0, text_x, Mm(top - 14.0), &font);
+
+        let quantity = item
+            .quantity
+            .map(|q| q.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        layer.use_text(format!("Quantity: {}", quantity), 9.0, text_x, Mm(top - 19.0), &font);
+
+        if let Some(id) = item.id {
+            let data = id.to_string();
+            let bars = code39_bars(&data);
+            let width = bars.last().map(|(x, w)| x + w).unwrap_or(0.0) * BARCODE_NARROW;
+            let left = PAGE_WIDTH - MARGIN - width;
+            let bottom = top - BARCODE_HEIGHT - 6.0;
+            for (x, w) in bars {
+                layer.add_rect(Rect::new(
+                    Mm(left + x * BARCODE_NARROW),
+                    Mm(bottom),
+                    Mm(left + (x + w) * BARCODE_NARROW),
+                    Mm(bottom + BARCODE_HEIGHT),
+                ));
+            }
+            layer.use_text(data, 8.0, Mm(left), Mm(bottom - 4.0), &font);
+        }
+
+        top -= ROW_HEIGHT;
+    }
+
+    doc.save_to_bytes().map_err(pdf_error)
+}
+
+/// Lists the items of an insurance report with their photo, where they are, what they cost and
+/// what they are worth, followed by the totals per currency
+pub fn insurance_report_pdf(report: &InsuranceReport) -> Result<Vec<u8>> {
+    let title = "Insurance report";
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Items");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(pdf_error)?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(pdf_error)?;
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    layer.use_text(title, 18.0, Mm(MARGIN), Mm(PAGE_HEIGHT - MARGIN - 6.0), &bold);
+    let generated = format!("Generated {}", util::iso_date(report.generated_at));
+    layer.use_text(generated, 9.0, Mm(MARGIN), Mm(PAGE_HEIGHT - MARGIN - 12.0), &font);
+    let mut top = PAGE_HEIGHT - MARGIN - 16.0;
+
+    let or_dash = |value: Option<&str>| value.unwrap_or("-").to_owned();
+    for item in &report.items {
+        if top - ROW_HEIGHT < MARGIN {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Items");
+            layer = doc.get_page(page).get_layer(new_layer);
+            top = PAGE_HEIGHT - MARGIN;
+        }
+
+        if let Some(thumbnail) = item.thumbnail.as_deref().and_then(decode_image) {
+            add_thumbnail(&layer, &thumbnail, top);
+        }
+
+        let text_x = Mm(MARGIN + THUMBNAIL_BOX + 4.0);
+        layer.use_text(item.name.as_str(), 12.0, text_x, Mm(top - 6.0), &bold);
+        let id_x = Mm(PAGE_WIDTH - MARGIN - 15.0);
+        layer.use_text(format!("#{}", item.id), 9.0, id_x, Mm(top - 6.0), &font);
+
+        let location = (!item.location_path.is_empty()).then(|| item.location_path.join(" / "));
+        let lines = [
+            format!(
+                "Category: {}    Location: {}",
+                or_dash(item.category.as_deref()),
+                or_dash(location.as_deref())
+            ),
+            format!(
+                "Purchased: {}    Price: {}    Quantity: {}",
+                or_dash(item.purchase_date.as_deref()),
+                or_dash(item.formatted_purchase_price.as_deref()),
+                item.quantity.map(|q| q.to_string()).unwrap_or_else(|| "-".to_owned())
+            ),
+            format!(
+                "Current value: {}    Photos: {}",
+                or_dash(item.formatted_current_value.as_deref()),
+                item.photo_count
+            ),
+        ];
+        for (i, line) in lines.into_iter().enumerate() {
+            layer.use_text(line, 9.0, text_x, Mm(top - 12.0 - 5.0 * i as f32), &font);
+        }
+
+        top -= ROW_HEIGHT;
+    }
+
+    if top - 10.0 - 5.0 * (report.totals.len() as f32) < MARGIN {
+        let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Totals");
+        layer = doc.get_page(page).get_layer(new_layer);
+        top = PAGE_HEIGHT - MARGIN;
+    }
+    layer.use_text("Totals", 12.0, Mm(MARGIN), Mm(top - 6.0), &bold);
+    for (i, total) in report.totals.iter().enumerate() {
+        let line = format!(
+            "{} items: purchased for {}, worth {}",
+            total.item_count, total.formatted_purchase_price, total.formatted_current_value
+        );
+        layer.use_text(line, 9.0, Mm(MARGIN), Mm(top - 12.0 - 5.0 * i as f32), &font);
+    }
+
+    doc.save_to_bytes().map_err(pdf_error)
+}
+
+/// Draws `thumbnail` into the thumbnail box at the left of the row starting at `top`
+fn add_thumbnail(layer: &PdfLayerReference, thumbnail: &image::DynamicImage, top: f32) {
+    let size = thumbnail.width().max(thumbnail.height()) as f32;
+    // choose the dpi, so the larger side fills the thumbnail box
+    let dpi = size / (THUMBNAIL_BOX / 25.4);
+    Image::from_dynamic_image(thumbnail).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(top - THUMBNAIL_BOX - 2.0)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+}
+
+fn decode_thumbnail(item: &Item) -> Option<image::DynamicImage> {
+    decode_image(item.thumbnail.as_ref()?)
+}
+
+fn decode_image(data: &str) -> Option<image::DynamicImage> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data).ok()?;
+    let image = image::load_from_memory(&bytes).ok()?;
+    // pdf images don't support an alpha channel
+    Some(image::DynamicImage::ImageRgb8(image.to_rgb8()))
+}
+
+fn pdf_error(e: printpdf::Error) -> CustError {
+    CustError::new(format!("PDF error: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Columns available in the csv export, in their default order
+pub const CSV_COLUMNS: [&str; 12] = [
+    "id",
+    "name",
+    "description",
+    "category_id",
+    "category",
+    "price",
+    "location_id",
+    "location",
+    "quantity",
+    "tags",
+    "color",
+    "icon",
+];
+
+/// Parses a comma separated column selection, rejecting unknown columns.
+pub fn parse_csv_columns(columns: Option<&str>) -> Result<Vec<&'static str>> {
+    let columns = match columns {
+        Some(columns) if !columns.trim().is_empty() => columns,
+        _ => return Ok(CSV_COLUMNS.to_vec()),
+    };
+
+    columns
+        .split(',')
+        .map(|c| {
+            let c = c.trim();
+            CSV_COLUMNS.iter().find(|known| **known == c).copied().ok_or_else(|| {
+                CustError::new(format!("unknown column: {}", c), StatusCode::BAD_REQUEST)
+            })
+        })
+        .collect()
+}
+
+/// Encodes a single csv line
+pub fn csv_record<I, T>(fields: I) -> Result<String>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields).map_err(anyhow::Error::from)?;
+    let bytes = writer.into_inner().map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn csv_row(row: &ItemExportRow, columns: &[&str]) -> Result<String> {
+    fn opt<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(ToString::to_string).unwrap_or_default()
+    }
+
+    csv_record(columns.iter().map(|column| match *column {
+        "id" => row.id.to_string(),
+        "name" => row.name.clone(),
+        "description" => opt(&row.description),
+        "category_id" => opt(&row.category_id),
+        "category" => opt(&row.category),
+        "price" => opt(&row.price),
+        "location_id" => opt(&row.location_id),
+        "location" => opt(&row.location),
+        "quantity" => opt(&row.quantity),
+        "tags" => opt(&row.tags),
+        "color" => opt(&row.color),
+        "icon" => opt(&row.icon),
+        _ => String::new(),
+    }))
+}
+
+/// Code 39 patterns of the supported characters, as narrow (n) or wide (w) elements, starting
+/// with a bar and alternating between bars and spaces.
+fn code39_pattern(c: char) -> Option<&'static str> {
+    Some(match c {
+        '0' => "nnnwwnwnn",
+        '1' => "wnnwnnnnw",
+        '2' => "nnwwnnnnw",
+        '3' => "wnwwnnnnn",
+        '4' => "nnnwwnnnw",
+        '5' => "wnnwwnnnn",
+        '6' => "nnwwwnnnn",
+        '7' => "nnnwnnwnw",
+        '8' => "wnnwnnwnn",
+        '9' => "nnwwnnwnn",
+        '*' => "nwnnwnwnn",
+        _ => return None,
+    })
+}
+
+/// Bars of a code 39 barcode as (offset, width) in multiples of the narrow bar width.
+/// Unsupported characters are skipped.
+pub fn code39_bars(data: &str) -> Vec<(f32, f32)> {
+    let mut bars = vec![];
+    let mut x = 0.0;
+    let encoded = format!("*{}*", data);
+
+    for pattern in encoded.chars().filter_map(code39_pattern) {
+        for (i, element) in pattern.chars().enumerate() {
+            let width = if element == 'w' { 3.0 } else { 1.0 };
+            if i % 2 == 0 {
+                bars.push((x, width));
+            }
+            x += width;
+        }
+        // narrow gap between characters
+        x += 1.0;
+    }
+
+    bars
+}
+
+#[cfg(test)]
+mod test_export {
+    use crate::{
+        code39_bars, csv_row, insurance_report_pdf, parse_csv_columns, InsuranceReport,
+        InsuredItem, ItemExportRow,
+    };
+
+    #[test]
+    fn csv_columns_are_selectable() {
+        let row = ItemExportRow {
+            id: 3,
+            name: "Hammer, big".to_owned(),
+            description: None,
+            category_id: None,
+            category: Some("Tools".to_owned()),
+            price: Some(12.5),
+            location_id: None,
+            location: None,
+            quantity: None,
+            tags: None,
+            color: Some("#ffaa00".to_owned()),
+            icon: None,
+        };
+
+        let columns = parse_csv_columns(Some("id, name,category,price")).unwrap();
+        assert_eq!(csv_row(&row, &columns).unwrap(), "3,\"Hammer, big\",Tools,12.5\n");
+
+        assert!(parse_csv_columns(Some("id,thumbnail")).is_err());
+        assert_eq!(parse_csv_columns(None).unwrap().len(), 12);
+    }
+
+    #[test]
+    fn code39_has_five_bars_per_character() {
+        // start, 4, 2, stop
+        let bars = code39_bars("42");
+        assert_eq!(bars.len(), 4 * 5);
+
+        // every character is 15 modules wide plus the gap
+        let (x, w) = bars.last().unwrap();
+        assert_eq!(x + w, 4.0 * 16.0 - 1.0);
+    }
+
+    #[test]
+    fn insurance_reports_continue_on_new_pages() {
+        let item = |id| InsuredItem {
+            id,
+            name: format!("Item {}", id),
+            description: None,
+            category: Some("Tools".to_owned()),
+            location_path: vec!["House".to_owned(), "Garage".to_owned()],
+            quantity: Some(1),
+            purchase_date: Some("2024-03-01".to_owned()),
+            purchase_price: Some(20.0),
+            current_value: None,
+            currency: Some("EUR".to_owned()),
+            formatted_purchase_price: Some("€20.00".to_owned()),
+            formatted_current_value: None,
+            photo_count: 0,
+            thumbnail: None,
+        };
+        let report = InsuranceReport {
+            generated_at: 0,
+            location_id: None,
+            category_id: None,
+            items: (1..=20).map(item).collect(),
+            totals: vec![],
+        };
+
+        let pdf = insurance_report_pdf(&report).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
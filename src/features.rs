@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{ConfigHandle, CustError, Result};
+
+/// Subsystems an operator can switch off per instance, without rebuilding the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Delivering events to registered webhooks, and managing them
+    Webhooks,
+    /// The `/public` api and making collections public
+    PublicSharing,
+    /// Finding items by a photo of them
+    ImageSearch,
+    /// Finding items by the vectors of their text, also needs `search.semantic.backend`
+    SemanticSearch,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [
+        Feature::Webhooks,
+        Feature::PublicSharing,
+        Feature::ImageSearch,
+        Feature::SemanticSearch,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Webhooks => "webhooks",
+            Feature::PublicSharing => "public_sharing",
+            Feature::ImageSearch => "image_search",
+            Feature::SemanticSearch => "semantic_search",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|feature| feature.as_str() == name)
+            .ok_or_else(|| {
+                CustError::new(format!("unknown feature {:?}", name), StatusCode::NOT_FOUND)
+            })
+    }
+}
+
+/// A feature flag as listed by `GET /admin/flags`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    pub name: Feature,
+    pub enabled: bool,
+    /// What the config file says
+    pub configured: bool,
+    /// Set by an admin at runtime, wins over the config file
+    pub overridden: Option<bool>,
+}
+
+/// Body of `PUT /admin/flags/:flag`, `null` goes back to the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagOverride {
+    pub enabled: Option<bool>,
+}
+
+/// Whether features are enabled: as in the config file, unless an admin overrode them at
+/// runtime. The config is read on every check, so reloads apply immediately. Clones share their
+/// overrides.
+#[derive(Debug, Clone)]
+pub struct FeatureFlags {
+    config: ConfigHandle,
+    overrides: Arc<RwLock<BTreeMap<Feature, bool>>>,
+}
+
+impl FeatureFlags {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self {
+            config,
+            overrides: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        let overridden = self.overrides.read().unwrap().get(&feature).copied();
+        overridden.unwrap_or_else(|| self.config.get().features.is_enabled(feature))
+    }
+
+    /// Fails with 404 if `feature` is switched off, as if its routes didn't exist
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.is_enabled(feature) {
+            return Ok(());
+        }
+        Err(CustError::new(
+            format!("the feature {} is disabled on this instance", feature.as_str()),
+            StatusCode::NOT_FOUND,
+        )
+            .with_code("feature_disabled"))
+    }
+
+    /// Overrides the config file for `feature`, `None` removes the override
+    pub fn set_override(&self, feature: Feature, enabled: Option<bool>) {
+        let mut overrides = self.overrides.write().unwrap();
+        match enabled {
+            Some(enabled) => overrides.insert(feature, enabled),
+            None => overrides.remove(&feature),
+        };
+    }
+
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let overrides = self.overrides.read().unwrap().clone();
+        let config = self.config.get();
+        Feature::ALL
+            .into_iter()
+            .map(|feature| {
+                let configured = config.features.is_enabled(feature);
+                let overridden = overrides.get(&feature).copied();
+                FeatureFlag {
+                    name: feature,
+                    enabled: overridden.unwrap_or(configured),
+                    configured,
+                    overridden,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_features {
+    use super::{Feature, FeatureFlags};
+    use crate::ConfigHandle;
+
+    #[test]
+    fn overrides_win_over_the_config() {
+        let path = std::env::temp_dir()
+            .join(format!("find_me_pls_config_features_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "features": { "image_search": false } }"#).unwrap();
+        let flags = FeatureFlags::new(ConfigHandle::load(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(flags.is_enabled(Feature::Webhooks));
+        assert!(!flags.is_enabled(Feature::ImageSearch));
+        let error = flags.require(Feature::ImageSearch).unwrap_err();
+        assert_eq!(error.code(), "feature_disabled");
+
+        flags.set_override(Feature::ImageSearch, Some(true));
+        flags.set_override(Feature::Webhooks, Some(false));
+        assert!(flags.require(Feature::ImageSearch).is_ok());
+        assert!(!flags.clone().is_enabled(Feature::Webhooks));
+
+        flags.set_override(Feature::ImageSearch, None);
+        let listed = flags.list();
+        assert!(!listed[2].enabled);
+        assert_eq!(listed[2].overridden, None);
+        assert_eq!(listed[0].overridden, Some(false));
+
+        assert!(Feature::parse("public_sharing").is_ok());
+        assert!(Feature::parse("semantic_search").is_ok());
+        assert!(Feature::parse("time_travel").is_err());
+    }
+}
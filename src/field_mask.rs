@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use axum::http::StatusCode;
+
+use crate::find_me_pls::v2;
+use crate::{CustError, Result};
+
+/// Fields of `find_me_pls.v2.Item` a field mask may name
+const ITEM_FIELDS: [&str; 25] = [
+    "id",
+    "name",
+    "description",
+    "category_id",
+    "price",
+    "thumbnail",
+    "fullsize",
+    "tags",
+    "location_id",
+    "quantity",
+    "width_cm",
+    "height_cm",
+    "depth_cm",
+    "weight_kg",
+    "purchase_price",
+    "current_value",
+    "currency",
+    "purchase_date",
+    "ownership_state",
+    "state_changed_at",
+    "uuid",
+    "last_accessed_at",
+    "image_text",
+    "color",
+    "icon",
+];
+
+/// The fields of items a gRPC client asked for with a `google.protobuf.FieldMask`. An empty mask
+/// asks for all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemFieldMask {
+    /// `None` for all fields
+    paths: Option<HashSet<String>>,
+}
+
+impl ItemFieldMask {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn from_paths(paths: Vec<String>) -> Result<Self> {
+        if paths.is_empty() {
+            return Ok(Self::all());
+        }
+        if let Some(unknown) = paths.iter().find(|path| !ITEM_FIELDS.contains(&path.as_str())) {
+            return Err(CustError::new(
+                format!("`{}` is not a field of items", unknown),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        Ok(Self {
+            paths: Some(paths.into_iter().collect()),
+        })
+    }
+
+    pub fn includes(&self, field: &str) -> bool {
+        self.paths.as_ref().is_none_or(|paths| paths.contains(field))
+    }
+
+    /// Whether the image file of an item has to be read, it holds the thumbnail and the full
+    /// size image
+    pub fn needs_files(&self) -> bool {
+        self.includes("thumbnail") || self.includes("fullsize")
+    }
+
+    /// Clears the fields that were not asked for
+    pub fn apply(&self, item: &mut v2::Item) {
+        macro_rules! clear_excluded {
+            ($($field:ident),*) => {
+                $(
+                    if !self.includes(stringify!($field)) {
+                        item.$field = Default::default();
+                    }
+                )*
+            };
+        }
+
+        if self.paths.is_none() {
+            return;
+        }
+        clear_excluded!(
+            id, name, description, category_id, price, thumbnail, fullsize, tags, location_id,
+            quantity, width_cm, height_cm, depth_cm, weight_kg, purchase_price, current_value,
+            currency, purchase_date, ownership_state, state_changed_at, uuid, last_accessed_at,
+            image_text, color, icon
+        );
+    }
+}
+
+impl TryFrom<Option<prost_types::FieldMask>> for ItemFieldMask {
+    type Error = CustError;
+
+    fn try_from(mask: Option<prost_types::FieldMask>) -> Result<Self> {
+        Self::from_paths(mask.map(|mask| mask.paths).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test_field_mask {
+    use super::ItemFieldMask;
+    use crate::find_me_pls::v2;
+
+    #[test]
+    fn excluded_fields_are_cleared() {
+        let mask = ItemFieldMask::from_paths(vec!["id".to_owned(), "name".to_owned()]).unwrap();
+        assert!(!mask.needs_files());
+
+        let mut item = v2::Item {
+            id: Some(1),
+            name: "hammer".to_owned(),
+            fullsize: Some(vec![1, 2, 3]),
+            tags: vec!["tools".to_owned()],
+            ..Default::default()
+        };
+        mask.apply(&mut item);
+        assert_eq!(item.id, Some(1));
+        assert_eq!(item.name, "hammer");
+        assert_eq!(item.fullsize, None);
+        assert!(item.tags.is_empty());
+    }
+
+    #[test]
+    fn empty_masks_include_everything() {
+        let mask = ItemFieldMask::from_paths(vec![]).unwrap();
+        assert!(mask.includes("fullsize"));
+        assert!(mask.needs_files());
+        assert!(ItemFieldMask::from_paths(vec!["weight".to_owned()]).is_err());
+    }
+}
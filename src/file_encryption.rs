@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use axum::http::StatusCode;
+
+use crate::{read_secret, CustError, FileEncryptionConfig, Result};
+
+/// Bytes of the random nonce in front of every encrypted file
+const NONCE_LEN: usize = 12;
+
+/// Encrypts stored files with AES-256-GCM, with a fresh nonce for every write. The header of a
+/// file stays readable but is authenticated together with the name of the file: a changed
+/// header fails the decryption like a changed body, and so does a file copied over another one.
+pub struct FileCipher {
+    cipher: Aes256Gcm,
+}
+
+impl FileCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(&key.into()),
+        }
+    }
+
+    /// From a key of 64 hex digits
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key: [u8; 32] = hex::decode(key.trim())
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| {
+                cipher_error("the file encryption key is not 64 hex digits".to_owned())
+            })?;
+        Ok(Self::new(key))
+    }
+
+    /// The nonce followed by the encrypted `body` of the file `name`
+    pub fn seal(&self, name: &str, header: &[u8], body: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = associated_data(name, header);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: body, aad: &aad })
+            .map_err(|_| cipher_error("could not encrypt the file".to_owned()))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts what [`Self::seal`] returned for the same `name` and `header`
+    pub fn open(&self, name: &str, header: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(cipher_error("the encrypted file is truncated".to_owned()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let aad = associated_data(name, header);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| {
+                cipher_error(
+                    "could not decrypt the file, it was changed or encrypted with another key"
+                        .to_owned(),
+                )
+            })
+    }
+}
+
+/// The length of the name comes first, so no name and header add up to another pair
+fn associated_data(name: &str, header: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(8 + name.len() + header.len());
+    aad.extend_from_slice(&(name.len() as u64).to_le_bytes());
+    aad.extend_from_slice(name.as_bytes());
+    aad.extend_from_slice(header);
+    aad
+}
+
+impl std::fmt::Debug for FileCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FileCipher(..)")
+    }
+}
+
+/// The cipher for the stored files a deployment configured, `None` if they are stored in
+/// plaintext. Only read on startup.
+pub fn file_cipher_from_config(config: &FileEncryptionConfig) -> Result<Option<Arc<FileCipher>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    match read_secret(&config.key_env, config.key_file.as_deref())? {
+        Some(key) => Ok(Some(Arc::new(FileCipher::from_hex(&key)?))),
+        None => Err(cipher_error(format!(
+            "file encryption is enabled, but neither ${} nor a key file is set",
+            config.key_env
+        ))),
+    }
+}
+
+fn cipher_error(message: String) -> CustError {
+    CustError::new(message, StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -1,21 +1,74 @@
-use std::{borrow::Cow, marker::PhantomData, path::PathBuf};
+use std::{borrow::Cow, io::ErrorKind, marker::PhantomData, path::PathBuf, sync::Arc};
 
+use axum::http::StatusCode;
 use tokio::{
-    fs::{create_dir_all, File},
+    fs::{create_dir_all, read_dir, remove_file, rename, File},
     io::{AsyncReadExt, AsyncWriteExt},
 };
+use tracing::instrument;
 
-use crate::Result;
+use crate::{CustError, FileCipher, Result};
+
+/// Start of every stored file, followed by the format version as little endian `u32`
+const MAGIC: &[u8; 4] = b"FMPD";
+
+/// Start of the files encrypted by a [`FileCipher`], followed by the format version like
+/// [`MAGIC`]. Everything after the version is encrypted.
+const ENCRYPTED_MAGIC: &[u8; 4] = b"FMPE";
+
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Version of the files written by [`FileStorage`]: lengths are little endian `u64`s. Files from
+/// before the header are version 0, the lengths in item files were native `usize`s then. They
+/// are still read, `find_me_pls migrate-data` upgrades them for good.
+pub const DATA_FORMAT_VERSION: u32 = 1;
 
 pub trait Storeable {
     fn filename<'a>(&'a self) -> Result<Cow<'a, str>>;
     fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>>;
     fn change_from_bytes(&mut self, bytes: &[u8]);
+
+    /// Converts the content of a version 0 file to the current format. Only types whose encoding
+    /// changed since then override it.
+    fn upgrade_legacy(bytes: &[u8]) -> Result<Vec<u8>>
+    where
+        Self: Sized,
+    {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Format version of the content of a stored file
+pub fn data_format_version(bytes: &[u8]) -> u32 {
+    let rest = bytes
+        .strip_prefix(MAGIC)
+        .or_else(|| bytes.strip_prefix(ENCRYPTED_MAGIC));
+    match rest {
+        Some(rest) if rest.len() >= 4 => u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]),
+        _ => 0,
+    }
+}
+
+fn with_header(body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_LEN + body.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&DATA_FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(body);
+    data
+}
+
+fn unknown_version(file: &str, version: u32) -> CustError {
+    CustError::new(
+        format!("{} has the unknown data format version {}", file, version),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
 }
 
 #[derive(Debug)]
 pub struct FileStorage<D> {
     path: PathBuf,
+    /// Encrypts the files written from now on, `None` to write them in plaintext
+    cipher: Option<Arc<FileCipher>>,
     d_data: PhantomData<D>,
 }
 
@@ -26,32 +79,290 @@ where
     pub fn new(path: PathBuf) -> Self {
         Self {
             path,
+            cipher: None,
             d_data: PhantomData {},
         }
     }
 
+    /// Encrypts the stored files with `cipher`. Plaintext files are not read anymore, only
+    /// [`Self::migrate`] encrypts them.
+    pub fn with_cipher(mut self, cipher: Option<Arc<FileCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// The content of the file `name` as it is written now
+    fn encode(&self, name: &str, body: &[u8]) -> Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => {
+                let mut data = Vec::with_capacity(HEADER_LEN + body.len());
+                data.extend_from_slice(ENCRYPTED_MAGIC);
+                data.extend_from_slice(&DATA_FORMAT_VERSION.to_le_bytes());
+                let sealed = cipher.seal(name, &data, body)?;
+                data.extend_from_slice(&sealed);
+                Ok(data)
+            }
+            None => Ok(with_header(body)),
+        }
+    }
+
+    /// The body of the stored file `name` in the current format, decrypted. With a cipher only
+    /// encrypted files are read: a plaintext file would be taken as it is, whoever wrote it.
+    fn decode(&self, name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+        if self.cipher.is_some() && !bytes.starts_with(ENCRYPTED_MAGIC) {
+            return Err(CustError::new(
+                format!(
+                    "{} is not encrypted although file encryption is enabled, run \
+                     `find_me_pls migrate-data` to encrypt it",
+                    name
+                ),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        self.decode_any(name, bytes)
+    }
+
+    /// Like [`Self::decode`], plaintext files are read even with a cipher
+    fn decode_any(&self, name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+        match data_format_version(bytes) {
+            DATA_FORMAT_VERSION if bytes.starts_with(ENCRYPTED_MAGIC) => {
+                let cipher = self.cipher.as_ref().ok_or_else(|| {
+                    CustError::new(
+                        format!("{} is encrypted, but no file encryption key is configured", name),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?;
+                let (header, sealed) = bytes.split_at(HEADER_LEN);
+                cipher.open(name, header, sealed).map_err(|e| e.context(name))
+            }
+            DATA_FORMAT_VERSION => Ok(bytes[HEADER_LEN..].to_vec()),
+            0 => D::upgrade_legacy(bytes),
+            version => Err(unknown_version(name, version)),
+        }
+    }
+
+    #[instrument(skip_all, fields(dir = %self.path.display()))]
     pub async fn store(&self, data: &D) -> Result<()> {
         let mut path = self.path.clone();
         dbg!(&path);
         create_dir_all(path.clone()).await?;
         dbg!(&path);
-        path.push(data.filename()?.as_ref());
+        let name = data.filename()?;
+        path.push(name.as_ref());
         let mut file = File::create(path).await?;
-        file.write_all(&(data.as_bytes()?)).await?;
+        file.write_all(&self.encode(&name, &data.as_bytes()?)?).await?;
 
         Ok(())
     }
 
-    pub async fn read(&self, data: &mut D) -> Result<()> {
+    /// Names and sizes of all stored files
+    pub async fn usage(&self) -> Result<Vec<(String, u64)>> {
+        let mut entries = match read_dir(&self.path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                files.push((entry.file_name().to_string_lossy().into_owned(), metadata.len()));
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Removes the file of `data`, a missing file is not an error.
+    pub async fn delete(&self, data: &D) -> Result<()> {
         let mut path = self.path.clone();
         path.push(data.filename()?.as_ref());
+        match remove_file(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[instrument(skip_all, fields(dir = %self.path.display()))]
+    pub async fn read(&self, data: &mut D) -> Result<()> {
+        let name = data.filename()?.into_owned();
+        let path = self.path.join(&name);
         dbg!(&path);
         let mut file = File::open(path).await?;
 
         let mut vec = vec![];
         let _ = file.read_to_end(&mut vec).await?;
 
-        data.change_from_bytes(&vec);
+        data.change_from_bytes(&self.decode(&name, &vec)?);
         Ok(())
     }
+
+    /// Names and headers of the stored files
+    async fn headers(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut headers = vec![];
+        let files = self.usage().await?;
+        for name in files.into_iter().map(|(name, _)| name).filter(|name| name.ends_with(".dat")) {
+            let mut header = vec![];
+            File::open(self.path.join(&name))
+                .await?
+                .take(HEADER_LEN as u64)
+                .read_to_end(&mut header)
+                .await?;
+            headers.push((name, header));
+        }
+        Ok(headers)
+    }
+
+    /// Names and format versions of the stored files that are not in the current format. Only
+    /// the headers are read.
+    pub async fn outdated(&self) -> Result<Vec<(String, u32)>> {
+        Ok(self
+            .headers()
+            .await?
+            .into_iter()
+            .map(|(name, header)| (name, data_format_version(&header)))
+            .filter(|(_, version)| *version != DATA_FORMAT_VERSION)
+            .collect())
+    }
+
+    /// Names of the stored files in plaintext although a cipher is configured, e.g. written
+    /// before the encryption was enabled. Only the headers are read.
+    pub async fn unencrypted(&self) -> Result<Vec<String>> {
+        if self.cipher.is_none() {
+            return Ok(vec![]);
+        }
+        Ok(self
+            .headers()
+            .await?
+            .into_iter()
+            .filter(|(_, header)| !header.starts_with(ENCRYPTED_MAGIC))
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Rewrites version 0 files in the current format and encrypts the plaintext files if a
+    /// cipher is configured, each through a temporary file so an interrupted migration leaves
+    /// no half written file. Files of unknown versions are an error. Returns the number of
+    /// migrated files.
+    pub async fn migrate(&self) -> Result<usize> {
+        let outdated = self.outdated().await?;
+        if let Some((name, version)) = outdated.iter().find(|(_, version)| *version != 0) {
+            return Err(unknown_version(name, *version));
+        }
+        let mut names: Vec<String> = outdated.into_iter().map(|(name, _)| name).collect();
+        for name in self.unencrypted().await? {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        for name in &names {
+            let path = self.path.join(name);
+            let mut bytes = vec![];
+            File::open(&path).await?.read_to_end(&mut bytes).await?;
+            let body = self.decode_any(name, &bytes).map_err(|e| e.context(name))?;
+
+            let temporary = self.path.join(format!("{}.migrating", name));
+            let mut file = File::create(&temporary).await?;
+            file.write_all(&self.encode(name, &body)?).await?;
+            file.sync_all().await?;
+            rename(&temporary, &path).await?;
+        }
+        Ok(names.len())
+    }
+}
+
+#[cfg(test)]
+mod test_files {
+    use std::sync::Arc;
+
+    use super::{FileStorage, DATA_FORMAT_VERSION};
+    use crate::{FileCipher, Item};
+
+    #[tokio::test]
+    async fn legacy_files_are_migrated() {
+        let dir = std::env::temp_dir().join(format!("find_me_pls_files_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let storage = FileStorage::<Item>::new(dir.clone());
+
+        // an item file as written before the header
+        let mut legacy = vec![];
+        for image in [&b"asdf"[..], &b"fdas"[..]] {
+            legacy.extend_from_slice(&image.len().to_le_bytes());
+            legacy.extend_from_slice(image);
+        }
+        tokio::fs::write(dir.join("1.dat"), legacy).await.unwrap();
+        let mut item = Item {
+            id: Some(1),
+            ..Default::default()
+        };
+        storage.read(&mut item).await.unwrap();
+        assert_eq!(item.fullsize.as_deref(), Some("ZmRhcw=="));
+        assert_eq!(storage.outdated().await.unwrap(), [("1.dat".to_owned(), 0)]);
+
+        assert_eq!(storage.migrate().await.unwrap(), 1);
+        assert!(storage.outdated().await.unwrap().is_empty());
+        item.thumbnail = None;
+        storage.read(&mut item).await.unwrap();
+        assert_eq!(item.thumbnail.as_deref(), Some("YXNkZg=="));
+
+        let mut newer = b"FMPD".to_vec();
+        newer.extend_from_slice(&(DATA_FORMAT_VERSION + 1).to_le_bytes());
+        tokio::fs::write(dir.join("2.dat"), newer).await.unwrap();
+        assert!(storage.migrate().await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn encrypted_files_need_the_key() {
+        let dir = std::env::temp_dir().join(format!("find_me_pls_encrypted_{}", std::process::id()));
+        let plain = FileStorage::<Item>::new(dir.clone());
+        let item = Item {
+            id: Some(1),
+            thumbnail: Some("YXNkZg==".to_owned()),
+            fullsize: Some("ZmRhcw==".to_owned()),
+            ..Default::default()
+        };
+        plain.store(&item).await.unwrap();
+
+        // files written before the encryption was enabled are only read to encrypt them
+        let cipher = Arc::new(FileCipher::new([7; 32]));
+        let encrypted = FileStorage::<Item>::new(dir.clone()).with_cipher(Some(cipher));
+        let mut read = Item {
+            id: Some(1),
+            ..Default::default()
+        };
+        assert!(encrypted.read(&mut read).await.is_err());
+        assert_eq!(encrypted.unencrypted().await.unwrap(), ["1.dat"]);
+        assert_eq!(encrypted.migrate().await.unwrap(), 1);
+        assert!(encrypted.unencrypted().await.unwrap().is_empty());
+        assert!(encrypted.outdated().await.unwrap().is_empty());
+
+        let bytes = tokio::fs::read(dir.join("1.dat")).await.unwrap();
+        assert!(!bytes.windows(4).any(|window| window == b"asdf"));
+        encrypted.read(&mut read).await.unwrap();
+        assert_eq!(read.fullsize, item.fullsize);
+        assert!(plain.read(&mut read).await.is_err());
+
+        let other_key = FileStorage::<Item>::new(dir.clone())
+            .with_cipher(Some(Arc::new(FileCipher::new([8; 32]))));
+        assert!(other_key.read(&mut read).await.is_err());
+
+        // the file of one item copied over the one of another isn't read as that item's
+        tokio::fs::write(dir.join("2.dat"), &bytes).await.unwrap();
+        let mut other = Item {
+            id: Some(2),
+            ..Default::default()
+        };
+        assert!(encrypted.read(&mut other).await.is_err());
+
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        tokio::fs::write(dir.join("1.dat"), tampered).await.unwrap();
+        assert!(encrypted.read(&mut read).await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
 }
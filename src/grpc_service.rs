@@ -1,19 +1,46 @@
 use std::sync::Arc;
 
+use axum::http::{self, Uri};
+use tonic::transport::Body;
 use tonic::{Request, Response, Status};
 
-use crate::BusinessRules;
+use crate::{
+    authorize, BusinessRules, ItemSort, MeasurementFilter, OwnershipFilter, Role, SearchOptions,
+    SearchScope,
+};
 
-pub use self::find_me_pls::find_me_pls_server::FindMePlsServer;
-use self::find_me_pls::{
+pub use self::find_me_pls::v1::find_me_pls_server::FindMePlsServer;
+use self::find_me_pls::v1::{
     find_me_pls_server::FindMePls, AddItemToCollectionRequest, Categories, Category, Collection,
     Collections, DeleteItemRequest, Empty, GetCollectionRequest, GetItemRequest, Item, Items,
     QueryItemsRequest, RemoveItemFromCollectionRequest,
 };
 
 pub mod find_me_pls {
-    #![allow(non_snake_case)]
-    tonic::include_proto!("find_me_pls");
+    pub mod v1 {
+        #![allow(non_snake_case)]
+        tonic::include_proto!("find_me_pls.v1");
+    }
+
+    pub mod v2 {
+        #![allow(non_snake_case)]
+        // `ReplicationEvent::change` holds whole messages next to plain ids
+        #![allow(clippy::large_enum_variant)]
+        tonic::include_proto!("find_me_pls.v2");
+    }
+}
+
+/// Clients built before the proto was versioned call `/find_me_pls.FindMePls/...`. The messages
+/// are wire compatible with v1, so those calls are routed to the v1 service.
+pub fn legacy_grpc_path(mut request: http::Request<Body>) -> http::Request<Body> {
+    if let Some(method) = request.uri().path().strip_prefix("/find_me_pls.FindMePls/") {
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = format!("/find_me_pls.v1.FindMePls/{}", method).parse().ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *request.uri_mut() = uri;
+        }
+    }
+    request
 }
 
 pub struct FindMePlsService {
@@ -39,6 +66,7 @@ impl Default for FindMePlsService {
 #[tonic::async_trait]
 impl FindMePls for FindMePlsService {
     async fn new_item(&self, request: Request<Item>) -> Result<Response<Item>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let result = self
             .business_rules
             .as_ref()
@@ -48,7 +76,7 @@ impl FindMePls for FindMePlsService {
                 let result = item_res.await;
                 match result {
                     Ok(item) => Ok(Response::new(item.into())),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -56,7 +84,12 @@ impl FindMePls for FindMePlsService {
     }
 
     async fn get_all_items(&self, _request: Request<Empty>) -> Result<Response<Items>, Status> {
-        let items_res = self.business_rules.as_ref().map(|t| t.get_all_items());
+        let ownership = OwnershipFilter::default();
+        let sort = ItemSort::default();
+        let items_res = self
+            .business_rules
+            .as_ref()
+            .map(|t| t.get_all_items(&ownership, &sort));
         match items_res {
             Some(items_res) => {
                 let result = items_res.await;
@@ -64,7 +97,7 @@ impl FindMePls for FindMePlsService {
                     Ok(items) => Ok(Response::new(Items {
                         items: items.into_iter().map(Into::into).collect(),
                     })),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -72,16 +105,20 @@ impl FindMePls for FindMePlsService {
     }
 
     async fn get_item(&self, request: Request<GetItemRequest>) -> Result<Response<Item>, Status> {
-        let item_res = self
-            .business_rules
-            .as_ref()
-            .map(|t| t.get_item(request.into_inner().id));
+        let id = request.into_inner().id;
+        let rules = self.business_rules.as_ref();
+        let item_res = rules.map(|t| t.get_item(id));
         match item_res {
             Some(item_res) => {
                 let result = item_res.await;
                 match result {
-                    Ok(item) => Ok(Response::new(item.into())),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Ok(item) => {
+                        if let Some(rules) = rules {
+                            rules.record_access(id);
+                        }
+                        Ok(Response::new(item.into()))
+                    }
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -93,7 +130,14 @@ impl FindMePls for FindMePlsService {
         request: Request<QueryItemsRequest>,
     ) -> Result<Response<Items>, Status> {
         let query = request.into_inner().query;
-        let items_res = self.business_rules.as_ref().map(|t| t.find_items(query));
+        let filter = MeasurementFilter::default();
+        let ownership = OwnershipFilter::default();
+        let scope = SearchScope::default();
+        let options = SearchOptions::default();
+        let items_res = self
+            .business_rules
+            .as_ref()
+            .map(|t| t.find_items(query, &filter, &ownership, &scope, &options));
         match items_res {
             Some(items_res) => {
                 let result = items_res.await;
@@ -101,7 +145,7 @@ impl FindMePls for FindMePlsService {
                     Ok(items) => Ok(Response::new(Items {
                         items: items.into_iter().map(Into::into).collect(),
                     })),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -112,6 +156,7 @@ impl FindMePls for FindMePlsService {
         &self,
         request: Request<DeleteItemRequest>,
     ) -> Result<Response<Item>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let item_res = self
             .business_rules
             .as_ref()
@@ -121,7 +166,7 @@ impl FindMePls for FindMePlsService {
                 let result = item_res.await;
                 match result {
                     Ok(item) => Ok(Response::new(item.into())),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -129,6 +174,7 @@ impl FindMePls for FindMePlsService {
     }
 
     async fn new_category(&self, request: Request<Category>) -> Result<Response<Category>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let result = self
             .business_rules
             .as_ref()
@@ -139,7 +185,7 @@ impl FindMePls for FindMePlsService {
                 let result = category_res.await;
                 match result {
                     Ok(category) => Ok(Response::new(category.into())),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -159,7 +205,7 @@ impl FindMePls for FindMePlsService {
                     Ok(categories) => Ok(Response::new(Categories {
                         categories: categories.into_iter().map(Into::into).collect(),
                     })),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -170,6 +216,7 @@ impl FindMePls for FindMePlsService {
         &self,
         request: Request<Collection>,
     ) -> Result<Response<Collection>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let result = self
             .business_rules
             .as_ref()
@@ -180,7 +227,7 @@ impl FindMePls for FindMePlsService {
                 let collection = coll.await;
                 match collection {
                     Ok(collection) => Ok(Response::new(collection.into())),
-                    Err(e) => Err(Status::from_error(e.into())),
+                    Err(e) => Err(Status::from(e)),
                 }
             }
             None => Err(Status::internal("Business rules not initialized")),
@@ -203,7 +250,7 @@ impl FindMePls for FindMePlsService {
                         collections: c.into_iter().map(Into::into).collect(),
                     })
                 })
-                .map_err(|e| Status::from_error(e.into())),
+                .map_err(Status::from),
             None => Err(Status::internal("Business rules not initialized")),
         }
     }
@@ -219,7 +266,7 @@ impl FindMePls for FindMePlsService {
             Some(future) => future
                 .await
                 .map(|c| Response::new(c.into()))
-                .map_err(|e| Status::from_error(e.into())),
+                .map_err(Status::from),
             None => Err(Status::internal("Business rules not initialized")),
         }
     }
@@ -228,6 +275,7 @@ impl FindMePls for FindMePlsService {
         &self,
         request: Request<AddItemToCollectionRequest>,
     ) -> Result<Response<Empty>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let add_item_request = request.into_inner();
         let item_id = add_item_request.item_id;
         let collection_id = add_item_request.collection_id;
@@ -241,7 +289,7 @@ impl FindMePls for FindMePlsService {
             Some(future) => future
                 .await
                 .map(|_| Response::new(Empty {}))
-                .map_err(|e| Status::from_error(e.into())),
+                .map_err(Status::from),
             None => Err(Status::internal("Business rules not initialized")),
         }
     }
@@ -250,6 +298,7 @@ impl FindMePls for FindMePlsService {
         &self,
         request: Request<RemoveItemFromCollectionRequest>,
     ) -> Result<Response<Empty>, Status> {
+        authorize(&request, Role::ReadWrite)?;
         let remove_item_request = request.into_inner();
         let item_id = remove_item_request.item_id;
         let collection_id = remove_item_request.collection_id;
@@ -263,8 +312,36 @@ impl FindMePls for FindMePlsService {
             Some(future) => future
                 .await
                 .map(|_| Response::new(Empty {}))
-                .map_err(|e| Status::from_error(e.into())),
+                .map_err(Status::from),
             None => Err(Status::internal("Business rules not initialized")),
         }
     }
 }
+
+#[cfg(test)]
+mod test_legacy_path {
+    use axum::http;
+    use tonic::transport::Body;
+
+    use crate::legacy_grpc_path;
+
+    #[test]
+    fn unversioned_calls_are_routed_to_v1() {
+        let request = http::Request::builder()
+            .uri("http://localhost:50051/find_me_pls.FindMePls/GetAllItems")
+            .body(Body::empty())
+            .unwrap();
+        let request = legacy_grpc_path(request);
+        assert_eq!(
+            request.uri(),
+            "http://localhost:50051/find_me_pls.v1.FindMePls/GetAllItems"
+        );
+
+        let request = http::Request::builder()
+            .uri("http://localhost:50051/find_me_pls.v2.FindMePls/GetAllItems")
+            .body(Body::empty())
+            .unwrap();
+        let request = legacy_grpc_path(request);
+        assert_eq!(request.uri().path(), "/find_me_pls.v2.FindMePls/GetAllItems");
+    }
+}
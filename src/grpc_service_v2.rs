@@ -0,0 +1,372 @@
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use futures::StreamExt;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::{
+    authorize, BatchMutate, BusinessRules, CustError, ItemFieldMask, ItemSort, MeasurementFilter,
+    OwnershipFilter, Role, SearchOptions, SearchScope, DEFAULT_SYNC_LIMIT, MAX_SYNC_LIMIT,
+};
+
+pub use crate::find_me_pls::v2::find_me_pls_server::FindMePlsServer as FindMePlsServerV2;
+use crate::find_me_pls::v2::{
+    find_me_pls_server::FindMePls, upload_item_image_request::Part, AddItemToCollectionRequest,
+    BatchMutateRequest, BatchMutateResponse, Categories, Category, Collection, Collections,
+    DeleteItemRequest, Empty, GetCollectionRequest, GetItemRequest, ImageKind, Item, ItemImage,
+    Items, ListItemsRequest, Location, Locations, QueryItemsRequest,
+    RemoveItemFromCollectionRequest, SyncPullRequest, SyncPullResponse, SyncPushRequest,
+    SyncPushResponse, UploadItemImageRequest,
+};
+
+/// v2 of the gRPC api. Shares the business rules with v1, only the messages differ.
+pub struct FindMePlsServiceV2 {
+    business_rules: Arc<BusinessRules>,
+}
+
+impl FindMePlsServiceV2 {
+    pub fn new(business_rules: Arc<BusinessRules>) -> Self {
+        Self { business_rules }
+    }
+}
+
+#[tonic::async_trait]
+impl FindMePls for FindMePlsServiceV2 {
+    async fn new_item(&self, request: Request<Item>) -> Result<Response<Item>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let mut item = request.into_inner();
+        let key = item.idempotency_key.take();
+        self.business_rules
+            .add_item_idempotent(item.into(), key.as_deref())
+            .await
+            .map(|item| Response::new(item.into()))
+            .map_err(Status::from)
+    }
+
+    async fn get_all_items(
+        &self,
+        request: Request<ListItemsRequest>,
+    ) -> Result<Response<Items>, Status> {
+        let request = request.into_inner();
+        let invalid = <Status as From<CustError>>::from;
+        let sort = ItemSort {
+            sort: request.sort.map(|sort| sort.parse()).transpose().map_err(invalid)?,
+            dir: request.dir.map(|dir| dir.parse()).transpose().map_err(invalid)?,
+        };
+        let mask = ItemFieldMask::try_from(request.field_mask).map_err(invalid)?;
+        self.business_rules
+            .get_all_items_masked(&OwnershipFilter::default(), &sort, &mask)
+            .await
+            .map(|items| Response::new(masked_items(items, &mask)))
+            .map_err(Status::from)
+    }
+
+    async fn get_item(&self, request: Request<GetItemRequest>) -> Result<Response<Item>, Status> {
+        let request = request.into_inner();
+        let mask = ItemFieldMask::try_from(request.field_mask).map_err(Status::from)?;
+        let id = match request.uuid {
+            Some(uuid) => self
+                .business_rules
+                .item_id_by_uuid(&uuid)
+                .await
+                .map_err(Status::from)?,
+            None => request.id,
+        };
+        let item = self
+            .business_rules
+            .get_item_masked(id, &mask)
+            .await
+            .map_err(Status::from)?;
+        self.business_rules.record_access(id);
+        let mut item: Item = item.into();
+        mask.apply(&mut item);
+        Ok(Response::new(item))
+    }
+
+    async fn query_items(
+        &self,
+        request: Request<QueryItemsRequest>,
+    ) -> Result<Response<Items>, Status> {
+        let request = request.into_inner();
+        let filter = MeasurementFilter {
+            min_width_cm: request.min_width_cm,
+            max_width_cm: request.max_width_cm,
+            min_height_cm: request.min_height_cm,
+            max_height_cm: request.max_height_cm,
+            min_depth_cm: request.min_depth_cm,
+            max_depth_cm: request.max_depth_cm,
+            min_weight_kg: request.min_weight_kg,
+            max_weight_kg: request.max_weight_kg,
+        };
+        let state = match request.state {
+            Some(state) => Some(state.parse().map_err(<Status as From<CustError>>::from)?),
+            None => None,
+        };
+        let ownership = OwnershipFilter { state };
+        let scope = SearchScope {
+            collection: request.collection_id,
+            location: request.location_id,
+            ..Default::default()
+        };
+        let options = SearchOptions {
+            prefix: request.prefix,
+            ..Default::default()
+        };
+        // search results are cached with their images, the mask only trims the response
+        let mask = ItemFieldMask::try_from(request.field_mask).map_err(Status::from)?;
+        self.business_rules
+            .find_items(request.query, &filter, &ownership, &scope, &options)
+            .await
+            .map(|items| Response::new(masked_items(items, &mask)))
+            .map_err(Status::from)
+    }
+
+    async fn delete_item(
+        &self,
+        request: Request<DeleteItemRequest>,
+    ) -> Result<Response<Item>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let request = request.into_inner();
+        let id = match request.uuid {
+            Some(uuid) => self
+                .business_rules
+                .item_id_by_uuid(&uuid)
+                .await
+                .map_err(Status::from)?,
+            None => request.id,
+        };
+        self.business_rules
+            .delete_item(id)
+            .await
+            .map(|item| Response::new(item.into()))
+            .map_err(Status::from)
+    }
+
+    async fn upload_item_image(
+        &self,
+        request: Request<Streaming<UploadItemImageRequest>>,
+    ) -> Result<Response<ItemImage>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let mut stream = request.into_inner();
+        let metadata = match stream.message().await?.and_then(|request| request.part) {
+            Some(Part::Metadata(metadata)) => metadata,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message of an upload must be its metadata",
+                ))
+            }
+        };
+
+        let chunks = stream.map(|request| {
+            let request = request.map_err(|status| {
+                CustError::new(
+                    format!("Upload failed: {}", status.message()),
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+            match request.part {
+                Some(Part::Chunk(chunk)) => Ok(chunk),
+                _ => Err(CustError::new(
+                    "only the first message of an upload may carry metadata".to_string(),
+                    StatusCode::BAD_REQUEST,
+                )),
+            }
+        });
+        let primary = metadata.kind() == ImageKind::Primary;
+        self.business_rules
+            .add_item_image_from_chunks(
+                metadata.item_id,
+                chunks,
+                metadata.filename,
+                metadata.caption,
+                primary,
+            )
+            .await
+            .map(|image| Response::new(image.into()))
+            .map_err(Status::from)
+    }
+
+    async fn new_category(&self, request: Request<Category>) -> Result<Response<Category>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        self.business_rules
+            .new_category(request.into_inner().into())
+            .await
+            .map(|category| Response::new(category.into()))
+            .map_err(Status::from)
+    }
+
+    async fn get_all_categories(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Categories>, Status> {
+        self.business_rules
+            .get_all_categories()
+            .await
+            .map(|categories| {
+                Response::new(Categories {
+                    categories: categories.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(Status::from)
+    }
+
+    async fn new_collection(
+        &self,
+        request: Request<Collection>,
+    ) -> Result<Response<Collection>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        self.business_rules
+            .new_collection(request.into_inner().into())
+            .await
+            .map(|collection| Response::new(collection.into()))
+            .map_err(Status::from)
+    }
+
+    async fn get_all_collections(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Collections>, Status> {
+        self.business_rules
+            .get_all_collections()
+            .await
+            .map(|c| {
+                Response::new(Collections {
+                    collections: c.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(Status::from)
+    }
+
+    async fn get_collection(
+        &self,
+        request: Request<GetCollectionRequest>,
+    ) -> Result<Response<Collection>, Status> {
+        let request = request.into_inner();
+        let id = match request.uuid {
+            Some(uuid) => self
+                .business_rules
+                .collection_id_by_uuid(&uuid)
+                .await
+                .map_err(Status::from)?,
+            None => request.id,
+        };
+        self.business_rules
+            .get_collection(id)
+            .await
+            .map(|c| Response::new(c.into()))
+            .map_err(Status::from)
+    }
+
+    async fn add_item_to_collection(
+        &self,
+        request: Request<AddItemToCollectionRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let add_item_request = request.into_inner();
+        self.business_rules
+            .add_item_to_collection(add_item_request.item_id, add_item_request.collection_id)
+            .await
+            .map(|_| Response::new(Empty {}))
+            .map_err(Status::from)
+    }
+
+    async fn remove_item_from_collection(
+        &self,
+        request: Request<RemoveItemFromCollectionRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let remove_item_request = request.into_inner();
+        self.business_rules
+            .remove_item_from_collection(
+                remove_item_request.item_id,
+                remove_item_request.collection_id,
+            )
+            .await
+            .map(|_| Response::new(Empty {}))
+            .map_err(Status::from)
+    }
+
+    async fn new_location(&self, request: Request<Location>) -> Result<Response<Location>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        self.business_rules
+            .new_location(request.into_inner().into())
+            .await
+            .map(|location| Response::new(location.into()))
+            .map_err(Status::from)
+    }
+
+    async fn get_all_locations(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Locations>, Status> {
+        self.business_rules
+            .get_all_locations()
+            .await
+            .map(|locations| {
+                Response::new(Locations {
+                    locations: locations.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(Status::from)
+    }
+
+    async fn sync_pull(
+        &self,
+        request: Request<SyncPullRequest>,
+    ) -> Result<Response<SyncPullResponse>, Status> {
+        let request = request.into_inner();
+        let limit = request.limit.unwrap_or(DEFAULT_SYNC_LIMIT).min(MAX_SYNC_LIMIT);
+        self.business_rules
+            .sync_pull(request.token.as_deref(), limit)
+            .await
+            .map(|changes| Response::new(changes.into()))
+            .map_err(Status::from)
+    }
+
+    async fn sync_push(
+        &self,
+        request: Request<SyncPushRequest>,
+    ) -> Result<Response<SyncPushResponse>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        self.business_rules
+            .sync_push(request.into_inner().into())
+            .await
+            .map(|results| {
+                Response::new(SyncPushResponse {
+                    results: results.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(Status::from)
+    }
+
+    async fn batch_mutate(
+        &self,
+        request: Request<BatchMutateRequest>,
+    ) -> Result<Response<BatchMutateResponse>, Status> {
+        authorize(&request, Role::ReadWrite)?;
+        let batch: BatchMutate = request.into_inner().try_into().map_err(Status::from)?;
+        self.business_rules
+            .batch_mutate(batch)
+            .await
+            .map(|results| {
+                Response::new(BatchMutateResponse {
+                    results: results.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(Status::from)
+    }
+}
+
+/// The message of a listing, without the fields the mask excludes
+fn masked_items(items: Vec<crate::Item>, mask: &ItemFieldMask) -> Items {
+    Items {
+        items: items
+            .into_iter()
+            .map(|item| {
+                let mut item: Item = item.into();
+                mask.apply(&mut item);
+                item
+            })
+            .collect(),
+    }
+}
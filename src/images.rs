@@ -0,0 +1,373 @@
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+
+use crate::{CustError, Result};
+
+/// Edge length of generated thumbnails in pixels
+const THUMBNAIL_SIZE: u32 = 256;
+/// Largest image that is downloaded from a remote url
+const MAX_REMOTE_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_FILENAME_CHARS: usize = 255;
+/// Largest width and height of an image that is decoded
+const MAX_IMAGE_DIMENSION: u32 = 16384;
+/// Most memory decoding a single image may allocate
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// An uploaded image, split into the original and a small preview
+pub struct ProcessedImage {
+    pub fullsize: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+}
+
+/// Validates that `bytes` is a decodable image and renders a png thumbnail of it.
+pub fn process_image(bytes: Vec<u8>) -> Result<ProcessedImage> {
+    let image = decode(&bytes)?;
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+    let mut thumbnail_bytes = Cursor::new(vec![]);
+    thumbnail.write_to(&mut thumbnail_bytes, ImageOutputFormat::Png)?;
+
+    Ok(ProcessedImage {
+        fullsize: bytes,
+        thumbnail: thumbnail_bytes.into_inner(),
+    })
+}
+
+/// Difference hash of an image: one bit per pair of horizontally adjacent pixels of a 9x8
+/// grayscale version. Resized or recompressed copies of an image get (almost) the same hash.
+pub fn perceptual_hash(bytes: &[u8]) -> Result<u64> {
+    let small = decode(bytes)?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// Decodes an image of any supported format. The size is limited before decoding, so a small
+/// file claiming huge dimensions is rejected instead of exhausting the memory.
+fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = Reader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+/// Number of differing bits of two perceptual hashes, 0 for the same picture and 64 at most
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// What is known about an image file besides its content
+#[derive(Debug, Clone)]
+pub struct ImageFileInfo {
+    pub filename: Option<String>,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    /// hex encoded sha256 of the content
+    pub sha256: String,
+}
+
+/// Describes `bytes`, sniffing the mime type from the content rather than trusting the client.
+pub fn image_file_info(bytes: &[u8], filename: Option<&str>) -> ImageFileInfo {
+    ImageFileInfo {
+        filename: filename.and_then(sanitize_filename),
+        mime_type: image::guess_format(bytes)
+            .map(|format| format.to_mime_type())
+            .unwrap_or("application/octet-stream")
+            .to_owned(),
+        size_bytes: bytes.len() as i64,
+        sha256: hex::encode(Sha256::digest(bytes)),
+    }
+}
+
+/// Reduces a client supplied filename to its last path component, without control characters
+/// or quotes. `None` if nothing is left.
+pub fn sanitize_filename(filename: &str) -> Option<String> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+
+    Some(name.to_owned())
+}
+
+/// Last path segment of an image url, used as the filename of downloaded images
+pub fn url_filename(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    sanitize_filename(url.path_segments()?.next_back()?)
+}
+
+/// `Content-Disposition` value for serving a file inline. Non ascii names are sent as
+/// `filename*` with an ascii fallback.
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '_' })
+        .collect();
+    if fallback == filename {
+        return format!("inline; filename=\"{}\"", filename);
+    }
+
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("inline; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+/// Downloads an image from a user supplied url. Only public addresses are contacted, redirects
+/// are not followed and the body size is capped.
+pub async fn fetch_image(url: &str) -> Result<Vec<u8>> {
+    let url = reqwest::Url::parse(url)
+        .map_err(|e| CustError::new(format!("Invalid url: {}", e), StatusCode::BAD_REQUEST))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(CustError::new(
+            "only http(s) urls can be fetched".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| CustError::new("url has no host".to_string(), StatusCode::BAD_REQUEST))?
+        .to_owned();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| CustError::new(format!("Could not resolve host: {}", e), StatusCode::BAD_REQUEST))?
+        .collect();
+    let addr = match addrs.first() {
+        Some(addr) if addrs.iter().all(|a| is_public_address(a.ip())) => *addr,
+        _ => {
+            return Err(CustError::new(
+                "url does not point to a public address".to_string(),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+
+    // pin the checked address, so a second dns lookup can't point somewhere else
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(&host, addr)
+        .build()?;
+
+    let mut response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(CustError::new(
+            format!("Remote server answered with {}", response.status()),
+            StatusCode::BAD_GATEWAY,
+        ));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("image/") {
+        return Err(CustError::new(
+            format!("Remote content is not an image: {}", content_type),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ));
+    }
+
+    if response.content_length().unwrap_or(0) as usize > MAX_REMOTE_IMAGE_BYTES {
+        return Err(CustError::new(
+            "Remote image is too large".to_string(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+
+    let mut bytes = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_REMOTE_IMAGE_BYTES {
+            return Err(CustError::new(
+                "Remote image is too large".to_string(),
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// Whether an address is reachable on the public internet, i.e. not loopback, private, link
+/// local or otherwise reserved.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            // 100.64.0.0/10 is carrier grade nat
+            let shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || shared
+                || octets[0] == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            // fc00::/7 is unique local, fe80::/10 is link local
+            let unique_local = (segments[0] & 0xfe00) == 0xfc00;
+            let link_local = (segments[0] & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_images {
+    use std::io::Cursor;
+    use std::net::IpAddr;
+
+    use axum::http::StatusCode;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+
+    use crate::{
+        content_disposition, hash_distance, image_file_info, is_public_address, perceptual_hash,
+        process_image, sanitize_filename,
+    };
+
+    #[test]
+    fn private_addresses_are_rejected() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.10",
+            "172.16.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+
+        for ip in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public_address(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn thumbnail_is_downscaled() {
+        let mut png = Cursor::new(vec![]);
+        RgbImage::new(1024, 512)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+
+        let processed = process_image(png.into_inner()).unwrap();
+        let thumbnail = image::load_from_memory(&processed.thumbnail).unwrap();
+        assert_eq!(thumbnail.width(), 256);
+        assert_eq!(thumbnail.height(), 128);
+
+        assert!(process_image(b"not an image".to_vec()).is_err());
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        let mut png = Cursor::new(vec![]);
+        RgbImage::new(20000, 1)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+
+        let error = process_image(png.into_inner()).err().unwrap();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn file_info_is_taken_from_the_content() {
+        let mut png = Cursor::new(vec![]);
+        RgbImage::new(4, 4)
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        // the extension of the name doesn't matter
+        let info = image_file_info(&png, Some("C:\\photos\\drill.jpg"));
+        assert_eq!(info.filename.as_deref(), Some("drill.jpg"));
+        assert_eq!(info.mime_type, "image/png");
+        assert_eq!(info.size_bytes, png.len() as i64);
+        assert_eq!(info.sha256.len(), 64);
+
+        assert_eq!(image_file_info(b"???", None).mime_type, "application/octet-stream");
+    }
+
+    #[test]
+    fn filenames_are_sanitized() {
+        assert_eq!(sanitize_filename("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(sanitize_filename("a\"b\nc.png").as_deref(), Some("abc.png"));
+        assert_eq!(sanitize_filename("dir/"), None);
+        assert_eq!(sanitize_filename(".."), None);
+
+        assert_eq!(content_disposition("drill.png"), "inline; filename=\"drill.png\"");
+        assert_eq!(
+            content_disposition("bohrer ä.png"),
+            "inline; filename=\"bohrer _.png\"; filename*=UTF-8''bohrer%20%C3%A4.png"
+        );
+    }
+
+    #[test]
+    fn resized_copies_hash_alike() {
+        let gradient = |width: u32, height: u32, flip: bool| {
+            let image = RgbImage::from_fn(width, height, |x, y| {
+                let x = if flip { width - 1 - x } else { x };
+                let v = (x * 255 / width) as u8 / 2 + (y * 255 / height) as u8 / 4;
+                Rgb([v, v, v])
+            });
+            let mut png = Cursor::new(vec![]);
+            image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+            perceptual_hash(&png.into_inner()).unwrap()
+        };
+
+        let original = gradient(640, 480, false);
+        assert!(hash_distance(original, gradient(160, 120, false)) <= 4);
+        assert!(hash_distance(original, gradient(640, 480, true)) > 32);
+        assert!(perceptual_hash(b"not an image").is_err());
+    }
+}
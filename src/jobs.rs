@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+use crate::{BusinessRules, ID};
+
+/// How often buffered item accesses are written to the database
+const ACCESS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often it is checked whether the daily snapshot of the inventory is due
+const DAILY_SNAPSHOT_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Work that is done in the background, outside of the request that caused it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    /// Load the documents of the search index before the first search needs them
+    WarmUpIndex,
+    /// Remove the documents of deleted items from the search index
+    CompactTombstones,
+    /// Rebuild the search index, dropping the terms no item uses anymore
+    CompactIndex,
+    /// Update the documents of the items in a renamed category
+    ReindexCategory(ID),
+    /// Update the documents of the items at a renamed location
+    ReindexLocation(ID),
+    /// Write the buffered item accesses to the access log
+    FlushAccessLog,
+    /// Read the text in the primary image of an item and index it
+    RecognizeText(ID),
+    /// Remove deleted items older than the retention of the trash for good
+    PurgeTrash,
+    /// Take the daily snapshot of the inventory, if it isn't taken yet, and report what changed
+    DailySnapshot,
+    /// Update the vector of an item that changed, or remove it
+    EmbedItem(ID),
+    /// Run the embedding job with this id: embed all items and replace the vectors with the new
+    /// ones at once
+    EmbedAllItems(ID),
+}
+
+/// In-process queue of background jobs, processed one after another by [`start_jobs`].
+pub struct JobQueue {
+    sender: mpsc::UnboundedSender<Job>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<Job>>>,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+}
+
+impl JobQueue {
+    pub fn push(&self, job: Job) {
+        if self.sender.send(job).is_err() {
+            error!("Job queue is not running, dropping {:?}", job);
+        }
+    }
+
+    fn take_receiver(&self) -> Option<mpsc::UnboundedReceiver<Job>> {
+        self.receiver.lock().unwrap().take()
+    }
+}
+
+/// Starts processing the job queue of `rules`. Calling it more than once has no effect.
+pub fn start_jobs(rules: Arc<BusinessRules>) {
+    let Some(mut receiver) = rules.jobs().take_receiver() else {
+        return;
+    };
+
+    let queue = rules.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACCESS_FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            queue.jobs().push(Job::FlushAccessLog);
+        }
+    });
+
+    let queue = rules.clone();
+    let purge_interval = rules.config().get().trash.purge_interval_secs.max(1);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(purge_interval));
+        loop {
+            interval.tick().await;
+            queue.jobs().push(Job::PurgeTrash);
+        }
+    });
+
+    let queue = rules.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DAILY_SNAPSHOT_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            queue.jobs().push(Job::DailySnapshot);
+        }
+    });
+
+    let compact_interval = rules.config().get().search.compact_interval_secs;
+    if compact_interval > 0 {
+        let queue = rules.clone();
+        tokio::spawn(async move {
+            // not right after startup, while the index is still warming up
+            let period = Duration::from_secs(compact_interval);
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                queue.jobs().push(Job::CompactIndex);
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        while let Some(job) = receiver.recv().await {
+            debug!("Running job {:?}", job);
+            let result = match job {
+                Job::WarmUpIndex => rules.warm_up_index().await,
+                Job::CompactTombstones => rules.compact_tombstones().await,
+                Job::CompactIndex => rules.compact_index().await.map(|_| ()),
+                Job::ReindexCategory(id) => rules.reindex_category(id).await,
+                Job::ReindexLocation(id) => rules.reindex_location(id).await,
+                Job::FlushAccessLog => rules.flush_access_log().await,
+                Job::RecognizeText(id) => rules.recognize_item_text(id).await,
+                Job::PurgeTrash => rules.purge_trash().await.map(|_| ()),
+                Job::DailySnapshot => rules.take_daily_snapshot().await.map(|_| ()),
+                Job::EmbedItem(id) => rules.embed_item(id).await,
+                Job::EmbedAllItems(job) => rules.embed_all_items(job).await,
+            };
+            if let Err(e) = result {
+                error!("Job {:?} failed: {}", job, e);
+            }
+        }
+    });
+}
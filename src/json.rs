@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::body::{Body, Bytes};
+use axum::extract::FromRequest;
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::error::Category;
+
+use crate::{BusinessRules, CustError, Result};
+
+/// Request header that turns strict parsing on (`true`) or off (`false`) for one request,
+/// overriding `strict_json` of the config
+pub const STRICT_JSON_HEADER: &str = "x-strict-json";
+
+/// JSON request and response body. Unlike [`axum::Json`], errors name the path of the field
+/// that failed to parse, and in strict mode fields the api doesn't know are rejected instead of
+/// dropped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T> FromRequest<Arc<BusinessRules>, Body> for Json<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = CustError;
+
+    async fn from_request(req: Request<Body>, state: &Arc<BusinessRules>) -> Result<Self> {
+        if !has_json_content_type(req.headers()) {
+            return Err(CustError::new(
+                "expected a request body with `Content-Type: application/json`".to_string(),
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ));
+        }
+        let strict = strict_header(req.headers())?.unwrap_or(state.config().get().strict_json);
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| CustError::new(rejection.body_text(), rejection.status()))?;
+        parse_json(&bytes, strict).map(Json)
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// Parses a request body. Invalid JSON is a 400, JSON that doesn't fit `T` and, if `strict`,
+/// unknown fields are a 422 with the path of the field in the details.
+pub fn parse_json<T: DeserializeOwned>(bytes: &[u8], strict: bool) -> Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let mut unknown = vec![];
+
+    let value = if strict {
+        let mut track = |path: serde_ignored::Path| unknown.push(ignored_path(&path));
+        let deserializer = serde_ignored::Deserializer::new(&mut deserializer, &mut track);
+        serde_path_to_error::deserialize(deserializer)
+    } else {
+        serde_path_to_error::deserialize(&mut deserializer)
+    };
+    let value = value.map_err(|e| {
+        let path = e.path().to_string();
+        json_error(e.into_inner(), path)
+    })?;
+    deserializer.end().map_err(|e| json_error(e, String::new()))?;
+
+    if let Some(path) = unknown.into_iter().next() {
+        return Err(CustError::new(
+            format!("unknown field at `{}`", path),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+            .with_code("unknown_field")
+            .with_details(serde_json::json!({ "path": path })));
+    }
+
+    Ok(value)
+}
+
+fn json_error(e: serde_json::Error, path: String) -> CustError {
+    match e.classify() {
+        Category::Data => CustError::new(
+            format!("invalid value at `{}`: {}", path, e),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+            .with_code("invalid_value")
+            .with_details(serde_json::json!({ "path": path })),
+        Category::Io | Category::Syntax | Category::Eof => {
+            CustError::new(format!("invalid JSON: {}", e), StatusCode::BAD_REQUEST)
+                .with_code("invalid_json")
+        }
+    }
+}
+
+/// Formats the path of an ignored field like [`serde_path_to_error`] does, e.g. `items[0].price`
+fn ignored_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", ignored_path(parent), index),
+        Path::Map { parent, key } => {
+            let parent = ignored_path(parent);
+            if parent.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", parent, key)
+            }
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => ignored_path(parent),
+    }
+}
+
+fn strict_header(headers: &HeaderMap) -> Result<Option<bool>> {
+    let Some(value) = headers.get(STRICT_JSON_HEADER) else {
+        return Ok(None);
+    };
+    match value.to_str().map(str::trim) {
+        Ok(value) if value.eq_ignore_ascii_case("true") => Ok(Some(true)),
+        Ok(value) if value.eq_ignore_ascii_case("false") => Ok(Some(false)),
+        _ => Err(CustError::new(
+            format!("the {} header must be `true` or `false`", STRICT_JSON_HEADER),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+#[cfg(test)]
+mod test_json {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use serde_json::Value;
+
+    use crate::{parse_json, BulkDelete, Item};
+
+    fn details(error: crate::CustError) -> (StatusCode, Value) {
+        let response = error.clone().into_response();
+        let body = serde_json::to_value(&error).unwrap();
+        (response.status(), body["details"].clone())
+    }
+
+    #[test]
+    fn unknown_fields_are_dropped_unless_strict() {
+        let body = br#"{"name": "hammer", "pricee": 5}"#;
+        let item: Item = parse_json(body, false).unwrap();
+        assert_eq!(item.name, "hammer");
+
+        let error = parse_json::<Item>(body, true).unwrap_err();
+        let (status, details) = details(error);
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(details["path"], "pricee");
+    }
+
+    #[test]
+    fn nested_paths_are_reported() {
+        let body = br#"{"filter": {"category_id": 1, "colection_id": 2}}"#;
+        let error = parse_json::<BulkDelete>(body, true).unwrap_err();
+        assert_eq!(details(error).1["path"], "filter.colection_id");
+
+        let body = br#"{"ids": [1, "two"]}"#;
+        let error = parse_json::<BulkDelete>(body, false).unwrap_err();
+        let (status, details) = details(error);
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(details["path"], "ids[1]");
+    }
+
+    #[test]
+    fn syntax_errors_are_bad_requests() {
+        let error = parse_json::<Item>(br#"{"name": "hammer""#, true).unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,387 @@
+use std::io::Cursor;
+use std::str::FromStr;
+
+use axum::http::StatusCode;
+use image::{GrayImage, ImageOutputFormat, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::{code39_bars, CustError, Result, ID};
+
+/// Printer resolution of 203 dpi, the most common one of thermal label printers
+const DOTS_PER_MM: u32 = 8;
+const MARGIN_MM: u32 = 2;
+const MIN_SIZE_MM: u32 = 20;
+const MAX_SIZE_MM: u32 = 150;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    /// For Zebra printers, which render the text and barcode themselves
+    #[default]
+    Zpl,
+    /// A black and white image at 203 dpi for any printer
+    Png,
+}
+
+impl LabelFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            LabelFormat::Zpl => "application/x-zpl",
+            LabelFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Label dimensions in millimeters, written as `<width>x<height>`, e.g. `57x32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelSize {
+    pub width_mm: u32,
+    pub height_mm: u32,
+}
+
+impl Default for LabelSize {
+    /// 2.25 x 1.25 inch, the usual size of shipping and inventory labels
+    fn default() -> Self {
+        Self {
+            width_mm: 57,
+            height_mm: 32,
+        }
+    }
+}
+
+impl FromStr for LabelSize {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            CustError::new(
+                format!(
+                    "label size must be <width>x<height> in mm between {} and {}, not {}",
+                    MIN_SIZE_MM, MAX_SIZE_MM, s
+                ),
+                StatusCode::BAD_REQUEST,
+            )
+        };
+
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let size = Self {
+            width_mm: width.trim().parse().map_err(|_| invalid())?,
+            height_mm: height.trim().parse().map_err(|_| invalid())?,
+        };
+        let range = MIN_SIZE_MM..=MAX_SIZE_MM;
+        if !range.contains(&size.width_mm) || !range.contains(&size.height_mm) {
+            return Err(invalid());
+        }
+        Ok(size)
+    }
+}
+
+/// What is printed on the label of an item
+#[derive(Debug, Clone)]
+pub struct LabelItem {
+    pub id: ID,
+    pub name: String,
+    pub location: Option<String>,
+}
+
+/// Positions on a label in dots, shared by both formats so they look alike
+struct Layout {
+    width: u32,
+    height: u32,
+    margin: u32,
+    name_height: u32,
+    location_y: u32,
+    location_height: u32,
+    barcode_y: u32,
+    barcode_height: u32,
+    /// Width of a narrow bar
+    module: u32,
+}
+
+impl Layout {
+    fn new(size: LabelSize, barcode_modules: u32) -> Self {
+        let width = size.width_mm * DOTS_PER_MM;
+        let height = size.height_mm * DOTS_PER_MM;
+        let margin = MARGIN_MM * DOTS_PER_MM;
+        let name_height = (height / 8).clamp(16, 64);
+        let location_height = (height / 11).clamp(12, 40);
+        let location_y = margin + name_height + name_height / 3;
+        let barcode_y = location_y + location_height + location_height / 2;
+        // leaves room for the id below the barcode
+        let barcode_height = height
+            .saturating_sub(barcode_y + margin + location_height + 4)
+            .max(16);
+        let module = ((width - 2 * margin) / barcode_modules.max(1)).clamp(1, 4);
+
+        Self {
+            width,
+            height,
+            margin,
+            name_height,
+            location_y,
+            location_height,
+            barcode_y,
+            barcode_height,
+            module,
+        }
+    }
+}
+
+/// Renders one label per item. ZPL labels are concatenated, which printers take as a batch. PNG
+/// labels are stacked into one image, separated by cut lines.
+pub fn render_labels(items: &[LabelItem], format: LabelFormat, size: LabelSize) -> Result<Vec<u8>> {
+    match format {
+        LabelFormat::Zpl => Ok(items
+            .iter()
+            .map(|item| zpl_label(item, size))
+            .collect::<String>()
+            .into_bytes()),
+        LabelFormat::Png => png_labels(items, size),
+    }
+}
+
+fn barcode_modules(id: ID) -> u32 {
+    code39_bars(&id.to_string())
+        .last()
+        .map(|(x, w)| (x + w) as u32)
+        .unwrap_or(0)
+}
+
+/// `^` and `~` start commands in ZPL, even inside of field data
+fn zpl_field(text: &str) -> String {
+    text.replace(['^', '~'], " ")
+}
+
+fn zpl_label(item: &LabelItem, size: LabelSize) -> String {
+    let layout = Layout::new(size, barcode_modules(item.id));
+    let text_width = layout.width - 2 * layout.margin;
+    let location = item.location.as_deref().unwrap_or("-");
+
+    format!(
+        concat!(
+            "^XA\n",
+            // utf-8 field data
+            "^CI28\n",
+            "^PW{width}^LL{height}\n",
+            "^FO{margin},{margin}^A0N,{name_height},{name_height}^FB{text_width},1,0,L^FD{name}^FS\n",
+            "^FO{margin},{location_y}^A0N,{location_height},{location_height}^FB{text_width},1,0,L^FD{location}^FS\n",
+            "^FO{margin},{barcode_y}^BY{module},3^B3N,N,{barcode_height},Y,N^FD{id}^FS\n",
+            "^XZ\n",
+        ),
+        width = layout.width,
+        height = layout.height,
+        margin = layout.margin,
+        name_height = layout.name_height,
+        text_width = text_width,
+        name = zpl_field(&item.name),
+        location_y = layout.location_y,
+        location_height = layout.location_height,
+        location = zpl_field(location),
+        barcode_y = layout.barcode_y,
+        module = layout.module,
+        barcode_height = layout.barcode_height,
+        id = item.id,
+    )
+}
+
+fn png_labels(items: &[LabelItem], size: LabelSize) -> Result<Vec<u8>> {
+    let width = size.width_mm * DOTS_PER_MM;
+    let height = size.height_mm * DOTS_PER_MM;
+    let count = items.len().max(1) as u32;
+    let mut sheet = GrayImage::from_pixel(width, height * count, Luma([255]));
+
+    for (i, item) in items.iter().enumerate() {
+        let top = height * i as u32;
+        draw_label(&mut sheet, top, item, size);
+        if i > 0 {
+            for x in (0..width).step_by(4) {
+                sheet.put_pixel(x, top, Luma([128]));
+            }
+        }
+    }
+
+    let mut png = Cursor::new(vec![]);
+    sheet.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+fn draw_label(sheet: &mut GrayImage, top: u32, item: &LabelItem, size: LabelSize) {
+    let layout = Layout::new(size, barcode_modules(item.id));
+    let text_width = layout.width - 2 * layout.margin;
+    let location = item.location.as_deref().unwrap_or("-");
+
+    let name_scale = (layout.name_height / GLYPH_HEIGHT).max(1);
+    draw_text(
+        sheet,
+        layout.margin,
+        top + layout.margin,
+        &item.name,
+        name_scale,
+        text_width,
+    );
+    let location_scale = (layout.location_height / GLYPH_HEIGHT).max(1);
+    draw_text(
+        sheet,
+        layout.margin,
+        top + layout.location_y,
+        location,
+        location_scale,
+        text_width,
+    );
+
+    let barcode_top = top + layout.barcode_y;
+    for (x, w) in code39_bars(&item.id.to_string()) {
+        let left = layout.margin + x as u32 * layout.module;
+        let right = (left + w as u32 * layout.module).min(layout.width - layout.margin);
+        for px in left..right {
+            for py in barcode_top..barcode_top + layout.barcode_height {
+                sheet.put_pixel(px, py, Luma([0]));
+            }
+        }
+    }
+    draw_text(
+        sheet,
+        layout.margin,
+        barcode_top + layout.barcode_height + 4,
+        &item.id.to_string(),
+        location_scale,
+        text_width,
+    );
+}
+
+/// Draws a single line of text, cut off with `..` where it would exceed `max_width`
+fn draw_text(sheet: &mut GrayImage, x: u32, y: u32, text: &str, scale: u32, max_width: u32) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    let fits = (max_width / advance) as usize;
+    let mut chars: Vec<char> = text.chars().collect();
+    if chars.len() > fits {
+        chars.truncate(fits.saturating_sub(2));
+        chars.extend(['.', '.']);
+    }
+
+    for (i, c) in chars.into_iter().enumerate() {
+        let glyph = glyph(c);
+        let left = x + i as u32 * advance;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < sheet.width() && py < sheet.height() {
+                            sheet.put_pixel(px, py, Luma([0]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: u32 = 5;
+/// Rows of a glyph plus one row of spacing
+const GLYPH_HEIGHT: u32 = 8;
+
+/// Rows of a glyph, the lowest 5 bits of each row are its pixels. Letters are printed as capitals,
+/// characters without a glyph as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod test_label {
+    use crate::{render_labels, LabelFormat, LabelItem, LabelSize};
+
+    fn items() -> Vec<LabelItem> {
+        vec![
+            LabelItem {
+                id: 42,
+                name: "Drill ^XZ".to_owned(),
+                location: Some("Garage".to_owned()),
+            },
+            LabelItem {
+                id: 7,
+                name: "Saw".to_owned(),
+                location: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn zpl_has_one_label_per_item() {
+        let zpl = render_labels(&items(), LabelFormat::Zpl, LabelSize::default()).unwrap();
+        let zpl = String::from_utf8(zpl).unwrap();
+
+        assert_eq!(zpl.matches("^XA").count(), 2);
+        assert!(zpl.contains("^PW456^LL256"));
+        assert!(zpl.contains("^FDDrill  XZ^FS"));
+        assert!(zpl.contains("^B3N,N,"));
+        assert!(zpl.contains("^FD42^FS"));
+    }
+
+    #[test]
+    fn png_labels_are_stacked() {
+        let size: LabelSize = "50x25".parse().unwrap();
+        let png = render_labels(&items(), LabelFormat::Png, size).unwrap();
+
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (400, 400));
+
+        assert!("10x25".parse::<LabelSize>().is_err());
+        assert!("50".parse::<LabelSize>().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+pub use admin_ui::*;
+pub use analyzer::*;
+pub use api::*;
+pub use api_version::*;
+pub use auth::*;
+pub use batch::*;
+pub use business::*;
+pub use cache::*;
+pub use config::*;
+pub use cors::*;
+pub use db_encryption::*;
+pub use db_health::*;
+pub use db_metrics::*;
+pub use deadline::*;
+pub use demo::*;
+pub use error::*;
+pub use export::*;
+pub use features::*;
+pub use field_mask::*;
+pub use file_encryption::*;
+pub use files::*;
+pub use grpc_service::*;
+pub use grpc_service_v2::*;
+pub use images::*;
+pub use jobs::*;
+pub use json::*;
+pub use label::*;
+pub use load_shed::*;
+pub use logging::*;
+pub use ocr::*;
+pub use policy::*;
+pub use pricing::*;
+pub use problem::*;
+pub use public_api::*;
+pub use replication::*;
+pub use routes::*;
+pub use scan::*;
+pub use smart::*;
+pub use sync::*;
+pub use telemetry::*;
+pub use types::*;
+pub use vector::*;
+pub use webhooks::*;
+
+pub mod grpc_service;
+
+pub mod grpc_service_v2;
+
+pub mod images;
+
+pub mod file_encryption;
+
+pub mod files;
+
+pub mod types;
+
+pub mod business;
+
+pub mod routes;
+
+pub mod error;
+
+pub mod export;
+
+pub mod features;
+
+pub mod field_mask;
+
+pub mod cache;
+
+pub mod config;
+
+pub mod cors;
+
+pub mod db_encryption;
+
+pub mod db_health;
+
+pub mod db_metrics;
+
+pub mod deadline;
+
+pub mod demo;
+
+pub mod metrics;
+
+pub mod webhooks;
+
+pub mod admin_ui;
+
+pub mod analyzer;
+
+pub mod api;
+
+pub mod api_version;
+
+pub mod auth;
+
+pub mod batch;
+
+pub mod jobs;
+
+pub mod json;
+
+pub mod label;
+
+pub mod load_shed;
+
+pub mod logging;
+
+pub mod ocr;
+
+pub mod policy;
+
+pub mod pricing;
+
+pub mod problem;
+
+pub mod public_api;
+
+pub mod replication;
+
+pub mod scan;
+
+pub mod smart;
+
+pub mod sync;
+
+pub mod telemetry;
+
+pub mod vector;
+
+mod util;
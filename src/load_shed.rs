@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::{ConfigHandle, CustError};
+
+/// Groups of expensive routes that share a concurrency limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpensiveRoute {
+    Search,
+    Export,
+}
+
+/// Requests currently handled by a group of routes. Requests over the configured limit are shed
+/// instead of queued, so cheap routes keep being served while the expensive ones are saturated.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    route: ExpensiveRoute,
+    config: ConfigHandle,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(config: ConfigHandle, route: ExpensiveRoute) -> Self {
+        Self {
+            route,
+            config,
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Takes a slot if less than `limit` are taken, 0 means unlimited.
+    fn acquire(&self, limit: usize) -> Option<InFlight> {
+        let taken = self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let slot = InFlight(Arc::clone(&self.in_flight));
+        if limit != 0 && taken >= limit {
+            return None;
+        }
+        Some(slot)
+    }
+}
+
+/// A taken slot, released when dropped, also if the request is cancelled.
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answers 503 with a `Retry-After` header while the route group is at its limit. The config is
+/// read on every request, so reloads apply immediately.
+pub async fn concurrency_limit_middleware<B>(
+    State(limit): State<ConcurrencyLimit>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let (max, retry_after) = {
+        let config = limit.config.get();
+        let max = match limit.route {
+            ExpensiveRoute::Search => config.concurrency.search,
+            ExpensiveRoute::Export => config.concurrency.export,
+        };
+        (max, config.concurrency.retry_after_secs)
+    };
+
+    let Some(_slot) = limit.acquire(max) else {
+        let mut response = CustError::new(
+            "server is busy, try again later".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after.max(1)));
+        return response;
+    };
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod test_load_shed {
+    use crate::{ConcurrencyLimit, ConfigHandle, ExpensiveRoute};
+
+    #[test]
+    fn slots_are_released_on_drop() {
+        let limit = ConcurrencyLimit::new(ConfigHandle::load("/nonexistent"), ExpensiveRoute::Search);
+
+        let first = limit.acquire(2).unwrap();
+        let second = limit.acquire(2).unwrap();
+        assert!(limit.acquire(2).is_none());
+
+        drop(first);
+        assert!(limit.acquire(2).is_some());
+        drop(second);
+
+        assert!(limit.acquire(0).is_some());
+    }
+}
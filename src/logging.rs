@@ -0,0 +1,95 @@
+use std::path::Path;
+
+use axum::http::StatusCode;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{Layer, Registry};
+
+use crate::{CustError, LogFileConfig, LogFormat, LogRotation, LoggingConfig, Result};
+
+/// A place log messages are written to
+pub type LogOutput = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Human-readable messages on stdout, if the configured outputs can't be set up
+pub fn default_log_outputs() -> Vec<LogOutput> {
+    vec![stdout_output(LogFormat::Text)]
+}
+
+/// The outputs of the config. The guard flushes the log file when it is dropped, it has to be
+/// kept until the server stops.
+pub fn log_outputs(config: &LoggingConfig) -> Result<(Vec<LogOutput>, Option<WorkerGuard>)> {
+    let mut outputs = vec![stdout_output(config.format)];
+    let guard = match &config.file.directory {
+        Some(directory) => {
+            let (output, guard) = file_output(directory, &config.file)?;
+            outputs.push(output);
+            Some(guard)
+        }
+        None => None,
+    };
+    Ok((outputs, guard))
+}
+
+fn stdout_output(format: LogFormat) -> LogOutput {
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    }
+}
+
+/// Writes on a background thread, so requests don't wait for the disk
+fn file_output(directory: &Path, config: &LogFileConfig) -> Result<(LogOutput, WorkerGuard)> {
+    let rotation = match config.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix("find_me_pls")
+        .filename_suffix("log")
+        .max_log_files(config.max_files.max(1))
+        .build(directory)
+        .map_err(|e| {
+            CustError::new(
+                format!("Could not write logs to {}: {}", directory.display(), e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false);
+    let output = match config.format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    Ok((output, guard))
+}
+
+#[cfg(test)]
+mod test_logging {
+    use tracing::level_filters::LevelFilter;
+    use tracing::Level;
+
+    use crate::Config;
+
+    #[test]
+    fn modules_override_the_log_level() {
+        let config: Config = serde_json::from_str(
+            r#"{"log_level": "info", "logging": {"modules": {"sqlx": "warn", "find_me_pls::webhooks": "trace"}}}"#,
+        )
+            .unwrap();
+        let filter = config.log_filter().unwrap();
+        assert!(filter.would_enable("find_me_pls::business", &Level::INFO));
+        assert!(!filter.would_enable("find_me_pls::business", &Level::DEBUG));
+        assert!(filter.would_enable("find_me_pls::webhooks", &Level::TRACE));
+        assert!(!filter.would_enable("sqlx::query", &Level::INFO));
+        assert_eq!(config.log_level_filter().unwrap(), LevelFilter::INFO);
+
+        let config: Config =
+            serde_json::from_str(r#"{"logging": {"modules": {"sqlx": "loud"}}}"#).unwrap();
+        assert!(config.log_filter().is_err());
+    }
+}
@@ -1,75 +1,247 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 
+use anyhow::{anyhow, Context};
+use axum::extract::DefaultBodyLimit;
+use axum::http::StatusCode;
+use axum::middleware;
 use axum::Router;
+use axum::ServiceExt;
 use axum::routing::delete;
 use axum::routing::get;
+use axum::routing::head;
 use axum::routing::post;
-use doc_search::EmptyWordFilter;
+use axum::routing::put;
 use doc_search::Index;
 use doc_search::MemoryStorage;
-use doc_search::SimpleTokenizer;
-use futures::join;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
-use tracing::Level;
-use tracing::log::info;
+use tower::util::MapRequestLayer;
+use tracing::level_filters::LevelFilter;
+use tracing::log::{error, info};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::Layer;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
 
-pub use business::*;
-pub use error::*;
-pub use files::*;
-pub use grpc_service::*;
-pub use routes::*;
-pub use types::*;
-
-pub mod grpc_service;
-
-pub mod files;
-
-pub mod types;
-
-pub mod business;
-
-pub mod routes;
-
-pub mod error;
-
-mod util;
+use ::find_me_pls::*;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
+    // logs and traces go where the config says, so messages about the config itself are
+    // written to stdout
+    let config = tracing::subscriber::with_default(tracing_subscriber::fmt().finish(), || {
+        ConfigHandle::load("config.json")
+    });
+
+    let (log_level, log_level_handle) =
+        reload::Layer::new(Targets::new().with_default(LevelFilter::DEBUG));
+    let (outputs, log_file_guard, log_error) = match log_outputs(&config.get().logging) {
+        Ok((outputs, guard)) => (outputs, guard, None),
+        Err(e) => (default_log_outputs(), None, Some(e)),
+    };
+    let (telemetry, telemetry_error) = match telemetry_layer(&config.get().telemetry) {
+        Ok(telemetry) => (telemetry, None),
+        Err(e) => (None, Some(e)),
+    };
+    let (db_statements, slow_query_threshold) = DbStatementLayer::new();
+    tracing_subscriber::registry()
+        .with(outputs.with_filter(log_level))
+        .with(db_statements.with_filter(DbStatementLayer::filter()))
+        .with(telemetry)
         .init();
     info!("Starting up");
+    if let Some(e) = log_error {
+        error!("{}, logging to stdout only", e);
+    }
+    if let Some(e) = telemetry_error {
+        error!("{}, traces are not exported", e);
+    }
+    // flushes the log file when the server stops
+    let _log_file_guard = log_file_guard;
 
+    let analyzer = Analyzer::from_config(&config.get().search.analyzer).unwrap_or_else(|e| {
+        error!("{}, using the default analyzer", e);
+        Analyzer::default()
+    });
+    let backend = config.get().search.backend;
+    let index = match backend {
+        SearchBackend::Index => {
+            let storage = MemoryStorage::new(INDEX_STORAGE_FILE);
+            // TODO: add qdrant
+            Some(Index::new(None, storage))
+        }
+        SearchBackend::Fts5 => None,
+    };
+
+    let body_limit = config.get().limits.request_body_bytes();
+    let bundle_limit = config.get().limits.bundle_bytes;
+    watch_log_level(&config, log_level_handle);
+    watch_slow_query_threshold(&config, slow_query_threshold);
+    #[cfg(unix)]
+    reload_on_hangup(config.clone());
 
-    let tokenizer = SimpleTokenizer::new();
-    let filter = EmptyWordFilter {};
-    let storage = MemoryStorage::new("storage.json");
+    // `find_me_pls encrypt-db` encrypts a plaintext database with the configured key instead
+    // of serving
+    if std::env::args().nth(1).as_deref() == Some("encrypt-db") {
+        let encrypted = async {
+            let key = database_key(&config.get().database.encryption)?.ok_or_else(|| {
+                CustError::new(
+                    "set database.encryption.kind to encrypt the database".to_string(),
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+            encrypt_database(Path::new(DATABASE_FILE), &key).await
+        };
+        match encrypted.await {
+            Ok(plaintext) => info!(
+                "Encrypted {}, delete the plaintext copy {} once the server started",
+                DATABASE_FILE,
+                plaintext.display()
+            ),
+            Err(e) => {
+                error!("Could not encrypt the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    // TODO: add qdrant
-    let index = Index::new(None, storage);
+    let state = BusinessRules::new(index, analyzer, config.clone()).await;
 
-    let state = BusinessRules::new(index, tokenizer, filter).await;
+    // `find_me_pls migrate-data` upgrades the stored files instead of serving
+    if std::env::args().nth(1).as_deref() == Some("migrate-data") {
+        if let Err(e) = state.migrate_data().await {
+            error!("Could not migrate the stored files: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Err(e) = state.check_data_format().await {
+        error!("{}", e);
+        std::process::exit(1);
+    }
 
     state.init_db().await;
     state.init().await;
 
+    if std::env::args().any(|arg| arg == "--seed-demo") {
+        match state.seed_demo(DEFAULT_DEMO_ITEMS).await {
+            Ok(summary) => info!("Seeded {} demo items", summary.items),
+            Err(e) => error!("Could not seed demo data: {}", e),
+        }
+    }
+
+    // search and exports are expensive, requests over their limits are shed with a 503
+    let search_limit = middleware::from_fn_with_state(
+        ConcurrencyLimit::new(config.clone(), ExpensiveRoute::Search),
+        concurrency_limit_middleware,
+    );
+    let export_limit = middleware::from_fn_with_state(
+        ConcurrencyLimit::new(config.clone(), ExpensiveRoute::Export),
+        concurrency_limit_middleware,
+    );
+
     // build our application with a single route
-    let app = Router::new()
-        .route("/item/search/:name", get(find_items)) // search for items by name (this can
+    let v1 = Router::new()
+        .route("/item/search/:name", get(find_items).route_layer(search_limit.clone())) // search for items by name (this can
         // containt any query string and will even
         // handle some fuzziness)
+        .route("/item/search/batch", post(find_items_batch).route_layer(search_limit.clone())) // best matches for every entry of a shopping or packing list
+        .route("/item/search/by-image", post(find_items_by_image).route_layer(search_limit.clone())) // items whose image looks like an uploaded photo
+        .route("/item/search/semantic", post(find_items_semantically).route_layer(search_limit.clone())) // items whose text is close to a query, see search.semantic
         .route("/item", post(add_item)) // create a new item
         .route("/item", get(get_all_items)) // gel all items
+        .route("/item", head(head_all_items)) // last change of the item listing
+        .route("/items", get(get_all_items)) // all items, or as they were at ?as_of=YYYY-MM-DD
         .route("/item/:id", get(get_item)) // get a specific item
-        .route("/item/:id", delete(delete_item)); // delete an item
+        .route("/item/uuid/:uuid", get(get_item_by_uuid)) // get a specific item by its uuid
+        .route("/item/:id", delete(delete_item)) // delete an item
+        .route("/item/:id/appearance", put(set_item_appearance)) // color and icon of the tile of an item
+        .route("/item/:id/move", post(move_item)) // pack an item into another one or take it out
+        .route("/item/:id/contents", get(get_item_contents)) // everything inside a container
+        .route("/item/:id/favorite", post(favorite_item)) // pin an item for the caller
+        .route("/item/:id/favorite", delete(unfavorite_item)) // unpin an item
+        .route("/item/:id/notes", get(get_item_notes)) // journal of the item, oldest first
+        .route("/item/:id/notes", post(add_item_note)) // add a note
+        .route("/item/:id/notes/:note_id", put(update_item_note)) // replace the text of a note
+        .route("/item/:id/notes/:note_id", delete(delete_item_note)) // remove a note
+        .route("/item/:id/translations", get(get_item_translations)) // name and description in other languages
+        .route("/item/:id/translations/:locale", put(set_item_translation)) // add or replace a translation
+        .route("/item/:id/translations/:locale", delete(delete_item_translation)) // remove a translation
+        .route("/item/:id/estimate-value", post(estimate_item_value)) // look up the market value of an item
+        .route("/item/:id/image/from-url", post(set_item_image_from_url)) // download an image for an item
+        .route("/item/:id/history", get(get_item_history)) // who changed an item and when
+        .route("/item/:id/collections", get(get_item_collections)) // collections containing an item
+        .route("/item/:id/mark-owned", post(mark_item_owned)) // an ordered or wished for item arrived
+        .route("/item/:id/mark-ordered", post(mark_item_ordered)) // an item is on its way
+        .route("/item/:id/mark-wishlist", post(mark_item_wishlist)) // move an item to the wishlist
+        .route("/item/:id/dispose", post(dispose_item)) // an item was sold or thrown away
+        .route("/item/:id/disposal", get(get_disposal)) // why, when and for how much it went
+        .route("/item/:id/reserve", post(reserve_item)) // reserve an item, e.g. a shared tool
+        .route("/item/:id/reserve", get(get_reservation)) // who reserved an item and until when
+        .route("/item/:id/reserve", delete(release_reservation)) // end a reservation early
+        .route("/item/:id/images", post(add_item_image)) // add an image to the gallery of an item
+        .route("/item/:id/images", get(get_item_images)) // gallery of an item, thumbnails only
+        .route("/item/:id/images/order", put(reorder_item_images)) // set the order of the gallery
+        .route("/item/:id/images/:image_id", get(get_item_image)) // a gallery image in full size
+        .route("/item/:id/images/:image_id", delete(delete_item_image)) // remove a gallery image
+        .route("/item/:id/images/:image_id/raw", get(download_item_image)) // a gallery image as a file
+        .route("/item/:id/images/:image_id/primary", put(set_primary_item_image)) // make an image the primary one
+        .route("/item/:id/label", get(get_item_label)) // printable label as zpl or png
+        .route("/items/bulk-delete", post(bulk_delete_items)) // move many items to the trash by id or filter, admins only
+        .route("/item/:id/restore", post(restore_item)) // take a bulk deleted item out of the trash, admins only
 
-    let app = app
+        .route("/items/stale", get(stale_items)) // owned items not looked at for a number of days
+        .route("/items/export.csv", get(export_items_csv).route_layer(export_limit.clone())); // csv export of item metadata
+
+    let v1 = v1
         .route("/category", post(new_category)) // create a new category
-        .route("/category", get(get_all_categories)); // get all categories
+        .route("/category", get(get_all_categories)) // get all categories
+        .route("/category", head(head_all_categories)) // last change of the category listing
+        .route("/category/uuid/:uuid", get(get_category_by_uuid)) // get a category by its uuid
+        .route("/category/:id/name", put(rename_category)) // rename a category
+        .route("/category/:id/move", post(move_category)) // move a category with its subcategories
+        .route("/category/:id/appearance", put(set_category_appearance)) // color and icon of a category
+        .route("/category/:id/thumbnail", post(upload_category_thumbnail)) // multipart upload of a category thumbnail
+        .route("/category/:id/thumbnail", get(get_category_thumbnail)); // the category thumbnail as a png file
 
-    let app = app
+    let v1 = v1
+        .route("/stocktake", post(start_stocktake)) // start counting the items at a location or in a collection
+        .route("/stocktake", get(get_stocktakes)) // all stock-takes, the latest first
+        .route("/stocktake/:id", get(get_stocktake)) // progress of a stock-take
+        .route("/stocktake/:id/confirm", post(confirm_stocktake_item)) // an item is present, by id or scanned label
+        .route("/stocktake/:id/end", post(end_stocktake)) // no more items are confirmed
+        .route("/stocktake/:id/report", get(stocktake_report)); // items missing and items found at the wrong place
+
+    let v1 = v1
+        .route("/valuation/categories", get(category_valuations)) // purchase cost vs. value per category
+        .route("/valuation/collections", get(collection_valuations)) // purchase cost vs. value per collection
+        .route(
+            // owned items with photos, purchase details and value, as json or pdf
+            "/reports/insurance",
+            get(insurance_report).route_layer(export_limit.clone()),
+        );
+
+    let v1 = v1
+        .route("/location", post(new_location)) // create a new location
+        .route("/location", get(get_all_locations)) // get all locations
+        .route("/location/uuid/:uuid", get(get_location_by_uuid)) // get a location by its uuid
+        .route("/location/:id/name", put(rename_location)); // rename a location
+
+    let v1 = v1
         .route("/collection", post(new_collection)) // create a new collection
+        .route("/collection", get(get_all_collections)) // get all collections
+        .route("/collection", head(head_all_collections)) // last change of the collection listing
+        .route("/collection/uuid/:uuid", get(get_collection_by_uuid)) // get a collection by its uuid
+        .route(
+            // recreate an exported collection with new ids
+            "/collection/import",
+            post(import_collection_bundle).route_layer(DefaultBodyLimit::max(bundle_limit)),
+        )
         .route(
             // add an item to a collection
             "/collection/:collection_id/:item_id",
@@ -80,34 +252,233 @@ async fn main() {
             "/collection/:collection_id/items",
             get(get_items_in_collection),
         )
+        .route(
+            // printable inventory sheet of a collection
+            "/collection/:collection_id/export.pdf",
+            get(export_collection_pdf).route_layer(export_limit.clone()),
+        )
+        .route(
+            // the collection with its items, images, categories and locations as one json bundle
+            "/collection/:collection_id/export",
+            get(export_collection_bundle).route_layer(export_limit.clone()),
+        )
+        .route(
+            // labels of all items in a collection, as zpl or png
+            "/collection/:collection_id/labels",
+            get(get_collection_labels).route_layer(export_limit),
+        )
+        .route(
+            // pin a collection for the caller
+            "/collection/:collection_id/favorite",
+            post(favorite_collection).delete(unfavorite_collection),
+        )
+        .route("/favorites", get(get_favorites)) // items and collections the caller pinned
+        .route(
+            // set the order of the items in a collection
+            "/collection/:collection_id/order",
+            put(reorder_collection),
+        )
+        .route(
+            // item count, value and completion of a collection
+            "/collection/:collection_id/stats",
+            get(collection_stats),
+        )
+        .route(
+            // names of the items that complete a collection
+            "/collection/:collection_id/targets",
+            get(get_collection_targets),
+        )
+        .route(
+            // replace the target list of a collection
+            "/collection/:collection_id/targets",
+            put(set_collection_targets),
+        )
+        .route(
+            // create the item of a target that was just acquired
+            "/collection/:collection_id/targets/:target_id/acquire",
+            post(acquire_collection_target),
+        )
+        .route(
+            // a new collection with the items of an existing one
+            "/collection/:collection_id/clone",
+            post(clone_collection),
+        )
+        .route(
+            // color and icon of the tile of a collection
+            "/collection/:collection_id/appearance",
+            put(set_collection_appearance),
+        )
+        .route(
+            // list a collection in the public api or hide it
+            "/collection/:collection_id/public",
+            put(set_collection_visibility),
+        )
         .route(
             // delete an item from a collection
             "/collection/:collection_id/:item_id",
             delete(remove_item_from_collection),
         );
 
+    let v1 = v1
+        .route("/webhook", post(new_webhook)) // register a new webhook
+        .route("/webhook", get(get_all_webhooks)) // get all webhooks
+        .route("/webhook/:id", delete(delete_webhook)) // remove a webhook
+        .route("/webhook/:id/deliveries", get(get_webhook_deliveries)); // delivery log of a webhook
+
+    let v1 = v1
+        .route("/admin/storage-usage", get(storage_usage)) // disk usage of stored images
+        .route("/admin/daily-diff", get(daily_diff)) // changes since the snapshot of the day before
+        .route("/admin/audit/verify", get(verify_audit_log)) // check the hash chain of the audit log
+        .route("/admin/index/compact", post(compact_index)) // rebuild the search index without stale terms
+        .route("/admin/embeddings", get(embedding_status).post(start_embedding)) // embedding model and jobs, or embed all items again
+        .route("/admin/flags", get(get_feature_flags)) // features enabled on this instance
+        .route("/admin/flags/:flag", put(set_feature_flag)) // override a feature flag of the config
+        .route("/admin/reload-config", post(reload_config)) // re-read config.json
+        .route("/admin/seed-demo", post(seed_demo)); // fill an empty database with demo data
+
+    let v1 = v1.route("/replication/events", get(get_replication_events)); // mutation log for read replicas
+
+    let v1 = v1
+        .route("/sync", get(sync_pull)) // changes since a sync token, for offline clients
+        .route("/sync", post(sync_push)); // local changes of an offline client
+
+    let v1 = v1
+        .route("/search/feedback", post(search_feedback)) // report the item chosen for a search
+        .route("/admin/search-analytics", get(search_analytics)); // top and zero-hit queries
+
+    // unauthenticated read-only api, only served when enabled in the config
+    let public = Router::new()
+        .route("/item/search/:name", get(public_find_items).route_layer(search_limit)) // search for items
+        .route("/item/:id", get(public_get_item)) // get a specific item
+        .route("/collection", get(public_get_collections)) // all public collections
+        .route("/collection/:collection_id/items", get(public_get_items_in_collection)) // items of a public collection
+        .route_layer(middleware::from_fn_with_state(
+            PublicApi::new(config.clone(), state.features()),
+            public_api_middleware,
+        ));
+    let v1 = v1.nest("/public", public);
+
+    let v1 = v1
+        .route("/auth/users", post(new_user)) // create a user account
+        .route("/auth/login", post(login)) // start a session
+        .route("/auth/refresh", post(refresh_session)) // extend a session
+        .route("/auth/logout", post(logout)); // end a session
+
+    // the JSON api is versioned, v2 answers like v1 but with an envelope around every body
+    let v2 = v1.clone().layer(middleware::from_fn(envelope_middleware));
+    let app = Router::new()
+        .nest(API_V1, v1)
+        .nest(API_V2, v2)
+        .route("/metrics", get(get_metrics)) // prometheus metrics
+        .route("/health/ready", get(get_readiness)) // whether the database and search index are usable
+        .route("/readyz", get(get_readiness)) // the same, at the path probes expect
+        .nest("/admin/ui", admin_ui_router()) // embedded admin frontend
+        .fallback(legacy_redirect); // paths from before versioning move to v1
+
     let rules = Arc::new(state);
-    let app = app.with_state(Arc::clone(&rules));
+    start_jobs(Arc::clone(&rules));
+    start_replication(Arc::clone(&rules));
+    let authenticator = rules.authenticator();
+    let grpc_web = config.get().grpc_web.clone();
+    let web_cors = grpc_web_cors(&config.get().cors);
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&rules),
+            auth_middleware,
+        ))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn(problem_middleware))
+        .layer(middleware::from_fn_with_state(config, cors_middleware))
+        .layer(middleware::from_fn(trace_middleware))
+        .with_state(Arc::clone(&rules));
+    // outside of the router, so retries are routed again
+    let app = DbRetry::new(app, rules.db_health());
 
-    let web_future = tokio::spawn(async {
-        // run it with hyper on localhost:3000
-        axum::Server::bind(&"0.0.0.0:8080".parse().unwrap())
-            .serve(app.into_make_service())
-            .await
-            .unwrap();
-    });
+    let rest_server = async {
+        let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        axum::Server::try_bind(&addr)
+            .with_context(|| format!("could not bind to {}", addr))?
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+        Ok(())
+    };
+
+    // the same services for browsers, which can't speak native gRPC
+    let grpc_web_server = {
+        let rules = Arc::clone(&rules);
+        let authenticator = authenticator.clone();
+        async move {
+            if !grpc_web.enabled {
+                return std::future::pending().await;
+            }
+            let addr = SocketAddr::from(([0, 0, 0, 0], grpc_web.port));
+            let find_me_pls_grpc = FindMePlsService::new(Arc::clone(&rules));
+            let find_me_pls_grpc_v2 = FindMePlsServiceV2::new(rules);
+            Server::builder()
+                .accept_http1(true)
+                .layer(MapRequestLayer::new(legacy_grpc_path))
+                .add_service(web_cors.enable(InterceptedService::new(
+                    request_scoped(FindMePlsServer::new(find_me_pls_grpc)),
+                    authenticator.clone(),
+                )))
+                .add_service(web_cors.enable(InterceptedService::new(
+                    request_scoped(FindMePlsServerV2::new(find_me_pls_grpc_v2)),
+                    authenticator,
+                )))
+                .serve(addr)
+                .await
+                .with_context(|| format!("could not serve on {}", addr))?;
+            Ok(())
+        }
+    };
 
-    let grpc_future = tokio::spawn(async {
-        let addr = "0.0.0.0:50051".parse().unwrap();
-        let find_me_pls_grpc = FindMePlsService::new(rules);
+    let grpc_server = async move {
+        let addr: SocketAddr = "0.0.0.0:50051".parse().unwrap();
+        let find_me_pls_grpc = FindMePlsService::new(Arc::clone(&rules));
+        let find_me_pls_grpc_v2 = FindMePlsServiceV2::new(rules);
         Server::builder()
-            .add_service(FindMePlsServer::new(find_me_pls_grpc))
+            .layer(MapRequestLayer::new(legacy_grpc_path))
+            .add_service(InterceptedService::new(
+                request_scoped(FindMePlsServer::new(find_me_pls_grpc)),
+                authenticator.clone(),
+            ))
+            .add_service(InterceptedService::new(
+                request_scoped(FindMePlsServerV2::new(find_me_pls_grpc_v2)),
+                authenticator,
+            ))
             .serve(addr)
             .await
-            .unwrap();
-    });
+            .with_context(|| format!("could not serve on {}", addr))?;
+        Ok(())
+    };
+
+    let error = supervise(rest_server, grpc_server, grpc_web_server).await;
+    error!("{:#}", error);
+    shutdown_telemetry();
+    std::process::exit(1);
+}
+
+/// Wraps a gRPC service, so the language, caller and deadline of a request are available while
+/// it is handled, and the request is traced
+fn request_scoped<S>(service: S) -> TraceScope<ProblemScope<CallerScope<DeadlineScope<S>>>> {
+    TraceScope::new(ProblemScope::new(CallerScope::new(DeadlineScope::new(service))))
+}
+
+/// Runs the REST, gRPC and gRPC-Web servers until the first of them stops, e.g. because its port
+/// is taken. The others are cancelled, so the process never keeps running half configured.
+async fn supervise(
+    rest: impl Future<Output = anyhow::Result<()>>,
+    grpc: impl Future<Output = anyhow::Result<()>>,
+    grpc_web: impl Future<Output = anyhow::Result<()>>,
+) -> anyhow::Error {
+    let (name, result) = tokio::select! {
+        result = rest => ("REST", result),
+        result = grpc => ("gRPC", result),
+        result = grpc_web => ("gRPC-Web", result),
+    };
 
-    let (web_res, grpc_res) = join!(web_future, grpc_future);
-    web_res.unwrap();
-    grpc_res.unwrap();
+    match result {
+        Ok(()) => anyhow!("{} server stopped unexpectedly", name),
+        Err(e) => e.context(format!("{} server failed", name)),
+    }
 }
@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Process wide metrics, rendered in the prometheus text format by `GET /metrics`.
+/// Names may carry labels, e.g. `search_cache_total{result="hit"}`.
+static REGISTRY: Mutex<BTreeMap<String, f64>> = Mutex::new(BTreeMap::new());
+
+pub fn increment(name: &str) {
+    add(name, 1.0);
+}
+
+/// Adds to a counter
+pub fn add(name: &str, value: f64) {
+    let mut registry = REGISTRY.lock().unwrap();
+    *registry.entry(name.to_owned()).or_insert(0.0) += value;
+}
+
+/// Sets a gauge
+pub fn set(name: &str, value: f64) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.insert(name.to_owned(), value);
+}
+
+pub fn get(name: &str) -> f64 {
+    REGISTRY.lock().unwrap().get(name).copied().unwrap_or(0.0)
+}
+
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    for (name, value) in registry.iter() {
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out
+}
@@ -0,0 +1,121 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+
+use crate::{CustError, OcrConfig, RecognizerKind, Result};
+
+/// Extracts the text visible in an image, e.g. model numbers and labels. Recognition may block,
+/// it is run on the blocking thread pool by a background job.
+pub trait TextRecognizer: Send + Sync {
+    fn recognize(&self, bytes: &[u8]) -> Result<String>;
+}
+
+/// Recognizes nothing, the default
+pub struct NoRecognizer;
+
+impl TextRecognizer for NoRecognizer {
+    fn recognize(&self, _bytes: &[u8]) -> Result<String> {
+        Ok(String::new())
+    }
+}
+
+/// Runs the `tesseract` command line tool, passing the image on stdin
+pub struct TesseractRecognizer {
+    binary: PathBuf,
+    /// Tesseract language codes, e.g. `eng+deu`
+    languages: String,
+}
+
+impl TesseractRecognizer {
+    pub fn new(binary: impl Into<PathBuf>, languages: impl Into<String>) -> Self {
+        Self {
+            binary: binary.into(),
+            languages: languages.into(),
+        }
+    }
+
+    fn unavailable(&self, reason: impl std::fmt::Display) -> CustError {
+        CustError::new(
+            format!("text recognition with {} failed: {}", self.binary.display(), reason),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+    }
+}
+
+impl TextRecognizer for TesseractRecognizer {
+    fn recognize(&self, bytes: &[u8]) -> Result<String> {
+        let mut child = Command::new(&self.binary)
+            .args(["stdin", "stdout", "-l", &self.languages])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.unavailable(e))?;
+
+        // written from another thread, so a full stdout pipe can't block the write
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = bytes.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let output = child.wait_with_output().map_err(|e| self.unavailable(e))?;
+        writer
+            .join()
+            .map_err(|_| self.unavailable("writing the image panicked"))?
+            .map_err(|e| self.unavailable(e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(self.unavailable(stderr.trim()));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// The recognizer a deployment configured. Only read on startup.
+pub fn recognizer_from_config(config: &OcrConfig) -> Arc<dyn TextRecognizer> {
+    match config.recognizer {
+        RecognizerKind::None => Arc::new(NoRecognizer),
+        RecognizerKind::Tesseract => Arc::new(TesseractRecognizer::new(
+            config.tesseract_path.clone(),
+            config.languages.clone(),
+        )),
+    }
+}
+
+/// Recognized text as it is stored and indexed: words separated by single spaces, without
+/// the fragments OCR produces from edges and noise. `None` if nothing is left.
+pub fn normalize_recognized_text(text: &str) -> Option<String> {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test_ocr {
+    use super::{normalize_recognized_text, TesseractRecognizer, TextRecognizer};
+
+    #[test]
+    fn recognized_text_is_normalized() {
+        assert_eq!(
+            normalize_recognized_text("BOSCH\n\n GSR 12V-15 |\n\u{c}").as_deref(),
+            Some("BOSCH GSR 12V-15")
+        );
+        assert_eq!(normalize_recognized_text(" — |\n"), None);
+        assert_eq!(normalize_recognized_text(""), None);
+    }
+
+    #[test]
+    fn missing_tesseract_is_an_error() {
+        let recognizer = TesseractRecognizer::new("/nonexistent/tesseract", "eng");
+        assert!(recognizer.recognize(b"not an image").is_err());
+    }
+}
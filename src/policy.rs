@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::{current_caller, util, AuthContext, CustError, Result, SmartQuery, ID};
+
+/// Restricts an api token to parts of the inventory, configured next to the token. A scoped
+/// token reaches the listed categories with their subcategories, the listed collections, and the
+/// items in either of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenScope {
+    #[serde(default)]
+    pub categories: Vec<ID>,
+    #[serde(default)]
+    pub collections: Vec<ID>,
+}
+
+/// A [`TokenScope`] resolved against the current state of the database
+#[derive(Debug, Clone, Default)]
+pub struct AccessScope {
+    /// Name of the token, for error messages
+    name: String,
+    categories: HashSet<ID>,
+    collections: HashSet<ID>,
+    items: HashSet<ID>,
+}
+
+impl AccessScope {
+    /// Resolves the scope of a caller, `None` if the caller is not restricted.
+    pub async fn of(conn: &SqlitePool, caller: Option<&AuthContext>) -> Result<Option<Self>> {
+        match caller {
+            Some(AuthContext {
+                name,
+                scope: Some(scope),
+                ..
+            }) => Ok(Some(Self::resolve(conn, name, scope).await?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn resolve(conn: &SqlitePool, name: &str, scope: &TokenScope) -> Result<Self> {
+        let query_str = format!(
+            r#"
+            WITH RECURSIVE tree(id) AS (
+                SELECT id FROM categories WHERE id IN ({})
+                UNION
+                SELECT categories.id FROM categories
+                JOIN tree ON categories.parent_category = tree.id
+            )
+            SELECT id FROM tree
+            "#,
+            util::placeholders(scope.categories.len())
+        );
+        let categories: HashSet<ID> = scope
+            .categories
+            .iter()
+            .fold(sqlx::query_scalar::<_, ID>(&query_str), |query, id| query.bind(*id))
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .collect();
+        let collections: HashSet<ID> = scope.collections.iter().copied().collect();
+
+        let query_str = format!(
+            r#"
+            SELECT id FROM items WHERE category_id IN ({})
+            UNION
+            SELECT item_id FROM collection_items WHERE collection_id IN ({})
+            "#,
+            util::placeholders(categories.len()),
+            util::placeholders(collections.len())
+        );
+        let query = categories
+            .iter()
+            .chain(&collections)
+            .fold(sqlx::query_scalar::<_, ID>(&query_str), |query, id| query.bind(*id));
+        let mut items: HashSet<ID> = query.fetch_all(conn).await?.into_iter().collect();
+
+        // the items of smart collections are not linked, they match the query of the collection
+        let query_str = format!(
+            "SELECT smart_query FROM collections WHERE id IN ({}) AND smart_query IS NOT NULL",
+            util::placeholders(collections.len())
+        );
+        let smart_queries = collections
+            .iter()
+            .fold(sqlx::query(&query_str), |query, id| query.bind(*id))
+            .fetch_all(conn)
+            .await?;
+        for row in smart_queries {
+            let query: String = row.get("smart_query");
+            let (condition, values) = SmartQuery::parse(&query)?.to_sql();
+            let query_str = format!("SELECT id FROM items WHERE {}", condition);
+            let ids = values
+                .iter()
+                .fold(sqlx::query_scalar::<_, ID>(&query_str), |query, value| query.bind(value))
+                .fetch_all(conn)
+                .await?;
+            items.extend(ids);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            categories,
+            collections,
+            items,
+        })
+    }
+
+    pub fn allows_item(&self, id: ID) -> bool {
+        self.items.contains(&id)
+    }
+
+    pub fn allows_category(&self, id: ID) -> bool {
+        self.categories.contains(&id)
+    }
+
+    pub fn allows_collection(&self, id: ID) -> bool {
+        self.collections.contains(&id)
+    }
+
+    pub fn require_item(&self, id: ID) -> Result<()> {
+        if self.allows_item(id) {
+            Ok(())
+        } else {
+            Err(self.denied(format!("item {}", id)))
+        }
+    }
+
+    /// Items without a category are outside of every scope.
+    pub fn require_category(&self, id: Option<ID>) -> Result<()> {
+        match id {
+            Some(id) if self.allows_category(id) => Ok(()),
+            Some(id) => Err(self.denied(format!("category {}", id))),
+            None => Err(self.denied("uncategorized items".to_owned())),
+        }
+    }
+
+    pub fn require_collection(&self, id: ID) -> Result<()> {
+        if self.allows_collection(id) {
+            Ok(())
+        } else {
+            Err(self.denied(format!("collection {}", id)))
+        }
+    }
+
+    fn denied(&self, what: String) -> CustError {
+        CustError::new(
+            format!("{} is not allowed to access {}", self.name, what),
+            StatusCode::FORBIDDEN,
+        )
+    }
+}
+
+/// Rejects callers of the current request that are restricted to a scope, for operations
+/// spanning the whole inventory like exports, sync and bulk changes.
+pub fn require_unscoped() -> Result<()> {
+    match current_caller() {
+        Some(AuthContext {
+            name,
+            scope: Some(_),
+            ..
+        }) => Err(CustError::new(
+            format!("{} is restricted to a scope and can't do this", name),
+            StatusCode::FORBIDDEN,
+        )),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{CustError, Price, PriceProviderKind, PricingConfig, Result};
+
+/// What the market value of an item is looked up by
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PriceQuery {
+    pub name: String,
+    /// EAN or UPC, if the caller scanned one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub barcode: Option<String>,
+    /// Currency the item is valued in, providers may answer in another one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+/// A market value found by a [`PriceProvider`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceQuote {
+    pub value: Price,
+    /// ISO 4217 code, e.g. `EUR`
+    pub currency: String,
+}
+
+/// Looks up the current market value of items, e.g. at a price comparison site
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    /// Recorded as the source of the estimates
+    fn name(&self) -> &str;
+
+    /// `None` if the provider knows no price for the item
+    async fn lookup(&self, query: &PriceQuery) -> Result<Option<PriceQuote>>;
+}
+
+/// Looks up nothing, the default. Estimates fail with 501 until a provider is configured.
+pub struct NoPriceProvider;
+
+#[async_trait]
+impl PriceProvider for NoPriceProvider {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    async fn lookup(&self, _query: &PriceQuery) -> Result<Option<PriceQuote>> {
+        Err(CustError::new(
+            "no price provider is configured".to_string(),
+            StatusCode::NOT_IMPLEMENTED,
+        ))
+    }
+}
+
+/// Asks a JSON api with `GET <url>?name=...&barcode=...&currency=...`. It answers with a
+/// [`PriceQuote`], or 404 if it knows no price.
+pub struct HttpPriceProvider {
+    url: String,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl HttpPriceProvider {
+    pub fn new(url: impl Into<String>, token: Option<String>, timeout: Duration) -> Self {
+        Self {
+            url: url.into(),
+            token,
+            timeout,
+        }
+    }
+
+    fn unavailable(&self, reason: impl std::fmt::Display) -> CustError {
+        CustError::new(
+            format!("price lookup at {} failed: {}", self.url, reason),
+            StatusCode::BAD_GATEWAY,
+        )
+    }
+}
+
+#[async_trait]
+impl PriceProvider for HttpPriceProvider {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn lookup(&self, query: &PriceQuery) -> Result<Option<PriceQuote>> {
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| self.unavailable(e))?;
+        let mut request = client.get(&self.url).query(query);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.map_err(|e| self.unavailable(e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(self.unavailable(format!("answered with {}", response.status())));
+        }
+        let quote: PriceQuote = response.json().await.map_err(|e| self.unavailable(e))?;
+        Ok(Some(quote))
+    }
+}
+
+/// The price provider a deployment configured. Only read on startup.
+pub fn price_provider_from_config(config: &PricingConfig) -> Arc<dyn PriceProvider> {
+    match (config.provider, &config.url) {
+        (PriceProviderKind::Http, Some(url)) => Arc::new(HttpPriceProvider::new(
+            url.clone(),
+            config.token.clone(),
+            Duration::from_secs(config.timeout_secs),
+        )),
+        _ => Arc::new(NoPriceProvider),
+    }
+}
@@ -0,0 +1,262 @@
+use std::task::{Context, Poll};
+
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::future::BoxFuture;
+use tonic::server::NamedService;
+use tower::Service;
+
+/// Prefix of the `type` of problem documents, followed by the code of the error
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:find-me-pls:problem:";
+
+/// Languages of the message catalog
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    /// Language tag, sent back as `Content-Language`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+
+    /// The preferred language of an `Accept-Language` header that the catalog has, English if
+    /// there is none.
+    pub fn negotiate(accept_language: &str) -> Locale {
+        let mut best = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let language = tag.split('-').next().unwrap_or_default();
+            let locale = match language {
+                "en" => Locale::En,
+                "de" => Locale::De,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+}
+
+/// Code of an error without a more specific one, derived from its status
+pub fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::BAD_GATEWAY => "bad_gateway",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        StatusCode::NOT_IMPLEMENTED => "not_implemented",
+        _ if status.is_client_error() => "bad_request",
+        _ => "internal_error",
+    }
+}
+
+/// Short, human readable summary of an error in the language of the caller. Codes missing from
+/// the catalog fall back to the title of their status.
+pub fn problem_title(code: &str, status: StatusCode, locale: Locale) -> &'static str {
+    catalog(code, locale)
+        .or_else(|| catalog(status_code_name(status), locale))
+        .unwrap_or_default()
+}
+
+fn catalog(code: &str, locale: Locale) -> Option<&'static str> {
+    let title = match (code, locale) {
+        ("bad_request", Locale::En) => "The request is invalid",
+        ("bad_request", Locale::De) => "Die Anfrage ist ungültig",
+        ("unauthorized", Locale::En) => "Authentication is required",
+        ("unauthorized", Locale::De) => "Anmeldung erforderlich",
+        ("forbidden", Locale::En) => "You are not allowed to do this",
+        ("forbidden", Locale::De) => "Dafür fehlt die Berechtigung",
+        ("not_found", Locale::En) => "Not found",
+        ("not_found", Locale::De) => "Nicht gefunden",
+        ("conflict", Locale::En) => "This conflicts with existing data",
+        ("conflict", Locale::De) => "Das widerspricht vorhandenen Daten",
+        ("payload_too_large", Locale::En) => "The upload is too large",
+        ("payload_too_large", Locale::De) => "Der Upload ist zu groß",
+        ("unsupported_media_type", Locale::En) => "The content type is not supported",
+        ("unsupported_media_type", Locale::De) => "Der Inhaltstyp wird nicht unterstützt",
+        ("unprocessable_entity", Locale::En) => "The request contains invalid values",
+        ("unprocessable_entity", Locale::De) => "Die Anfrage enthält ungültige Werte",
+        ("invalid_json", Locale::En) => "The request body is not valid JSON",
+        ("invalid_json", Locale::De) => "Der Inhalt der Anfrage ist kein gültiges JSON",
+        ("invalid_value", Locale::En) => "A field has an invalid value",
+        ("invalid_value", Locale::De) => "Ein Feld hat einen ungültigen Wert",
+        ("unknown_field", Locale::En) => "The request contains an unknown field",
+        ("unknown_field", Locale::De) => "Die Anfrage enthält ein unbekanntes Feld",
+        ("too_many_requests", Locale::En) => "Too many requests, try again later",
+        ("too_many_requests", Locale::De) => "Zu viele Anfragen, bitte später erneut versuchen",
+        ("bad_gateway", Locale::En) => "An upstream server failed",
+        ("bad_gateway", Locale::De) => "Ein vorgelagerter Server ist fehlgeschlagen",
+        ("unavailable", Locale::En) => "The service is temporarily unavailable",
+        ("unavailable", Locale::De) => "Der Dienst ist vorübergehend nicht verfügbar",
+        ("not_implemented", Locale::En) => "Not implemented",
+        ("not_implemented", Locale::De) => "Nicht implementiert",
+        ("internal_error", Locale::En) => "Internal server error",
+        ("internal_error", Locale::De) => "Interner Serverfehler",
+        _ => return None,
+    };
+    Some(title)
+}
+
+/// Request the error responses of the current task are written for
+#[derive(Debug, Clone, Default)]
+pub struct ProblemContext {
+    pub locale: Locale,
+    /// Path of the request, the `instance` of problem documents
+    pub instance: Option<String>,
+}
+
+impl ProblemContext {
+    pub fn of<B>(request: &Request<B>) -> Self {
+        let locale = request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Locale::negotiate)
+            .unwrap_or_default();
+        Self {
+            locale,
+            instance: Some(request.uri().path().to_owned()),
+        }
+    }
+}
+
+tokio::task_local! {
+    static PROBLEM_CONTEXT: ProblemContext;
+}
+
+/// Context of the request currently being handled, the default outside of one
+pub fn problem_context() -> ProblemContext {
+    PROBLEM_CONTEXT.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Makes the language and path of a REST request available to the errors it produces
+pub async fn problem_middleware<B>(request: Request<B>, next: Next<B>) -> Response {
+    let context = ProblemContext::of(&request);
+    PROBLEM_CONTEXT.scope(context, next.run(request)).await
+}
+
+/// Makes the language of a gRPC request available to the statuses it produces
+#[derive(Debug, Clone)]
+pub struct ProblemScope<S> {
+    inner: S,
+}
+
+impl<S> ProblemScope<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service<Request<B>> for ProblemScope<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, core::result::Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let context = ProblemContext::of(&request);
+        Box::pin(PROBLEM_CONTEXT.scope(context, self.inner.call(request)))
+    }
+}
+
+impl<S: NamedService> NamedService for ProblemScope<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+#[cfg(test)]
+mod test_problem {
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    use tonic::{Code, Status};
+    use tonic_types::StatusExt;
+
+    use super::{ProblemContext, PROBLEM_CONTEXT};
+    use crate::{problem_title, status_code_name, CustError, Locale, PROBLEM_CONTENT_TYPE};
+
+    fn german() -> ProblemContext {
+        ProblemContext {
+            locale: Locale::De,
+            instance: Some("/api/v1/item/7".to_owned()),
+        }
+    }
+
+    #[test]
+    fn languages_are_negotiated() {
+        assert_eq!(Locale::negotiate("de-DE,de;q=0.9,en;q=0.8"), Locale::De);
+        assert_eq!(Locale::negotiate("fr, en;q=0.5, de;q=0.7"), Locale::De);
+        assert_eq!(Locale::negotiate("de;q=0, en"), Locale::En);
+        assert_eq!(Locale::negotiate("fr"), Locale::En);
+        assert_eq!(Locale::negotiate(""), Locale::En);
+    }
+
+    #[test]
+    fn titles_fall_back_to_their_status() {
+        let not_found = StatusCode::NOT_FOUND;
+        let code = status_code_name(not_found);
+        assert_eq!(problem_title(code, not_found, Locale::De), "Nicht gefunden");
+        assert_eq!(problem_title("upload_flagged", not_found, Locale::En), "Not found");
+        assert_eq!(
+            problem_title("gone", StatusCode::GONE, Locale::De),
+            "Die Anfrage ist ungültig"
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_are_problem_documents() {
+        let error = CustError::new("item 7 does not exist".to_string(), StatusCode::NOT_FOUND)
+            .with_details(serde_json::json!({ "item_id": 7 }));
+        let problem = PROBLEM_CONTEXT.scope(german(), async { error.problem() }).await;
+        assert_eq!(problem["type"], "urn:find-me-pls:problem:not_found");
+        assert_eq!(problem["title"], "Nicht gefunden");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["detail"], "item 7 does not exist");
+        assert_eq!(problem["instance"], "/api/v1/item/7");
+        assert_eq!(problem["details"]["item_id"], 7);
+
+        let response = error.with_code("unknown_field").into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_CONTENT_TYPE);
+        assert_eq!(response.headers()[header::CONTENT_LANGUAGE], "en");
+    }
+
+    #[tokio::test]
+    async fn statuses_carry_a_localized_message() {
+        let error = CustError::new("name value is empty".to_string(), StatusCode::BAD_REQUEST);
+        let status = PROBLEM_CONTEXT.scope(german(), async { Status::from(error) }).await;
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "name value is empty");
+
+        let localized = status.get_details_localized_message().unwrap();
+        assert_eq!(localized.locale, "de");
+        assert_eq!(localized.message, "Die Anfrage ist ungültig");
+        assert_eq!(status.get_details_error_info().unwrap().reason, "bad_request");
+    }
+}
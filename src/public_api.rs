@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::{ConfigHandle, CustError, Feature, FeatureFlags};
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Number of tracked clients above which expired windows are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Counts requests per client address in fixed one minute windows.
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// Counts a request of `client`. If it is over `limit`, the time until the window resets is
+    /// returned instead.
+    pub fn check(&self, client: IpAddr, limit: u32) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+        }
+
+        let (start, count) = windows.entry(client).or_insert((now, 0));
+        if now.duration_since(*start) >= WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= limit {
+            return Err(WINDOW.saturating_sub(now.duration_since(*start)));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+/// State of the public api middleware
+#[derive(Clone)]
+pub struct PublicApi {
+    config: ConfigHandle,
+    features: FeatureFlags,
+    limiter: Arc<RateLimiter>,
+}
+
+impl PublicApi {
+    pub fn new(config: ConfigHandle, features: FeatureFlags) -> Self {
+        Self {
+            config,
+            features,
+            limiter: Default::default(),
+        }
+    }
+}
+
+/// Guards the routes under `/public`: they answer 404 unless the public api and the
+/// [`Feature::PublicSharing`] flag are enabled, and are rate limited per client. The config is
+/// read on every request, so reloads apply immediately.
+pub async fn public_api_middleware<B>(
+    State(api): State<PublicApi>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let (enabled, limit, trust_forwarded_for) = {
+        let config = api.config.get();
+        (
+            config.public.enabled,
+            config.public.requests_per_minute,
+            config.public.trust_forwarded_for,
+        )
+    };
+    if !enabled || !api.features.is_enabled(Feature::PublicSharing) {
+        return CustError::new("not found".to_string(), StatusCode::NOT_FOUND).into_response();
+    }
+
+    let forwarded = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .and_then(|client| client.trim().parse().ok())
+        .filter(|_| trust_forwarded_for);
+    let client = forwarded.unwrap_or(addr.ip());
+
+    if let Err(retry_after) = api.limiter.check(client, limit) {
+        let mut response =
+            CustError::new("too many requests".to_string(), StatusCode::TOO_MANY_REQUESTS)
+                .into_response();
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(retry_after.as_secs().max(1)),
+        );
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod test_public_api {
+    use std::net::IpAddr;
+
+    use crate::RateLimiter;
+
+    #[test]
+    fn clients_are_limited_independently() {
+        let limiter = RateLimiter::default();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert!(limiter.check(a, 2).is_ok());
+        assert!(limiter.check(a, 2).is_ok());
+        let retry_after = limiter.check(a, 2).unwrap_err();
+        assert!(retry_after.as_secs() <= 60);
+
+        assert!(limiter.check(b, 2).is_ok());
+    }
+}
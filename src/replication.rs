@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use prost::bytes::Buf;
+use prost::Message;
+use tracing::{debug, error, info};
+
+use crate::find_me_pls::v2::ReplicationEvent;
+use crate::{BusinessRules, ReplicationConfig, Result, API_V1};
+
+/// Media type of the replication log, a sequence of length delimited `ReplicationEvent`s
+pub const REPLICATION_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Most events sent in response to one request
+pub const MAX_REPLICATION_BATCH: u32 = 5000;
+
+/// Mirrors the configured primary: pulls its replication log and applies the events, until the
+/// process ends. Idles while no primary is configured, so reloading the config starts and stops
+/// it.
+pub fn start_replication(rules: Arc<BusinessRules>) {
+    tokio::spawn(async move {
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Could not create replication http client: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            let config = rules.config().get().replication.clone();
+            if let Some(primary) = &config.primary {
+                match pull(&client, &rules, primary, &config).await {
+                    Ok(applied) => {
+                        if applied > 0 {
+                            info!("Applied {} events from {}", applied, primary);
+                        }
+                        // a full batch means the replica is still catching up
+                        if applied >= config.batch_size as usize {
+                            continue;
+                        }
+                    }
+                    Err(e) => error!("Replication from {} failed: {}", primary, e),
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(config.poll_secs.max(1))).await;
+        }
+    });
+}
+
+/// Fetches one batch of events after the last applied one and applies them in order. The
+/// position is stored after every event, so a failed batch resumes where it stopped.
+async fn pull(
+    client: &reqwest::Client,
+    rules: &BusinessRules,
+    primary: &str,
+    config: &ReplicationConfig,
+) -> Result<usize> {
+    let since = rules.replication_position(primary).await?;
+    let url = format!("{}{}/replication/events", primary.trim_end_matches('/'), API_V1);
+    let mut request = client.get(url).query(&[
+        ("since", since.to_string()),
+        ("limit", config.batch_size.to_string()),
+    ]);
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+
+    let mut body = request.send().await?.error_for_status()?.bytes().await?;
+    let mut applied = 0;
+    while body.has_remaining() {
+        let event =
+            ReplicationEvent::decode_length_delimited(&mut body).map_err(anyhow::Error::from)?;
+        let seq = event.seq;
+        debug!("Applying replication event {} ({})", seq, event.kind);
+        rules.apply_replication_event(event).await?;
+        rules.set_replication_position(primary, seq).await?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
@@ -1,36 +1,215 @@
 use std::sync::Arc;
-use axum::extract::Path;
-use axum::{extract::State, Json};
+use axum::body::StreamBody;
+use axum::extract::multipart::MultipartError;
+use axum::extract::{Multipart, Path, Query};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::extract::State;
+use base64::Engine;
 
-use crate::{BusinessRules, Category, Collection, CollectionItem, Item, Name, Result, ID};
+use crate::{
+    content_disposition, metrics, require_unscoped, session_cookie, util, AcquireTarget,
+    Appearance, AsOfQuery, AuditEntry, AuditVerification, BatchSearch, BatchSearchResult,
+    BulkDelete, BulkDeleteResult, BundleImportResult, BusinessRules, Category, CategoryMove,
+    CloneCollectionQuery, Collection, CollectionBundle, CollectionItem, CollectionStats,
+    CollectionTarget, Credentials, CustError, DailyDiff, DailyDiffQuery, DemoSummary, Disposal,
+    DryRunQuery, EmbeddingJob, EmbeddingStatus, EstimateQuery, Favorites, FeatureFlag, FlagOverride,
+    IdStrategy, ImageSearch, ImageUrl, IndexCompaction, InsuranceReportQuery, Item, ItemDetails,
+    ItemExportQuery, ItemImage, ItemInclude, ItemMove, ItemNote, ItemSort, ItemTranslation, Json,
+    LabelQuery, Location, MeasurementFilter, Name, NewDisposal, NewItemImage, NewItemNote,
+    NewReservation, NewStocktake, NewUser, OwnershipFilter, OwnershipState, PublicItem, Rename,
+    ReplicationQuery, ReportFormat, Reservation, Result, SearchAnalytics, SearchFeedback,
+    SearchOptions, SearchScope, SeedDemo, SemanticMatch, SemanticSearch, SimilarItem, StaleQuery,
+    Stocktake, StocktakeConfirmation, StocktakeReport, StocktakeScan, StorageUsage, SyncChanges,
+    SyncPullQuery, SyncPush, SyncPushResult, TargetEntry, User, Valuation, ValuationQuery,
+    ValueEstimate, Visibility, Webhook, WebhookDelivery, DEFAULT_DEMO_ITEMS,
+    DEFAULT_SYNC_LIMIT, ID, MAX_REPLICATION_BATCH, MAX_SYNC_LIMIT, REPLICATION_CONTENT_TYPE,
+    SESSION_COOKIE, TOTAL_COUNT_HEADER,
+};
+
+/// Media type of newline delimited JSON, streamed by listings that are asked for it
+const NDJSON: &str = "application/x-ndjson";
+
+/// Request header that makes creating an item safe to retry, see
+/// [`BusinessRules::add_item_idempotent`]
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
 
 #[axum_macros::debug_handler]
 pub async fn add_item(
     State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
     Json(item): Json<Item>,
 ) -> Result<Json<Item>> {
-    Ok(Json(state.add_item(item).await?))
+    let key = headers.get(IDEMPOTENCY_KEY_HEADER).and_then(|value| value.to_str().ok());
+    Ok(Json(state.add_item_idempotent(item, key).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_all_items(
+    State(state): State<Arc<BusinessRules>>,
+    Query(ownership): Query<OwnershipFilter>,
+    Query(sort): Query<ItemSort>,
+    Query(as_of): Query<AsOfQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if let Some(until) = as_of.timestamp()? {
+        return Ok(Json(state.items_as_of(until, &ownership).await?).into_response());
+    }
+    let last_modified = state.last_modified("item").await?;
+    if not_modified(&headers, last_modified) {
+        return Ok(with_last_modified(StatusCode::NOT_MODIFIED, last_modified));
+    }
+
+    let ndjson = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|accept| accept.to_str().ok())
+        .any(|accept| accept.contains(NDJSON));
+    if ndjson {
+        let items = state.stream_all_items(ownership, sort);
+        let response = ([(header::CONTENT_TYPE, NDJSON)], StreamBody::new(items));
+        return Ok(with_last_modified(response, last_modified));
+    }
+
+    let mut items = state.get_all_items(&ownership, &sort).await?;
+    state.localize_items(&mut items, &request_locales(&headers)).await?;
+    Ok(with_last_modified(Json(items), last_modified))
 }
 
 #[axum_macros::debug_handler]
-pub async fn get_all_items(State(state): State<Arc<BusinessRules>>) -> Result<Json<Vec<Item>>> {
-    Ok(Json(state.get_all_items().await?))
+pub async fn head_all_items(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    listing_head(&state, "item", &headers).await
 }
 
 #[axum_macros::debug_handler]
 pub async fn get_item(
     State(state): State<Arc<BusinessRules>>,
     Path(id): Path<ID>,
+    Query(include): Query<ItemInclude>,
+    headers: HeaderMap,
+) -> Result<Json<ItemDetails>> {
+    let include_collections = include.collections()?;
+    let include_notes = include.notes()?;
+    let mut item = state.get_item(id).await?;
+    let locales = request_locales(&headers);
+    state.localize_items(std::slice::from_mut(&mut item), &locales).await?;
+    state.record_access(id);
+    let collections = if include_collections {
+        Some(state.get_item_collections(id).await?)
+    } else {
+        None
+    };
+    let notes = if include_notes {
+        Some(state.get_item_notes(id).await?)
+    } else {
+        None
+    };
+    Ok(Json(ItemDetails {
+        item,
+        collections,
+        notes,
+    }))
+}
+
+#[axum_macros::debug_handler]
+pub async fn bulk_delete_items(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<DryRunQuery>,
+    Json(mut request): Json<BulkDelete>,
+) -> Result<Json<BulkDeleteResult>> {
+    request.dry_run |= query.dry_run;
+    Ok(Json(state.bulk_delete_items(request).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn restore_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.restore_item(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn stale_items(
+
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<StaleQuery>,
+) -> Result<Json<Vec<Item>>> {
+    Ok(Json(state.stale_items(query.not_accessed_for_days).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_by_uuid(
+    State(state): State<Arc<BusinessRules>>,
+    Path(uuid): Path<String>,
 ) -> Result<Json<Item>> {
-    Ok(Json(state.get_item(id).await?))
+    let item = state.get_item_by_uuid(&uuid).await?;
+    if let Some(id) = item.id {
+        state.record_access(id);
+    }
+    Ok(Json(item))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_collections(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<Collection>>> {
+    Ok(Json(state.get_item_collections(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn find_items_by_image(
+    State(state): State<Arc<BusinessRules>>,
+    Json(search): Json<ImageSearch>,
+) -> Result<Json<Vec<SimilarItem>>> {
+    Ok(Json(state.find_items_by_image(search).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn find_items_semantically(
+    State(state): State<Arc<BusinessRules>>,
+    Json(search): Json<SemanticSearch>,
+) -> Result<Json<Vec<SemanticMatch>>> {
+    Ok(Json(state.find_items_semantically(search).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn find_items_batch(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+    Json(batch): Json<BatchSearch>,
+) -> Result<Json<Vec<BatchSearchResult>>> {
+    Ok(Json(state.find_items_batch(batch, &request_locales(&headers)).await?))
 }
 
 #[axum_macros::debug_handler]
 pub async fn find_items(
     State(state): State<Arc<BusinessRules>>,
     Path(name): Path<Name>,
-) -> Result<Json<Vec<Item>>> {
-    Ok(Json(state.find_items(name).await?))
+    Query(filter): Query<MeasurementFilter>,
+    Query(ownership): Query<OwnershipFilter>,
+    Query(options): Query<SearchOptions>,
+    Query(scope): Query<SearchScope>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if options.explain {
+        let explanation = state.explain_search(name, &filter, &ownership, &options).await?;
+        return Ok(Json(explanation).into_response());
+    }
+    let hits = state
+        .find_items_counted(name, &filter, &ownership, &scope, &options)
+        .await?;
+    let mut items = hits.items;
+    state.localize_items(&mut items, &request_locales(&headers)).await?;
+    let mut response = Json(items).into_response();
+    response
+        .headers_mut()
+        .insert(TOTAL_COUNT_HEADER, HeaderValue::from(hits.total));
+    Ok(response)
 }
 
 #[axum_macros::debug_handler]
@@ -41,6 +220,176 @@ pub async fn delete_item(
     Ok(Json(state.delete_item(id).await?))
 }
 
+#[axum_macros::debug_handler]
+pub async fn mark_item_owned(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.set_ownership_state(id, OwnershipState::Owned).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn mark_item_ordered(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.set_ownership_state(id, OwnershipState::Ordered).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn mark_item_wishlist(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.set_ownership_state(id, OwnershipState::Wishlist).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn dispose_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(disposal): Json<NewDisposal>,
+) -> Result<Json<Disposal>> {
+    Ok(Json(state.dispose_item(id, disposal).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn reserve_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(reservation): Json<NewReservation>,
+) -> Result<Json<Reservation>> {
+    Ok(Json(state.reserve_item(id, reservation).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_reservation(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Reservation>> {
+    Ok(Json(state.get_reservation(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn release_reservation(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Reservation>> {
+    Ok(Json(state.release_reservation(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn start_stocktake(
+    State(state): State<Arc<BusinessRules>>,
+    Json(stocktake): Json<NewStocktake>,
+) -> Result<Json<Stocktake>> {
+    Ok(Json(state.start_stocktake(stocktake).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_stocktakes(State(state): State<Arc<BusinessRules>>) -> Result<Json<Vec<Stocktake>>> {
+    Ok(Json(state.get_stocktakes().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_stocktake(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Stocktake>> {
+    Ok(Json(state.get_stocktake(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn confirm_stocktake_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(scan): Json<StocktakeScan>,
+) -> Result<Json<StocktakeConfirmation>> {
+    Ok(Json(state.confirm_stocktake_item(id, scan).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn end_stocktake(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Stocktake>> {
+    Ok(Json(state.end_stocktake(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn stocktake_report(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<StocktakeReport>> {
+    Ok(Json(state.stocktake_report(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_disposal(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Disposal>> {
+    Ok(Json(state.get_disposal(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn export_items_csv(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<ItemExportQuery>,
+) -> Result<impl IntoResponse> {
+    let rows = state.export_items_csv(query)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+        StreamBody::new(rows),
+    ))
+}
+
+/// The replication log after `?since=`, pulled by read replicas
+#[axum_macros::debug_handler]
+pub async fn get_replication_events(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<ReplicationQuery>,
+) -> Result<impl IntoResponse> {
+    require_unscoped()?;
+    let limit = query
+        .limit
+        .unwrap_or(MAX_REPLICATION_BATCH)
+        .min(MAX_REPLICATION_BATCH);
+    let events = state.replication_events(query.since, limit);
+    Ok((
+        [(header::CONTENT_TYPE, REPLICATION_CONTENT_TYPE)],
+        StreamBody::new(events),
+    ))
+}
+
+/// Changes since `?token=` for offline clients
+#[axum_macros::debug_handler]
+pub async fn sync_pull(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<SyncPullQuery>,
+) -> Result<Json<SyncChanges>> {
+    let limit = query.limit.unwrap_or(DEFAULT_SYNC_LIMIT).min(MAX_SYNC_LIMIT);
+    Ok(Json(state.sync_pull(query.token.as_deref(), limit).await?))
+}
+
+/// Local changes of an offline client, answered with the state to keep per item
+#[axum_macros::debug_handler]
+pub async fn sync_push(
+    State(state): State<Arc<BusinessRules>>,
+    Json(push): Json<SyncPush>,
+) -> Result<Json<Vec<SyncPushResult>>> {
+    Ok(Json(state.sync_push(push).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_item_image_from_url(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(image): Json<ImageUrl>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.set_item_image_from_url(id, &image.url).await?))
+}
+
 #[axum_macros::debug_handler]
 pub async fn new_category(
     State(state): State<Arc<BusinessRules>>,
@@ -50,30 +399,892 @@ pub async fn new_category(
 }
 
 #[axum_macros::debug_handler]
-pub async fn get_all_categories(State(state): State<Arc<BusinessRules>>) -> Result<Json<Vec<Category>>> {
-    Ok(Json(state.get_all_categories().await?))
+pub async fn get_all_categories(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let last_modified = state.last_modified("category").await?;
+    if not_modified(&headers, last_modified) {
+        return Ok(with_last_modified(StatusCode::NOT_MODIFIED, last_modified));
+    }
+    Ok(with_last_modified(Json(state.get_all_categories().await?), last_modified))
+}
+
+#[axum_macros::debug_handler]
+pub async fn head_all_categories(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    listing_head(&state, "category", &headers).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_category_by_uuid(
+    State(state): State<Arc<BusinessRules>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<Category>> {
+    Ok(Json(state.get_category_by_uuid(&uuid).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn rename_category(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(rename): Json<Rename>,
+) -> Result<Json<Category>> {
+    Ok(Json(state.rename_category(id, rename.name).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn move_category(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Query(query): Query<CategoryMove>,
+) -> Result<Json<Category>> {
+    Ok(Json(state.move_category(id, query.new_parent).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_category_appearance(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(appearance): Json<Appearance>,
+) -> Result<Json<Category>> {
+    Ok(Json(state.set_category_appearance(id, appearance).await?))
+}
+
+/// Multipart upload of a category thumbnail, the image is the first file part of the form
+#[axum_macros::debug_handler]
+pub async fn upload_category_thumbnail(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    mut multipart: Multipart,
+) -> Result<Json<Category>> {
+    while let Some(field) = multipart.next_field().await.map_err(invalid_multipart)? {
+        if field.file_name().is_none() && field.name() != Some("image") {
+            continue;
+        }
+        let filename = field.file_name().map(str::to_owned);
+        let image = field.bytes().await.map_err(invalid_multipart)?;
+        return Ok(Json(
+            state
+                .set_category_thumbnail(id, image.to_vec(), filename)
+                .await?,
+        ));
+    }
+
+    Err(CustError::new(
+        "the form contains no image".to_string(),
+        StatusCode::BAD_REQUEST,
+    ))
+}
+
+/// The thumbnail of a category as a png file
+#[axum_macros::debug_handler]
+pub async fn get_category_thumbnail(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Response> {
+    let thumbnail = state.category_thumbnail(id).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, thumbnail.mime_type),
+            (header::CONTENT_DISPOSITION, content_disposition(&thumbnail.filename)),
+            (header::ETAG, format!("\"{}\"", thumbnail.sha256)),
+        ],
+        thumbnail.bytes,
+    )
+        .into_response())
+}
+
+#[axum_macros::debug_handler]
+pub async fn new_location(
+    State(state): State<Arc<BusinessRules>>,
+    Json(location): Json<Location>,
+) -> Result<Json<Location>> {
+    Ok(Json(state.new_location(location).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_all_locations(State(state): State<Arc<BusinessRules>>) -> Result<Json<Vec<Location>>> {
+    Ok(Json(state.get_all_locations().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_location_by_uuid(
+    State(state): State<Arc<BusinessRules>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<Location>> {
+    Ok(Json(state.get_location_by_uuid(&uuid).await?))
 }
 
 #[axum_macros::debug_handler]
-pub async fn new_collection(Json(_collection): Json<Collection>) -> Result<Json<Collection>> {
-    todo!()
+pub async fn rename_location(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(rename): Json<Rename>,
+) -> Result<Json<Location>> {
+    Ok(Json(state.rename_location(id, rename.name).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn new_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Json(collection): Json<Collection>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.new_collection(collection).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_all_collections(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let last_modified = state.last_modified("collection").await?;
+    if not_modified(&headers, last_modified) {
+        return Ok(with_last_modified(StatusCode::NOT_MODIFIED, last_modified));
+    }
+    Ok(with_last_modified(Json(state.get_all_collections().await?), last_modified))
+}
+
+#[axum_macros::debug_handler]
+pub async fn head_all_collections(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    listing_head(&state, "collection", &headers).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_collection_by_uuid(
+    State(state): State<Arc<BusinessRules>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.get_collection_by_uuid(&uuid).await?))
 }
 
 #[axum_macros::debug_handler]
 pub async fn add_item_to_collection(
-    Path((_collection_id, _item_id)): Path<(ID, ID)>,
+    State(state): State<Arc<BusinessRules>>,
+    Path((collection_id, item_id)): Path<(ID, ID)>,
 ) -> Result<Json<CollectionItem>> {
-    todo!()
+    state.add_item_to_collection(item_id, collection_id).await?;
+    Ok(Json(CollectionItem {
+        collection_id,
+        item_id,
+    }))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_items_in_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Query(ownership): Query<OwnershipFilter>,
+) -> Result<Json<Vec<Item>>> {
+    Ok(Json(state.get_items_in_collection(collection_id, &ownership).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn collection_stats(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+) -> Result<Json<CollectionStats>> {
+    Ok(Json(state.collection_stats(collection_id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_collection_targets(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+) -> Result<Json<Vec<CollectionTarget>>> {
+    Ok(Json(state.get_collection_targets(collection_id).await?))
 }
 
 #[axum_macros::debug_handler]
-pub async fn get_items_in_collection(Path(_collection_id): Path<ID>) -> Result<Json<Vec<Item>>> {
-    todo!()
+pub async fn set_collection_targets(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Json(targets): Json<Vec<TargetEntry>>,
+) -> Result<Json<Vec<CollectionTarget>>> {
+    Ok(Json(state.set_collection_targets(collection_id, targets).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn acquire_collection_target(
+    State(state): State<Arc<BusinessRules>>,
+    Path((collection_id, target_id)): Path<(ID, ID)>,
+    Json(acquire): Json<AcquireTarget>,
+) -> Result<Json<CollectionTarget>> {
+    Ok(Json(state.acquire_collection_target(collection_id, target_id, acquire).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn export_collection_pdf(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+) -> Result<impl IntoResponse> {
+    let pdf = state.export_collection_pdf(collection_id).await?;
+    Ok(([(header::CONTENT_TYPE, "application/pdf")], pdf))
+}
+
+#[axum_macros::debug_handler]
+pub async fn export_collection_bundle(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+) -> Result<Json<CollectionBundle>> {
+    Ok(Json(state.export_collection_bundle(collection_id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn import_collection_bundle(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<DryRunQuery>,
+    Json(bundle): Json<CollectionBundle>,
+) -> Result<Json<BundleImportResult>> {
+    Ok(Json(state.import_collection_bundle(bundle, query.dry_run).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_label(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Query(query): Query<LabelQuery>,
+) -> Result<impl IntoResponse> {
+    let format = query.format.unwrap_or_default();
+    let label = state.item_label(id, format, query.size()?).await?;
+    Ok(([(header::CONTENT_TYPE, format.content_type())], label))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_collection_labels(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Query(query): Query<LabelQuery>,
+) -> Result<impl IntoResponse> {
+    let format = query.format.unwrap_or_default();
+    let labels = state
+        .collection_labels(collection_id, format, query.size()?)
+        .await?;
+    Ok(([(header::CONTENT_TYPE, format.content_type())], labels))
+}
+
+#[axum_macros::debug_handler]
+pub async fn reorder_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Json(item_ids): Json<Vec<ID>>,
+) -> Result<()> {
+    state.reorder_collection(collection_id, item_ids).await
 }
 
 #[axum_macros::debug_handler]
 pub async fn remove_item_from_collection(
-    Path((_collection_id, _item_id)): Path<(ID, ID)>,
+    State(state): State<Arc<BusinessRules>>,
+    Path((collection_id, item_id)): Path<(ID, ID)>,
 ) -> Result<Json<CollectionItem>> {
-    todo!()
+    state.remove_item_from_collection(item_id, collection_id).await?;
+    Ok(Json(CollectionItem {
+        collection_id,
+        item_id,
+    }))
+}
+
+#[axum_macros::debug_handler]
+pub async fn new_webhook(
+    State(state): State<Arc<BusinessRules>>,
+    Json(webhook): Json<Webhook>,
+) -> Result<Json<Webhook>> {
+    Ok(Json(state.new_webhook(webhook).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_all_webhooks(State(state): State<Arc<BusinessRules>>) -> Result<Json<Vec<Webhook>>> {
+    Ok(Json(state.get_all_webhooks().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_webhook(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Webhook>> {
+    Ok(Json(state.delete_webhook(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_webhook_deliveries(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<WebhookDelivery>>> {
+    Ok(Json(state.get_webhook_deliveries(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn search_feedback(
+    State(state): State<Arc<BusinessRules>>,
+    Json(feedback): Json<SearchFeedback>,
+) -> Result<()> {
+    state.search_feedback(feedback).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn search_analytics(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<SearchAnalytics>> {
+    Ok(Json(state.search_analytics().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_metrics() -> String {
+    metrics::render()
+}
+
+/// Readiness of the service: 503 while the database can't be reached or its breaker is open
+#[axum_macros::debug_handler]
+pub async fn get_readiness(State(state): State<Arc<BusinessRules>>) -> Response {
+    let (db_ready, report) = state.db_readiness().await;
+    let index = state.index_readiness();
+    let ready = db_ready && index.ready;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({ "ready": ready, "database": report, "search_index": index });
+    (status, Json(body)).into_response()
+}
+
+#[axum_macros::debug_handler]
+pub async fn daily_diff(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<DailyDiffQuery>,
+) -> Result<Json<DailyDiff>> {
+    Ok(Json(state.daily_diff(query.day.as_deref()).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn verify_audit_log(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<AuditVerification>> {
+    Ok(Json(state.verify_audit_chain().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_feature_flags(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<Vec<FeatureFlag>>> {
+    Ok(Json(state.feature_flags().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_feature_flag(
+    State(state): State<Arc<BusinessRules>>,
+    Path(flag): Path<String>,
+    Json(flag_override): Json<FlagOverride>,
+) -> Result<Json<Vec<FeatureFlag>>> {
+    Ok(Json(state.set_feature_flag(&flag, flag_override).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn compact_index(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<IndexCompaction>> {
+    Ok(Json(state.compact_index().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn start_embedding(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<(StatusCode, Json<EmbeddingJob>)> {
+    Ok((StatusCode::ACCEPTED, Json(state.start_embedding().await?)))
+}
+
+#[axum_macros::debug_handler]
+pub async fn embedding_status(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<EmbeddingStatus>> {
+    Ok(Json(state.embedding_status().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn storage_usage(State(state): State<Arc<BusinessRules>>) -> Result<Json<StorageUsage>> {
+    Ok(Json(state.storage_usage().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_history(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<AuditEntry>>> {
+    Ok(Json(state.get_item_history(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn new_user(
+    State(state): State<Arc<BusinessRules>>,
+    Json(user): Json<NewUser>,
+) -> Result<Json<User>> {
+    Ok(Json(state.new_user(user).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn login(
+    State(state): State<Arc<BusinessRules>>,
+    Json(credentials): Json<Credentials>,
+) -> Result<impl IntoResponse> {
+    let (user, cookie) = state.login(credentials).await?;
+    Ok(([(header::SET_COOKIE, session_cookie_header(&state, &cookie))], Json(user)))
+}
+
+#[axum_macros::debug_handler]
+pub async fn refresh_session(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let cookie = session_cookie(&headers).ok_or_else(|| {
+        CustError::new("not logged in".to_string(), StatusCode::UNAUTHORIZED)
+    })?;
+    let cookie = state.refresh_session(cookie).await?;
+    Ok([(header::SET_COOKIE, session_cookie_header(&state, &cookie))])
+}
+
+#[axum_macros::debug_handler]
+pub async fn logout(
+    State(state): State<Arc<BusinessRules>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    if let Some(cookie) = session_cookie(&headers) {
+        state.logout(cookie).await?;
+    }
+    Ok([(
+        header::SET_COOKIE,
+        format!("{}=; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age=0", SESSION_COOKIE),
+    )])
+}
+
+fn session_cookie_header(state: &BusinessRules, cookie: &str) -> String {
+    format!(
+        "{}={}; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age={}",
+        SESSION_COOKIE,
+        cookie,
+        state.session_ttl_secs()
+    )
+}
+
+#[axum_macros::debug_handler]
+pub async fn category_valuations(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<ValuationQuery>,
+) -> Result<Json<Vec<Valuation>>> {
+    Ok(Json(state.category_valuations(query.locale.as_deref()).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn collection_valuations(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<ValuationQuery>,
+) -> Result<Json<Vec<Valuation>>> {
+    Ok(Json(state.collection_valuations(query.locale.as_deref()).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn insurance_report(
+    State(state): State<Arc<BusinessRules>>,
+    Query(query): Query<InsuranceReportQuery>,
+) -> Result<Response> {
+    match query.format {
+        ReportFormat::Json => Ok(Json(state.insurance_report(&query).await?).into_response()),
+        ReportFormat::Pdf => {
+            let pdf = state.insurance_report_pdf(&query).await?;
+            Ok(([(header::CONTENT_TYPE, "application/pdf")], pdf).into_response())
+        }
+    }
+}
+
+#[axum_macros::debug_handler]
+pub async fn add_item_image(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(image): Json<NewItemImage>,
+) -> Result<Json<ItemImage>> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(image.image)?;
+    Ok(Json(
+        state
+            .add_item_image(id, bytes, image.filename, image.caption, image.primary)
+            .await?,
+    ))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_images(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<ItemImage>>> {
+    Ok(Json(state.get_item_images(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_image(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, image_id)): Path<(ID, ID)>,
+) -> Result<Json<ItemImage>> {
+    Ok(Json(state.get_item_image(id, image_id).await?))
+}
+
+/// The full size image as a file, with its content type and original name
+#[axum_macros::debug_handler]
+pub async fn download_item_image(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, image_id)): Path<(ID, ID)>,
+) -> Result<Response> {
+    let download = state.download_item_image(id, image_id).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, download.mime_type),
+            (header::CONTENT_DISPOSITION, content_disposition(&download.filename)),
+            (header::ETAG, format!("\"{}\"", download.sha256)),
+        ],
+        download.bytes,
+    )
+        .into_response())
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_item_image(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, image_id)): Path<(ID, ID)>,
+) -> Result<Json<ItemImage>> {
+    Ok(Json(state.delete_item_image(id, image_id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn reorder_item_images(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(image_ids): Json<Vec<ID>>,
+) -> Result<()> {
+    state.reorder_item_images(id, image_ids).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_primary_item_image(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, image_id)): Path<(ID, ID)>,
+) -> Result<()> {
+    state.set_primary_item_image(id, image_id).await
+}
+
+#[axum_macros::debug_handler]
+pub async fn seed_demo(
+    State(state): State<Arc<BusinessRules>>,
+    Json(seed): Json<SeedDemo>,
+) -> Result<Json<DemoSummary>> {
+    let items = seed.items.unwrap_or(DEFAULT_DEMO_ITEMS);
+    Ok(Json(state.seed_demo(items).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn reload_config(State(state): State<Arc<BusinessRules>>) -> Result<()> {
+    state.config().reload().map(|_| ())
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_collection_visibility(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Json(visibility): Json<Visibility>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.set_collection_public(collection_id, visibility.public).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn clone_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Query(query): Query<CloneCollectionQuery>,
+) -> Result<Json<Collection>> {
+    Ok(Json(
+        state
+            .clone_collection(collection_id, query.name, query.smart_query)
+            .await?,
+    ))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_collection_appearance(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<ID>,
+    Json(appearance): Json<Appearance>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.set_collection_appearance(collection_id, appearance).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_item_appearance(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(appearance): Json<Appearance>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.set_item_appearance(id, appearance).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn move_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Query(query): Query<ItemMove>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.move_item(id, query.container).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_contents(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<Item>>> {
+    Ok(Json(state.get_item_contents(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn favorite_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.favorite_item(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn unfavorite_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Item>> {
+    Ok(Json(state.unfavorite_item(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn favorite_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.favorite_collection(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn unfavorite_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Collection>> {
+    Ok(Json(state.unfavorite_collection(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_favorites(State(state): State<Arc<BusinessRules>>) -> Result<Json<Favorites>> {
+    Ok(Json(state.get_favorites().await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_notes(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<ItemNote>>> {
+    Ok(Json(state.get_item_notes(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn add_item_note(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Json(note): Json<NewItemNote>,
+) -> Result<Json<ItemNote>> {
+    Ok(Json(state.add_item_note(id, note).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn update_item_note(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, note_id)): Path<(ID, ID)>,
+    Json(note): Json<NewItemNote>,
+) -> Result<Json<ItemNote>> {
+    Ok(Json(state.update_item_note(id, note_id, note).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_item_note(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, note_id)): Path<(ID, ID)>,
+) -> Result<Json<ItemNote>> {
+    Ok(Json(state.delete_item_note(id, note_id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_item_translations(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+) -> Result<Json<Vec<ItemTranslation>>> {
+    Ok(Json(state.get_item_translations(id).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn set_item_translation(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, locale)): Path<(ID, String)>,
+    Json(translation): Json<ItemTranslation>,
+) -> Result<Json<ItemTranslation>> {
+    Ok(Json(state.set_item_translation(id, &locale, translation).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_item_translation(
+    State(state): State<Arc<BusinessRules>>,
+    Path((id, locale)): Path<(ID, String)>,
+) -> Result<Json<ItemTranslation>> {
+    Ok(Json(state.delete_item_translation(id, &locale).await?))
+}
+
+#[axum_macros::debug_handler]
+pub async fn estimate_item_value(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<ID>,
+    Query(query): Query<EstimateQuery>,
+) -> Result<Json<ValueEstimate>> {
+    Ok(Json(state.estimate_item_value(id, query).await?))
+}
+
+/// Whether a listing didn't change since the `If-Modified-Since` date the caller sent
+fn not_modified(headers: &HeaderMap, last_modified: Option<i64>) -> bool {
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(util::parse_http_date);
+    matches!((since, last_modified), (Some(since), Some(modified)) if modified <= since)
+}
+
+fn with_last_modified(response: impl IntoResponse, last_modified: Option<i64>) -> Response {
+    match last_modified {
+        Some(modified) => {
+            ([(header::LAST_MODIFIED, util::http_date(modified))], response).into_response()
+        }
+        None => response.into_response(),
+    }
+}
+
+/// Answer to `HEAD` on a listing: its `Last-Modified` date without loading it, for clients
+/// polling for changes
+async fn listing_head(
+    state: &BusinessRules,
+    entity: &str,
+    headers: &HeaderMap,
+) -> Result<Response> {
+    let last_modified = state.last_modified(entity).await?;
+    let status = if not_modified(headers, last_modified) {
+        StatusCode::NOT_MODIFIED
+    } else {
+        StatusCode::OK
+    };
+    let response = (status, [(header::CONTENT_TYPE, "application/json")]);
+    Ok(with_last_modified(response, last_modified))
+}
+
+/// Languages the caller prefers, from its `Accept-Language` header
+fn request_locales(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(util::accepted_locales)
+        .unwrap_or_default()
+}
+
+fn public_ids(state: &BusinessRules) -> IdStrategy {
+    state.config().get().public.ids
+}
+
+/// The integer id in a public api path, anything else is treated like an unknown id.
+fn parse_public_id(id: &str) -> Result<ID> {
+    id.parse()
+        .map_err(|_| CustError::new(format!("{} not found", id), StatusCode::NOT_FOUND))
+}
+
+#[axum_macros::debug_handler]
+pub async fn public_find_items(
+    State(state): State<Arc<BusinessRules>>,
+    Path(name): Path<Name>,
+) -> Result<Json<Vec<PublicItem>>> {
+    let ids = public_ids(&state);
+    let scope = SearchScope {
+        public_only: true,
+        ..Default::default()
+    };
+    let items = state
+        .find_items(
+            name,
+            &MeasurementFilter::default(),
+            &OwnershipFilter::default(),
+            &scope,
+            &SearchOptions::default(),
+        )
+        .await?;
+    Ok(Json(items.into_iter().map(|item| PublicItem::new(item, ids)).collect()))
+}
+
+#[axum_macros::debug_handler]
+pub async fn public_get_item(
+    State(state): State<Arc<BusinessRules>>,
+    Path(id): Path<String>,
+) -> Result<Json<PublicItem>> {
+    let ids = public_ids(&state);
+    let id = match ids {
+        IdStrategy::Integer => parse_public_id(&id)?,
+        IdStrategy::Uuid => state.item_id_by_uuid(&id).await?,
+    };
+    // items outside of public collections are answered like unknown ones
+    if !state.is_shared_publicly(id).await? {
+        return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+    }
+    let item = state.get_item(id).await?;
+    if !OwnershipFilter::default().matches(&item) {
+        return Err(CustError::new("item not found".to_string(), StatusCode::NOT_FOUND));
+    }
+    Ok(Json(PublicItem::new(item, ids)))
+}
+
+#[axum_macros::debug_handler]
+pub async fn public_get_collections(
+    State(state): State<Arc<BusinessRules>>,
+) -> Result<Json<Vec<Collection>>> {
+    let ids = public_ids(&state);
+    let collections = state.get_all_collections().await?;
+    Ok(Json(
+        collections
+            .into_iter()
+            .filter(|c| c.public)
+            .map(|mut c| {
+                if ids == IdStrategy::Uuid {
+                    c.id = None;
+                }
+                c
+            })
+            .collect(),
+    ))
+}
+
+#[axum_macros::debug_handler]
+pub async fn public_get_items_in_collection(
+    State(state): State<Arc<BusinessRules>>,
+    Path(collection_id): Path<String>,
+) -> Result<Json<Vec<PublicItem>>> {
+    let ids = public_ids(&state);
+    let collection_id = match ids {
+        IdStrategy::Integer => parse_public_id(&collection_id)?,
+        IdStrategy::Uuid => state.collection_id_by_uuid(&collection_id).await?,
+    };
+    if !state.get_collection(collection_id).await?.public {
+        return Err(CustError::new("collection not found".to_string(), StatusCode::NOT_FOUND));
+    }
+    let items = state
+        .get_items_in_collection(collection_id, &OwnershipFilter::default())
+        .await?;
+    Ok(Json(items.into_iter().map(|item| PublicItem::new(item, ids)).collect()))
+}
+
+fn invalid_multipart(e: MultipartError) -> CustError {
+    CustError::new(format!("Invalid multipart form: {}", e), StatusCode::BAD_REQUEST)
 }
@@ -0,0 +1,214 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use sha2::{Digest, Sha256};
+
+use crate::{util, CustError, Result, ScanConfig, ScannerKind};
+
+/// Size of the chunks an upload is streamed to clamd in
+const CLAMD_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Outcome of scanning an upload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    /// Flagged, with the name of the signature or rule that matched
+    Flagged(String),
+}
+
+/// Checks uploaded files before they are stored. Scanning may block, it is run on the blocking
+/// thread pool.
+pub trait Scanner: Send + Sync {
+    fn scan(&self, bytes: &[u8]) -> Result<ScanVerdict>;
+}
+
+/// Accepts everything, the default
+pub struct NoScanner;
+
+impl Scanner for NoScanner {
+    fn scan(&self, _bytes: &[u8]) -> Result<ScanVerdict> {
+        Ok(ScanVerdict::Clean)
+    }
+}
+
+/// Streams uploads to a ClamAV daemon with its `INSTREAM` command
+pub struct ClamdScanner {
+    /// `host:port`, or the path of a unix socket
+    address: String,
+    timeout: Duration,
+}
+
+impl ClamdScanner {
+    pub fn new(address: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            address: address.into(),
+            timeout,
+        }
+    }
+
+    fn unavailable(&self, reason: impl std::fmt::Display) -> CustError {
+        CustError::new(
+            format!("content scanner at {} is unavailable: {}", self.address, reason),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+    }
+
+    #[cfg(unix)]
+    fn scan_unix(&self, bytes: &[u8]) -> Result<String> {
+        let mut stream = std::os::unix::net::UnixStream::connect(&self.address)
+            .map_err(|e| self.unavailable(e))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| self.unavailable(e))?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| self.unavailable(e))?;
+        instream(&mut stream, bytes).map_err(|e| self.unavailable(e))
+    }
+
+    #[cfg(not(unix))]
+    fn scan_unix(&self, _bytes: &[u8]) -> Result<String> {
+        Err(self.unavailable("unix sockets are not supported on this platform"))
+    }
+
+    fn scan_tcp(&self, bytes: &[u8]) -> Result<String> {
+        let addr = self
+            .address
+            .to_socket_addrs()
+            .map_err(|e| self.unavailable(e))?
+            .next()
+            .ok_or_else(|| self.unavailable("the address does not resolve"))?;
+        let mut stream =
+            TcpStream::connect_timeout(&addr, self.timeout).map_err(|e| self.unavailable(e))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| self.unavailable(e))?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| self.unavailable(e))?;
+        instream(&mut stream, bytes).map_err(|e| self.unavailable(e))
+    }
+}
+
+impl Scanner for ClamdScanner {
+    fn scan(&self, bytes: &[u8]) -> Result<ScanVerdict> {
+        let reply = if self.address.contains('/') {
+            self.scan_unix(bytes)?
+        } else {
+            self.scan_tcp(bytes)?
+        };
+        parse_clamd_reply(&reply).ok_or_else(|| self.unavailable(reply))
+    }
+}
+
+/// The scanner a deployment configured. Only read on startup.
+pub fn scanner_from_config(config: &ScanConfig) -> Arc<dyn Scanner> {
+    match config.scanner {
+        ScannerKind::None => Arc::new(NoScanner),
+        ScannerKind::Clamd => Arc::new(ClamdScanner::new(
+            config.clamd_address.clone(),
+            Duration::from_secs(config.timeout_secs),
+        )),
+    }
+}
+
+/// Sends `bytes` with the `INSTREAM` command: length prefixed chunks, ended by an empty one.
+fn instream(stream: &mut (impl Read + Write), bytes: &[u8]) -> std::io::Result<String> {
+    stream.write_all(b"zINSTREAM\0")?;
+    for chunk in bytes.chunks(CLAMD_CHUNK_BYTES) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes())?;
+        stream.write_all(chunk)?;
+    }
+    stream.write_all(&0u32.to_be_bytes())?;
+    stream.flush()?;
+
+    let mut reply = vec![];
+    stream.read_to_end(&mut reply)?;
+    Ok(String::from_utf8_lossy(&reply).trim_end_matches('\0').trim().to_owned())
+}
+
+/// `stream: OK` or `stream: <signature> FOUND`, anything else is an error of clamd
+fn parse_clamd_reply(reply: &str) -> Option<ScanVerdict> {
+    let result = reply.strip_prefix("stream:")?.trim();
+    if result == "OK" {
+        return Some(ScanVerdict::Clean);
+    }
+    result
+        .strip_suffix(" FOUND")
+        .map(|signature| ScanVerdict::Flagged(signature.trim().to_owned()))
+}
+
+/// Keeps a flagged upload for inspection, next to a json file saying why it was flagged. The
+/// blob is named after its sha256, so the same file uploaded again is kept once.
+pub async fn quarantine(
+    dir: &Path,
+    bytes: &[u8],
+    filename: Option<&str>,
+    signature: &str,
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let sha256 = hex::encode(Sha256::digest(bytes));
+
+    let blob = dir.join(format!("{}.bin", sha256));
+    tokio::fs::write(&blob, bytes).await?;
+    let metadata = serde_json::json!({
+        "signature": signature,
+        "filename": filename,
+        "size_bytes": bytes.len(),
+        "quarantined_at": util::now(),
+    });
+    tokio::fs::write(dir.join(format!("{}.json", sha256)), metadata.to_string()).await?;
+
+    Ok(blob)
+}
+
+#[cfg(test)]
+mod test_scan {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use super::{parse_clamd_reply, ClamdScanner, ScanVerdict, Scanner};
+
+    #[test]
+    fn clamd_replies() {
+        assert_eq!(parse_clamd_reply("stream: OK"), Some(ScanVerdict::Clean));
+        assert_eq!(
+            parse_clamd_reply("stream: Eicar-Test-Signature FOUND"),
+            Some(ScanVerdict::Flagged("Eicar-Test-Signature".to_owned()))
+        );
+        assert_eq!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR"), None);
+    }
+
+    #[test]
+    fn streams_the_upload_to_clamd() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let clamd = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut command = [0; 10];
+            stream.read_exact(&mut command).unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+
+            let mut received = vec![];
+            loop {
+                let mut len = [0; 4];
+                stream.read_exact(&mut len).unwrap();
+                let len = u32::from_be_bytes(len) as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; len];
+                stream.read_exact(&mut chunk).unwrap();
+                received.extend(chunk);
+            }
+            let reply: &[u8] = if received.starts_with(b"X5O!") {
+                b"stream: Eicar-Test-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            stream.write_all(reply).unwrap();
+        });
+
+        let scanner = ClamdScanner::new(address, Duration::from_secs(5));
+        let verdict = scanner.scan(b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR").unwrap();
+        assert_eq!(verdict, ScanVerdict::Flagged("Eicar-Test-Signature".to_owned()));
+        clamd.join().unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use axum::http::StatusCode;
+
+use crate::{CustError, OwnershipState, Result};
+
+/// A condition of a smart collection: `field:value` terms that all have to match, optionally
+/// joined by `AND`, e.g. `category:Tools AND tag:power`. Values with spaces are quoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmartQuery {
+    pub terms: Vec<SmartTerm>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmartTerm {
+    /// The category with this name or one of its subcategories
+    Category(String),
+    Tag(String),
+    /// The location with this name or one below it
+    Location(String),
+    State(OwnershipState),
+    /// The name contains the value, ignoring case
+    Name(String),
+}
+
+impl SmartQuery {
+    pub fn parse(query: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            CustError::new(format!("invalid smart query: {}", reason), StatusCode::BAD_REQUEST)
+        };
+
+        let mut terms = vec![];
+        for word in split_words(query).map_err(invalid)? {
+            if word == "AND" {
+                continue;
+            }
+            let (field, value) = word
+                .split_once(':')
+                .ok_or_else(|| invalid(format!("`{}` is not a field:value term", word)))?;
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(invalid(format!("{} has no value", field)));
+            }
+            terms.push(match field {
+                "category" => SmartTerm::Category(value.to_owned()),
+                "tag" => SmartTerm::Tag(value.to_lowercase()),
+                "location" => SmartTerm::Location(value.to_owned()),
+                "state" => SmartTerm::State(value.parse()?),
+                "name" => SmartTerm::Name(value.to_owned()),
+                _ => return Err(invalid(format!("unknown field `{}`", field))),
+            });
+        }
+
+        if terms.is_empty() {
+            return Err(invalid("the query has no terms".to_owned()));
+        }
+        Ok(Self { terms })
+    }
+
+    /// SQL condition on `items` matching the query, with the values to bind in order
+    pub fn to_sql(&self) -> (String, Vec<String>) {
+        let mut conditions = vec![];
+        let mut values = vec![];
+        for term in &self.terms {
+            let (condition, value) = match term {
+                SmartTerm::Category(name) => (
+                    r#"items.category_id IN (
+                        WITH RECURSIVE tree(id) AS (
+                            SELECT id FROM categories WHERE lower(name) = lower(?)
+                            UNION
+                            SELECT categories.id FROM categories
+                            JOIN tree ON categories.parent_category = tree.id
+                        )
+                        SELECT id FROM tree
+                    )"#,
+                    name.clone(),
+                ),
+                SmartTerm::Tag(tag) => (
+                    "items.id IN (SELECT item_id FROM item_tags WHERE tag = ?)",
+                    tag.clone(),
+                ),
+                SmartTerm::Location(name) => (
+                    r#"items.location_id IN (
+                        WITH RECURSIVE tree(id) AS (
+                            SELECT id FROM locations WHERE lower(name) = lower(?)
+                            UNION
+                            SELECT locations.id FROM locations
+                            JOIN tree ON locations.parent_location = tree.id
+                        )
+                        SELECT id FROM tree
+                    )"#,
+                    name.clone(),
+                ),
+                SmartTerm::State(state) => ("items.ownership_state = ?", state.as_str().to_owned()),
+                SmartTerm::Name(name) => ("instr(lower(items.name), lower(?)) > 0", name.clone()),
+            };
+            conditions.push(condition);
+            values.push(value);
+        }
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// Splits at whitespace outside of double quotes, removing the quotes
+fn split_words(query: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err("unbalanced quotes".to_owned());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test_smart {
+    use super::{SmartQuery, SmartTerm};
+    use crate::OwnershipState;
+
+    #[test]
+    fn terms_are_parsed() {
+        let query = SmartQuery::parse(r#"category:"Power tools" AND tag:Cordless state:owned"#);
+        assert_eq!(
+            query.unwrap().terms,
+            [
+                SmartTerm::Category("Power tools".to_owned()),
+                SmartTerm::Tag("cordless".to_owned()),
+                SmartTerm::State(OwnershipState::Owned),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        assert!(SmartQuery::parse("").is_err());
+        assert!(SmartQuery::parse("AND").is_err());
+        assert!(SmartQuery::parse("drill").is_err());
+        assert!(SmartQuery::parse("color:red").is_err());
+        assert!(SmartQuery::parse("tag:").is_err());
+        assert!(SmartQuery::parse("state:lost").is_err());
+        assert!(SmartQuery::parse(r#"name:"open"#).is_err());
+    }
+}
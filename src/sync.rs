@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::find_me_pls::v2;
+use crate::{Category, CustError, Item, Location, Result, ID};
+
+/// Node of the changes made through the other apis, clients can't push under this name
+pub const SERVER_NODE: &str = "server";
+
+/// Changes sent per pull if the client doesn't ask for a number
+pub const DEFAULT_SYNC_LIMIT: u32 = 500;
+pub const MAX_SYNC_LIMIT: u32 = 5000;
+
+/// Number of changes per node that lead to the state of an item. Comparing two vectors tells
+/// whether one state was derived from the other or both were changed independently.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector(pub BTreeMap<String, u64>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    /// The other state includes all changes of this one and more
+    Before,
+    /// This state includes all changes of the other one and more
+    After,
+    /// Both states have changes the other one lacks
+    Concurrent,
+}
+
+impl VersionVector {
+    fn counter(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+
+    pub fn increment(&mut self, node: &str) {
+        *self.0.entry(node.to_owned()).or_default() += 1;
+    }
+
+    pub fn compare(&self, other: &Self) -> Causality {
+        let nodes: BTreeSet<&String> = self.0.keys().chain(other.0.keys()).collect();
+        let (mut behind, mut ahead) = (false, false);
+        for node in nodes {
+            let (mine, theirs) = (self.counter(node), other.counter(node));
+            behind |= mine < theirs;
+            ahead |= mine > theirs;
+        }
+
+        match (behind, ahead) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    /// The version including the changes of both
+    pub fn merge(&self, other: &Self) -> Self {
+        let mut merged = self.clone();
+        for (node, counter) in &other.0 {
+            let entry = merged.0.entry(node.clone()).or_default();
+            *entry = (*entry).max(*counter);
+        }
+        merged
+    }
+}
+
+/// What happens to a pushed change of an item the server already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The pushed state replaces the server's
+    Apply,
+    /// The server's state stays, the client has to take it over
+    Keep,
+    /// The server already has exactly this state, e.g. because a push was retried
+    Duplicate,
+}
+
+/// A change that includes the server's state is applied, a stale one is not. Concurrent changes
+/// are resolved by last writer wins on `modified_at`, ties keep the server's state.
+pub fn resolve(
+    server: &VersionVector,
+    server_modified_at: i64,
+    pushed: &VersionVector,
+    pushed_modified_at: i64,
+) -> Resolution {
+    match pushed.compare(server) {
+        Causality::Equal => Resolution::Duplicate,
+        Causality::After => Resolution::Apply,
+        Causality::Before => Resolution::Keep,
+        Causality::Concurrent if pushed_modified_at > server_modified_at => Resolution::Apply,
+        Causality::Concurrent => Resolution::Keep,
+    }
+}
+
+/// The position of a client in the replication log, opaque to clients
+pub fn sync_token(seq: i64) -> String {
+    seq.to_string()
+}
+
+pub fn parse_sync_token(token: &str) -> Result<i64> {
+    token
+        .parse()
+        .ok()
+        .filter(|seq: &i64| *seq >= 0)
+        .ok_or_else(|| {
+            CustError::new(format!("invalid sync token {}", token), StatusCode::BAD_REQUEST)
+        })
+}
+
+/// Whether `uuid` is a UUID in its hyphenated form, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`
+pub fn is_uuid(uuid: &str) -> bool {
+    uuid.len() == 36
+        && uuid.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// An item as exchanged with offline clients, identified by a uuid the client generated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncItem {
+    pub uuid: String,
+    /// Not set for deleted items, images are not synced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<Item>,
+    #[serde(default)]
+    pub version: VersionVector,
+    #[serde(default)]
+    pub deleted: bool,
+    /// Unix seconds of the change, the later one wins when changes are concurrent
+    pub modified_at: i64,
+}
+
+/// `?token=<token>&limit=<n>`, without a token everything is sent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncPullQuery {
+    pub token: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Changes since the token of a pull. Collections are not synced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncChanges {
+    pub items: Vec<SyncItem>,
+    pub categories: Vec<Category>,
+    pub locations: Vec<Location>,
+    /// Passed to the next pull
+    pub token: String,
+    /// More changes are waiting, the client should pull again right away
+    pub more: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPush {
+    /// Name of the client, whose counter its changes increase
+    pub node: String,
+    pub items: Vec<SyncItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPushResult {
+    pub uuid: String,
+    pub item_id: Option<ID>,
+    /// False when the server's state won, the client should replace its copy with `current`
+    pub applied: bool,
+    pub current: SyncItem,
+}
+
+impl From<v2::SyncItem> for SyncItem {
+    fn from(item: v2::SyncItem) -> Self {
+        Self {
+            uuid: item.uuid,
+            item: item.item.map(Into::into),
+            version: VersionVector(
+                item.version
+                    .into_iter()
+                    .map(|entry| (entry.node, entry.counter))
+                    .collect(),
+            ),
+            deleted: item.deleted,
+            modified_at: item.modified_at,
+        }
+    }
+}
+
+impl From<SyncItem> for v2::SyncItem {
+    fn from(item: SyncItem) -> Self {
+        Self {
+            uuid: item.uuid,
+            item: item.item.map(Into::into),
+            version: item
+                .version
+                .0
+                .into_iter()
+                .map(|(node, counter)| v2::VersionEntry { node, counter })
+                .collect(),
+            deleted: item.deleted,
+            modified_at: item.modified_at,
+        }
+    }
+}
+
+impl From<SyncChanges> for v2::SyncPullResponse {
+    fn from(changes: SyncChanges) -> Self {
+        Self {
+            items: changes.items.into_iter().map(Into::into).collect(),
+            categories: changes.categories.into_iter().map(Into::into).collect(),
+            locations: changes.locations.into_iter().map(Into::into).collect(),
+            token: changes.token,
+            more: changes.more,
+        }
+    }
+}
+
+impl From<v2::SyncPushRequest> for SyncPush {
+    fn from(request: v2::SyncPushRequest) -> Self {
+        Self {
+            node: request.node,
+            items: request.items.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<SyncPushResult> for v2::SyncPushResult {
+    fn from(result: SyncPushResult) -> Self {
+        Self {
+            uuid: result.uuid,
+            item_id: result.item_id,
+            applied: result.applied,
+            current: Some(result.current.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sync {
+    use super::{is_uuid, resolve, Causality, Resolution, VersionVector};
+
+    fn version(entries: &[(&str, u64)]) -> VersionVector {
+        VersionVector(entries.iter().map(|(node, c)| (node.to_string(), *c)).collect())
+    }
+
+    #[test]
+    fn versions_are_compared_per_node() {
+        let server = version(&[("server", 2), ("phone", 1)]);
+
+        assert_eq!(server.compare(&server.clone()), Causality::Equal);
+        assert_eq!(version(&[("server", 2)]).compare(&server), Causality::Before);
+        assert_eq!(version(&[("server", 2), ("phone", 2)]).compare(&server), Causality::After);
+        assert_eq!(version(&[("server", 1), ("phone", 2)]).compare(&server), Causality::Concurrent);
+
+        let merged = version(&[("server", 1), ("tablet", 3)]).merge(&server);
+        assert_eq!(merged, version(&[("server", 2), ("phone", 1), ("tablet", 3)]));
+    }
+
+    #[test]
+    fn concurrent_changes_go_to_the_last_writer() {
+        let server = version(&[("server", 2)]);
+        let pushed = version(&[("server", 1), ("phone", 1)]);
+
+        assert_eq!(resolve(&server, 100, &pushed, 101), Resolution::Apply);
+        assert_eq!(resolve(&server, 100, &pushed, 100), Resolution::Keep);
+        assert_eq!(resolve(&server, 100, &server.clone(), 0), Resolution::Duplicate);
+
+        let mut newer = server.clone();
+        newer.increment("phone");
+        assert_eq!(resolve(&server, 100, &newer, 0), Resolution::Apply);
+    }
+
+    #[test]
+    fn uuids_are_validated() {
+        assert!(is_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!is_uuid("67e5504410b1426f9247bb680e5fe0c8"));
+        assert!(!is_uuid("67e55044-10b1-426f-9247-bb680e5fe0cg"));
+    }
+}
@@ -0,0 +1,160 @@
+use std::task::{Context, Poll};
+
+use axum::extract::MatchedPath;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::future::BoxFuture;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, Tracer};
+use opentelemetry_sdk::Resource;
+use tonic::server::NamedService;
+use tower::Service;
+use tracing::level_filters::LevelFilter;
+use tracing::{info_span, Instrument, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::filter::{Filtered, Targets};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::{CustError, Result, TelemetryConfig};
+
+/// Exports spans to an OTLP collector. SQL statements are events of the spans they ran in.
+pub type TelemetryLayer<S> = Filtered<OpenTelemetryLayer<S, Tracer>, Targets, S>;
+
+/// The layer exporting the spans of requests, `None` unless telemetry is enabled. Only read on
+/// startup.
+pub fn telemetry_layer<S>(config: &TelemetryConfig) -> Result<Option<TelemetryLayer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if !config.enabled {
+        return Ok(None);
+    }
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.endpoint),
+        )
+        .with_trace_config(
+            opentelemetry_sdk::trace::config()
+                .with_sampler(sampler)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    config.service_name.clone(),
+                )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .map_err(|e| {
+            CustError::new(
+                format!("Could not export traces to {}: {}", config.endpoint, e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    // spans of requests and business methods, and the statements sqlx logs at debug
+    let filter = Targets::new()
+        .with_default(LevelFilter::INFO)
+        .with_target("sqlx::query", LevelFilter::DEBUG);
+    Ok(Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter),
+    ))
+}
+
+/// Sends the spans that are not exported yet, before the process exits
+pub fn shutdown_telemetry() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Reads the W3C `traceparent` header, gRPC clients send it as metadata
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Span of a request, continuing the trace of the client if it sent one
+fn request_span(method: &str, route: &str, headers: &HeaderMap) -> Span {
+    let span = info_span!(
+        "request",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        http.method = %method,
+        http.route = %route,
+        http.status_code = tracing::field::Empty,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    span.set_parent(parent);
+    span
+}
+
+/// Traces the requests of the JSON api, named by their route
+pub async fn trace_middleware<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let span = request_span(request.method().as_str(), &route, request.headers());
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+/// Traces the requests of a gRPC service, named by their method
+#[derive(Debug, Clone)]
+pub struct TraceScope<S> {
+    inner: S,
+}
+
+impl<S> TraceScope<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service<Request<B>> for TraceScope<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, core::result::Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span = request_span("POST", request.uri().path(), request.headers());
+        let future = {
+            let _entered = span.enter();
+            self.inner.call(request)
+        };
+        Box::pin(future.instrument(span))
+    }
+}
+
+impl<S: NamedService> NamedService for TraceScope<S> {
+    const NAME: &'static str = S::NAME;
+}
@@ -1,4 +1,6 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
 use axum::http::StatusCode;
 use base64::Engine;
@@ -6,9 +8,15 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use crate::CustError;
+use crate::Role;
+use crate::LabelFormat;
+use crate::LabelSize;
+use crate::SearchBackend;
+use crate::IdStrategy;
 use crate::find_me_pls;
 use crate::Result;
 use crate::Storeable;
+use crate::util;
 
 pub type ID = i32;
 pub type Name = String;
@@ -17,22 +25,56 @@ pub type Price = f32;
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Collection {
     pub id: Option<ID>,
+    /// Public identifier, generated by the server
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
+    /// Stored on disk, not in the collections table
+    #[sqlx(default)]
     pub thumbnail: Option<String>,
+    /// Number of items in the collection, computed when listing
+    #[serde(default)]
+    #[sqlx(default)]
+    pub item_count: i64,
+    /// Listed by the public api
+    #[serde(default)]
+    #[sqlx(default)]
+    pub public: bool,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub kind: CollectionKind,
+    /// Condition the items of a smart collection match, see [`SmartQuery`](crate::SmartQuery)
+    #[serde(default)]
+    #[sqlx(default, rename = "smart_query")]
+    pub query: Option<String>,
 }
 
-impl From<find_me_pls::Collection> for Collection {
-    fn from(collection: find_me_pls::Collection) -> Self {
+impl From<find_me_pls::v1::Collection> for Collection {
+    fn from(collection: find_me_pls::v1::Collection) -> Self {
         Self {
             id: collection.id,
+            uuid: None,
             name: collection.name,
             thumbnail: collection.thumbnail
                 .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            item_count: 0,
+            public: false,
+            color: None,
+            icon: None,
+            kind: CollectionKind::Static,
+            query: None,
         }
     }
 }
 
-impl From<Collection> for find_me_pls::Collection {
+impl From<Collection> for find_me_pls::v1::Collection {
     fn from(collection: Collection) -> Self {
         let thumbnail = match collection.thumbnail {
             Some(thumbnail) => match base64::engine::general_purpose::STANDARD.decode(thumbnail) {
@@ -49,6 +91,48 @@ impl From<Collection> for find_me_pls::Collection {
     }
 }
 
+impl From<find_me_pls::v2::Collection> for Collection {
+    fn from(collection: find_me_pls::v2::Collection) -> Self {
+        Self {
+            id: collection.id,
+            uuid: collection.uuid,
+            name: collection.name,
+            thumbnail: collection
+                .thumbnail
+                .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            item_count: 0,
+            public: false,
+            color: collection.color,
+            icon: collection.icon.and_then(|icon| icon.parse().ok()),
+            kind: collection.kind.and_then(|kind| kind.parse().ok()).unwrap_or_default(),
+            query: collection.query,
+        }
+    }
+}
+
+impl From<Collection> for find_me_pls::v2::Collection {
+    fn from(collection: Collection) -> Self {
+        let item_count = collection.item_count;
+        let uuid = collection.uuid.clone();
+        let color = collection.color.clone();
+        let icon = collection.icon.map(|icon| icon.as_str().to_owned());
+        let kind = Some(collection.kind.as_str().to_owned());
+        let query = collection.query.clone();
+        let collection: find_me_pls::v1::Collection = collection.into();
+        Self {
+            id: collection.id,
+            uuid,
+            name: collection.name,
+            thumbnail: collection.thumbnail,
+            item_count,
+            color,
+            icon,
+            kind,
+            query,
+        }
+    }
+}
+
 impl Storeable for Collection {
     fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>> {
         Ok(match &self.thumbnail {
@@ -81,16 +165,66 @@ pub struct CollectionItem {
     pub item_id: ID,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Location {
+    pub id: Option<ID>,
+    /// Public identifier, generated by the server
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
+    pub name: Name,
+    pub parent_location: Option<ID>,
+}
+
+impl From<find_me_pls::v2::Location> for Location {
+    fn from(location: find_me_pls::v2::Location) -> Self {
+        Self {
+            id: location.id,
+            uuid: location.uuid,
+            name: location.name,
+            parent_location: location.parent_location,
+        }
+    }
+}
+
+impl From<Location> for find_me_pls::v2::Location {
+    fn from(location: Location) -> Self {
+        Self {
+            id: location.id,
+            uuid: location.uuid,
+            name: location.name,
+            parent_location: location.parent_location,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Category {
     pub id: Option<ID>,
+    /// Public identifier, generated by the server
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
     pub parent_category: Option<ID>,
     pub thumbnail: Option<String>,
+    /// Reject items whose normalized name already exists in this category
+    #[serde(default)]
+    pub unique_item_names: bool,
+    /// Number of items in the category and all of its subcategories, computed when listing
+    #[serde(default)]
+    #[sqlx(default)]
+    pub item_count: i64,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
 }
 
-impl From<find_me_pls::Category> for Category {
-    fn from(category: find_me_pls::Category) -> Self {
+impl From<find_me_pls::v1::Category> for Category {
+    fn from(category: find_me_pls::v1::Category) -> Self {
         Self {
             id: category.id,
             name: category.name,
@@ -98,11 +232,16 @@ impl From<find_me_pls::Category> for Category {
             thumbnail: category
                 .thumbnail
                 .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            unique_item_names: false,
+            item_count: 0,
+            uuid: None,
+            color: None,
+            icon: None,
         }
     }
 }
 
-impl From<Category> for find_me_pls::Category {
+impl From<Category> for find_me_pls::v1::Category {
     fn from(category: Category) -> Self {
         let thumbnail = match category.thumbnail {
             Some(thumbnail) => match base64::engine::general_purpose::STANDARD.decode(thumbnail) {
@@ -121,6 +260,46 @@ impl From<Category> for find_me_pls::Category {
     }
 }
 
+impl From<find_me_pls::v2::Category> for Category {
+    fn from(category: find_me_pls::v2::Category) -> Self {
+        Self {
+            id: category.id,
+            name: category.name,
+            parent_category: category.parent_category,
+            thumbnail: category
+                .thumbnail
+                .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            unique_item_names: category.unique_item_names,
+            item_count: 0,
+            uuid: category.uuid,
+            color: category.color,
+            icon: category.icon.and_then(|icon| icon.parse().ok()),
+        }
+    }
+}
+
+impl From<Category> for find_me_pls::v2::Category {
+    fn from(category: Category) -> Self {
+        let unique_item_names = category.unique_item_names;
+        let item_count = category.item_count;
+        let uuid = category.uuid.clone();
+        let color = category.color.clone();
+        let icon = category.icon.map(|icon| icon.as_str().to_owned());
+        let category: find_me_pls::v1::Category = category.into();
+        Self {
+            uuid,
+            id: category.id,
+            name: category.name,
+            parent_category: category.parent_category,
+            thumbnail: category.thumbnail,
+            unique_item_names,
+            item_count,
+            color,
+            icon,
+        }
+    }
+}
+
 impl Storeable for Category {
     fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>> {
         Ok(match &self.thumbnail {
@@ -147,19 +326,691 @@ impl Storeable for Category {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LengthUnit {
+    Mm,
+    #[default]
+    Cm,
+    M,
+    In,
+    Ft,
+}
+
+impl LengthUnit {
+    fn cm_per_unit(self) -> f64 {
+        match self {
+            Self::Mm => 0.1,
+            Self::Cm => 1.0,
+            Self::M => 100.0,
+            Self::In => 2.54,
+            Self::Ft => 30.48,
+        }
+    }
+}
+
+/// A length in any supported unit. Lengths are stored in centimetres, the unit defaults to cm.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Length {
+    pub value: f64,
+    #[serde(default)]
+    pub unit: LengthUnit,
+}
+
+impl Length {
+    pub fn cm(value: f64) -> Self {
+        Self {
+            value,
+            unit: LengthUnit::Cm,
+        }
+    }
+
+    pub fn to_cm(self) -> f64 {
+        self.value * self.unit.cm_per_unit()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeightUnit {
+    G,
+    #[default]
+    Kg,
+    Oz,
+    Lb,
+}
+
+impl WeightUnit {
+    fn kg_per_unit(self) -> f64 {
+        match self {
+            Self::G => 0.001,
+            Self::Kg => 1.0,
+            Self::Oz => 0.028_349_523_125,
+            Self::Lb => 0.453_592_37,
+        }
+    }
+}
+
+/// A weight in any supported unit. Weights are stored in kilograms, the unit defaults to kg.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Weight {
+    pub value: f64,
+    #[serde(default)]
+    pub unit: WeightUnit,
+}
+
+impl Weight {
+    pub fn kg(value: f64) -> Self {
+        Self {
+            value,
+            unit: WeightUnit::Kg,
+        }
+    }
+
+    pub fn to_kg(self) -> f64 {
+        self.value * self.unit.kg_per_unit()
+    }
+}
+
+/// Whether an item is in the inventory, or only wanted, on its way or already gone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum OwnershipState {
+    #[default]
+    Owned,
+    Wishlist,
+    Ordered,
+    Disposed,
+}
+
+impl OwnershipState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OwnershipState::Owned => "owned",
+            OwnershipState::Wishlist => "wishlist",
+            OwnershipState::Ordered => "ordered",
+            OwnershipState::Disposed => "disposed",
+        }
+    }
+}
+
+impl FromStr for OwnershipState {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "owned" => Ok(OwnershipState::Owned),
+            "wishlist" => Ok(OwnershipState::Wishlist),
+            "ordered" => Ok(OwnershipState::Ordered),
+            "disposed" => Ok(OwnershipState::Disposed),
+            _ => Err(CustError::new(
+                format!("unknown ownership state: {}", s),
+                StatusCode::BAD_REQUEST,
+            )),
+        }
+    }
+}
+
+/// Whether the items of a collection are added by hand or computed from a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum CollectionKind {
+    #[default]
+    Static,
+    /// Has every item matching its query, nothing is stored in `collection_items`
+    Smart,
+}
+
+impl CollectionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollectionKind::Static => "static",
+            CollectionKind::Smart => "smart",
+        }
+    }
+}
+
+impl FromStr for CollectionKind {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "static" => Ok(CollectionKind::Static),
+            "smart" => Ok(CollectionKind::Smart),
+            _ => Err(CustError::new(
+                format!("unknown collection kind: {}", s),
+                StatusCode::BAD_REQUEST,
+            )),
+        }
+    }
+}
+
+/// Icon of the tile of an item, category or collection, for clients without a photo to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TileIcon {
+    Box,
+    Tool,
+    Book,
+    Game,
+    Music,
+    Movie,
+    Camera,
+    Computer,
+    Phone,
+    Kitchen,
+    Clothing,
+    Toy,
+    Sport,
+    Garden,
+    Document,
+    Jewelry,
+}
+
+impl TileIcon {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TileIcon::Box => "box",
+            TileIcon::Tool => "tool",
+            TileIcon::Book => "book",
+            TileIcon::Game => "game",
+            TileIcon::Music => "music",
+            TileIcon::Movie => "movie",
+            TileIcon::Camera => "camera",
+            TileIcon::Computer => "computer",
+            TileIcon::Phone => "phone",
+            TileIcon::Kitchen => "kitchen",
+            TileIcon::Clothing => "clothing",
+            TileIcon::Toy => "toy",
+            TileIcon::Sport => "sport",
+            TileIcon::Garden => "garden",
+            TileIcon::Document => "document",
+            TileIcon::Jewelry => "jewelry",
+        }
+    }
+}
+
+impl FromStr for TileIcon {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "box" => Ok(TileIcon::Box),
+            "tool" => Ok(TileIcon::Tool),
+            "book" => Ok(TileIcon::Book),
+            "game" => Ok(TileIcon::Game),
+            "music" => Ok(TileIcon::Music),
+            "movie" => Ok(TileIcon::Movie),
+            "camera" => Ok(TileIcon::Camera),
+            "computer" => Ok(TileIcon::Computer),
+            "phone" => Ok(TileIcon::Phone),
+            "kitchen" => Ok(TileIcon::Kitchen),
+            "clothing" => Ok(TileIcon::Clothing),
+            "toy" => Ok(TileIcon::Toy),
+            "sport" => Ok(TileIcon::Sport),
+            "garden" => Ok(TileIcon::Garden),
+            "document" => Ok(TileIcon::Document),
+            "jewelry" => Ok(TileIcon::Jewelry),
+            _ => Err(CustError::new(format!("unknown icon: {}", s), StatusCode::BAD_REQUEST)),
+        }
+    }
+}
+
+/// Color and icon of a tile, set together
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Appearance {
+    /// Hex color, `#rrggbb` or `#rgb`
+    pub color: Option<String>,
+    pub icon: Option<TileIcon>,
+}
+
+/// Restricts item listings to one ownership state, e.g. `?state=wishlist`. Without a state all
+/// items except disposed ones are listed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OwnershipFilter {
+    pub state: Option<OwnershipState>,
+}
+
+impl OwnershipFilter {
+    pub fn matches(&self, item: &Item) -> bool {
+        match self.state {
+            Some(state) => item.ownership_state == state,
+            None => item.ownership_state != OwnershipState::Disposed,
+        }
+    }
+}
+
+/// Order of item listings, e.g. `?sort=price&dir=desc`. Unset fields fall back to the default
+/// from the config, without any items are listed in insertion order.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ItemSort {
+    pub sort: Option<SortField>,
+    pub dir: Option<SortDirection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Name,
+    Price,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl FromStr for SortField {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(SortField::Name),
+            "price" => Ok(SortField::Price),
+            "created_at" => Ok(SortField::CreatedAt),
+            "updated_at" => Ok(SortField::UpdatedAt),
+            _ => Err(CustError::new(
+                format!("unknown sort field: {}", s),
+                StatusCode::BAD_REQUEST,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl FromStr for SortDirection {
+    type Err = CustError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "asc" => Ok(SortDirection::Asc),
+            "desc" => Ok(SortDirection::Desc),
+            _ => Err(CustError::new(
+                format!("unknown sort direction: {}", s),
+                StatusCode::BAD_REQUEST,
+            )),
+        }
+    }
+}
+
+impl ItemSort {
+    /// ORDER BY clause for the items table. Only built from the enums above, never from user
+    /// input. Ties are broken by id, so pages stay stable.
+    pub fn order_by(&self, default: &ItemSort) -> String {
+        let dir = match self.dir.or(default.dir).unwrap_or_default() {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        };
+        let column = match self.sort.or(default.sort) {
+            Some(SortField::Name) => "name COLLATE NOCASE",
+            Some(SortField::Price) => "price",
+            Some(SortField::CreatedAt) => "created_at",
+            Some(SortField::UpdatedAt) => "updated_at",
+            None => return format!("id {}", dir),
+        };
+        format!("{} {}, id {}", column, dir, dir)
+    }
+}
+
+/// Why and when an item was sold or thrown away
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewDisposal {
+    pub reason: String,
+    /// `YYYY-MM-DD`, today if not set
+    pub date: Option<String>,
+    /// Proceeds of a sale, in the currency of the item
+    pub sale_price: Option<Price>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Disposal {
+    pub item_id: ID,
+    pub reason: String,
+    pub date: String,
+    pub sale_price: Option<Price>,
+    pub created_at: i64,
+}
+
+/// Query of `POST /item/:id/estimate-value`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EstimateQuery {
+    /// EAN or UPC to look the item up by, more precise than its name
+    pub barcode: Option<String>,
+}
+
+/// A market value looked up for an item by a [`crate::PriceProvider`]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ValueEstimate {
+    pub id: ID,
+    pub item_id: ID,
+    pub value: Price,
+    pub currency: String,
+    /// The provider that found the value
+    pub source: String,
+    pub estimated_at: i64,
+    /// Whether it became the current value of the item. Estimates in another currency than the
+    /// one of the item are only recorded.
+    pub applied: bool,
+}
+
+/// Items and collections the caller pinned, most recently pinned first. Favorites belong to the
+/// api token that pinned them, without auth they are shared by everyone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Favorites {
+    pub items: Vec<Item>,
+    pub collections: Vec<Collection>,
+}
+
+/// Reserves an item, e.g. a shared ladder for the weekend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewReservation {
+    /// Unix timestamp at which the reservation ends
+    pub until: i64,
+    /// Who reserves the item while auth is disabled, otherwise the caller is used
+    pub reserved_by: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Reservation {
+    pub item_id: ID,
+    pub reserved_by: String,
+    pub until: i64,
+    pub note: Option<String>,
+    pub created_at: i64,
+}
+
+/// Starts a stock-take of the owned items at a location and the locations below it and/or in a
+/// collection. Without either, all owned items are expected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewStocktake {
+    pub location_id: Option<ID>,
+    pub collection_id: Option<ID>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Stocktake {
+    pub id: ID,
+    pub location_id: Option<ID>,
+    pub collection_id: Option<ID>,
+    pub started_by: String,
+    pub started_at: i64,
+    /// Unset while items can still be confirmed
+    pub ended_at: Option<i64>,
+    /// Items in the scope when the stock-take started
+    #[serde(default)]
+    #[sqlx(default)]
+    pub expected_count: i64,
+    /// Expected items confirmed so far
+    #[serde(default)]
+    #[sqlx(default)]
+    pub confirmed_count: i64,
+}
+
+/// An item found during a stock-take, by its id or the code scanned from its label
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StocktakeScan {
+    pub item_id: Option<ID>,
+    /// The barcode of a label, which is the item id, or the uuid of the item
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StocktakeConfirmation {
+    pub item: Item,
+    /// The item was in the scope of the stock-take when it started
+    pub expected: bool,
+    /// The item was confirmed before, e.g. its label was scanned twice
+    pub already_confirmed: bool,
+}
+
+/// Outcome of a stock-take, available while it is running and after it ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StocktakeReport {
+    pub stocktake: Stocktake,
+    /// Expected but not confirmed
+    pub missing: Vec<Item>,
+    /// Confirmed but not expected, e.g. items stored at the wrong location
+    pub unexpected: Vec<Item>,
+}
+
+/// Lists the items as they were at a point in time, e.g. `?as_of=2024-01-01`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AsOfQuery {
+    /// `YYYY-MM-DD` for the end of that day in UTC, or a unix timestamp
+    pub as_of: Option<String>,
+}
+
+impl AsOfQuery {
+    /// The point in time as a unix timestamp, `None` for the current state
+    pub fn timestamp(&self) -> Result<Option<i64>> {
+        let Some(as_of) = self.as_of.as_deref().map(str::trim) else {
+            return Ok(None);
+        };
+        if let Ok(timestamp) = as_of.parse::<i64>() {
+            return Ok(Some(timestamp));
+        }
+        match util::iso_date_timestamp(as_of) {
+            Some(midnight) => Ok(Some(midnight + 24 * 60 * 60 - 1)),
+            None => Err(CustError::new(
+                format!("as_of must be a YYYY-MM-DD date or a unix timestamp, not {}", as_of),
+                StatusCode::BAD_REQUEST,
+            )),
+        }
+    }
+}
+
+/// Query of `POST /collection/:id/clone`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneCollectionQuery {
+    /// Name of the new collection
+    pub name: Name,
+    /// Clone a smart collection with its query, instead of as a static collection of the items
+    /// it has right now
+    #[serde(default)]
+    pub smart_query: bool,
+}
+
+/// Query of the report of items nobody looked at for a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaleQuery {
+    pub not_accessed_for_days: u32,
+}
+
+impl Default for StaleQuery {
+    fn default() -> Self {
+        Self {
+            not_accessed_for_days: 365,
+        }
+    }
+}
+
+/// Deletes many items at once, either those listed in `ids` or those matching `filter`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkDelete {
+    #[serde(default)]
+    pub ids: Vec<ID>,
+    pub filter: Option<BulkDeleteFilter>,
+    /// Delete in a transaction that is rolled back, to see what would change
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// `?dry_run=true` runs a bulk operation without keeping its changes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DryRunQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Items matching all of the set fields. At least one has to be set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkDeleteFilter {
+    pub category_id: Option<ID>,
+    pub collection_id: Option<ID>,
+    pub state: Option<OwnershipState>,
+}
+
+impl BulkDeleteFilter {
+    pub fn is_empty(&self) -> bool {
+        self.category_id.is_none() && self.collection_id.is_none() && self.state.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDeleteResult {
+    /// Items that exist and were selected
+    pub matched: usize,
+    /// Zero for a dry run
+    pub deleted: usize,
+    pub dry_run: bool,
+    pub item_ids: Vec<ID>,
+    /// What the delete changed, or would have changed for a dry run
+    pub changes: ChangeReport,
+}
+
+/// Rows a bulk operation created, updated and deleted per table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeReport {
+    pub created: BTreeMap<String, u64>,
+    pub updated: BTreeMap<String, u64>,
+    pub deleted: BTreeMap<String, u64>,
+    /// Documents removed from the search index. FTS5 rows follow the items table.
+    pub index_removals: usize,
+}
+
+impl ChangeReport {
+    pub fn record_created(&mut self, table: &str, rows: u64) {
+        Self::record(&mut self.created, table, rows);
+    }
+
+    pub fn record_updated(&mut self, table: &str, rows: u64) {
+        Self::record(&mut self.updated, table, rows);
+    }
+
+    pub fn record_deleted(&mut self, table: &str, rows: u64) {
+        Self::record(&mut self.deleted, table, rows);
+    }
+
+    fn record(counts: &mut BTreeMap<String, u64>, table: &str, rows: u64) {
+        if rows > 0 {
+            *counts.entry(table.to_owned()).or_default() += rows;
+        }
+    }
+}
+
+/// Range filters on the measurements of items, in cm and kg. Items without the filtered
+/// measurement never match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeasurementFilter {
+    pub min_width_cm: Option<f64>,
+    pub max_width_cm: Option<f64>,
+    pub min_height_cm: Option<f64>,
+    pub max_height_cm: Option<f64>,
+    pub min_depth_cm: Option<f64>,
+    pub max_depth_cm: Option<f64>,
+    pub min_weight_kg: Option<f64>,
+    pub max_weight_kg: Option<f64>,
+}
+
+impl MeasurementFilter {
+    pub fn matches(&self, item: &Item) -> bool {
+        fn in_range(value: Option<f64>, min: Option<f64>, max: Option<f64>) -> bool {
+            if min.is_none() && max.is_none() {
+                return true;
+            }
+            match value {
+                Some(value) => {
+                    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+                }
+                None => false,
+            }
+        }
+
+        in_range(item.width.map(Length::to_cm), self.min_width_cm, self.max_width_cm)
+            && in_range(item.height.map(Length::to_cm), self.min_height_cm, self.max_height_cm)
+            && in_range(item.depth.map(Length::to_cm), self.min_depth_cm, self.max_depth_cm)
+            && in_range(item.weight.map(Weight::to_kg), self.min_weight_kg, self.max_weight_kg)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Item {
     pub id: Option<ID>,
+    /// Public identifier, generated by the server
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
     pub name: Name,
     pub description: Option<String>,
     pub category_id: Option<ID>,
     pub price: Option<Price>,
     pub thumbnail: Option<String>,
     pub fullsize: Option<String>,
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+    pub location_id: Option<ID>,
+    pub quantity: Option<i32>,
+    #[sqlx(skip)]
+    pub width: Option<Length>,
+    #[sqlx(skip)]
+    pub height: Option<Length>,
+    #[sqlx(skip)]
+    pub depth: Option<Length>,
+    #[sqlx(skip)]
+    pub weight: Option<Weight>,
+    pub purchase_price: Option<Price>,
+    pub current_value: Option<Price>,
+    /// ISO 4217 code of the purchase price and current value, e.g. `EUR`
+    pub currency: Option<String>,
+    /// Date of purchase as `YYYY-MM-DD`
+    pub purchase_date: Option<String>,
+    #[serde(default)]
+    pub ownership_state: OwnershipState,
+    /// Unix timestamp of the last change of the ownership state
+    pub state_changed_at: Option<i64>,
+    /// Unix timestamps, unset for items created before they were recorded
+    #[serde(default)]
+    pub created_at: Option<i64>,
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    /// Unix timestamp of the last time the item was fetched or chosen from a search result
+    #[serde(default)]
+    #[sqlx(default)]
+    pub last_accessed_at: Option<i64>,
+    /// Text recognized in the primary image, set by the server
+    #[serde(default)]
+    #[sqlx(default)]
+    pub image_text: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub icon: Option<TileIcon>,
+    /// The item this one is packed into, e.g. a toolbox. Set by moving the item, while it is in
+    /// a container it is wherever the container is.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub contained_in_item_id: Option<ID>,
+    /// Where the item is, from the outermost location down to the container it is in. Only set
+    /// in search results.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(skip)]
+    pub location_path: Vec<Name>,
 }
 
-impl From<find_me_pls::Item> for Item {
-    fn from(item: find_me_pls::Item) -> Self {
+impl From<find_me_pls::v1::Item> for Item {
+    fn from(item: find_me_pls::v1::Item) -> Self {
         Self {
             id: item.id,
             name: item.name,
@@ -172,11 +1023,74 @@ impl From<find_me_pls::Item> for Item {
             fullsize: item
                 .fullsize
                 .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            tags: vec![],
+            location_id: None,
+            quantity: None,
+            width: None,
+            height: None,
+            depth: None,
+            weight: None,
+            purchase_price: None,
+            current_value: None,
+            currency: None,
+            purchase_date: None,
+            ownership_state: OwnershipState::Owned,
+            state_changed_at: None,
+            created_at: None,
+            updated_at: None,
+            uuid: None,
+            last_accessed_at: None,
+            image_text: None,
+            color: None,
+            icon: None,
+            contained_in_item_id: None,
+            location_path: vec![],
+        }
+    }
+}
+
+/// What the unauthenticated public api shows of an item. Fields are listed here one by one, so
+/// fields added to [`Item`] stay private until they are added here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicItem {
+    /// Left out when only uuids are public
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<ID>,
+    pub uuid: Option<String>,
+    pub name: Name,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    pub tags: Vec<String>,
+    pub quantity: Option<i32>,
+    pub width: Option<Length>,
+    pub height: Option<Length>,
+    pub depth: Option<Length>,
+    pub weight: Option<Weight>,
+    pub color: Option<String>,
+    pub icon: Option<TileIcon>,
+}
+
+impl PublicItem {
+    pub fn new(item: Item, ids: IdStrategy) -> Self {
+        Self {
+            id: item.id.filter(|_| ids == IdStrategy::Integer),
+            uuid: item.uuid,
+            name: item.name,
+            description: item.description,
+            thumbnail: item.thumbnail,
+            tags: item.tags,
+            quantity: item.quantity,
+            width: item.width,
+            height: item.height,
+            depth: item.depth,
+            weight: item.weight,
+            color: item.color,
+            icon: item.icon,
         }
     }
 }
 
-impl From<Item> for find_me_pls::Item {
+impl From<Item> for find_me_pls::v1::Item {
     fn from(item: Item) -> Self {
         let thumbnail = match item.thumbnail {
             Some(thumbnail) => match base64::engine::general_purpose::STANDARD.decode(thumbnail) {
@@ -206,62 +1120,133 @@ impl From<Item> for find_me_pls::Item {
     }
 }
 
-impl Storeable for Item {
-    fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>> {
-        let mut data = vec![];
-
-        let mut thumbnail_data = match &self.thumbnail {
-            Some(thumbnail) => base64::engine::general_purpose::STANDARD.decode(thumbnail)?,
-            None => vec![],
-        };
-
-        data.extend_from_slice(&thumbnail_data.len().to_le_bytes());
-        data.append(&mut thumbnail_data);
-
-        let mut image_data = match &self.fullsize {
-            Some(fullsize) => base64::engine::general_purpose::STANDARD.decode(fullsize)?,
-            None => vec![],
-        };
-
-        data.extend_from_slice(&image_data.len().to_le_bytes());
-        data.append(&mut image_data);
-
-        Ok(Cow::Owned(data))
+impl From<find_me_pls::v2::Item> for Item {
+    fn from(item: find_me_pls::v2::Item) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            description: item.description,
+            category_id: item.category_id,
+            price: item.price,
+            thumbnail: item
+                .thumbnail
+                .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            fullsize: item
+                .fullsize
+                .map(|t| base64::engine::general_purpose::STANDARD.encode(t)),
+            tags: item.tags,
+            location_id: item.location_id,
+            quantity: item.quantity,
+            width: item.width_cm.map(Length::cm),
+            height: item.height_cm.map(Length::cm),
+            depth: item.depth_cm.map(Length::cm),
+            weight: item.weight_kg.map(Weight::kg),
+            purchase_price: item.purchase_price,
+            current_value: item.current_value,
+            currency: item.currency,
+            purchase_date: item.purchase_date,
+            ownership_state: item
+                .ownership_state
+                .and_then(|state| state.parse().ok())
+                .unwrap_or_default(),
+            state_changed_at: item.state_changed_at,
+            created_at: None,
+            updated_at: None,
+            uuid: item.uuid,
+            last_accessed_at: None,
+            image_text: None,
+            color: item.color,
+            icon: item.icon.and_then(|icon| icon.parse().ok()),
+            contained_in_item_id: None,
+            location_path: vec![],
+        }
     }
+}
 
-    fn change_from_bytes(&mut self, bytes: &[u8]) {
-        // read first 4 to 8 (depending on 64 or 32 system) as the size for the thumbnail data
-        // stream
-        let mut size_bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
-        size_bytes.copy_from_slice(&bytes[0..(usize::BITS / 8) as usize]);
-        let size = usize::from_le_bytes(size_bytes);
-
-        // remove first size bytes as they are only used for the rest size
-        let rest = &bytes[((usize::BITS / 8) as usize)..];
-
-        // read thumbnail data
-        let data = &rest[0..size];
-        let rest = &rest[size..];
-
-        let thumbnail = base64::engine::general_purpose::STANDARD.encode(data);
-        self.thumbnail = Some(thumbnail);
+impl From<Item> for find_me_pls::v2::Item {
+    fn from(item: Item) -> Self {
+        let tags = item.tags.clone();
+        let location_id = item.location_id;
+        let quantity = item.quantity;
+        let width_cm = item.width.map(|l| l.to_cm());
+        let height_cm = item.height.map(|l| l.to_cm());
+        let depth_cm = item.depth.map(|l| l.to_cm());
+        let weight_kg = item.weight.map(|w| w.to_kg());
+        let purchase_price = item.purchase_price;
+        let current_value = item.current_value;
+        let currency = item.currency.clone();
+        let purchase_date = item.purchase_date.clone();
+        let ownership_state = item.ownership_state.as_str().to_owned();
+        let state_changed_at = item.state_changed_at;
+        let uuid = item.uuid.clone();
+        let last_accessed_at = item.last_accessed_at;
+        let image_text = item.image_text.clone();
+        let color = item.color.clone();
+        let icon = item.icon.map(|icon| icon.as_str().to_owned());
+        let item: find_me_pls::v1::Item = item.into();
 
-        // read image size
-        let mut size_bytes: [u8; (usize::BITS / 8) as usize] = [0; (usize::BITS / 8) as usize];
-        size_bytes.copy_from_slice(&rest[0..(usize::BITS / 8) as usize]);
-        let size = usize::from_le_bytes(size_bytes);
+        Self {
+            id: item.id,
+            name: item.name,
+            description: item.description,
+            category_id: item.category_id,
+            price: item.price,
+            thumbnail: item.thumbnail,
+            fullsize: item.fullsize,
+            tags,
+            location_id,
+            quantity,
+            width_cm,
+            height_cm,
+            depth_cm,
+            weight_kg,
+            purchase_price,
+            current_value,
+            currency,
+            purchase_date,
+            ownership_state: Some(ownership_state),
+            state_changed_at,
+            uuid,
+            last_accessed_at,
+            image_text,
+            idempotency_key: None,
+            color,
+            icon,
+        }
+    }
+}
 
-        // define rest without image size
-        let rest = &rest[((usize::BITS / 8) as usize)..];
+impl Storeable for Item {
+    fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>> {
+        Ok(Cow::Owned(encode_images([&self.thumbnail, &self.fullsize])?))
+    }
 
-        // read image data
-        let data = &rest[0..size];
-        let rest = &rest[size..];
+    fn change_from_bytes(&mut self, bytes: &[u8]) {
+        let mut images = decode_images(bytes).into_iter();
+        self.thumbnail = images.next();
+        self.fullsize = images.next();
+    }
 
-        let image = base64::engine::general_purpose::STANDARD.encode(data);
-        self.fullsize = Some(image);
+    fn upgrade_legacy(bytes: &[u8]) -> Result<Vec<u8>> {
+        // the lengths were native usizes, 8 or 4 bytes depending on the platform that wrote the
+        // file. Only the width it was written with splits it into exactly two images.
+        let native = (usize::BITS / 8) as usize;
+        let images = [native, 12 - native]
+            .into_iter()
+            .find_map(|width| split_legacy_images(bytes, width))
+            .ok_or_else(|| {
+                CustError::new(
+                    "the file is not a legacy item file".to_owned(),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
 
-        assert!(rest.len() == 0);
+        let mut data = vec![];
+        for image in images {
+            data.extend_from_slice(&(image.len() as u64).to_le_bytes());
+            data.extend_from_slice(image);
+        }
+        Ok(data)
     }
 
     fn filename<'a>(&'a self) -> Result<Cow<'a, str>> {
@@ -275,20 +1260,223 @@ impl Storeable for Item {
     }
 }
 
+/// One image of the gallery of an item. The primary image is mirrored into the thumbnail and
+/// fullsize fields of the item.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ItemImage {
+    pub id: ID,
+    pub item_id: ID,
+    pub position: i32,
+    pub caption: Option<String>,
+    pub is_primary: bool,
+    pub created_at: i64,
+    /// Name of the uploaded file, if the client sent one
+    pub filename: Option<String>,
+    /// Mime type sniffed from the content. Missing for images stored before it was recorded.
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<i64>,
+    /// Hex encoded sha256 of the full size image
+    pub sha256: Option<String>,
+    #[sqlx(skip)]
+    pub thumbnail: Option<String>,
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fullsize: Option<String>,
+}
+
+impl Storeable for ItemImage {
+    fn as_bytes<'a>(&'a self) -> Result<Cow<'a, Vec<u8>>> {
+        Ok(Cow::Owned(encode_images([&self.thumbnail, &self.fullsize])?))
+    }
+
+    fn change_from_bytes(&mut self, bytes: &[u8]) {
+        let mut images = decode_images(bytes).into_iter();
+        self.thumbnail = images.next();
+        self.fullsize = images.next();
+    }
+
+    fn filename<'a>(&'a self) -> Result<Cow<'a, str>> {
+        Ok(Cow::Owned(format!("{}_{}.dat", self.item_id, self.id)))
+    }
+}
+
+/// Base64 images as stored in item and image files: each one as its length in a little endian
+/// `u64`, followed by its bytes
+fn encode_images(images: [&Option<String>; 2]) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    for image in images {
+        let mut image = match image {
+            Some(image) => base64::engine::general_purpose::STANDARD.decode(image)?,
+            None => vec![],
+        };
+        data.extend_from_slice(&(image.len() as u64).to_le_bytes());
+        data.append(&mut image);
+    }
+    Ok(data)
+}
+
+/// The thumbnail and full size image of a legacy item file whose lengths are `width` bytes wide,
+/// `None` if they don't add up to the file
+fn split_legacy_images(bytes: &[u8], width: usize) -> Option<[&[u8]; 2]> {
+    let mut rest = bytes;
+    let mut images = [&[][..]; 2];
+    for image in &mut images {
+        if rest.len() < width {
+            return None;
+        }
+        let (size, tail) = rest.split_at(width);
+        let size = match *size {
+            [a, b, c, d] => u32::from_le_bytes([a, b, c, d]) as usize,
+            _ => usize::try_from(u64::from_le_bytes(size.try_into().ok()?)).ok()?,
+        };
+        if size > tail.len() {
+            return None;
+        }
+        (*image, rest) = tail.split_at(size);
+    }
+    rest.is_empty().then_some(images)
+}
+
+/// Reverse of [`encode_images`], a truncated file ends with the bytes that are there
+fn decode_images(bytes: &[u8]) -> Vec<String> {
+    let mut rest = bytes;
+    let mut images = vec![];
+    while rest.len() >= 8 {
+        let (size, tail) = rest.split_at(8);
+        let size = u64::from_le_bytes(size.try_into().unwrap_or_default()) as usize;
+        let (image, tail) = tail.split_at(size.min(tail.len()));
+        images.push(base64::engine::general_purpose::STANDARD.encode(image));
+        rest = tail;
+    }
+    images
+}
+
+impl From<ItemImage> for find_me_pls::v2::ItemImage {
+    fn from(image: ItemImage) -> Self {
+        Self {
+            id: image.id,
+            item_id: image.item_id,
+            position: image.position,
+            caption: image.caption,
+            primary: image.is_primary,
+            filename: image.filename,
+            mime_type: image.mime_type,
+            size_bytes: image.size_bytes,
+            sha256: image.sha256,
+            thumbnail: image
+                .thumbnail
+                .and_then(|t| base64::engine::general_purpose::STANDARD.decode(t).ok()),
+        }
+    }
+}
+
+/// Full size image with what is needed to serve it as a file
+#[derive(Debug, Clone)]
+pub struct ImageDownload {
+    pub bytes: Vec<u8>,
+    pub filename: String,
+    pub mime_type: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewItemImage {
+    /// base64 encoded image, the thumbnail is generated from it
+    pub image: String,
+    /// Original name of the file, returned as is in downloads
+    pub filename: Option<String>,
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// A packing or shopping list to search for at once, e.g. `{"queries": ["tent", "stove"]}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSearch {
+    pub queries: Vec<Name>,
+    /// Most matches per query, 3 by default
+    pub limit: Option<usize>,
+}
+
+/// The best matches for one query of a [`BatchSearch`], in the order of the queries. A query
+/// without matches has none. Full size images are left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSearchResult {
+    pub query: Name,
+    pub matches: Vec<Item>,
+}
+
+/// A photo to find similar looking items for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSearch {
+    /// base64 encoded image
+    pub image: String,
+    /// Most results to return
+    pub limit: Option<usize>,
+    /// Largest accepted distance between the image hashes, from 0 (the same picture) to 64
+    pub max_distance: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarItem {
+    pub item: Item,
+    /// Differing bits of the image hashes, lower is more similar
+    pub distance: u32,
+    /// 1.0 for the same picture, 0.0 for the opposite one
+    pub similarity: f64,
+}
+
+/// A text to find items with a similar text for, see [`crate::Embedder`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemanticSearch {
+    pub query: String,
+    /// Most results to return
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemanticMatch {
+    pub item: Item,
+    /// Cosine similarity of the item and the query, 1.0 for the same text
+    pub similarity: f32,
+}
+
 #[cfg(test)]
 mod test_image_to_file {
-    use crate::{Item, Storeable};
+    use crate::{Item, ItemImage, OwnershipState, Storeable};
 
     #[test]
     fn serialize_and_deserialize() {
         let item = Item {
             id: None,
+            uuid: None,
             name: "".to_owned(),
             description: None,
             category_id: None,
             price: None,
             thumbnail: Some("YXNkZg==".to_owned()),
             fullsize: Some("ZmRhcw==".to_owned()),
+            tags: vec![],
+            location_id: None,
+            quantity: None,
+            width: None,
+            height: None,
+            depth: None,
+            weight: None,
+            purchase_price: None,
+            current_value: None,
+            currency: None,
+            purchase_date: None,
+            ownership_state: OwnershipState::Owned,
+            state_changed_at: None,
+            created_at: None,
+            updated_at: None,
+            last_accessed_at: None,
+            image_text: None,
+            color: None,
+            icon: None,
+            contained_in_item_id: None,
+            location_path: vec![],
         };
         let data = item.as_bytes();
         assert!(data.is_ok());
@@ -302,6 +1490,70 @@ mod test_image_to_file {
         assert!(item.thumbnail == item2.thumbnail);
         assert!(item.fullsize == item2.fullsize);
     }
+
+    #[test]
+    fn gallery_image_roundtrip() {
+        let image = ItemImage {
+            id: 2,
+            item_id: 1,
+            position: 0,
+            caption: None,
+            is_primary: true,
+            created_at: 0,
+            filename: None,
+            mime_type: None,
+            size_bytes: None,
+            sha256: None,
+            thumbnail: Some("YXNkZg==".to_owned()),
+            fullsize: Some("ZmRhcw==".to_owned()),
+        };
+        let data = image.as_bytes().unwrap();
+
+        let mut image2 = image.clone();
+        image2.thumbnail = None;
+        image2.fullsize = None;
+
+        image2.change_from_bytes(data.as_ref());
+        assert_eq!(image.thumbnail, image2.thumbnail);
+        assert_eq!(image.fullsize, image2.fullsize);
+        assert_eq!(image2.filename().unwrap(), "1_2.dat");
+    }
+
+    /// An item file as written before the format header, by a platform with `width` byte usizes
+    fn legacy_file(width: usize) -> Vec<u8> {
+        let mut legacy = vec![];
+        for image in [&b"asdf"[..], &b"fdas"[..]] {
+            legacy.extend_from_slice(&(image.len() as u64).to_le_bytes()[..width]);
+            legacy.extend_from_slice(image);
+        }
+        legacy
+    }
+
+    #[test]
+    fn legacy_item_files_of_both_widths_are_upgraded() {
+        for width in [4, 8] {
+            let legacy = legacy_file(width);
+            let data = Item::upgrade_legacy(&legacy).unwrap();
+
+            let mut item = Item::default();
+            item.change_from_bytes(&data);
+            assert_eq!(item.thumbnail.as_deref(), Some("YXNkZg=="));
+            assert_eq!(item.fullsize.as_deref(), Some("ZmRhcw=="));
+            assert!(Item::upgrade_legacy(&legacy[..legacy.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn lengths_are_platform_independent() {
+        let item = Item {
+            thumbnail: Some("YXNkZg==".to_owned()),
+            fullsize: Some(String::new()),
+            ..Default::default()
+        };
+        let data = item.as_bytes().unwrap();
+        assert_eq!(&data[..8], &4u64.to_le_bytes());
+        assert_eq!(&data[12..], &0u64.to_le_bytes());
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -325,3 +1577,848 @@ pub fn description_extract(d: &ItemSearch) -> Vec<&str> {
         None => vec![],
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: Option<ID>,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Comma separated list of event names (e.g. `item.created,item.deleted`) or `*` for all
+    pub events: String,
+}
+
+impl Webhook {
+    pub fn accepts(&self, event: &str) -> bool {
+        self.events
+            .split(',')
+            .map(str::trim)
+            .any(|e| e == "*" || e == event)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuationQuery {
+    /// Locale of the formatted amounts, e.g. `de-DE`
+    pub locale: Option<String>,
+}
+
+/// Purchase cost, current value and sale proceeds of all items of a category or collection in one
+/// currency. Disposed items don't count towards the current value.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Valuation {
+    /// Id of the category or collection, `None` for items without a category
+    pub group_id: Option<ID>,
+    pub name: Option<String>,
+    pub currency: Option<String>,
+    pub item_count: i64,
+    pub total_purchase_price: f64,
+    pub total_current_value: f64,
+    /// Sale prices of disposed items
+    pub total_proceeds: f64,
+    #[sqlx(default)]
+    pub formatted_purchase_price: String,
+    #[sqlx(default)]
+    pub formatted_current_value: String,
+    #[sqlx(default)]
+    pub formatted_proceeds: String,
+}
+
+/// Format of a report, `pdf` for a printable document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Pdf,
+}
+
+/// `?location_id=&category_id=` limit the report to the items below a location or in a category
+/// and its subcategories
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InsuranceReportQuery {
+    #[serde(default)]
+    pub format: ReportFormat,
+    pub location_id: Option<ID>,
+    pub category_id: Option<ID>,
+    /// Locale of the formatted amounts, e.g. `de-DE`
+    pub locale: Option<String>,
+}
+
+/// The owned items with what an insurer asks for after a claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceReport {
+    /// Unix timestamp
+    pub generated_at: i64,
+    pub location_id: Option<ID>,
+    pub category_id: Option<ID>,
+    pub items: Vec<InsuredItem>,
+    /// Purchase cost and current value of the listed items, one entry per currency
+    pub totals: Vec<Valuation>,
+}
+
+/// An item of an [`InsuranceReport`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuredItem {
+    pub id: ID,
+    pub name: Name,
+    pub description: Option<String>,
+    pub category: Option<Name>,
+    /// From the outermost location down to the container the item is in
+    pub location_path: Vec<Name>,
+    pub quantity: Option<i32>,
+    /// `YYYY-MM-DD`
+    pub purchase_date: Option<String>,
+    pub purchase_price: Option<Price>,
+    pub current_value: Option<Price>,
+    pub currency: Option<String>,
+    pub formatted_purchase_price: Option<String>,
+    pub formatted_current_value: Option<String>,
+    /// Images of the item, its gallery or the single image of items without one
+    pub photo_count: i64,
+    /// Base64 encoded thumbnail of the primary image
+    pub thumbnail: Option<String>,
+}
+
+/// Progress of a collection towards its target list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub collection_id: ID,
+    pub item_count: i64,
+    /// Purchase cost and current value of the items, one entry per currency
+    pub value: Vec<Valuation>,
+    pub target_count: i64,
+    /// Targets matched by the name of an item in the collection
+    pub targets_owned: i64,
+    /// `None` while the collection has no target list
+    pub completion_percent: Option<f64>,
+    pub missing_targets: Vec<Name>,
+    /// The target list with the item completing each target
+    pub targets: Vec<CollectionTarget>,
+    /// Most recently added items, newest first
+    pub recent_additions: Vec<RecentAddition>,
+}
+
+/// How the items of a collection are matched against a target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TargetMatch {
+    /// The item has the pattern as its name, ignoring case and whitespace
+    #[default]
+    Name,
+    /// The name of the item contains the pattern, ignoring case and whitespace
+    Contains,
+    /// The item has the pattern as a tag
+    Tag,
+}
+
+/// An entry of the target list of a collection, e.g. one expansion of a game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionTarget {
+    /// Assigned by the server, changes when the target list is replaced
+    #[serde(default)]
+    pub id: Option<ID>,
+    pub name: Name,
+    #[serde(default)]
+    pub match_rule: TargetMatch,
+    /// What `match_rule` looks for, the name of the target if not set
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Id of the entry in an external catalog, e.g. an ISBN. Items tagged with it complete the
+    /// target whatever the match rule, items created by acquiring the target get the tag.
+    #[serde(default)]
+    pub catalog_ref: Option<String>,
+    /// Owned item of the collection completing the target, computed
+    #[serde(default)]
+    pub item_id: Option<ID>,
+    #[serde(default)]
+    pub owned: bool,
+}
+
+impl CollectionTarget {
+    /// Whether an item with `name` and `tags` (normalized) completes the target
+    pub fn matches(&self, name: &str, tags: &[String]) -> bool {
+        let catalog_ref = self.catalog_ref.as_deref().map(|r| r.trim().to_lowercase());
+        if catalog_ref.is_some_and(|r| tags.contains(&r)) {
+            return true;
+        }
+
+        let pattern = self.pattern.as_deref().unwrap_or(&self.name);
+        match self.match_rule {
+            TargetMatch::Name => util::normalize_name(name) == util::normalize_name(pattern),
+            TargetMatch::Contains => {
+                util::normalize_name(name).contains(&util::normalize_name(pattern))
+            }
+            TargetMatch::Tag => tags.contains(&pattern.trim().to_lowercase()),
+        }
+    }
+}
+
+/// An entry of a new target list: the name of an item, or a target with a match rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TargetEntry {
+    Name(Name),
+    Target(CollectionTarget),
+}
+
+impl From<TargetEntry> for CollectionTarget {
+    fn from(entry: TargetEntry) -> Self {
+        match entry {
+            TargetEntry::Name(name) => Self {
+                id: None,
+                name,
+                match_rule: TargetMatch::Name,
+                pattern: None,
+                catalog_ref: None,
+                item_id: None,
+                owned: false,
+            },
+            TargetEntry::Target(target) => target,
+        }
+    }
+}
+
+/// Details of the item created when a target is acquired, its name is that of the target
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcquireTarget {
+    pub purchase_price: Option<Price>,
+    pub currency: Option<String>,
+    /// `YYYY-MM-DD`
+    pub purchase_date: Option<String>,
+    pub category_id: Option<ID>,
+    pub location_id: Option<ID>,
+}
+
+/// Version of the collection bundle format, bumped when old bundles can't be imported anymore
+pub const COLLECTION_BUNDLE_VERSION: u32 = 1;
+
+/// A collection with everything needed to recreate it on another instance. Ids only link the
+/// entries of the bundle to each other, the importing instance assigns new ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionBundle {
+    pub version: u32,
+    pub exported_at: i64,
+    pub collection: Collection,
+    /// Categories of the items, including their parents
+    pub categories: Vec<Category>,
+    /// Locations of the items, including their parents
+    pub locations: Vec<Location>,
+    /// In the order of the collection
+    pub items: Vec<BundleItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleItem {
+    pub item: Item,
+    /// Gallery in display order, with the full size images. Items without a gallery keep their
+    /// image in `item`.
+    #[serde(default)]
+    pub images: Vec<NewItemImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleImportResult {
+    /// `None` for a dry run
+    pub collection: Option<Collection>,
+    pub dry_run: bool,
+    /// What the import created, or would have created for a dry run
+    pub changes: ChangeReport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RecentAddition {
+    pub item_id: ID,
+    pub name: Name,
+    /// Unix timestamp, `None` for items added before it was recorded
+    pub added_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct User {
+    pub id: ID,
+    pub username: String,
+    pub role: Role,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewUser {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A mutation recorded together with the user or token that made it
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: ID,
+    pub actor: String,
+    pub action: String,
+    pub entity: String,
+    pub entity_id: ID,
+    pub created_at: i64,
+    /// Hash of the entry before it, see `GET /admin/audit/verify`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub prev_hash: Option<String>,
+    /// Hash of this entry, covering `prev_hash`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub hash: Option<String>,
+}
+
+/// Whether the hash chain of the audit log is intact
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    /// Number of entries checked
+    pub entries: usize,
+    /// Hash of the latest entry, `None` for an empty log
+    pub head: Option<String>,
+    /// First entry that does not hash to what was recorded for it, or does not link to the
+    /// entry before it
+    pub first_broken_id: Option<ID>,
+    /// Days of the daily snapshots whose audit log head is no longer part of the chain, e.g.
+    /// because entries were removed from the end of the log
+    pub missing_snapshot_heads: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: Option<ID>,
+    pub webhook_id: ID,
+    pub event: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub delivered_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchFeedback {
+    pub query: String,
+    pub item_id: ID,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueryStat {
+    pub query: String,
+    pub count: i64,
+    pub avg_results: f64,
+    pub avg_latency_ms: f64,
+    pub selections: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchAnalytics {
+    pub total_searches: i64,
+    pub top_queries: Vec<QueryStat>,
+    pub zero_hit_queries: Vec<QueryStat>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SeedDemo {
+    /// Number of items, 300 if not set
+    pub items: Option<usize>,
+}
+
+/// What [`crate::BusinessRules::seed_demo`] created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemoSummary {
+    pub categories: usize,
+    pub locations: usize,
+    pub items: usize,
+}
+
+/// Restricts a search to the items of a collection and/or below a location, e.g.
+/// `?collection=5&location=12`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchScope {
+    pub collection: Option<ID>,
+    /// Includes all sublocations
+    pub location: Option<ID>,
+    /// Only items of collections shared publicly, set by the public api
+    #[serde(skip)]
+    pub public_only: bool,
+}
+
+impl SearchScope {
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_none() && self.location.is_none() && !self.public_only
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchOptions {
+    /// Return a [`SearchExplanation`] instead of the items
+    #[serde(default)]
+    pub explain: bool,
+    /// Also match item names with words that start with a word of the query, e.g. `dri` finds
+    /// the drill
+    #[serde(default)]
+    pub prefix: bool,
+    /// Most items returned, `search.result_limit` of the config if not set. 0 returns all.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Lowest ranked score of a returned item, `search.min_score` of the config if not set
+    #[serde(default)]
+    pub min_score: Option<f64>,
+    /// Only run one of the searches, instead of merging both as set in `search.fusion`
+    #[serde(default)]
+    pub source: Option<SearchSource>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSource {
+    /// The text search of `search.backend`
+    Text,
+    /// The vector search of `search.semantic`, fails without a vector backend
+    Vector,
+}
+
+/// Items found by a search, after the limit of the request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHits {
+    pub items: Vec<Item>,
+    /// Matches before the limit was applied
+    pub total: usize,
+}
+
+/// Why a search returned what it did. The index doesn't expose its autocorrect, so token
+/// candidates are the closest words in the text of the matched items.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchExplanation {
+    pub query: String,
+    pub backend: SearchBackend,
+    pub tokens: Vec<TokenExplanation>,
+    /// Matches in ranking order, after filters
+    pub results: Vec<ResultExplanation>,
+    /// Matches removed by the measurement or ownership filters
+    pub filtered_out: usize,
+    pub timings: SearchTimings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenExplanation {
+    pub token: String,
+    /// Closest word of every matched item, nearest first
+    pub candidates: Vec<TokenCandidate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenCandidate {
+    pub item_id: ID,
+    pub word: String,
+    /// Levenshtein distance between the token and the word
+    pub distance: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultExplanation {
+    pub rank: usize,
+    pub item_id: ID,
+    pub name: Name,
+    pub score: f64,
+    /// Closest word of the item for every token of the query
+    pub matches: Vec<TokenMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMatch {
+    pub token: String,
+    pub word: String,
+    pub distance: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchTimings {
+    pub search_ms: f64,
+    pub analysis_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+/// Related data to embed into a fetched item, e.g. `?include=collections,notes`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemInclude {
+    /// Comma separated, `collections` and `notes` are supported
+    pub include: Option<String>,
+}
+
+impl ItemInclude {
+    pub fn collections(&self) -> Result<bool> {
+        self.includes("collections")
+    }
+
+    pub fn notes(&self) -> Result<bool> {
+        self.includes("notes")
+    }
+
+    fn includes(&self, name: &str) -> Result<bool> {
+        let mut included = false;
+        for part in self.include.iter().flat_map(|include| include.split(',')) {
+            match part.trim() {
+                "collections" | "notes" | "" => included |= part.trim() == name,
+                other => {
+                    return Err(CustError::new(
+                        format!("unknown include: {}", other),
+                        StatusCode::BAD_REQUEST,
+                    ))
+                }
+            }
+        }
+        Ok(included)
+    }
+}
+
+/// Name and description of an item in another language than the default locale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ItemTranslation {
+    /// Language tag like `de` or `pt-br`, taken from the path when a translation is set
+    #[serde(default)]
+    pub locale: String,
+    pub name: Name,
+    pub description: Option<String>,
+}
+
+/// A timestamped free-text note on an item, e.g. when its battery was replaced or whom it was
+/// left with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ItemNote {
+    pub id: ID,
+    pub item_id: ID,
+    pub text: String,
+    /// Name of the api token that wrote the note, `None` without auth
+    pub author: Option<String>,
+    pub created_at: i64,
+    /// `None` until the note is edited
+    pub updated_at: Option<i64>,
+}
+
+/// Text of a note to add or to replace the text of a note with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewItemNote {
+    pub text: String,
+}
+
+/// An item with the related data that was asked for with [`ItemInclude`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDetails {
+    #[serde(flatten)]
+    pub item: Item,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collections: Option<Vec<Collection>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<ItemNote>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Visibility {
+    pub public: bool,
+}
+
+/// New name of a category or location
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rename {
+    pub name: Name,
+}
+
+/// `?new_parent=<id>`, without a parent the category becomes a root category
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CategoryMove {
+    pub new_parent: Option<ID>,
+}
+
+/// `?container=<id>` to pack an item into another one, without a container it is taken out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemMove {
+    pub container: Option<ID>,
+}
+
+/// `?format=zpl|png&size=57x32`, the size is in millimeters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LabelQuery {
+    pub format: Option<LabelFormat>,
+    pub size: Option<String>,
+}
+
+impl LabelQuery {
+    pub fn size(&self) -> Result<LabelSize> {
+        match &self.size {
+            Some(size) => size.parse(),
+            None => Ok(LabelSize::default()),
+        }
+    }
+}
+
+/// `?since=<seq>&limit=<n>`, the events after `since` in the order they were recorded
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplicationQuery {
+    #[serde(default)]
+    pub since: i64,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemExportQuery {
+    pub category: Option<ID>,
+    pub collection: Option<ID>,
+    /// Comma separated list of columns, defaults to all columns
+    pub columns: Option<String>,
+}
+
+/// Whether the documents of the search index are loaded, see
+/// [`SearchConfig::warm_up`](crate::SearchConfig::warm_up)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexReadiness {
+    pub ready: bool,
+    /// Documents in the index after the warm-up, including those of deleted items that are not
+    /// compacted yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_seconds: Option<f64>,
+}
+
+/// What a compaction of the search index did, see
+/// [`BusinessRules::compact_index`](crate::BusinessRules::compact_index)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexCompaction {
+    /// Documents of the items in the compacted index
+    pub documents: usize,
+    /// Documents of deleted items that were still in the index
+    pub removed_documents: usize,
+    /// Terms of the vocabulary, always 0 with the FTS5 backend
+    pub terms: usize,
+    /// Terms no item uses anymore, always 0 with the FTS5 backend which prunes them itself
+    pub pruned_terms: usize,
+    pub seconds: f64,
+}
+
+/// A run that embeds all items with one model, see
+/// [`BusinessRules::start_embedding`](crate::BusinessRules::start_embedding)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EmbeddingJob {
+    pub id: ID,
+    /// [`Embedder::model`](crate::Embedder::model) the items are embedded with
+    pub model: String,
+    pub state: EmbeddingJobState,
+    /// Items to embed, 0 until the job starts
+    pub total: i64,
+    pub done: i64,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum EmbeddingJobState {
+    /// Queued or embedding, searches still use the vectors of the previous job
+    Running,
+    /// The vectors of this job replaced the previous ones
+    Completed,
+    /// The previous vectors are kept
+    Failed,
+}
+
+/// Which model the vectors searched right now were embedded with, and the latest jobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingStatus {
+    /// Model of the configured embedder
+    pub model: String,
+    /// Model of the vectors in the backend, `None` until the first job completed. If it differs
+    /// from `model`, a job has to embed the items again.
+    pub embedded_model: Option<String>,
+    /// Newest first
+    pub jobs: Vec<EmbeddingJob>,
+}
+
+/// Flat item metadata as exported to csv
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ItemExportRow {
+    pub id: ID,
+    pub name: Name,
+    pub description: Option<String>,
+    pub category_id: Option<ID>,
+    pub category: Option<String>,
+    pub price: Option<Price>,
+    pub location_id: Option<ID>,
+    pub location: Option<String>,
+    pub quantity: Option<i32>,
+    pub tags: Option<String>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityStorageUsage {
+    pub entity: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemStorageUsage {
+    pub item_id: ID,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub total_bytes: u64,
+    pub entities: Vec<EntityStorageUsage>,
+    /// Items sorted by their disk usage, largest first
+    pub items: Vec<ItemStorageUsage>,
+}
+
+/// Query of `GET /admin/daily-diff`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyDiffQuery {
+    /// `YYYY-MM-DD`, the latest snapshot if missing
+    pub day: Option<String>,
+}
+
+/// Changes of one type of entity between two daily snapshots
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityDiff {
+    /// `items`, `collections`, `categories`, `locations` or `images`
+    pub entity: String,
+    /// Number of entities on the day of the newer snapshot
+    pub count: usize,
+    pub added: usize,
+    pub removed: usize,
+    /// Entities whose content changed, for images the ones whose file was replaced
+    pub changed: usize,
+}
+
+/// What changed in the inventory since the previous daily snapshot, to notice accidental bulk
+/// deletions early
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyDiff {
+    /// UTC day of the snapshot, `YYYY-MM-DD`
+    pub day: String,
+    /// Day of the snapshot it is compared with, `None` for the first one
+    pub previous_day: Option<String>,
+    pub entities: Vec<EntityDiff>,
+    /// e.g. `+12 items, -1 collection, 3 images replaced`
+    pub summary: String,
+}
+
+#[cfg(test)]
+mod test_measurements {
+    use crate::{Item, Length, LengthUnit, MeasurementFilter, Weight, WeightUnit};
+
+    #[test]
+    fn units_are_converted() {
+        let length: Length = serde_json::from_str(r#"{"value": 10, "unit": "in"}"#).unwrap();
+        assert_eq!(length.unit, LengthUnit::In);
+        assert!((length.to_cm() - 25.4).abs() < 1e-9);
+
+        let length: Length = serde_json::from_str(r#"{"value": 12}"#).unwrap();
+        assert_eq!(length.to_cm(), 12.0);
+
+        let weight: Weight = serde_json::from_str(r#"{"value": 500, "unit": "g"}"#).unwrap();
+        assert_eq!(weight.unit, WeightUnit::G);
+        assert!((weight.to_kg() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn filter_requires_measurement_in_range() {
+        let mut item: Item = serde_json::from_str(r#"{"name": "Box"}"#).unwrap();
+        let filter = MeasurementFilter {
+            max_weight_kg: Some(5.0),
+            ..Default::default()
+        };
+
+        assert!(MeasurementFilter::default().matches(&item));
+        assert!(!filter.matches(&item));
+
+        item.weight = Some(Weight::kg(4.0));
+        assert!(filter.matches(&item));
+
+        item.weight = Some(Weight {
+            value: 12.0,
+            unit: WeightUnit::Lb,
+        });
+        assert!(!filter.matches(&item));
+    }
+}
+
+#[cfg(test)]
+mod test_public_item {
+    use crate::{IdStrategy, Item, PublicItem};
+
+    #[test]
+    fn only_listed_fields_are_public() {
+        let item = Item {
+            id: Some(7),
+            uuid: Some("0b6f".to_owned()),
+            name: "drill".to_owned(),
+            price: Some(120.0),
+            image_text: Some("SN 4711".to_owned()),
+            contained_in_item_id: Some(3),
+            last_accessed_at: Some(1),
+            location_path: vec!["House".to_owned(), "Garage".to_owned()],
+            ..Default::default()
+        };
+
+        let public = serde_json::to_value(PublicItem::new(item.clone(), IdStrategy::Integer))
+            .unwrap();
+        assert_eq!(public["id"], 7);
+        assert_eq!(public["name"], "drill");
+        for private in [
+            "price",
+            "image_text",
+            "contained_in_item_id",
+            "last_accessed_at",
+            "location_path",
+            "category_id",
+        ] {
+            assert!(public.get(private).is_none(), "{} is public", private);
+        }
+
+        let public = serde_json::to_value(PublicItem::new(item, IdStrategy::Uuid)).unwrap();
+        assert!(public.get("id").is_none());
+        assert_eq!(public["uuid"], "0b6f");
+    }
+}
+
+#[cfg(test)]
+mod test_item_sort {
+    use crate::{ItemSort, SortDirection, SortField};
+
+    #[test]
+    fn requests_override_the_default_sort() {
+        let default = ItemSort {
+            sort: Some(SortField::UpdatedAt),
+            dir: Some(SortDirection::Desc),
+        };
+        assert_eq!(ItemSort::default().order_by(&ItemSort::default()), "id ASC");
+        assert_eq!(
+            ItemSort::default().order_by(&default),
+            "updated_at DESC, id DESC"
+        );
+
+        let request = ItemSort {
+            sort: Some(SortField::Name),
+            dir: None,
+        };
+        assert_eq!(request.order_by(&default), "name COLLATE NOCASE DESC, id DESC");
+        assert!("name; DROP TABLE items".parse::<SortField>().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
 use crate::{NameError, Result};
 
 
@@ -8,4 +10,441 @@ pub fn sanitize_name(name: &str) -> Result<&str> {
     }
 
     Ok(name)
-}
\ No newline at end of file
+}
+
+/// Current unix timestamp in seconds, as stored in the database.
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Normalizes a search query for grouping in the search log.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Lowercased words of a text, as compared by the search explanation
+pub fn search_tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Words of `texts` that start with `prefix` and are longer than it, shortest first
+pub fn prefix_expansions<'a>(
+    prefix: &str,
+    texts: impl IntoIterator<Item = &'a str>,
+    limit: usize,
+) -> Vec<String> {
+    let mut words: Vec<String> = texts
+        .into_iter()
+        .flat_map(search_tokens)
+        .filter(|word| word.starts_with(prefix) && word != prefix)
+        .collect();
+    words.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    words.dedup();
+    words.truncate(limit);
+    words
+}
+
+/// Number of single character insertions, deletions or substitutions turning `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Turns a free text search into an FTS5 match expression: every word is quoted, so FTS5
+/// operators in the input are matched literally, and matches as a prefix. Any word may match.
+pub fn fts_query(query: &str) -> String {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+/// `?, ?, ...` for an `IN` list of `count` values, `NULL` for an empty one so it matches nothing
+pub fn placeholders(count: usize) -> String {
+    if count == 0 {
+        "NULL".to_owned()
+    } else {
+        format!("?{}", ", ?".repeat(count - 1))
+    }
+}
+
+/// Trims and lowercases tags, dropping empty and duplicate ones.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Normalizes a name for equality checks: case and whitespace are ignored.
+pub fn normalize_name(name: &str) -> String {
+    normalize_query(name)
+}
+
+/// Size of base64 encoded data once decoded, without decoding it.
+pub fn base64_decoded_len(data: &str) -> usize {
+    let padding = data.bytes().rev().take_while(|b| *b == b'=').count();
+    (data.len() / 4 * 3).saturating_sub(padding)
+}
+
+/// 32 random bytes, hex encoded
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Random (version 4) UUID in its hyphenated form
+pub fn new_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// UTC date of a unix timestamp as `YYYY-MM-DD`
+pub fn iso_date(timestamp: i64) -> String {
+    // civil_from_days from http://howardhinnant.github.io/date_algorithms.html
+    let z = timestamp.div_euclid(24 * 60 * 60) + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Unix timestamp of midnight UTC at the start of a `YYYY-MM-DD` date, `None` if it isn't a
+/// valid date.
+pub fn iso_date_timestamp(date: &str) -> Option<i64> {
+    if !is_iso_date(date) {
+        return None;
+    }
+    let mut parts = date.split('-').map(|part| part.parse::<i64>().unwrap_or_default());
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+
+    // days_from_civil from the same source as iso_date
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some((era * 146_097 + day_of_era - 719_468) * 24 * 60 * 60)
+}
+
+/// Whether `date` is a valid calendar date formatted as `YYYY-MM-DD`.
+pub fn is_iso_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return false;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return false;
+    }
+
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse::<u32>(), month.parse::<u32>(), day.parse::<u32>()) else {
+        return false;
+    };
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+
+    (1..=days).contains(&day)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A unix timestamp as HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(timestamp: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    let days = timestamp.div_euclid(24 * 60 * 60);
+    let seconds = timestamp.rem_euclid(24 * 60 * 60);
+    let date = iso_date(timestamp);
+    let month: usize = date[5..7].parse().unwrap_or(1);
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        &date[8..10],
+        MONTHS[month - 1],
+        &date[..4],
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Unix timestamp of an HTTP date as written by [`http_date`], `None` for anything else. The
+/// obsolete formats clients may still send are not understood.
+pub fn parse_http_date(date: &str) -> Option<i64> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    let midnight = iso_date_timestamp(&format!("{}-{:02}-{}", year, month, day))?;
+
+    let mut time = time.split(':').map(|part| part.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(midnight + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// A css hex color as it is stored: `#rrggbb` in lower case. `#rgb` is expanded, anything else
+/// is `None`.
+pub fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_owned(),
+        _ => return None,
+    };
+    Some(format!("#{}", hex.to_ascii_lowercase()))
+}
+
+/// Red, green and blue of a color normalized by [`normalize_color`]
+pub fn color_rgb(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = normalize_color(color)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(1)?, channel(3)?, channel(5)?))
+}
+
+/// A language tag as it is stored, like `de` or `pt-br`: lower case with `-` separators. `None`
+/// if `locale` isn't a language tag.
+pub fn normalize_locale(locale: &str) -> Option<String> {
+    let locale = locale.trim().replace('_', "-").to_ascii_lowercase();
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+    let valid = (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    valid.then_some(locale)
+}
+
+/// Languages of an `Accept-Language` header as normalized tags, the most preferred first. A
+/// regional tag is followed by its language, so `de-AT` falls back to `de`.
+pub fn accepted_locales(accept_language: &str) -> Vec<String> {
+    let mut ranges: Vec<(f32, String)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let locale = normalize_locale(parts.next().unwrap_or_default())?;
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((quality, locale))
+        })
+        .collect();
+    // stable, ties keep the order of the header
+    ranges.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let mut locales: Vec<String> = vec![];
+    for (_, locale) in ranges {
+        let language = locale.split('-').next().unwrap_or_default().to_owned();
+        for locale in [locale, language] {
+            if !locales.contains(&locale) {
+                locales.push(locale);
+            }
+        }
+    }
+    locales
+}
+
+/// Formats an amount of money with the separators and currency placement of a locale, e.g.
+/// `1,234.50 EUR` for `en` and `1.234,50 EUR` for `de-DE`. Unknown locales fall back to `en`.
+pub fn format_money(amount: f64, currency: &str, locale: &str) -> String {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let (group, decimal) = match language.as_str() {
+        "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" => (".", ","),
+        "fr" | "pl" | "cs" | "sv" | "fi" | "nb" | "ru" | "uk" => ("\u{202f}", ","),
+        _ => (",", "."),
+    };
+
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let digits = (cents / 100).to_string();
+    let mut integer = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            integer.push_str(group);
+        }
+        integer.push(c);
+    }
+
+    let sign = if amount < 0.0 && cents > 0 { "-" } else { "" };
+    let number = format!("{}{}{}{:02}", sign, integer, decimal, cents % 100);
+    if currency.is_empty() {
+        number
+    } else {
+        format!("{} {}", number, currency)
+    }
+}
+
+#[cfg(test)]
+mod test_util {
+    use super::{
+        accepted_locales, color_rgb, format_money, fts_query, http_date, is_iso_date, iso_date,
+        iso_date_timestamp, levenshtein, normalize_color, normalize_locale, parse_http_date,
+        placeholders, prefix_expansions, search_tokens,
+    };
+    use proptest::prelude::*;
+
+    #[test]
+    fn money_is_formatted_per_locale() {
+        assert_eq!(format_money(1234.5, "EUR", "en-US"), "1,234.50 EUR");
+        assert_eq!(format_money(1234.5, "EUR", "de-DE"), "1.234,50 EUR");
+        assert_eq!(format_money(1234567.891, "", "fr"), "1\u{202f}234\u{202f}567,89");
+        assert_eq!(format_money(-5.0, "USD", "xx"), "-5.00 USD");
+    }
+
+    #[test]
+    fn colors_are_normalized() {
+        assert_eq!(normalize_color(" #3A7BD5 ").as_deref(), Some("#3a7bd5"));
+        assert_eq!(normalize_color("#fa0").as_deref(), Some("#ffaa00"));
+        assert_eq!(normalize_color("3a7bd5"), None);
+        assert_eq!(normalize_color("#3a7bd"), None);
+        assert_eq!(normalize_color("#ggg"), None);
+        assert_eq!(color_rgb("#3a7bd5"), Some((0x3a, 0x7b, 0xd5)));
+    }
+
+    #[test]
+    fn accepted_locales_fall_back_to_their_language() {
+        assert_eq!(normalize_locale(" pt_BR ").as_deref(), Some("pt-br"));
+        assert_eq!(normalize_locale("german"), None);
+        assert_eq!(normalize_locale("*"), None);
+        assert_eq!(
+            accepted_locales("en;q=0.5, de-AT, fr;q=0, *;q=0.1"),
+            ["de-at", "de", "en"]
+        );
+        assert!(accepted_locales("").is_empty());
+    }
+
+    #[test]
+    fn iso_dates_are_validated() {
+        assert!(is_iso_date("2024-02-29"));
+        assert!(!is_iso_date("2023-02-29"));
+        assert!(!is_iso_date("2024-13-01"));
+        assert!(!is_iso_date("24-01-01"));
+        assert!(!is_iso_date("2024-1-01"));
+    }
+
+    #[test]
+    fn timestamps_become_iso_dates() {
+        assert_eq!(iso_date(0), "1970-01-01");
+        assert_eq!(iso_date(951_782_400), "2000-02-29");
+        assert_eq!(iso_date(1_735_603_200 + 86_399), "2024-12-31");
+
+        assert_eq!(iso_date_timestamp("1970-01-01"), Some(0));
+        assert_eq!(iso_date_timestamp("2000-02-29"), Some(951_782_400));
+        assert_eq!(iso_date_timestamp("2024-12-31"), Some(1_735_603_200));
+        assert_eq!(iso_date_timestamp("2023-02-29"), None);
+    }
+
+    #[test]
+    fn http_dates_round_trip() {
+        assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+        assert_eq!(parse_http_date(&http_date(1_735_689_599)), Some(1_735_689_599));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 31 Feb 1994 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("hammer", "hammer"), 0);
+        assert_eq!(levenshtein("hamer", "hammer"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(search_tokens("Red-Hammer, 2x"), ["red", "hammer", "2x"]);
+    }
+
+    #[test]
+    fn fts_queries_quote_every_word() {
+        assert_eq!(fts_query("red  hammer"), "\"red\"* OR \"hammer\"*");
+        assert_eq!(fts_query("NOT \"x\" AND y*"), "\"NOT\"* OR \"x\"* OR \"AND\"* OR \"y\"*");
+        assert_eq!(fts_query(" -*- "), "");
+    }
+
+    proptest! {
+        #[test]
+        fn fts_queries_are_only_quoted_words(query in "\\PC{0,40}") {
+            let fts = fts_query(&query);
+            for term in fts.split(" OR ").filter(|_| !fts.is_empty()) {
+                let word = term.strip_prefix('"').and_then(|term| term.strip_suffix("\"*"));
+                prop_assert!(word.is_some_and(|word| word.chars().all(char::is_alphanumeric)));
+            }
+        }
+
+        #[test]
+        fn placeholders_match_the_values(count in 0usize..50) {
+            let placeholders = placeholders(count);
+            prop_assert_eq!(placeholders.matches('?').count(), count);
+            prop_assert_eq!(placeholders == "NULL", count == 0);
+        }
+    }
+
+    #[test]
+    fn prefixes_expand_to_shortest_words() {
+        let names = ["Cordless Drill", "drill bits", "Driver set", "Hammer drill"];
+        assert_eq!(prefix_expansions("dri", names, 2), ["drill", "driver"]);
+        assert_eq!(prefix_expansions("drill", names, 20), Vec::<String>::new());
+    }
+}
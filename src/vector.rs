@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use axum::async_trait;
+
+use crate::{Result, SemanticConfig, VectorBackendKind, ID};
+
+/// Turns the text of an item or a query into a vector, similar texts end up close to each other.
+/// How similar depends on the embedder: a sentence model places synonyms close to each other,
+/// [`HashingEmbedder`] only words that are spelled alike.
+pub trait Embedder: Send + Sync {
+    /// Names the model and the settings it embeds with. Vectors of different models can't be
+    /// compared, so all items are embedded again when it changes.
+    fn model(&self) -> String;
+
+    /// Length of every vector the embedder returns
+    fn dimensions(&self) -> usize;
+
+    /// Normalized to a length of 1, or all zeros if the text has no words
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+/// Changes whenever [`HashingEmbedder`] embeds the same text differently
+const HASHING_EMBEDDER_VERSION: u32 = 1;
+
+/// Embeds texts by hashing their words and the character trigrams of the words into the
+/// dimensions of the vector. This is not a language model: it knows no synonyms, `mallet` is
+/// not close to `hammer`. It runs in-process without a model file and matches inflections and
+/// typos like `hammers` or `hamer` with `hammer`.
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+
+    fn add(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        // the sign keeps collisions of unrelated features from adding up
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[(hash % self.dimensions as u64) as usize] += sign * weight;
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> String {
+        format!("hashing-v{}-{}", HASHING_EMBEDDER_VERSION, self.dimensions)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        let text = text.to_lowercase();
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            self.add(&mut vector, word, 1.0);
+            let chars: Vec<char> = format!("#{}#", word).chars().collect();
+            for trigram in chars.windows(3) {
+                self.add(&mut vector, &trigram.iter().collect::<String>(), 0.5);
+            }
+        }
+
+        let length = norm(&vector);
+        if length > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= length);
+        }
+        vector
+    }
+}
+
+/// 64 bit FNV-1a, unlike the std hasher it is stable across releases and runs
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Stores the vectors of items and finds the ones closest to a query
+#[async_trait]
+pub trait VectorBackend: Send + Sync {
+    /// Replaces the vector of the item, if it has one
+    async fn upsert(&self, id: ID, vector: Vec<f32>) -> Result<()>;
+
+    /// Does nothing if the item has no vector
+    async fn remove(&self, id: ID) -> Result<()>;
+
+    /// Replaces all vectors at once. Searches see either the old or the new vectors, never a
+    /// mix of both.
+    async fn replace_all(&self, vectors: HashMap<ID, Vec<f32>>) -> Result<()>;
+
+    /// Up to `limit` items with their cosine similarity to `vector`, most similar first
+    async fn search(&self, vector: &[f32], limit: usize) -> Result<Vec<(ID, f32)>>;
+}
+
+/// Keeps the vectors in memory and compares the query with every one of them. There is no
+/// approximate index like HNSW: the results are exact, and a few thousand items are searched in
+/// well under a millisecond. The vectors are not persisted, they are embedded again on startup.
+#[derive(Default)]
+pub struct MemoryVectorIndex {
+    vectors: RwLock<HashMap<ID, Vec<f32>>>,
+}
+
+#[async_trait]
+impl VectorBackend for MemoryVectorIndex {
+    async fn upsert(&self, id: ID, vector: Vec<f32>) -> Result<()> {
+        self.vectors.write().unwrap().insert(id, vector);
+        Ok(())
+    }
+
+    async fn remove(&self, id: ID) -> Result<()> {
+        self.vectors.write().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn replace_all(&self, vectors: HashMap<ID, Vec<f32>>) -> Result<()> {
+        *self.vectors.write().unwrap() = vectors;
+        Ok(())
+    }
+
+    async fn search(&self, vector: &[f32], limit: usize) -> Result<Vec<(ID, f32)>> {
+        let mut matches: Vec<(ID, f32)> = self
+            .vectors
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, other)| (*id, cosine_similarity(vector, other)))
+            .collect();
+        matches.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let length = norm(a) * norm(b);
+    if length > 0.0 {
+        dot / length
+    } else {
+        0.0
+    }
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|v| v * v).sum::<f32>().sqrt()
+}
+
+/// The vector backend a deployment configured, `None` if vector search is off. Only read on
+/// startup.
+pub fn vector_backend_from_config(config: &SemanticConfig) -> Option<Arc<dyn VectorBackend>> {
+    match config.backend {
+        VectorBackendKind::None => None,
+        VectorBackendKind::Memory => Some(Arc::new(MemoryVectorIndex::default())),
+    }
+}
+
+/// The embedder of the configured vector backend. Only read on startup.
+pub fn embedder_from_config(config: &SemanticConfig) -> Arc<dyn Embedder> {
+    Arc::new(HashingEmbedder::new(config.dimensions))
+}
+
+#[cfg(test)]
+mod test_vector {
+    use super::{Embedder, HashingEmbedder, MemoryVectorIndex, VectorBackend};
+
+    #[test]
+    fn embeddings_are_normalized() {
+        let embedder = HashingEmbedder::new(64);
+        let vector = embedder.embed("Cordless drill");
+        assert_eq!(vector.len(), 64);
+        assert_eq!(embedder.model(), "hashing-v1-64");
+        assert!((super::norm(&vector) - 1.0).abs() < 1e-5);
+        assert!(embedder.embed(" - ").iter().all(|v| *v == 0.0));
+    }
+
+    #[tokio::test]
+    async fn closest_vectors_come_first() {
+        let embedder = HashingEmbedder::new(256);
+        let index = MemoryVectorIndex::default();
+        for (id, text) in [(1, "claw hammer"), (2, "hand saw"), (3, "camping tent")] {
+            index.upsert(id, embedder.embed(text)).await.unwrap();
+        }
+
+        let matches = index.search(&embedder.embed("hammers"), 2).await.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].0, 1);
+
+        index.remove(1).await.unwrap();
+        let matches = index.search(&embedder.embed("hammers"), 3).await.unwrap();
+        assert!(matches.iter().all(|(id, _)| *id != 1));
+
+        let replaced = [(4, embedder.embed("tent pegs"))].into_iter().collect();
+        index.replace_all(replaced).await.unwrap();
+        let matches = index.search(&embedder.embed("tent"), 3).await.unwrap();
+        assert_eq!(matches.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [4]);
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+use crate::{util, Webhook};
+
+/// Number of delivery attempts per webhook and event before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    ItemCreated,
+    ItemUpdated,
+    ItemDeleted,
+    /// A bulk deleted item was taken out of the trash
+    ItemRestored,
+    ItemStateChanged,
+    ItemDisposed,
+    ItemReserved,
+    ItemReservationReleased,
+    CategoryCreated,
+    CategoryRenamed,
+    CategoryUpdated,
+    CategoryMoved,
+    LocationCreated,
+    LocationRenamed,
+    CollectionCreated,
+    CollectionUpdated,
+    CollectionItemAdded,
+    CollectionItemRemoved,
+    CollectionReordered,
+    /// The inventory changed since the snapshot of the day before, see [`crate::DailyDiff`]
+    DailyDiffReported,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::ItemCreated => "item.created",
+            EventKind::ItemUpdated => "item.updated",
+            EventKind::ItemDeleted => "item.deleted",
+            EventKind::ItemRestored => "item.restored",
+            EventKind::ItemStateChanged => "item.state_changed",
+            EventKind::ItemDisposed => "item.disposed",
+            EventKind::ItemReserved => "item.reserved",
+            EventKind::ItemReservationReleased => "item.reservation_released",
+            EventKind::CategoryCreated => "category.created",
+            EventKind::CategoryRenamed => "category.renamed",
+            EventKind::CategoryUpdated => "category.updated",
+            EventKind::CategoryMoved => "category.moved",
+            EventKind::LocationCreated => "location.created",
+            EventKind::LocationRenamed => "location.renamed",
+            EventKind::CollectionCreated => "collection.created",
+            EventKind::CollectionUpdated => "collection.updated",
+            EventKind::CollectionItemAdded => "collection.item_added",
+            EventKind::CollectionItemRemoved => "collection.item_removed",
+            EventKind::CollectionReordered => "collection.reordered",
+            EventKind::DailyDiffReported => "inventory.daily_diff",
+        }
+    }
+
+    /// Type of the entity the event is about
+    pub fn entity(&self) -> &'static str {
+        match self {
+            EventKind::ItemCreated
+            | EventKind::ItemUpdated
+            | EventKind::ItemDeleted
+            | EventKind::ItemRestored
+            | EventKind::ItemStateChanged
+
+            | EventKind::ItemDisposed
+            | EventKind::ItemReserved
+            | EventKind::ItemReservationReleased => "item",
+            EventKind::CategoryCreated
+            | EventKind::CategoryRenamed
+            | EventKind::CategoryUpdated
+            | EventKind::CategoryMoved => "category",
+            EventKind::LocationCreated | EventKind::LocationRenamed => "location",
+            EventKind::CollectionCreated
+            | EventKind::CollectionUpdated
+            | EventKind::CollectionItemAdded
+            | EventKind::CollectionItemRemoved
+            | EventKind::CollectionReordered => "collection",
+            EventKind::DailyDiffReported => "inventory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookEvent {
+    pub event: &'static str,
+    pub timestamp: i64,
+    pub data: serde_json::Value,
+}
+
+/// Queues mutation events and delivers them to all registered webhooks in the background.
+pub struct WebhookDispatcher {
+    sender: mpsc::UnboundedSender<WebhookEvent>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(conn: sqlx::SqlitePool) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run_dispatcher(conn, receiver));
+        Self { sender }
+    }
+
+    pub fn fire<T: Serialize>(&self, kind: EventKind, data: &T) {
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Could not serialize webhook payload: {}", e);
+                return;
+            }
+        };
+
+        let event = WebhookEvent {
+            event: kind.as_str(),
+            timestamp: util::now(),
+            data,
+        };
+
+        if self.sender.send(event).is_err() {
+            error!("Webhook dispatcher is not running");
+        }
+    }
+}
+
+async fn run_dispatcher(conn: sqlx::SqlitePool, mut receiver: mpsc::UnboundedReceiver<WebhookEvent>) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Could not create webhook http client: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = receiver.recv().await {
+        let hooks = match sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks")
+            .fetch_all(&conn)
+            .await
+        {
+            Ok(hooks) => hooks,
+            Err(e) => {
+                error!("Could not load webhooks: {}", e);
+                continue;
+            }
+        };
+
+        for hook in hooks.into_iter().filter(|h| h.accepts(event.event)) {
+            tokio::spawn(deliver(conn.clone(), client.clone(), hook, event.clone()));
+        }
+    }
+}
+
+async fn deliver(conn: sqlx::SqlitePool, client: reqwest::Client, hook: Webhook, event: WebhookEvent) {
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Could not serialize webhook event: {}", e);
+            return;
+        }
+    };
+    let signature = sign(&hook.secret, &body);
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        debug!("Delivering {} to {} (attempt {})", event.event, hook.url, attempt);
+        let result = client
+            .post(&hook.url)
+            .header("Content-Type", "application/json")
+            .header("X-FindMePls-Event", event.event)
+            .header("X-FindMePls-Signature", format!("sha256={}", signature))
+            .body(body.clone())
+            .send()
+            .await;
+
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16() as i32), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16() as i32),
+                Some(format!("unexpected status {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let success = error.is_none();
+
+        let logged = sqlx::query(
+            "INSERT INTO webhook_deliveries (webhook_id, event, attempt, status_code, error, delivered_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(hook.id)
+        .bind(event.event)
+        .bind(attempt as i32)
+        .bind(status_code)
+        .bind(error.clone())
+        .bind(util::now())
+        .execute(&conn)
+        .await;
+        if let Err(e) = logged {
+            error!("Could not log webhook delivery: {}", e);
+        }
+
+        if success {
+            return;
+        }
+
+        warn!(
+            "Webhook delivery to {} failed: {}",
+            hook.url,
+            error.unwrap_or_default()
+        );
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(backoff(attempt)).await;
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the request body, keyed with the webhook secret
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC accepts keys of any length, so this can't fail
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Exponential backoff: 1s, 2s, 4s, ...
+pub fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << (attempt - 1).min(10))
+}
+
+#[cfg(test)]
+mod test_webhooks {
+    use std::time::Duration;
+
+    use crate::{backoff, sign, Webhook};
+
+    #[test]
+    fn signature_matches_reference() {
+        // RFC 4231 test case 2
+        let signature = sign("Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(5), Duration::from_secs(16));
+    }
+
+    #[test]
+    fn event_filter() {
+        let hook = Webhook {
+            id: None,
+            url: "http://localhost".to_owned(),
+            secret: "".to_owned(),
+            events: "item.created, item.deleted".to_owned(),
+        };
+        assert!(hook.accepts("item.deleted"));
+        assert!(!hook.accepts("category.created"));
+
+        let hook = Webhook {
+            events: "*".to_owned(),
+            ..hook
+        };
+        assert!(hook.accepts("category.created"));
+    }
+}